@@ -0,0 +1,33 @@
+// Plays per second of epsilon-greedy on the Gaussian testbed as the number
+// of arms grows, with and without a kernel. A play should cost about the
+// same at 10 arms as at 10,000.
+//
+//     cargo bench --bench throughput
+
+extern crate n_armed_bandit_greedy;
+
+use std::time::Instant;
+
+use n_armed_bandit_greedy::bench;
+
+const TASKS: usize = 32;
+const PLAYS: usize = 2000;
+
+fn main() {
+    for &(agent, arms) in &[("epsilon-greedy(epsilon=0.1)", 10),
+                            ("epsilon-greedy(epsilon=0.1)", 1000),
+                            ("epsilon-greedy(epsilon=0.1)", 10000),
+                            ("epsilon-greedy(epsilon=0.1,kernel-width=5)", 10),
+                            ("epsilon-greedy(epsilon=0.1,kernel-width=5)", 10000)] {
+        // the fastest of a few runs
+        let mut best = None;
+        for _ in 0..4 {
+            let started = Instant::now();
+            bench::gaussian_run(agent, arms, TASKS, PLAYS, 1).unwrap();
+            let secs = started.elapsed().as_secs_f64();
+            best = Some(best.map_or(secs, |best: f64| best.min(secs)));
+        }
+        let plays_per_sec = (TASKS * PLAYS) as f64 / best.unwrap();
+        println!("{:<44} {:>6} arms {:>12.0} plays/s", agent, arms, plays_per_sec);
+    }
+}
@@ -0,0 +1,57 @@
+/* C interface to the bandit agents; see src/ffi.rs. */
+
+#ifndef BANDIT_H
+#define BANDIT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BanditAgent BanditAgent;
+
+/* spec is an agent spec as JSON, e.g. {"kind": "ucb", "c": 2.0}, or NULL for
+ * epsilon-greedy with epsilon 0.1. Only "kind" is required: parameters left
+ * out take the defaults of the command-line flags, so {"kind": "ucb"} is UCB
+ * with c = 2. The exceptions are a registered agent's "name", a meta agent's
+ * "selector" and "agents", a wrapped agent's "wrapper" and "agent", and a
+ * preprocessed agent's "preprocessing" and "agent". Returns NULL if the spec
+ * is invalid or arms is 0. */
+BanditAgent *bandit_new(const char *spec, size_t arms, uint64_t seed);
+
+/* The arm to play next, or SIZE_MAX on failure. */
+size_t bandit_choose(BanditAgent *agent);
+
+/* Tells the agent an arm's reward. Returns 0, or -1 for a NULL agent, an arm
+   out of range or a reward that isn't finite. */
+int bandit_reward(BanditAgent *agent, size_t arm, double reward);
+
+void bandit_free(BanditAgent *agent);
+
+/* One task of the gaussian testbed, to play an agent against step by step:
+ * every arm's true value is drawn from a standard normal, and a play pays it
+ * plus standard normal noise. */
+typedef struct BanditSim BanditSim;
+
+/* Returns NULL if arms is 0. */
+BanditSim *bandit_sim_new(size_t arms, uint64_t seed);
+
+/* Plays an arm and returns its reward, or NaN for a NULL simulator or an arm
+   out of range. */
+double bandit_sim_play(BanditSim *sim, size_t arm);
+
+/* An arm's true value, or NaN on failure. */
+double bandit_sim_mean(const BanditSim *sim, size_t arm);
+
+/* The arm with the highest true value, or SIZE_MAX for a NULL simulator. */
+size_t bandit_sim_best(const BanditSim *sim);
+
+void bandit_sim_free(BanditSim *sim);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,281 @@
+// A/B test simulator: a website experiment in the terms it's usually
+// planned in. Every variant has a conversion rate, given with
+// `--rates 0.05,0.055` (the first is the control), visitors arrive at
+// `--visitors-per-day` for `--days`, and each visitor converts (reward 1) or
+// doesn't (reward 0). The agent given with the usual agent flags assigns the
+// visitors of every simulated experiment, and is compared with a classic
+// A/B test that splits them evenly between the variants at random (50/50
+// for two). Both see the same visitors: whether visitor t would convert on
+// variant a is drawn in advance.
+//
+// For both, at the end of every day it reports
+//
+//     regret    expected conversions lost so far against sending everyone
+//               to the best variant
+//     detected  fraction of experiments that have declared a winner, i.e.
+//               some variant's posterior probability of being the best is
+//               at least `--confidence` (0.95) (see stopping), checked once
+//               a day as a dashboard would be; and the fraction that
+//               declared the right one
+//
+// written as CSV, one line per day:
+//
+//     day,agent_regret,split_regret,agent_detected,split_detected,
+//     agent_correct,split_correct
+//
+// followed by a summary of the conversions the agent saved over the split
+// and the median time to detect a winner, in days.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{Environment, FixedRewards};
+use crate::output::OutputFile;
+use crate::paired::mean_and_std_dev;
+use crate::progress;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::stats::ArmSummary;
+use crate::stopping::{BetaProbabilityBest, StoppingRule};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, Agent};
+
+pub struct Options {
+    pub rates: Vec<f64>,
+    pub visitors_per_day: usize,
+    pub days: usize,
+    pub num_tasks: usize,
+    pub seed: Option<usize>,
+
+    // posterior probability of being the best that declares a winner, and
+    // the posterior draws it's estimated from
+    pub confidence: f64,
+    pub samples: usize,
+    pub agent: AgentSpec,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            rates: vec![],
+            visitors_per_day: 1000,
+            days: 14,
+            num_tasks: 1000,
+            seed: None,
+            confidence: 0.95,
+            samples: 1000,
+            agent: AgentSpec::from_flags(flags)?,
+            output: "ab-test.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "rates" => {
+                    for part in value.split(',') {
+                        opts.rates.push(parse_value(key, part)?);
+                    }
+                }
+                "visitors-per-day" => opts.visitors_per_day = parse_value(key, value)?,
+                "days" => opts.days = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "confidence" => opts.confidence = parse_value(key, value)?,
+                "samples" => opts.samples = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.rates.len() < 2 {
+            return Err("an A/B test needs at least two conversion rates (`--rates`)"
+                       .to_string());
+        }
+        if !opts.rates.iter().all(|&p| (0.0..=1.0).contains(&p)) {
+            return Err("conversion rates must be in [0, 1]".to_string());
+        }
+        if opts.visitors_per_day == 0 || opts.days == 0 || opts.num_tasks < 2 {
+            return Err("visitors per day and days must be positive and there must be at \
+                        least 2 tasks".to_string());
+        }
+        if !(opts.confidence > 0.0 && opts.confidence < 1.0) || opts.samples == 0 {
+            return Err("confidence must be in (0, 1) and samples positive".to_string());
+        }
+        Ok(opts)
+    }
+
+    fn best_rate(&self) -> f64 {
+        self.rates.iter().fold(0.0, |acc: f64, &p| acc.max(p))
+    }
+}
+
+// One simulated experiment run by one policy.
+struct Experiment {
+    // expected conversions lost by the end of every day
+    regret: Vec<f64>,
+
+    // the day (from 1) a winner was declared, and the winner
+    detected: Option<(usize, usize)>,
+}
+
+// Runs the experiment on `env`, assigning visitors with `agent`, or evenly
+// at random without one.
+fn simulate<R: Rng>(env: &mut FixedRewards, mut agent: Option<&mut dyn Agent>, opts: &Options,
+                    rng: &mut R, check_rng: &mut R) -> Experiment {
+    let k = opts.rates.len();
+    let best = opts.best_rate();
+    let rule = BetaProbabilityBest { threshold: opts.confidence, samples: opts.samples };
+    let mut arms: Vec<ArmSummary> = (0..k).map(|_| ArmSummary { count: 0, mean: 0.0 }).collect();
+    let mut conversions = vec![0.0; k];
+    let mut experiment = Experiment { regret: vec![], detected: None };
+    let mut regret = 0.0;
+    for day in 1..opts.days + 1 {
+        for _ in 0..opts.visitors_per_day {
+            let action = match agent {
+                Some(ref mut agent) => agent.choose_action(rng),
+                None => rng.gen_range(0, k),
+            };
+            let reward = env.step(action, rng).unwrap();
+            if let Some(ref mut agent) = agent {
+                agent.receive_reward(reward, action);
+            }
+            conversions[action] += reward;
+            arms[action].count += 1;
+            arms[action].mean = conversions[action] / arms[action].count as f64;
+            regret += best - opts.rates[action];
+        }
+        experiment.regret.push(regret);
+        if experiment.detected.is_none() {
+            let plays = day * opts.visitors_per_day;
+            if let Some(decision) = rule.check(&arms, plays, check_rng) {
+                experiment.detected = Some((day, decision.winner));
+            }
+        }
+    }
+    experiment
+}
+
+// Every task's experiment run by the agent and by the even split.
+fn run_tasks<R: Rng>(rng: &mut R, opts: &Options) -> Vec<(Experiment, Experiment)> {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let visitors = opts.days * opts.visitors_per_day;
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut experiments = vec![];
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let mut env = FixedRewards::draw_bernoulli(opts.rates.clone(), visitors, &mut rng);
+            let (choice_seed, check_seed): (usize, usize) = (rng.gen(), rng.gen());
+
+            let mut agent = opts.agent.build(opts.rates.len());
+            let by_agent = simulate(&mut env, Some(&mut *agent), opts,
+                                    &mut seeded_rng(choice_seed), &mut seeded_rng(check_seed));
+            env.rewind();
+            let by_split = simulate(&mut env, None, opts, &mut seeded_rng(choice_seed),
+                                    &mut seeded_rng(check_seed));
+            experiments.push((by_agent, by_split));
+            progress::task_done(i);
+        }
+        experiments
+    });
+    chunks.into_iter().flatten().collect()
+}
+
+// The agent's experiment for p = 0, the split's for p = 1.
+fn by(experiments: &(Experiment, Experiment), p: usize) -> &Experiment {
+    if p == 0 { &experiments.0 } else { &experiments.1 }
+}
+
+// Median day a winner was declared, among the experiments that declared
+// one.
+fn median_detection(days: &mut [usize]) -> Option<f64> {
+    if days.is_empty() {
+        return None;
+    }
+    days.sort();
+    let m = days.len() / 2;
+    Some(if days.len() % 2 == 1 { days[m] as f64 } else { (days[m - 1] + days[m]) as f64 / 2.0 })
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let experiments = match opts.seed {
+        Some(seed) => run_tasks(&mut seeded_rng(seed), opts),
+        None => run_tasks(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&experiments, opts).map_err(|e| format!("couldn't write `{}`: {}", opts.output,
+                                                      e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let visitors = opts.days * opts.visitors_per_day;
+    println!("A/B test of {} variants, conversion rates {:?}: {} visitors/day for {} days, {} \
+              simulated experiments", opts.rates.len(), opts.rates, opts.visitors_per_day,
+             opts.days, opts.num_tasks);
+    println!("{:<32} {:>12} {:>10} {:>10} {:>10} {:>14}",
+             "policy", "regret", "std err", "% detected", "% correct", "median days");
+    let split_label = format!("even split (1/{})", opts.rates.len());
+    for (p, label) in [opts.agent.label(), split_label].iter().enumerate() {
+        let (sum, sum_sq) = experiments.iter().fold((0.0, 0.0), |(s, s2), e| {
+            let regret = by(e, p).regret[opts.days - 1];
+            (s + regret, s2 + regret * regret)
+        });
+        let (mean, std_dev) = mean_and_std_dev(sum, sum_sq, opts.num_tasks);
+        let mut days: Vec<usize> = experiments.iter().filter_map(|e| by(e, p).detected)
+                                              .map(|(day, _)| day).collect();
+        let correct = experiments.iter().filter_map(|e| by(e, p).detected)
+                                 .filter(|&(_, winner)| opts.rates[winner] == opts.best_rate())
+                                 .count();
+        let detected = days.len();
+        let median = match median_detection(&mut days) {
+            Some(median) => format!("{}", median),
+            None => "-".to_string(),
+        };
+        println!("{:<32} {:>12.2} {:>10.2} {:>10.1} {:>10.1} {:>14}", label, mean,
+                 std_dev / tasks.sqrt(), 100.0 * detected as f64 / tasks,
+                 100.0 * correct as f64 / tasks, median);
+    }
+
+    let diffs: Vec<f64> = experiments.iter()
+        .map(|e| e.1.regret[opts.days - 1] - e.0.regret[opts.days - 1])
+        .collect();
+    let sum = diffs.iter().fold(0.0, |acc, d| acc + d);
+    let sum_sq = diffs.iter().fold(0.0, |acc, d| acc + d * d);
+    let (mean, std_dev) = mean_and_std_dev(sum, sum_sq, opts.num_tasks);
+    println!("conversions saved by the agent over the split: {:.2} (std err {:.2}) of {} \
+              visitors", mean, std_dev / tasks.sqrt(), visitors);
+    Ok(())
+}
+
+fn write_csv(experiments: &[(Experiment, Experiment)], opts: &Options) -> io::Result<()> {
+    let tasks = opts.num_tasks as f64;
+    let best = opts.best_rate();
+    let mut f = OutputFile::create(&opts.output)?;
+    writeln!(f, "day,agent_regret,split_regret,agent_detected,split_detected,agent_correct,\
+                 split_correct")?;
+    for d in 0..opts.days {
+        let day = d + 1;
+        let mut regret = [0.0; 2];
+        let mut detected = [0.0; 2];
+        let mut correct = [0.0; 2];
+        for e in experiments {
+            for (p, run) in [&e.0, &e.1].iter().enumerate() {
+                regret[p] += run.regret[d];
+                if let Some((on, winner)) = run.detected {
+                    if on <= day {
+                        detected[p] += 1.0;
+                        if opts.rates[winner] == best {
+                            correct[p] += 1.0;
+                        }
+                    }
+                }
+            }
+        }
+        writeln!(f, "{},{},{},{},{},{},{}", day, regret[0] / tasks, regret[1] / tasks,
+                 detected[0] / tasks, detected[1] / tasks, correct[0] / tasks,
+                 correct[1] / tasks)?;
+    }
+    f.finish()
+}
@@ -0,0 +1,157 @@
+// Saved agents. An agent's state() together with the spec it was built from
+// is enough to rebuild it in another process, with everything it has
+// learned, so it can be saved to a JSON file like
+//
+//     {
+//       "spec": { "kind": "ucb", "c": 2.0 },
+//       "state": { "stats": { "counts": [...], ... }, "c": 2.0 }
+//     }
+//
+// and loaded later, e.g. to continue a run or to start a new one from what
+// an earlier one learned.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+
+use crate::error::Error;
+use crate::spec::AgentSpec;
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedAgent {
+    pub spec: AgentSpec,
+    pub state: serde_json::Value,
+}
+
+impl SavedAgent {
+    pub fn new(spec: &AgentSpec, agent: &dyn Agent) -> SavedAgent {
+        SavedAgent { spec: spec.clone(), state: agent.state() }
+    }
+
+    pub fn load(path: &str) -> Result<SavedAgent, Error> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| Error::io(format!("read agent `{}`", path), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| Error::Invalid(format!("invalid agent `{}`: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let write = || -> io::Result<()> {
+            let mut f = File::create(path)?;
+            serde_json::to_writer_pretty(&mut f, self)?;
+            writeln!(f)
+        };
+        write().map_err(|e| Error::io(format!("write `{}`", path), e))
+    }
+
+    pub fn agent(&self) -> Result<Box<dyn Agent>, Error> {
+        self.spec.validate()?;
+        Ok(self.spec.restore(self.state.clone())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::normal::StandardNormal;
+    use rand::Rng;
+
+    use crate::explore_pool::ExplorePolicy;
+    use crate::preprocess::{Normalization, Preprocessing};
+    use crate::seeded_rng;
+    use crate::wrappers::WrapperSpec;
+
+    const ARMS: usize = 5;
+
+    fn eps(step_size: Option<f64>, mom_groups: Option<usize>, kernel_width: Option<f64>,
+           history: Option<usize>) -> AgentSpec {
+        AgentSpec::EpsilonGreedy {
+            epsilon: 0.1,
+            step_size,
+            tie_tolerance: 0.0,
+            min_samples: 2,
+            mom_groups,
+            kernel_width,
+            history,
+        }
+    }
+
+    // Every kind of agent, and every way of building one.
+    fn specs() -> Vec<AgentSpec> {
+        vec![
+            eps(None, None, None, None),
+            eps(Some(0.2), None, None, None),
+            eps(None, Some(3), None, Some(20)),
+            eps(None, None, Some(1.5), None),
+            AgentSpec::ExplorePool { fraction: 0.2, policy: ExplorePolicy::LeastPulled },
+            AgentSpec::Ucb { c: 2.0 },
+            AgentSpec::Softmax { tau: 0.5 },
+            AgentSpec::Forecast { alpha: 0.1, beta: 0.05, c: 0.5 },
+            AgentSpec::Cvar { level: 0.2, c: 1.0, history: Some(30) },
+            AgentSpec::Bootstrap { replicates: 20 },
+            AgentSpec::Moss { horizon: Some(100), reset: false, history: None },
+            AgentSpec::Moss { horizon: None, reset: false, history: Some(16) },
+            AgentSpec::ExploreCommit { horizon: Some(100), reset: false, history: None },
+            AgentSpec::ExploreCommit { horizon: None, reset: true, history: None },
+            AgentSpec::Preprocessed {
+                preprocessing: Preprocessing {
+                    clip: Some((-1.0, 2.0)),
+                    normalize: Some(Normalization::Standardize),
+                },
+                agent: Box::new(AgentSpec::Ucb { c: 1.0 }),
+            },
+            AgentSpec::Meta {
+                selector: Box::new(AgentSpec::Ucb { c: 1.0 }),
+                agents: vec![eps(None, None, None, None), AgentSpec::Softmax { tau: 0.2 }],
+            },
+            AgentSpec::Wrapped {
+                wrapper: WrapperSpec::CountBonus { beta: 0.5 },
+                agent: Box::new(eps(None, None, None, None)),
+            },
+            AgentSpec::Wrapped {
+                wrapper: WrapperSpec::ChangeReset { delta: 0.0, threshold: 3.0 },
+                agent: Box::new(AgentSpec::Ucb { c: 1.0 }),
+            },
+        ]
+    }
+
+    // Plays `plays` plays on arms with means 0, 0.25, ..., with an arm
+    // added and one retired part way through.
+    fn play<R: Rng>(agent: &mut dyn Agent, plays: usize, rng: &mut R) -> Vec<usize> {
+        (0..plays).map(|t| {
+            if t == plays / 3 {
+                agent.add_arm(None);
+                agent.set_retired(1, true);
+            }
+            let action = agent.choose_action(rng);
+            let StandardNormal(noise) = rng.gen();
+            agent.receive_reward(0.25 * action as f64 + noise, action);
+            action
+        }).collect()
+    }
+
+    fn summaries(agent: &dyn Agent) -> Vec<(usize, f64)> {
+        agent.arm_summaries().iter().map(|arm| (arm.count, arm.mean)).collect()
+    }
+
+    // An agent saved and loaded knows what it knew, and goes on to play
+    // exactly as the original does on the same draws.
+    #[test]
+    fn every_kind_of_agent_survives_saving() {
+        for spec in specs() {
+            spec.validate().unwrap();
+            let mut agent = spec.build(ARMS);
+            play(&mut *agent, 90, &mut seeded_rng(1));
+            let json = serde_json::to_string(&SavedAgent::new(&spec, &*agent)).unwrap();
+            let saved: SavedAgent = serde_json::from_str(&json).unwrap();
+            assert_eq!(saved.spec, spec);
+            let mut restored = saved.agent().unwrap();
+            assert_eq!(summaries(&*restored), summaries(&*agent), "{}", spec.label());
+            assert_eq!(play(&mut *restored, 40, &mut seeded_rng(2)),
+                       play(&mut *agent, 40, &mut seeded_rng(2)), "{}", spec.label());
+        }
+    }
+}
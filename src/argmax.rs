@@ -0,0 +1,137 @@
+// Argmax over values that change one at a time, for agents that act
+// greedily on per-arm estimates and update one arm per play. The values sit
+// in the leaves of a binary tree whose every node holds the larger of its
+// two children, so changing a value costs O(log n), the maximum is at the
+// root, and the values within a tolerance of it are found by descending
+// only into subtrees whose maximum reaches the threshold, O(log n) per
+// value found, instead of rescanning all n arms every play.
+
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug)]
+pub struct MaxTree {
+    len: usize,
+
+    // the leaves start at index `leaves`, a power of two, and node i > 0 is
+    // the larger of nodes 2i and 2i + 1; leaves past `len` are -inf
+    leaves: usize,
+    nodes: Vec<f64>,
+}
+
+impl MaxTree {
+    pub fn new(values: &[f64]) -> MaxTree {
+        let leaves = values.len().next_power_of_two();
+        let mut nodes = vec![f64::NEG_INFINITY; 2 * leaves];
+        nodes[leaves..leaves + values.len()].copy_from_slice(values);
+        for i in (1..leaves).rev() {
+            nodes[i] = nodes[2 * i].max(nodes[2 * i + 1]);
+        }
+        MaxTree { len: values.len(), leaves, nodes }
+    }
+
+    pub fn get(&self, i: usize) -> f64 {
+        assert!(i < self.len);
+        self.nodes[self.leaves + i]
+    }
+
+    pub fn set(&mut self, i: usize, value: f64) {
+        assert!(i < self.len);
+        let mut node = self.leaves + i;
+        self.nodes[node] = value;
+        while node > 1 {
+            node /= 2;
+            self.nodes[node] = self.nodes[2 * node].max(self.nodes[2 * node + 1]);
+        }
+    }
+
+    // Appends a value, rebuilding the tree when it's full.
+    pub fn push(&mut self, value: f64) {
+        if self.len < self.leaves {
+            self.len += 1;
+            self.set(self.len - 1, value);
+        } else {
+            let mut values: Vec<f64> = self.nodes[self.leaves..self.leaves + self.len].to_vec();
+            values.push(value);
+            *self = MaxTree::new(&values);
+        }
+    }
+
+    // The largest value, or -inf if there are none.
+    pub fn max(&self) -> f64 {
+        if self.len == 0 { f64::NEG_INFINITY } else { self.nodes[1] }
+    }
+
+    // The indices of the values >= `threshold`, in increasing order.
+    pub fn at_least(&self, threshold: f64) -> Vec<usize> {
+        let mut found = vec![];
+        if self.len > 0 {
+            self.collect(1, threshold, &mut found);
+        }
+        found
+    }
+
+    fn collect(&self, node: usize, threshold: f64, found: &mut Vec<usize>) {
+        if !self.nodes[node].partial_cmp(&threshold).is_some_and(Ordering::is_ge) {
+            return;
+        }
+        if node >= self.leaves {
+            if node - self.leaves < self.len {
+                found.push(node - self.leaves);
+            }
+            return;
+        }
+        self.collect(2 * node, threshold, found);
+        self.collect(2 * node + 1, threshold, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::seeded_rng;
+
+    fn at_least(values: &[f64], threshold: f64) -> Vec<usize> {
+        (0..values.len()).filter(|&i| values[i] >= threshold).collect()
+    }
+
+    // Random updates and appends, checked against a linear scan.
+    #[test]
+    fn matches_a_linear_scan() {
+        let mut rng = seeded_rng(3);
+        let mut values: Vec<f64> = (0..5).map(|_| rng.next_f64()).collect();
+        let mut tree = MaxTree::new(&values);
+        for step in 0..500 {
+            if step % 25 == 0 {
+                values.push(rng.next_f64());
+                tree.push(values[values.len() - 1]);
+            } else {
+                let i = rng.gen_range(0, values.len());
+                // coarse values, so that some are tied
+                values[i] = (rng.next_f64() * 8.0).floor();
+                tree.set(i, values[i]);
+            }
+            let max = values.iter().fold(f64::NEG_INFINITY, |acc, &v| acc.max(v));
+            assert_eq!(tree.max(), max);
+            assert_eq!(tree.at_least(max), at_least(&values, max));
+            assert_eq!(tree.at_least(max - 2.0), at_least(&values, max - 2.0));
+            for (i, &value) in values.iter().enumerate() {
+                assert_eq!(tree.get(i), value);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_and_unusual_values() {
+        let mut tree = MaxTree::new(&[]);
+        assert_eq!(tree.max(), f64::NEG_INFINITY);
+        assert!(tree.at_least(f64::NEG_INFINITY).is_empty());
+        tree.push(1.0);
+        tree.push(f64::NAN);
+        tree.push(f64::NEG_INFINITY);
+        assert_eq!(tree.max(), 1.0);
+        // NaN is never at least anything, and the padding past the values
+        // isn't reported
+        assert_eq!(tree.at_least(f64::NEG_INFINITY), vec![0, 2]);
+    }
+}
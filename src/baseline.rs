@@ -0,0 +1,138 @@
+// Regression checks against a stored baseline. A grid search run with
+// `--save-baseline base.json` records the key metrics of every configuration
+// it ran (e.g. the epsilon-greedy settings currently in production). Later
+// runs given `--baseline base.json` compare each configuration that is also
+// in the baseline against it, and report a regression when
+//
+//     mean reward or final reward   drops by more than tolerance * |baseline|
+//     cumulative regret             rises by more than tolerance * |baseline|
+//
+// Every regression is printed and, with `--alerts`, appended to that file as
+// one JSON record per line; the run then exits with status 1.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+
+use crate::grid::GridResult;
+use crate::output::unix_time;
+
+#[derive(Serialize, Deserialize)]
+pub struct Baseline {
+    pub created_at: u64,
+    pub arms: usize,
+    pub tasks: usize,
+    pub plays: usize,
+    pub window: usize,
+    pub entries: Vec<BaselineEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub config: String,
+    pub mean_reward: f64,
+    pub final_reward: f64,
+    pub cumulative_regret: f64,
+}
+
+#[derive(Serialize)]
+pub struct Alert {
+    pub config: String,
+    pub metric: String,
+    pub baseline: f64,
+    pub current: f64,
+    pub tolerance: f64,
+    pub at: u64,
+}
+
+impl Baseline {
+    pub fn from_results(results: &[GridResult], arms: usize, tasks: usize, plays: usize,
+                        window: usize) -> Baseline {
+        Baseline {
+            created_at: unix_time(),
+            arms,
+            tasks,
+            plays,
+            window,
+            entries: results.iter().map(|r| BaselineEntry {
+                config: r.spec.label(),
+                mean_reward: r.mean_reward,
+                final_reward: r.final_reward,
+                cumulative_regret: r.cumulative_regret,
+            }).collect(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Baseline, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("couldn't read baseline `{}`: {}", path, e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("invalid baseline `{}`: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        serde_json::to_writer_pretty(&mut f, self)?;
+        writeln!(f)
+    }
+
+    // Metrics only compare on the same testbed shape; the number of tasks
+    // may differ.
+    pub fn check_comparable(&self, arms: usize, plays: usize, window: usize)
+                            -> Result<(), String> {
+        if self.arms != arms || self.plays != plays || self.window != window {
+            return Err(format!("baseline was run with {} arms, {} plays and window {}; \
+                                this run has {}, {} and {}",
+                               self.arms, self.plays, self.window, arms, plays, window));
+        }
+        Ok(())
+    }
+
+    // Regressions of `results` against the baseline. Configurations that
+    // aren't in the baseline are skipped.
+    pub fn regressions(&self, results: &Vec<GridResult>, tolerance: f64) -> Vec<Alert> {
+        let mut alerts = vec![];
+        let now = unix_time();
+        for r in results {
+            let label = r.spec.label();
+            let entry = match self.entries.iter().find(|e| e.config == label) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let checks = [("mean_reward", entry.mean_reward, r.mean_reward, true),
+                          ("final_reward", entry.final_reward, r.final_reward, true),
+                          ("cumulative_regret", entry.cumulative_regret, r.cumulative_regret,
+                           false)];
+            for &(metric, baseline, current, higher_is_better) in &checks {
+                let slack = tolerance * baseline.abs();
+                let regressed = if higher_is_better {
+                    current < baseline - slack
+                } else {
+                    current > baseline + slack
+                };
+                if regressed {
+                    alerts.push(Alert {
+                        config: label.clone(),
+                        metric: metric.to_string(),
+                        baseline,
+                        current,
+                        tolerance,
+                        at: now,
+                    });
+                }
+            }
+        }
+        alerts
+    }
+}
+
+// Appends one JSON record per alert.
+pub fn append_alerts(alerts: &Vec<Alert>, path: &str) -> io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    for alert in alerts {
+        serde_json::to_writer(&mut f, alert)?;
+        writeln!(f)?;
+    }
+    Ok(())
+}
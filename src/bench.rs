@@ -0,0 +1,22 @@
+// Entry points for the benchmarks in benches/, which only see the crate's
+// public API.
+
+use crate::environment::Testbed;
+use crate::meta;
+use crate::progress;
+use crate::{run_experiment_with, seeded_rng, RunConfig};
+
+// Runs `agent`, a kind with its parameters as in `--meta-agents` (e.g.
+// "epsilon-greedy(epsilon=0.1)"), on the original Gaussian testbed, and
+// returns the average reward at the last play.
+pub fn gaussian_run(agent: &str, arms: usize, tasks: usize, plays: usize, seed: usize)
+                    -> Result<f64, String> {
+    let spec = meta::parse_agent(agent)?;
+    spec.validate()?;
+    progress::set_quiet();
+    let make_agent = || spec.build(arms);
+    let testbed = Testbed::Gaussian { sd: None };
+    let run = RunConfig::new(&testbed, arms, tasks, plays);
+    let curves = run_experiment_with(&mut seeded_rng(seed), &run, &make_agent);
+    Ok(curves.avg_reward[plays - 1])
+}
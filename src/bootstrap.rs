@@ -0,0 +1,170 @@
+// Bootstrapped Thompson sampling (Eckles & Kaptein, 2014). Instead of a
+// parametric posterior every arm keeps B bootstrap replicates of its
+// reward history, maintained online: each reward goes into each replicate
+// with weight 2 or 0, with even odds ("double or nothing"). To act, one
+// replicate of every arm is picked at random and the arm whose picked
+// replicate has the highest mean is played, so arms are played about as
+// often as the bootstrap thinks they're the best, whatever the shape of
+// their reward distribution. A replicate with no weight yet counts as
+// maximizing, so arms with few rewards keep getting tried.
+//
+// The weights need random draws and receive_reward has no RNG, so rewards
+// wait until the next choice and are weighted with its RNG.
+//
+// The replicate means stand in for a posterior: an arm's posterior mean and
+// variance are those of its weighted replicates' means. Rewards still
+// waiting for the next choice aren't in them yet.
+
+use rand::Rng;
+
+use crate::stats::{ArmPosterior, ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct BootstrapBandit {
+    stats: ArmStats,
+
+    // (weighted sum, weight) of every replicate of every arm
+    replicates: Vec<Vec<(f64, f64)>>,
+    num_replicates: usize,
+
+    // rewards not yet added to the replicates
+    pending: Vec<(usize, f64)>,
+}
+
+impl BootstrapBandit {
+    pub fn new(n: usize, num_replicates: usize) -> BootstrapBandit {
+        assert!(n > 0);
+        assert!(num_replicates > 0);
+        BootstrapBandit {
+            stats: ArmStats::new(n),
+            replicates: vec![vec![(0.0, 0.0); num_replicates]; n],
+            num_replicates,
+            pending: vec![],
+        }
+    }
+
+    fn replicate_mean(&self, action: usize, j: usize) -> f64 {
+        let (sum, weight) = self.replicates[action][j];
+        if weight == 0.0 { f64::INFINITY } else { sum / weight }
+    }
+}
+
+impl Agent for BootstrapBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        for (action, reward) in self.pending.drain(..) {
+            for replicate in self.replicates[action].iter_mut() {
+                if rng.gen::<bool>() {
+                    replicate.0 += 2.0 * reward;
+                    replicate.1 += 2.0;
+                }
+            }
+        }
+
+        let active = self.stats.active();
+        let mut max_actions = vec![];
+        let mut max_value = f64::NEG_INFINITY;
+        for &i in &active {
+            let j = rng.gen_range(0, self.num_replicates);
+            let value = self.replicate_mean(i, j);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+        self.pending.push((action, reward));
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    // Every replicate of a new arm starts from the prior's pseudo-observations.
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        let start = prior.map_or((0.0, 0.0), |p| (p.weight * p.mean, p.weight));
+        self.replicates.push(vec![start; self.num_replicates]);
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    // An arm none of whose replicates has weight yet has its estimate as its
+    // mean and infinite variance.
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        Some((0..self.replicates.len()).map(|action| {
+            let means: Vec<f64> = self.replicates[action].iter()
+                                      .filter(|&&(_, weight)| weight > 0.0)
+                                      .map(|&(sum, weight)| sum / weight)
+                                      .collect();
+            if means.is_empty() {
+                return ArmPosterior {
+                    mean: self.stats.estimate(action),
+                    variance: f64::INFINITY,
+                };
+            }
+            let k = means.len() as f64;
+            let mean = means.iter().fold(0.0, |acc, m| acc + m) / k;
+            let variance = means.iter().fold(0.0, |acc, m| acc + (m - mean) * (m - mean)) / k;
+            ArmPosterior { mean, variance }
+        }).collect())
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::normal::StandardNormal;
+
+    use crate::seeded_rng;
+
+    // Every reward goes into about half the replicates, twice over, once the
+    // next choice draws its weights.
+    #[test]
+    fn double_or_nothing_weights() {
+        let mut agent = BootstrapBandit::new(2, 400);
+        let mut rng = seeded_rng(1);
+        for _ in 0..50 {
+            agent.receive_reward(3.0, 0);
+        }
+        assert!(agent.replicates[0].iter().all(|&(_, weight)| weight == 0.0));
+        agent.choose_action(&mut rng);
+        let total: f64 = agent.replicates[0].iter().map(|&(_, weight)| weight).sum();
+        assert!((total / 400.0 - 50.0).abs() < 2.0, "{}", total / 400.0);
+        assert!(agent.replicates[0].iter().all(|&(sum, weight)| sum == 3.0 * weight));
+        let posteriors = agent.posteriors().unwrap();
+        assert_eq!((posteriors[0].mean, posteriors[0].variance), (3.0, 0.0));
+        assert_eq!(posteriors[1].variance, f64::INFINITY);
+
+        let arm = agent.add_arm(Some(ArmPrior { mean: 0.5, weight: 4.0 }));
+        assert!(agent.replicates[arm].iter().all(|&r| r == (2.0, 4.0)));
+    }
+
+    #[test]
+    fn plays_the_best_arm_most() {
+        let mut agent = BootstrapBandit::new(3, 100);
+        let mut rng = seeded_rng(2);
+        let mut plays = [0; 3];
+        for _ in 0..1000 {
+            let action = agent.choose_action(&mut rng);
+            let StandardNormal(noise) = rng.gen();
+            agent.receive_reward(action as f64 + noise, action);
+            plays[action] += 1;
+        }
+        assert!(plays[2] > 800, "{:?}", plays);
+    }
+}
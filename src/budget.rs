@@ -0,0 +1,295 @@
+// Budgeted bandits. In the `budget` mode every arm has a known cost per
+// pull, drawn for every task uniformly from [min-cost, max-cost], and arm a
+// pays 1 with probability p_a (drawn uniformly from [0, 1]) and 0 otherwise.
+// Instead of a fixed number of plays a task has a budget: it ends as soon as
+// the agent picks an arm that costs more than what is left. The objective is
+// reward per unit cost, so the best arm is the one maximizing p_a / c_a, not
+// the one paying most often.
+//
+// Every task is played by the agent given with the usual agent flags, which
+// knows nothing about costs, and by CostAwareUcb, on the same arms and with
+// the same random choices.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::environment::{Environment, Testbed};
+use crate::progress;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, Agent};
+
+// UCB on reward per unit cost: plays the arm maximizing
+//
+//     (Q_t(a) + c * sqrt(ln t / N_t(a))) / cost(a)
+//
+// trying every arm once first, as UcbBandit does.
+#[derive(Serialize, Deserialize)]
+pub struct CostAwareUcb {
+    stats: ArmStats,
+    costs: Vec<f64>,
+
+    // degree of exploration
+    c: f64,
+}
+
+impl CostAwareUcb {
+    pub fn new(costs: Vec<f64>, c: f64) -> CostAwareUcb {
+        assert!(!costs.is_empty() && costs.iter().all(|&cost| cost > 0.0));
+        assert!(c >= 0.0);
+        CostAwareUcb { stats: ArmStats::new(costs.len()), costs, c }
+    }
+
+    fn index(&self, action: usize) -> f64 {
+        let weight = self.stats.weight(action);
+        if weight == 0.0 {
+            return f64::INFINITY;
+        }
+        let t = self.stats.total() as f64;
+        let upper = self.stats.estimate(action) + self.c * (t.max(1.0).ln() / weight).sqrt();
+        upper / self.costs[action]
+    }
+}
+
+impl Agent for CostAwareUcb {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active = self.stats.active();
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.index(active[0]);
+        for &i in &active[1..] {
+            let value = self.index(i);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    // A new arm's cost isn't known, so it is taken to be the average cost.
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        let mean_cost = self.costs.iter().fold(0.0, |acc, c| acc + c) / self.costs.len() as f64;
+        self.costs.push(mean_cost);
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub seed: Option<usize>,
+    pub budget: f64,
+    pub min_cost: f64,
+    pub max_cost: f64,
+
+    // exploration of the cost-aware agent
+    pub cost_c: f64,
+    pub agent: AgentSpec,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            seed: None,
+            budget: 100.0,
+            min_cost: 0.1,
+            max_cost: 1.0,
+            cost_c: 1.0,
+            agent: AgentSpec::from_flags(flags)?,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "budget" => opts.budget = parse_value(key, value)?,
+                "min-cost" => opts.min_cost = parse_value(key, value)?,
+                "max-cost" => opts.max_cost = parse_value(key, value)?,
+                "cost-c" => opts.cost_c = parse_value(key, value)?,
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 {
+            return Err("arms and tasks must be positive".to_string());
+        }
+        if opts.budget.is_nan() || opts.budget <= 0.0 {
+            return Err("budget must be positive".to_string());
+        }
+        if !(opts.min_cost > 0.0 && opts.min_cost <= opts.max_cost) {
+            return Err("costs must be positive with min-cost <= max-cost".to_string());
+        }
+        if opts.cost_c.is_nan() || opts.cost_c < 0.0 {
+            return Err("cost-c must be non-negative".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// What one agent did with one task's budget.
+#[derive(Clone, Copy)]
+struct Spending {
+    plays: usize,
+    reward: f64,
+    cost: f64,
+}
+
+impl Spending {
+    fn new() -> Spending {
+        Spending { plays: 0, reward: 0.0, cost: 0.0 }
+    }
+
+    fn add(&mut self, other: &Spending) {
+        self.plays += other.plays;
+        self.reward += other.reward;
+        self.cost += other.cost;
+    }
+}
+
+// Plays until the agent picks an arm it can't afford.
+fn spend<R: Rng>(env: &mut dyn Environment, agent: &mut dyn Agent, costs: &[f64], budget: f64,
+                 rng: &mut R) -> Spending {
+    let mut spent = Spending::new();
+    loop {
+        let action = agent.choose_action(rng);
+        if spent.cost + costs[action] > budget {
+            return spent;
+        }
+        spent.plays += 1;
+        spent.cost += costs[action];
+        if let Some(reward) = env.step(action, rng) {
+            spent.reward += reward;
+            agent.receive_reward(reward, action);
+        }
+    }
+}
+
+// Totals over tasks for the plain and the cost-aware agent, and the sum of
+// the best expected reward per unit cost of each task.
+struct Totals {
+    plain: Spending,
+    aware: Spending,
+    oracle_ratio: f64,
+}
+
+fn run_tasks<R: Rng>(rng: &mut R, opts: &Options) -> Totals {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let testbed = Testbed::Bernoulli { probs: None };
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut totals = Totals {
+            plain: Spending::new(),
+            aware: Spending::new(),
+            oracle_ratio: 0.0,
+        };
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let mut env = testbed.draw(opts.n, i, &mut rng);
+            let costs: Vec<f64> = (0..opts.n).map(|_| {
+                opts.min_cost + (opts.max_cost - opts.min_cost) * rng.next_f64()
+            }).collect();
+            let choice_seed: usize = rng.gen();
+
+            let mut plain = opts.agent.build(opts.n);
+            totals.plain.add(&spend(&mut *env, &mut *plain, &costs, opts.budget,
+                                    &mut seeded_rng(choice_seed)));
+            let mut aware = CostAwareUcb::new(costs.clone(), opts.cost_c);
+            totals.aware.add(&spend(&mut *env, &mut aware, &costs, opts.budget,
+                                    &mut seeded_rng(choice_seed)));
+
+            let means = env.means();
+            totals.oracle_ratio += (0..opts.n).map(|a| means[a] / costs[a])
+                                              .fold(0.0, |acc: f64, r| acc.max(r));
+            progress::task_done(i);
+        }
+        totals
+    });
+
+    let mut totals = Totals { plain: Spending::new(), aware: Spending::new(), oracle_ratio: 0.0 };
+    for chunk in &chunks {
+        totals.plain.add(&chunk.plain);
+        totals.aware.add(&chunk.aware);
+        totals.oracle_ratio += chunk.oracle_ratio;
+    }
+    totals
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let totals = match opts.seed {
+        Some(seed) => run_tasks(&mut seeded_rng(seed), opts),
+        None => run_tasks(&mut rand::thread_rng(), opts),
+    };
+
+    let tasks = opts.num_tasks as f64;
+    println!("budget {} per task, costs in [{}, {}]: {} tasks, {} arms",
+             opts.budget, opts.min_cost, opts.max_cost, opts.num_tasks, opts.n);
+    println!("{:<32} {:>10} {:>10} {:>10} {:>12}",
+             "agent", "plays", "reward", "cost", "reward/cost");
+    let rows = [(opts.agent.label(), totals.plain),
+                (format!("cost-aware-ucb(c={})", opts.cost_c), totals.aware)];
+    for &(ref label, spent) in &rows {
+        println!("{:<32} {:>10.1} {:>10.2} {:>10.2} {:>12.4}",
+                 label, spent.plays as f64 / tasks, spent.reward / tasks, spent.cost / tasks,
+                 spent.reward / spent.cost);
+    }
+    println!("best expected reward/cost, averaged over tasks: {:.4}",
+             totals.oracle_ratio / tasks);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{BernoulliTestbed, ScriptedRewards};
+
+    // A single arm costing 0.3 can be afforded three times out of 1.
+    #[test]
+    fn spending_stops_at_the_first_unaffordable_pull() {
+        let mut env = ScriptedRewards::new(vec![vec![2.0]]);
+        let mut agent = CostAwareUcb::new(vec![0.3], 1.0);
+        let spent = spend(&mut env, &mut agent, &[0.3], 1.0, &mut seeded_rng(1));
+        assert_eq!((spent.plays, spent.reward), (3, 6.0));
+        assert!((spent.cost - 0.9).abs() < 1e-12);
+    }
+
+    // With arms that pay alike the cheapest is the best value, which the
+    // cost-aware agent finds and plain UCB doesn't.
+    #[test]
+    fn cost_aware_ucb_prefers_cheap_arms() {
+        let costs = [1.0, 0.2, 1.0];
+        let budget = 200.0;
+        let mut env = BernoulliTestbed::new(vec![0.5; 3]);
+        let mut aware = CostAwareUcb::new(costs.to_vec(), 1.0);
+        let aware = spend(&mut env, &mut aware, &costs, budget, &mut seeded_rng(2));
+        let mut plain = AgentSpec::Ucb { c: 1.0 }.build(3);
+        let plain = spend(&mut env, &mut *plain, &costs, budget, &mut seeded_rng(2));
+        assert!(aware.cost <= budget && budget - aware.cost < 1.0);
+        assert!(aware.plays > 2 * plain.plays);
+        assert!(aware.reward > 2.0 * plain.reward);
+    }
+}
@@ -0,0 +1,389 @@
+// Experiments assembled in code rather than by editing constants or writing
+// a plan file. An ExperimentBuilder starts from the usual defaults (10 arms,
+// 2000 tasks of 1000 plays on the gaussian testbed, a fresh seed, .dat
+// files and every summary column) and takes one or more named agents:
+//
+//     let experiment = ExperimentBuilder::new()
+//         .plays(500)
+//         .testbed(Testbed::Bernoulli { probs: None })
+//         .agent("greedy", greedy_spec)
+//         .agent("ucb", ucb_spec)
+//         .seed(42)
+//         .plot("curves.png")
+//         .metrics(&[Metric::FinalReward, Metric::TotalRegret])
+//         .build()?;
+//     let curves = experiment.run()?;
+//
+// build() checks everything that can be checked before running (positive
+// sizes, valid hyperparameters, distinct names and files), so a bad
+// experiment fails before its first task. run() runs the agents in order
+// from the same seed, writes each one's series to "<name>.dat" (or the path
+// it was given) and prints a summary of the chosen metrics. Config plans
+// and the default run are both built this way.
+//
+// With a checkpoint, every agent checkpoints to "<file>.<name>" and agents
+// without one start from scratch when resuming; raw results go to
+// "<dir>/<name>". An experiment of one agent can use the file and directory
+// themselves instead, with files_per_agent(false), and then resuming
+// requires the checkpoint.
+
+use std::time::Instant;
+
+use rand::Rng;
+
+use crate::checkpoint::{self, CheckpointFlags, Checkpointer};
+use crate::environment::Testbed;
+use crate::error::Error;
+use crate::events::{EventFormat, EventLog};
+use crate::histogram::HistogramBins;
+use crate::output::{self, Format, PartialWriter, RawWriter, RunInfo};
+use crate::plot;
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::timing::{self, PhaseTimer, Timing};
+use crate::summary::{self, Metric, RunSummary, ALL_METRICS};
+use crate::warm_start::WarmStart;
+use crate::{run_experiment_with, seeded_rng, Curves, RunConfig};
+
+struct ExperimentAgent {
+    name: String,
+    spec: AgentSpec,
+
+    // defaults to "<name>.dat" (or whatever the format makes it)
+    output: Option<String>,
+}
+
+pub struct ExperimentBuilder {
+    n: usize,
+    num_tasks: usize,
+    num_plays: usize,
+    testbed: Testbed,
+    agents: Vec<ExperimentAgent>,
+    seed: Option<usize>,
+    format: Format,
+    compress: bool,
+    plot: Option<String>,
+    summary: Option<String>,
+    partial_every: Option<usize>,
+    raw_output: Option<String>,
+    events: Option<EventFormat>,
+    histogram: Option<HistogramBins>,
+    timing: bool,
+    checkpoint: Option<CheckpointFlags>,
+    files_per_agent: bool,
+
+    // history every agent starts from, and the file it came from
+    warm_start: Option<(WarmStart, String)>,
+    metrics: Vec<Metric>,
+    verbose: bool,
+}
+
+impl ExperimentBuilder {
+    pub fn new() -> ExperimentBuilder {
+        ExperimentBuilder {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            testbed: Testbed::Gaussian { sd: None },
+            agents: vec![],
+            seed: None,
+            format: Format::Dat,
+            compress: false,
+            plot: None,
+            summary: None,
+            partial_every: None,
+            raw_output: None,
+            events: None,
+            histogram: None,
+            timing: false,
+            checkpoint: None,
+            files_per_agent: true,
+            warm_start: None,
+            metrics: ALL_METRICS.to_vec(),
+            verbose: false,
+        }
+    }
+
+    pub fn arms(mut self, n: usize) -> ExperimentBuilder {
+        self.n = n;
+        self
+    }
+
+    pub fn tasks(mut self, num_tasks: usize) -> ExperimentBuilder {
+        self.num_tasks = num_tasks;
+        self
+    }
+
+    pub fn plays(mut self, num_plays: usize) -> ExperimentBuilder {
+        self.num_plays = num_plays;
+        self
+    }
+
+    pub fn testbed(mut self, testbed: Testbed) -> ExperimentBuilder {
+        self.testbed = testbed;
+        self
+    }
+
+    pub fn agent(mut self, name: &str, spec: AgentSpec) -> ExperimentBuilder {
+        self.agents.push(ExperimentAgent { name: name.to_string(), spec, output: None });
+        self
+    }
+
+    // An agent whose series goes to `path` instead of one named after it.
+    pub fn agent_with_output(mut self, name: &str, spec: AgentSpec, path: &str)
+                             -> ExperimentBuilder {
+        self.agents.push(ExperimentAgent {
+            name: name.to_string(),
+            spec,
+            output: Some(path.to_string()),
+        });
+        self
+    }
+
+    pub fn seed(mut self, seed: usize) -> ExperimentBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> ExperimentBuilder {
+        self.format = format;
+        self
+    }
+
+    pub fn compress(mut self, compress: bool) -> ExperimentBuilder {
+        self.compress = compress;
+        self
+    }
+
+    pub fn plot(mut self, path: &str) -> ExperimentBuilder {
+        self.plot = Some(path.to_string());
+        self
+    }
+
+    // Also writes the summary table to `path` as CSV.
+    pub fn summary(mut self, path: &str) -> ExperimentBuilder {
+        self.summary = Some(path.to_string());
+        self
+    }
+
+    pub fn partial_every(mut self, every: usize) -> ExperimentBuilder {
+        self.partial_every = Some(every);
+        self
+    }
+
+    pub fn raw_output(mut self, dir: &str) -> ExperimentBuilder {
+        self.raw_output = Some(dir.to_string());
+        self
+    }
+
+    pub fn events(mut self, format: EventFormat) -> ExperimentBuilder {
+        self.events = Some(format);
+        self
+    }
+
+    // Also writes every agent's reward histograms to "<path>.hist" (see
+    // histogram).
+    pub fn histogram(mut self, bins: HistogramBins) -> ExperimentBuilder {
+        self.histogram = Some(bins);
+        self
+    }
+
+    // Also times every phase of the runs (see timing).
+    pub fn timing(mut self, timing: bool) -> ExperimentBuilder {
+        self.timing = timing;
+        self
+    }
+
+    pub fn checkpoint(mut self, flags: CheckpointFlags) -> ExperimentBuilder {
+        self.checkpoint = Some(flags);
+        self
+    }
+
+    // Whether checkpoints and raw results are kept per agent (see above).
+    pub fn files_per_agent(mut self, per_agent: bool) -> ExperimentBuilder {
+        self.files_per_agent = per_agent;
+        self
+    }
+
+    // Starts every agent from `history`, read from `path` (see warm_start).
+    pub fn warm_start(mut self, history: WarmStart, path: &str) -> ExperimentBuilder {
+        self.warm_start = Some((history, path.to_string()));
+        self
+    }
+
+    // The summary columns to print, in order.
+    pub fn metrics(mut self, metrics: &[Metric]) -> ExperimentBuilder {
+        self.metrics = metrics.to_vec();
+        self
+    }
+
+    // Reports every task as it finishes.
+    pub fn verbose(mut self, verbose: bool) -> ExperimentBuilder {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn build(self) -> Result<Experiment, Error> {
+        if self.n == 0 || self.num_tasks == 0 || self.num_plays == 0 {
+            return Err("arms, tasks and plays must all be positive".into());
+        }
+        if self.agents.is_empty() {
+            return Err("an experiment needs at least one agent".into());
+        }
+        if self.partial_every == Some(0) {
+            return Err("partial-every must be positive".into());
+        }
+        if self.metrics.is_empty() {
+            return Err("an experiment needs at least one metric".into());
+        }
+        if !self.files_per_agent && self.agents.len() > 1 {
+            return Err("only an experiment of one agent can share its files".into());
+        }
+        let paths: Vec<String> = self.agents.iter().map(|agent| {
+            match agent.output {
+                Some(ref path) => path.clone(),
+                None => output::file_name(&agent.name, self.format, self.compress),
+            }
+        }).collect();
+        for (i, agent) in self.agents.iter().enumerate() {
+            agent.spec.validate()
+                 .map_err(|e| Error::Invalid(format!("agent `{}`: {}", agent.name, e)))?;
+            for (j, other) in self.agents[..i].iter().enumerate() {
+                if other.name == agent.name {
+                    return Err(Error::Invalid(format!("agent name `{}` is used twice",
+                                                      agent.name)));
+                }
+                if paths[j] == paths[i] {
+                    return Err(Error::Invalid(format!("agents `{}` and `{}` write to the \
+                                                       same file", other.name, agent.name)));
+                }
+            }
+        }
+        Ok(Experiment { config: self, paths })
+    }
+}
+
+pub struct Experiment {
+    config: ExperimentBuilder,
+
+    // every agent's output path
+    paths: Vec<String>,
+}
+
+impl Experiment {
+    // Runs every agent, writes the outputs and prints the summary, and
+    // returns every agent's name and curves.
+    pub fn run(&self) -> Result<Vec<(String, Curves)>, Error> {
+        let c = &self.config;
+        let mut results = vec![];
+        let mut timings = vec![];
+        for (agent, path) in c.agents.iter().zip(&self.paths) {
+            println!("Running {} ({}) on {} tasks x {} plays",
+                     agent.name, agent.spec.label(), c.num_tasks, c.num_plays);
+            let mut checkpointer = match c.checkpoint {
+                Some(ref flags) => {
+                    let warm_start = c.warm_start.as_ref().map(|(_, path)| &path[..]);
+                    let run = checkpoint::describe_run(&agent.spec, &c.testbed, c.n,
+                                                       c.num_plays, warm_start);
+                    let file = if c.files_per_agent {
+                        format!("{}.{}", flags.path, agent.name)
+                    } else {
+                        flags.path.clone()
+                    };
+                    Some(Checkpointer::new(&file, flags.every, run, c.num_tasks, c.seed,
+                                           flags.resume, !c.files_per_agent)?)
+                }
+                None => None,
+            };
+
+            // Without an explicit seed, pick one so the results can still
+            // record how to reproduce them. A resumed run continues with its
+            // own seed (which the checkpointer made sure is the one given).
+            let resumed_seed = checkpointer.as_ref().and_then(|c| c.resumed.as_ref())
+                                           .map(|c| c.seed);
+            let seed = match resumed_seed {
+                Some(resumed) => resumed,
+                None => c.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+            };
+            if let Some(ref mut checkpointer) = checkpointer {
+                checkpointer.seed = seed;
+            }
+
+            let partial = c.partial_every.map(|every| PartialWriter {
+                path: output::companion(path, ".partial"),
+                every,
+            });
+            let compress = path.ends_with(".gz");
+            let raw = match c.raw_output {
+                Some(ref dir) if c.files_per_agent => {
+                    Some(RawWriter::new(&format!("{}/{}", dir, agent.name), compress)?)
+                }
+                Some(ref dir) => Some(RawWriter::new(dir, compress)?),
+                None => None,
+            };
+
+            let started_at = output::unix_time();
+            let started = Instant::now();
+            let timer = if c.timing { Some(PhaseTimer::new()) } else { None };
+            let resumed_tasks = checkpointer.as_ref().and_then(|c| c.resumed.as_ref())
+                                            .map_or(0, |c| c.tasks_done);
+            let events = match c.events {
+                Some(format) => Some(EventLog::create(path, format, resumed_tasks)?),
+                None => None,
+            };
+            let make_agent = || {
+                let mut built = agent.spec.build(c.n);
+                if let Some((ref history, _)) = c.warm_start {
+                    history.apply(&mut *built);
+                }
+                built
+            };
+            let run = RunConfig {
+                verbose: c.verbose,
+                partial: partial.as_ref(),
+                raw: raw.as_ref(),
+                checkpoint: checkpointer.as_ref(),
+                events: events.as_ref(),
+                histogram: c.histogram.as_ref(),
+                timer: timer.as_ref(),
+                ..RunConfig::new(&c.testbed, c.n, c.num_tasks, c.num_plays)
+            };
+            let curves = run_experiment_with(&mut seeded_rng(seed), &run, &make_agent);
+            if let Some(events) = events {
+                events.finish()?;
+            }
+            let timing = Timing::since(started, (c.num_tasks - resumed_tasks) * c.num_plays,
+                                       timer.map(|t| t.totals()));
+
+            let mut info = RunInfo::new(&agent.spec, c.n, c.num_tasks, c.num_plays, seed,
+                                        started_at);
+            info.timing = Some(timing);
+            output::write_series(path, c.format, &info, &curves.columns())
+                .map_err(|e| Error::io(format!("write `{}`", path), e))?;
+            output::write_pulls(path, &curves.pulls_by_rank)?;
+            if let Some(ref histograms) = curves.histograms {
+                histograms.write(path)?;
+            }
+            progress::config_done(&agent.name);
+            results.push((agent.name.clone(), curves));
+            timings.push((agent.name.clone(), timing));
+        }
+
+        let rows: Vec<(String, RunSummary)> = results.iter().map(|(name, curves)| {
+            (name.clone(), RunSummary::of(curves))
+        }).collect();
+        summary::print_metrics(&rows, &c.metrics);
+        timing::print_table(&timings);
+        if let Some(ref path) = c.summary {
+            summary::write_csv(&rows, path)
+                .map_err(|e| Error::io(format!("write `{}`", path), e))?;
+        }
+        if let Some(ref path) = c.plot {
+            let series: Vec<(String, &Curves)> = results.iter().map(|(name, curves)| {
+                (name.clone(), curves)
+            }).collect();
+            plot::plot_curves(path, &series)?;
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,297 @@
+// Checkpoints, so long runs can survive an interruption. With
+// `--checkpoint file` the totals over the tasks finished so far are saved
+// every `--checkpoint-every` tasks (rounded up to whole chunks), together
+// with the run's seed, which every task's RNG is derived from, and the
+// number of tasks done. `--resume file` picks such a run up where its last
+// checkpoint left off and keeps checkpointing to the same file. Tasks are
+// summed in the same order either way, so a resumed run ends with exactly
+// the results it would have had without the interruption.
+//
+// A checkpoint also records a fingerprint of the run: the agent's spec, the
+// testbed with all its parameters, the sizes and any warm start. It is only
+// resumed into a run with the same fingerprint and, if the run is given a
+// seed, the same seed.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+
+
+use crate::environment::Testbed;
+use crate::error::Error;
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::{parse_value, Curves, TASK_CHUNK};
+
+// `--checkpoint`, `--resume` and `--checkpoint-every` as given.
+#[derive(Clone)]
+pub struct CheckpointFlags {
+    pub path: String,
+    pub every: usize,
+    pub resume: bool,
+}
+
+impl CheckpointFlags {
+    pub fn from_flags(flags: &HashMap<String, String>)
+                      -> Result<Option<CheckpointFlags>, String> {
+        let every = match flags.get("checkpoint-every") {
+            Some(value) => parse_value("checkpoint-every", value)?,
+            None => 500,
+        };
+        match (flags.get("checkpoint"), flags.get("resume")) {
+            (Some(_), Some(_)) => {
+                Err("`--resume` keeps checkpointing to its own file; leave out `--checkpoint`"
+                    .to_string())
+            }
+            (Some(path), None) => {
+                Ok(Some(CheckpointFlags { path: path.clone(), every, resume: false }))
+            }
+            (None, Some(path)) => {
+                Ok(Some(CheckpointFlags { path: path.clone(), every, resume: true }))
+            }
+            (None, None) if flags.contains_key("checkpoint-every") => {
+                Err("`--checkpoint-every` needs `--checkpoint` or `--resume`".to_string())
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+// What a checkpoint must match to be resumed: the fingerprint, and the
+// label to name the run by when it doesn't.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunId {
+    pub label: String,
+    pub fingerprint: String,
+}
+
+#[derive(Serialize)]
+struct Fingerprint<'a> {
+    agent: &'a AgentSpec,
+    testbed: &'a Testbed,
+    arms: usize,
+    plays: usize,
+    warm_start: Option<&'a str>,
+}
+
+// Identifies a run of `spec` on `testbed`, warm-started from the file
+// `warm_start` if given.
+pub fn describe_run(spec: &AgentSpec, testbed: &Testbed, n: usize, num_plays: usize,
+                    warm_start: Option<&str>) -> RunId {
+    let mut label = format!("{} on a {} testbed with {} arms, {} plays", spec.label(),
+                            testbed.label(), n, num_plays);
+    if let Some(path) = warm_start {
+        label = format!("{}, warm-started from `{}`", label, path);
+    }
+    let fingerprint = Fingerprint {
+        agent: spec,
+        testbed,
+        arms: n,
+        plays: num_plays,
+        warm_start,
+    };
+    RunId { label, fingerprint: serde_json::to_string(&fingerprint).unwrap() }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub run: RunId,
+    pub seed: usize,
+    pub num_tasks: usize,
+    pub tasks_done: usize,
+
+    // totals over the finished tasks, not yet averaged
+    pub totals: Curves,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Checkpoint, Error> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| Error::io(format!("read checkpoint `{}`", path), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| Error::Invalid(format!("invalid checkpoint `{}`: {}", path, e)))
+    }
+}
+
+pub struct Checkpointer {
+    pub path: String,
+
+    // tasks between checkpoints
+    pub every: usize,
+    pub run: RunId,
+    pub seed: usize,
+
+    // the checkpoint being resumed, if any
+    pub resumed: Option<Checkpoint>,
+}
+
+impl Checkpointer {
+    // Checkpoints run `run` to `path`. When resuming, the checkpoint already
+    // at `path` is loaded and must be of the same run, and of the run's
+    // `seed` if it has one; a missing one is an error only if `required`,
+    // and otherwise the run starts over.
+    pub fn new(path: &str, every: usize, run: RunId, num_tasks: usize, seed: Option<usize>,
+               resume: bool, required: bool) -> Result<Checkpointer, String> {
+        if every == 0 {
+            return Err("checkpoint-every must be positive".to_string());
+        }
+        let resumed = if resume && (required || fs::metadata(path).is_ok()) {
+            let saved = Checkpoint::load(path)?;
+            if saved.run.fingerprint != run.fingerprint || saved.num_tasks != num_tasks {
+                return Err(format!("checkpoint `{}` is of another run: {} with {} tasks",
+                                   path, saved.run.label, saved.num_tasks));
+            }
+            match seed {
+                Some(seed) if seed != saved.seed => {
+                    return Err(format!("checkpoint `{}` was run with seed {}", path,
+                                       saved.seed));
+                }
+                _ => {}
+            }
+            Some(saved)
+        } else {
+            None
+        };
+        Ok(Checkpointer {
+            path: path.to_string(),
+            every,
+            run,
+            seed: resumed.as_ref().map_or(0, |saved| saved.seed),
+            resumed,
+        })
+    }
+
+    // Tasks to run between checkpoints, rounded up to whole chunks like
+    // PartialWriter::batch_size.
+    pub fn batch_size(&self) -> usize {
+        cmp::max(1, self.every.div_ceil(TASK_CHUNK)) * TASK_CHUNK
+    }
+
+    // Replaces the checkpoint with the totals over the first `done` tasks.
+    // A failed write only costs the ability to resume, so the run goes on.
+    pub fn write(&self, totals: &Curves, done: usize, num_tasks: usize) {
+        match self.try_write(totals, done, num_tasks) {
+            Ok(()) => progress::checkpoint_written(&self.path, done, num_tasks),
+            Err(e) => {
+                warn!("couldn't write checkpoint `{}`: {}", self.path, e);
+            }
+        }
+    }
+
+    fn try_write(&self, totals: &Curves, done: usize, num_tasks: usize) -> io::Result<()> {
+        let checkpoint = Checkpoint {
+            run: self.run.clone(),
+            seed: self.seed,
+            num_tasks,
+            tasks_done: done,
+            totals: totals.clone(),
+        };
+        let tmp = format!("{}.tmp", self.path);
+        {
+            let mut f = File::create(&tmp)?;
+            serde_json::to_writer(&mut f, &checkpoint)?;
+            writeln!(f)?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{run_experiment_with, seeded_rng, Agent, RunConfig};
+
+    const ARMS: usize = 10;
+    const TASKS: usize = 96;
+    const PLAYS: usize = 50;
+    const SEED: usize = 7;
+
+    fn spec() -> AgentSpec {
+        AgentSpec::Ucb { c: 2.0 }
+    }
+
+    fn testbed() -> Testbed {
+        Testbed::RandomWalk { sigma: 0.01 }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("{}-{}", name, ::std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn checkpointer(path: &str, every: usize, resume: bool) -> Checkpointer {
+        let run = describe_run(&spec(), &testbed(), ARMS, PLAYS, None);
+        let mut checkpointer = Checkpointer::new(path, every, run, TASKS, Some(SEED), resume,
+                                                 true).unwrap();
+        checkpointer.seed = SEED;
+        checkpointer
+    }
+
+    // Runs the test run, panicking when the agent of the `fail_at`-th task
+    // started is made, as if the run had been killed there.
+    fn run(checkpointer: Option<&Checkpointer>, fail_at: Option<usize>) -> Curves {
+        let made = AtomicUsize::new(0);
+        let make_agent = || -> Box<dyn Agent> {
+            if Some(made.fetch_add(1, Ordering::SeqCst)) == fail_at {
+                panic!("interrupted");
+            }
+            spec().build(ARMS)
+        };
+        let testbed = testbed();
+        let run = RunConfig {
+            checkpoint: checkpointer,
+            ..RunConfig::new(&testbed, ARMS, TASKS, PLAYS)
+        };
+        run_experiment_with(&mut seeded_rng(SEED), &run, &make_agent)
+    }
+
+    // Interrupts a checkpointed run, resumes it, and checks it ends exactly
+    // where an uninterrupted run does. Returns the tasks the resumed run
+    // picked up from.
+    fn check_resume(path: &str, every: usize, fail_at: usize) -> usize {
+        let whole = serde_json::to_string(&run(None, None)).unwrap();
+        let _ = fs::remove_file(path);
+        let first = checkpointer(path, every, false);
+        let interrupted = AssertUnwindSafe(|| run(Some(&first), Some(fail_at)));
+        assert!(panic::catch_unwind(interrupted).is_err());
+        let resumed = checkpointer(path, every, true);
+        let tasks_done = resumed.resumed.as_ref().unwrap().tasks_done;
+        assert_eq!(serde_json::to_string(&run(Some(&resumed), None)).unwrap(), whole);
+        fs::remove_file(path).unwrap();
+        tasks_done
+    }
+
+    #[test]
+    fn resume_matches_an_uninterrupted_run() {
+        assert_eq!(check_resume(&temp_path("checkpoint-resume"), 16, 40), 32);
+    }
+
+    // The fingerprint tells apart runs the label doesn't, and a run given a
+    // seed only resumes a checkpoint of that seed.
+    #[test]
+    fn resumes_only_the_same_run() {
+        let path = temp_path("checkpoint-fingerprint");
+        let first = checkpointer(&path, 16, false);
+        run(Some(&first), None);
+
+        let other = Testbed::RandomWalk { sigma: 0.02 };
+        assert_eq!(other.label(), testbed().label());
+        let run_id = describe_run(&spec(), &other, ARMS, PLAYS, None);
+        assert!(Checkpointer::new(&path, 16, run_id, TASKS, None, true, true).is_err());
+        let run_id = describe_run(&AgentSpec::Ucb { c: 1.0 }, &testbed(), ARMS, PLAYS, None);
+        assert!(Checkpointer::new(&path, 16, run_id, TASKS, None, true, true).is_err());
+
+        let run_id = describe_run(&spec(), &testbed(), ARMS, PLAYS, None);
+        assert!(Checkpointer::new(&path, 16, run_id.clone(), TASKS, Some(SEED + 1), true, true)
+                    .is_err());
+        assert!(Checkpointer::new(&path, 16, run_id, TASKS, None, true, true).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
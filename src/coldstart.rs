@@ -0,0 +1,96 @@
+// Cold-start bootstrapping for arms added while a task is running. Rather
+// than starting a new arm from nothing, its statistics can be seeded from
+// arms the caller considers similar to it. The similarity map gives each
+// similar arm a weight in [0, 1]; the new arm's prior pools their rewards,
+// each arm's observations counting `weight` times:
+//
+//     mean   = sum_i w_i * N_i * Q_i / sum_i w_i * N_i
+//     weight = min(sum_i w_i * N_i, max_weight)
+//
+// Capping the prior's weight keeps the new arm's own rewards from being
+// drowned out by a long history of its neighbours.
+
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+
+use crate::stats::{ArmPrior, ArmSummary};
+
+// Prior for a new arm from the statistics of existing arms, given as
+// (arm, similarity) pairs. None if none of the similar arms has any
+// observations yet (or all similarities are 0).
+pub fn bootstrap_prior(arms: &[ArmSummary], similar: &[(usize, f64)],
+                       max_weight: f64) -> Option<ArmPrior> {
+    let mut weight = 0.0;
+    let mut sum = 0.0;
+    for &(arm, similarity) in similar {
+        assert!((0.0..=1.0).contains(&similarity));
+        let w = similarity * arms[arm].count as f64;
+        weight += w;
+        sum += w * arms[arm].mean;
+    }
+    if weight == 0.0 {
+        return None;
+    }
+    Some(ArmPrior { mean: sum / weight, weight: weight.min(max_weight) })
+}
+
+// An arm that joins the testbed at play `at`. Its true value is drawn around
+// the similarity-weighted mean of the similar arms' true values, with
+// standard deviation `spread`, so that the similarity map is actually
+// informative; without similar arms it's drawn like any other arm's.
+pub struct ArmArrival {
+    pub at: usize,
+    pub similar: Vec<(usize, f64)>,
+    pub spread: f64,
+
+    // cap on the prior's pseudo-observations; 0 disables bootstrapping
+    pub max_weight: f64,
+}
+
+impl ArmArrival {
+    // Parses a similarity map like "0:1,3:0.5".
+    pub fn parse_similar(value: &str, num_arms: usize) -> Result<Vec<(usize, f64)>, String> {
+        let mut similar = vec![];
+        for part in value.split(',') {
+            let fields: Vec<&str> = part.trim().split(':').collect();
+            if fields.len() != 2 {
+                return Err(format!("invalid similarity `{}` (expected ARM:WEIGHT)", part));
+            }
+            let arm: usize = fields[0].parse()
+                .map_err(|_| format!("invalid arm `{}` in `{}`", fields[0], part))?;
+            let weight: f64 = fields[1].parse()
+                .map_err(|_| format!("invalid weight `{}` in `{}`", fields[1], part))?;
+            if arm >= num_arms {
+                return Err(format!("similarity `{}`: no arm {}", part, arm));
+            }
+            if !(0.0..=1.0).contains(&weight) {
+                return Err(format!("similarity `{}`: weight must be between 0 and 1", part));
+            }
+            if similar.iter().any(|&(a, _)| a == arm) {
+                return Err(format!("arm {} is listed twice in the similarity map", arm));
+            }
+            similar.push((arm, weight));
+        }
+        Ok(similar)
+    }
+
+    pub fn true_value<R: Rng>(&self, q_star: &[f64], rng: &mut R) -> f64 {
+        let StandardNormal(z) = rng.gen();
+        let total: f64 = self.similar.iter().map(|&(_, w)| w).sum();
+        if total == 0.0 {
+            return z;
+        }
+        let mut mean = 0.0;
+        for &(arm, w) in &self.similar {
+            mean += w * q_star[arm];
+        }
+        mean / total + self.spread * z
+    }
+
+    pub fn prior(&self, arms: &[ArmSummary]) -> Option<ArmPrior> {
+        if self.max_weight == 0.0 {
+            return None;
+        }
+        bootstrap_prior(arms, &self.similar, self.max_weight)
+    }
+}
@@ -0,0 +1,1142 @@
+// TOML experiment plans. A plan describes the testbed once and then lists
+// the agents to run on it, each with its own hyperparameters and output
+// file, e.g.
+//
+//     format = "csv"     # or "json", or "dat", the default
+//     compress = "gzip"  # gzip every agent's file (as does an output ending in .gz)
+//     plot = "plan.png"  # every agent's curves in one image
+//     partial_every = 100  # write "<output>.partial" every 100 tasks
+//     summary = "summary.csv"  # the end-of-run summary table, as CSV
+//     metrics = ["final_reward", "total_regret"]  # its columns; all of them by default
+//     raw_output = "raw"  # every task's rewards, in "raw/<agent name>/task_<i>.csv"
+//     events = "csv"  # every play, in "<output>.events.csv" (or "jsonl"; see events)
+//     histogram = "-4:4:40"  # rewards seen per arm, in "<output>.hist" (see histogram)
+//     timing = true  # time every phase of the run (see timing)
+//     warm_start = "history.csv"  # history every agent starts from (see warm_start)
+//
+//     [testbed]
+//     arms = 10
+//     tasks = 2000
+//     plays = 1000
+//     seed = 42
+//     kind = "bernoulli"  # or "random-walk", "heavy-tailed", "mortal", "correlated",
+//                         # or "gaussian", the default
+//     probs = [0.1, 0.12, 0.15]  # bernoulli only; drawn per task if left out
+//     walk_sigma = 0.01  # random-walk only; the default
+//     tail_dof = 2.0     # heavy-tailed only; the default
+//     lifetime = 200.0   # mortal only (mean plays an arm lives); the default
+//     length_scale = 2.0 # correlated only (arms apart that stay alike); the default
+//
+// A gaussian testbed has reward noise with standard deviation 1 unless one
+// of these is given:
+//
+//     reward_sd = 0.5                 # for every arm
+//     reward_sds = [0.5, 1.0, 2.0]    # one per arm
+//     reward_sd_range = [0.5, 2.0]    # drawn uniformly per arm and task
+//
+// A "mixed" testbed gives every arm its own reward distribution, one entry
+// per arm:
+//
+//     kind = "mixed"
+//     arms = 3
+//     arm_dists = [
+//         { dist = "constant", value = 0.5 },
+//         { dist = "gaussian", mean = 0.6, sd = 2.0 },   # sd defaults to 1
+//         { dist = "bernoulli", p = 0.55 },
+//     ]
+//
+// and also takes { dist = "exponential", mean = ... } and
+// { dist = "uniform", low = ..., high = ... }.
+//
+// A "scripted" testbed plays the same rewards in every task, either read
+// from a CSV file with one line per play and one column per arm (lines
+// starting with '#' are skipped; the script repeats if it is shorter than
+// the run), or generated so that the best arm changes every so many plays:
+//
+//     kind = "scripted"
+//     script = "rewards.csv"
+//     switch_every = 200   # instead of a script: arm k pays 1 in the k-th
+//                          # block of 200 plays (mod arms), the rest 0
+//
+// A "replay" testbed plays back a log of interactions, e.g. a task file
+// from a run's raw_output or data from another system. The log is a CSV
+// file whose header names its first three columns step, arm (or action) and
+// reward; further columns and lines starting with '#' are ignored:
+//
+//     kind = "replay"
+//     log = "raw/eps-0.1/task_0.csv"
+//
+// Any testbed whose tasks are fixed by their arms' true values can save the
+// tasks it draws from its seed, before any agent runs, and a "saved" testbed
+// plays them back (see saved_testbed), so other runs face the same tasks:
+//
+//     save = "testbed.json"
+//
+//     kind = "saved"
+//     saved = "testbed.json"
+//
+// Any kind of testbed can hold every reward back from the agent for a
+// number of plays, fixed or drawn uniformly up to a maximum per reward:
+//
+//     delay = 20
+//     max_delay = 50   # instead of delay
+//
+// and can let the agent play only some of the arms each play, with every
+// arm available (awake) with some probability:
+//
+//     awake_prob = 0.7
+//
+// A "difficulty" testbed is described by how hard it is instead (see the
+// environment module); these are the defaults:
+//
+//     kind = "difficulty"
+//     near_optimal = 1     # arms within a tenth of the gap of the best, itself included
+//     gap = 0.5            # mean gap of the other arms
+//     gap_dist = "fixed"   # or "uniform", or "exponential"
+//     noise_to_gap = 2.0   # reward noise standard deviation over the gap
+//
+//     [[agents]]
+//     name = "greedy"
+//     epsilon = 0.0
+//     output = "eps_0.dat"
+//
+//     [[agents]]
+//     name = "eps-0.1"
+//     epsilon = 0.1
+//
+//     [[agents]]
+//     name = "pool"
+//     kind = "explore-pool"
+//     explore_fraction = 0.05
+//     explore_policy = "round-robin"
+//
+//     [[agents]]
+//     name = "ucb-2"
+//     kind = "ucb"
+//     c = 2.0
+//
+//     [[agents]]
+//     name = "holt"
+//     kind = "forecast"
+//     smoothing = 0.1
+//     trend_smoothing = 0.05
+//     c = 0.5
+//
+//     [[agents]]
+//     name = "cautious"
+//     kind = "cvar"
+//     cvar_level = 0.1
+//     c = 1.0
+//
+//     [[agents]]
+//     name = "moss"
+//     kind = "moss"
+//     horizon = 1000    # or leave it out for the doubling trick
+//
+//     [[agents]]
+//     name = "bts"
+//     kind = "bootstrap"
+//     replicates = 100
+//
+//     [[agents]]
+//     name = "mine"
+//     kind = "sticky"   # an agent registered under this name (see registry)
+//     params = { stickiness = 0.9 }
+//
+//     [[agents]]
+//     name = "softmax-scaled"
+//     kind = "softmax"
+//     tau = 0.1
+//     reward_clip = [-3.0, 3.0]          # any kind; see preprocess
+//     reward_normalize = "minmax"        # or "standardize"
+//
+//     [[agents]]
+//     name = "meta"
+//     kind = "meta"     # a selector choosing among sub-agents (see meta)
+//     selector = { name = "ucb", kind = "ucb", c = 2.0 }
+//     sub_agents = [{ name = "eps-0.01", epsilon = 0.01 },
+//                   { name = "eps-0.1", epsilon = 0.1 }]
+//
+//     [[agents]]
+//     name = "adaptive"
+//     epsilon = 0.1
+//     wrappers = [{ kind = "count-bonus", beta = 0.5 },   # any kind; see wrappers
+//                 { kind = "change-reset", threshold = 20.0 }]
+//
+// `--checkpoint file` and `--resume file` (see checkpoint.rs) may be given
+// with `--config`; every agent then checkpoints to "<file>.<name>".
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+
+
+use crate::builder::ExperimentBuilder;
+use crate::checkpoint::CheckpointFlags;
+use crate::error::Error;
+use crate::environment::{ArmDist, ArmSd, Delay, GapDist, Testbed};
+use crate::events::EventFormat;
+use crate::histogram::HistogramBins;
+use crate::explore_pool::ExplorePolicy;
+use crate::output::{self, Format};
+use crate::preprocess::{Normalization, Preprocessing};
+use crate::registry::{self, Params};
+use crate::saved_testbed::SavedTasks;
+use crate::spec::AgentSpec;
+use crate::summary::{Metric, ALL_METRICS};
+use crate::warm_start::WarmStart;
+use crate::wrappers::WrapperSpec;
+use crate::{seeded_rng, task_seeds};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    // output format of every agent's file
+    pub format: Option<String>,
+    pub compress: Option<String>,
+    pub plot: Option<String>,
+    pub partial_every: Option<usize>,
+    pub summary: Option<String>,
+    pub metrics: Option<Vec<String>>,
+    pub raw_output: Option<String>,
+    pub events: Option<String>,
+    pub histogram: Option<String>,
+    pub timing: Option<bool>,
+    pub warm_start: Option<String>,
+    pub testbed: TestbedConfig,
+    pub agents: Vec<AgentConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestbedConfig {
+    #[serde(default = "default_arms")]
+    pub arms: usize,
+    #[serde(default = "default_tasks")]
+    pub tasks: usize,
+    #[serde(default = "default_plays")]
+    pub plays: usize,
+
+    // Each agent is run from this seed, so they all face the same first
+    // task. Without one, every agent gets a fresh random stream.
+    pub seed: Option<usize>,
+
+    #[serde(default = "default_testbed_kind")]
+    pub kind: String,
+
+    // success probability of each arm of a bernoulli testbed
+    pub probs: Option<Vec<f64>>,
+
+    // step size of a random-walk testbed
+    pub walk_sigma: Option<f64>,
+
+    // degrees of freedom of a heavy-tailed testbed's noise
+    pub tail_dof: Option<f64>,
+
+    // mean lifetime of an arm of a mortal testbed
+    pub lifetime: Option<f64>,
+
+    // correlation length of a correlated testbed's true values
+    pub length_scale: Option<f64>,
+
+    // reward noise standard deviations of a gaussian testbed; at most one
+    pub reward_sd: Option<f64>,
+    pub reward_sds: Option<Vec<f64>>,
+    pub reward_sd_range: Option<Vec<f64>>,
+
+    // every arm's distribution, for a mixed testbed
+    pub arm_dists: Option<Vec<ArmDistConfig>>,
+
+    // the rewards of a scripted testbed, from a file or generated
+    pub script: Option<String>,
+    pub switch_every: Option<usize>,
+
+    // the interaction log of a replay testbed
+    pub log: Option<String>,
+
+    // file to save the drawn tasks to, and the file of a saved testbed
+    pub save: Option<String>,
+    pub saved: Option<String>,
+
+    // plays every reward is held back for, at most one of the two
+    pub delay: Option<usize>,
+    pub max_delay: Option<usize>,
+
+    // probability of each arm being available at a play
+    pub awake_prob: Option<f64>,
+
+    // descriptors of a difficulty testbed
+    pub near_optimal: Option<usize>,
+    pub gap: Option<f64>,
+    pub gap_dist: Option<String>,
+    pub noise_to_gap: Option<f64>,
+}
+
+impl TestbedConfig {
+    pub fn testbed(&self) -> Result<Testbed, String> {
+        let mut testbed = self.kind_testbed()?;
+        if let Some(awake) = self.awake_prob {
+            if !(awake > 0.0 && awake <= 1.0) {
+                return Err("testbed awake_prob must be in (0, 1]".to_string());
+            }
+            testbed = Testbed::Sleeping { testbed: Box::new(testbed), awake };
+        }
+        let delay = match (self.delay, self.max_delay) {
+            (None, None) => return Ok(testbed),
+            (Some(d), None) => Delay::Fixed(d),
+            (None, Some(max)) => Delay::Uniform { max },
+            _ => return Err("testbed takes only one of delay and max_delay".to_string()),
+        };
+        Ok(Testbed::Delayed { testbed: Box::new(testbed), delay })
+    }
+
+    fn kind_testbed(&self) -> Result<Testbed, String> {
+        if self.probs.is_some() && self.kind != "bernoulli" {
+            return Err("testbed probs only apply to kind `bernoulli`".to_string());
+        }
+        if self.walk_sigma.is_some() && self.kind != "random-walk" {
+            return Err("testbed walk_sigma only applies to kind `random-walk`".to_string());
+        }
+        if self.length_scale.is_some() && self.kind != "correlated" {
+            return Err("testbed length_scale only applies to kind `correlated`".to_string());
+        }
+        if self.lifetime.is_some() && self.kind != "mortal" {
+            return Err("testbed lifetime only applies to kind `mortal`".to_string());
+        }
+        if self.tail_dof.is_some() && self.kind != "heavy-tailed" {
+            return Err("testbed tail_dof only applies to kind `heavy-tailed`".to_string());
+        }
+        let sds = [self.reward_sd.is_some(), self.reward_sds.is_some(),
+                   self.reward_sd_range.is_some()];
+        if sds.iter().any(|&given| given) && self.kind != "gaussian" {
+            return Err("testbed reward_sd, reward_sds and reward_sd_range only apply to kind \
+                        `gaussian`".to_string());
+        }
+        if sds.iter().filter(|&&given| given).count() > 1 {
+            return Err("testbed takes only one of reward_sd, reward_sds and reward_sd_range"
+                       .to_string());
+        }
+        if (self.script.is_some() || self.switch_every.is_some()) && self.kind != "scripted" {
+            return Err("testbed script and switch_every only apply to kind `scripted`"
+                       .to_string());
+        }
+        if self.log.is_some() && self.kind != "replay" {
+            return Err("testbed log only applies to kind `replay`".to_string());
+        }
+        if self.saved.is_some() && self.kind != "saved" {
+            return Err("testbed saved only applies to kind `saved`".to_string());
+        }
+        if self.arm_dists.is_some() && self.kind != "mixed" {
+            return Err("testbed arm_dists only apply to kind `mixed`".to_string());
+        }
+        let descriptors = self.near_optimal.is_some() || self.gap.is_some() ||
+                          self.gap_dist.is_some() || self.noise_to_gap.is_some();
+        if descriptors && self.kind != "difficulty" {
+            return Err("testbed near_optimal, gap, gap_dist and noise_to_gap only apply to \
+                        kind `difficulty`".to_string());
+        }
+        match &self.kind[..] {
+            "gaussian" => Ok(Testbed::Gaussian { sd: self.arm_sd()? }),
+            "bernoulli" => {
+                if let Some(ref probs) = self.probs {
+                    if probs.len() != self.arms {
+                        return Err(format!("testbed has {} arms but {} probs",
+                                           self.arms, probs.len()));
+                    }
+                    if !probs.iter().all(|&p| (0.0..=1.0).contains(&p)) {
+                        return Err("testbed probs must be in [0, 1]".to_string());
+                    }
+                }
+                Ok(Testbed::Bernoulli { probs: self.probs.clone() })
+            }
+            "random-walk" => {
+                let sigma = self.walk_sigma.unwrap_or(0.01);
+                if sigma.is_nan() || sigma < 0.0 {
+                    return Err("testbed walk_sigma must be non-negative".to_string());
+                }
+                Ok(Testbed::RandomWalk { sigma })
+            }
+            "heavy-tailed" => {
+                let dof = self.tail_dof.unwrap_or(2.0);
+                if dof.is_nan() || dof <= 1.0 {
+                    return Err("testbed tail_dof must be greater than 1".to_string());
+                }
+                Ok(Testbed::HeavyTailed { dof })
+            }
+            "correlated" => {
+                let length_scale = self.length_scale.unwrap_or(2.0);
+                if !(length_scale > 0.0 && length_scale.is_finite()) {
+                    return Err("testbed length_scale must be positive".to_string());
+                }
+                Ok(Testbed::Correlated { length_scale })
+            }
+            "mortal" => {
+                let lifetime = self.lifetime.unwrap_or(200.0);
+                if lifetime.is_nan() || lifetime < 1.0 {
+                    return Err("testbed lifetime must be at least 1".to_string());
+                }
+                Ok(Testbed::Mortal { lifetime })
+            }
+            "difficulty" => {
+                let near_optimal = self.near_optimal.unwrap_or(1);
+                let gap = self.gap.unwrap_or(0.5);
+                let noise_to_gap = self.noise_to_gap.unwrap_or(2.0);
+                let name = self.gap_dist.as_ref().map(|s| &s[..]).unwrap_or("fixed");
+                let gap_dist = GapDist::from_name(name)
+                                   .ok_or(format!("unknown testbed gap_dist `{}`", name))?;
+                if near_optimal == 0 || near_optimal > self.arms {
+                    return Err("testbed near_optimal must be between 1 and the number of arms"
+                               .to_string());
+                }
+                if !(gap > 0.0 && noise_to_gap > 0.0) {
+                    return Err("testbed gap and noise_to_gap must be positive".to_string());
+                }
+                Ok(Testbed::Difficulty {
+                    near_optimal,
+                    gap,
+                    gap_dist,
+                    noise_to_gap,
+                })
+            }
+            "mixed" => {
+                let configs = self.arm_dists.as_ref()
+                                  .ok_or("testbed arm_dists is required for kind `mixed`")?;
+                if configs.len() != self.arms {
+                    return Err(format!("testbed has {} arms but {} arm_dists",
+                                       self.arms, configs.len()));
+                }
+                let mut arms = vec![];
+                for (a, config) in configs.iter().enumerate() {
+                    arms.push(config.arm_dist().map_err(|e| format!("testbed arm {}: {}", a, e))?);
+                }
+                Ok(Testbed::Mixed { arms })
+            }
+            "scripted" => match (&self.script, self.switch_every) {
+                (Some(path), None) => {
+                    Ok(Testbed::Scripted { rewards: read_script(path, self.arms)? })
+                }
+                (&None, Some(every)) => {
+                    if every == 0 {
+                        return Err("testbed switch_every must be positive".to_string());
+                    }
+                    Ok(Testbed::switching(self.arms, every))
+                }
+                _ => Err("testbed kind `scripted` takes one of script and switch_every"
+                         .to_string()),
+            },
+            "replay" => {
+                let path = self.log.as_ref().ok_or("testbed log is required for kind `replay`")?;
+                Ok(Testbed::Replay { log: read_log(path, self.arms)? })
+            }
+            "saved" => {
+                let path = self.saved.as_ref().ok_or("testbed saved is required for kind `saved`")?;
+                Ok(Testbed::Saved { tasks: SavedTasks::load(path, self.arms, self.tasks)? })
+            }
+            kind => Err(format!("unknown testbed kind `{}`", kind)),
+        }
+    }
+
+    fn arm_sd(&self) -> Result<Option<ArmSd>, String> {
+        let positive = "testbed reward standard deviations must be positive";
+        if let Some(sd) = self.reward_sd {
+            if sd.is_nan() || sd <= 0.0 {
+                return Err(positive.to_string());
+            }
+            return Ok(Some(ArmSd::Fixed(sd)));
+        }
+        if let Some(ref sds) = self.reward_sds {
+            if sds.len() != self.arms {
+                return Err(format!("testbed has {} arms but {} reward_sds",
+                                   self.arms, sds.len()));
+            }
+            if !sds.iter().all(|&sd| sd > 0.0) {
+                return Err(positive.to_string());
+            }
+            return Ok(Some(ArmSd::PerArm(sds.clone())));
+        }
+        if let Some(ref range) = self.reward_sd_range {
+            if range.len() != 2 || !range[0].partial_cmp(&range[1]).is_some_and(Ordering::is_le) {
+                return Err("testbed reward_sd_range must be [low, high] with low <= high"
+                           .to_string());
+            }
+            if range[0].is_nan() || range[0] <= 0.0 {
+                return Err(positive.to_string());
+            }
+            return Ok(Some(ArmSd::Uniform { low: range[0], high: range[1] }));
+        }
+        Ok(None)
+    }
+}
+
+// Reads a reward script: one line of `arms` comma-separated rewards per play.
+fn read_script(path: &str, arms: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("couldn't read script `{}`: {}", path, e))?;
+    let mut rewards = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row: Result<Vec<f64>, _> = line.split(',').map(|r| r.trim().parse()).collect();
+        match row {
+            Ok(ref row) if row.len() == arms => rewards.push(row.clone()),
+            _ => return Err(format!("script `{}` line {}: expected {} rewards",
+                                    path, i + 1, arms)),
+        }
+    }
+    if rewards.is_empty() {
+        return Err(format!("script `{}` has no rewards", path));
+    }
+    Ok(rewards)
+}
+
+// Reads an interaction log: (arm, reward) events in the order of their step
+// column.
+pub fn read_log(path: &str, arms: usize) -> Result<Vec<(usize, f64)>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("couldn't read log `{}`: {}", path, e))?;
+    let mut lines = text.lines().enumerate()
+                        .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => line.split(',').map(|c| c.trim().to_string()).collect(),
+        None => return Err(format!("log `{}` is empty", path)),
+    };
+    if header.len() < 3 || header[0] != "step" ||
+       (header[1] != "arm" && header[1] != "action") || header[2] != "reward" {
+        return Err(format!("log `{}` must start with columns step, arm and reward", path));
+    }
+
+    let mut events = vec![];
+    for (i, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let bad = || format!("log `{}` line {}: expected step, arm and reward", path, i + 1);
+        if fields.len() < 3 {
+            return Err(bad());
+        }
+        let step: usize = fields[0].parse().map_err(|_| bad())?;
+        let arm: usize = fields[1].parse().map_err(|_| bad())?;
+        let reward: f64 = fields[2].parse().map_err(|_| bad())?;
+        if arm >= arms {
+            return Err(format!("log `{}` line {}: arm {} but the testbed has {} arms",
+                               path, i + 1, arm, arms));
+        }
+        events.push((step, arm, reward));
+    }
+    if events.is_empty() {
+        return Err(format!("log `{}` has no events", path));
+    }
+    events.sort_by_key(|&(step, _, _)| step);
+    Ok(events.into_iter().map(|(_, arm, reward)| (arm, reward)).collect())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArmDistConfig {
+    pub dist: String,
+    pub mean: Option<f64>,
+    pub sd: Option<f64>,
+    pub p: Option<f64>,
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    pub value: Option<f64>,
+}
+
+impl ArmDistConfig {
+    fn arm_dist(&self) -> Result<ArmDist, String> {
+        let given = [("mean", self.mean.is_some()),
+                     ("sd", self.sd.is_some()),
+                     ("p", self.p.is_some()),
+                     ("low", self.low.is_some()),
+                     ("high", self.high.is_some()),
+                     ("value", self.value.is_some())];
+        let used: &[&str] = match &self.dist[..] {
+            "gaussian" => &["mean", "sd"],
+            "bernoulli" => &["p"],
+            "exponential" => &["mean"],
+            "uniform" => &["low", "high"],
+            "constant" => &["value"],
+            dist => return Err(format!("unknown dist `{}`", dist)),
+        };
+        for &(param, present) in &given {
+            if present && !used.contains(&param) {
+                return Err(format!("{} doesn't apply to dist `{}`", param, self.dist));
+            }
+        }
+        let required = |value: Option<f64>, param: &str| {
+            value.ok_or(format!("{} is required for dist `{}`", param, self.dist))
+        };
+
+        match &self.dist[..] {
+            "gaussian" => {
+                let sd = self.sd.unwrap_or(1.0);
+                if sd.is_nan() || sd <= 0.0 {
+                    return Err("sd must be positive".to_string());
+                }
+                Ok(ArmDist::Gaussian { mean: required(self.mean, "mean")?, sd })
+            }
+            "bernoulli" => {
+                let p = required(self.p, "p")?;
+                if !(0.0..=1.0).contains(&p) {
+                    return Err("p must be in [0, 1]".to_string());
+                }
+                Ok(ArmDist::Bernoulli { p })
+            }
+            "exponential" => {
+                let mean = required(self.mean, "mean")?;
+                if mean.is_nan() || mean <= 0.0 {
+                    return Err("mean must be positive".to_string());
+                }
+                Ok(ArmDist::Exponential { mean })
+            }
+            "uniform" => {
+                let low = required(self.low, "low")?;
+                let high = required(self.high, "high")?;
+                if !low.partial_cmp(&high).is_some_and(Ordering::is_le) {
+                    return Err("low must not be above high".to_string());
+                }
+                Ok(ArmDist::Uniform { low, high })
+            }
+            _ => Ok(ArmDist::Constant(required(self.value, "value")?)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentConfig {
+    pub name: String,
+    #[serde(default = "default_kind")]
+    pub kind: String,
+
+    // epsilon-greedy; without a step_size estimates are sample averages,
+    // without a tie_tolerance only exactly equal estimates are tied, and
+    // min_samples defaults to 0; mom_groups makes estimates medians of means
+    // and kernel_width shares rewards between nearby arms
+    pub epsilon: Option<f64>,
+    pub step_size: Option<f64>,
+    pub tie_tolerance: Option<f64>,
+    pub min_samples: Option<usize>,
+    pub mom_groups: Option<usize>,
+    pub kernel_width: Option<f64>,
+
+    // explore-pool; the policy defaults to "uniform"
+    pub explore_fraction: Option<f64>,
+    pub explore_policy: Option<String>,
+
+    // ucb, and the uncertainty bonus of forecast and cvar
+    pub c: Option<f64>,
+
+    // softmax
+    pub tau: Option<f64>,
+
+    // forecast
+    pub smoothing: Option<f64>,
+    pub trend_smoothing: Option<f64>,
+
+    // cvar
+    pub cvar_level: Option<f64>,
+
+    // moss and explore-commit; without a horizon they're run by the doubling
+    // trick, with doubling "rescale" (the default) or "reset"
+    pub horizon: Option<usize>,
+    pub doubling: Option<String>,
+
+    // bootstrap; defaults to 100
+    pub replicates: Option<usize>,
+
+    // rewards kept per arm by mom_groups and cvar, or plays kept by the
+    // doubling trick; defaults to 10000 (see ring)
+    pub history: Option<usize>,
+
+    // the parameters of an agent registered under `kind` (see registry)
+    pub params: Option<BTreeMap<String, toml::Value>>,
+
+    // meta; the agent choosing among sub_agents every play, each configured
+    // like any other agent
+    pub selector: Option<Box<AgentConfig>>,
+    pub sub_agents: Option<Vec<AgentConfig>>,
+
+    // reward preprocessing, for any kind (see preprocess)
+    pub reward_clip: Option<Vec<f64>>,
+    pub reward_normalize: Option<String>,
+
+    // wrappers around the agent, for any kind, each around the ones before
+    // it; the reward preprocessing is outside them all (see wrappers)
+    pub wrappers: Option<Vec<WrapperConfig>>,
+
+    // defaults to "<name>.dat" (or .csv)
+    pub output: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WrapperConfig {
+    pub kind: String,
+
+    // count-bonus
+    pub beta: Option<f64>,
+
+    // change-reset; delta defaults to 0
+    pub delta: Option<f64>,
+    pub threshold: Option<f64>,
+
+    // preprocess
+    pub reward_clip: Option<Vec<f64>>,
+    pub reward_normalize: Option<String>,
+
+    // the parameters of a wrapper registered under `kind` (see registry)
+    pub params: Option<BTreeMap<String, toml::Value>>,
+}
+
+fn default_arms() -> usize { 10 }
+fn default_tasks() -> usize { 2000 }
+fn default_plays() -> usize { 1000 }
+fn default_kind() -> String { "epsilon-greedy".to_string() }
+fn default_testbed_kind() -> String { "gaussian".to_string() }
+
+impl AgentConfig {
+    // Checks the agent's kind and hyperparameters and turns them into a
+    // spec. Hyperparameters the kind doesn't use must be left out, so a
+    // misplaced one isn't silently ignored.
+    pub fn spec(&self) -> Result<AgentSpec, String> {
+        let given = [("epsilon", self.epsilon.is_some()),
+                     ("step_size", self.step_size.is_some()),
+                     ("tie_tolerance", self.tie_tolerance.is_some()),
+                     ("min_samples", self.min_samples.is_some()),
+                     ("mom_groups", self.mom_groups.is_some()),
+                     ("kernel_width", self.kernel_width.is_some()),
+                     ("explore_fraction", self.explore_fraction.is_some()),
+                     ("explore_policy", self.explore_policy.is_some()),
+                     ("c", self.c.is_some()),
+                     ("tau", self.tau.is_some()),
+                     ("smoothing", self.smoothing.is_some()),
+                     ("trend_smoothing", self.trend_smoothing.is_some()),
+                     ("cvar_level", self.cvar_level.is_some()),
+                     ("horizon", self.horizon.is_some()),
+                     ("doubling", self.doubling.is_some()),
+                     ("replicates", self.replicates.is_some()),
+                     ("history", self.history.is_some()),
+                     ("params", self.params.is_some()),
+                     ("selector", self.selector.is_some()),
+                     ("sub_agents", self.sub_agents.is_some())];
+        let used: &[&str] = match &self.kind[..] {
+            "epsilon-greedy" => &["epsilon", "step_size", "tie_tolerance", "min_samples",
+                                  "mom_groups", "kernel_width", "history"],
+            "explore-pool" => &["explore_fraction", "explore_policy"],
+            "ucb" => &["c"],
+            "softmax" => &["tau"],
+            "forecast" => &["smoothing", "trend_smoothing", "c"],
+            "cvar" => &["cvar_level", "c", "history"],
+            "moss" | "explore-commit" => &["horizon", "doubling", "history"],
+            "bootstrap" => &["replicates"],
+            "meta" => &["selector", "sub_agents"],
+            kind if registry::is_registered(kind) => &["params"],
+            kind => return Err(format!("agent `{}`: {}", self.name, registry::unknown(kind))),
+        };
+        for &(param, present) in &given {
+            if present && !used.contains(&param) {
+                return Err(format!("agent `{}`: {} doesn't apply to kind `{}`",
+                                   self.name, param, self.kind));
+            }
+        }
+
+        let mut spec = match &self.kind[..] {
+            "epsilon-greedy" => AgentSpec::EpsilonGreedy {
+                epsilon: self.required(self.epsilon, "epsilon")?,
+                step_size: self.step_size,
+                tie_tolerance: self.tie_tolerance.unwrap_or(0.0),
+                min_samples: self.min_samples.unwrap_or(0),
+                mom_groups: self.mom_groups,
+                kernel_width: self.kernel_width,
+                history: self.history,
+            },
+            "explore-pool" => AgentSpec::ExplorePool {
+                fraction: self.required(self.explore_fraction, "explore_fraction")?,
+                policy: self.explore_policy()?,
+            },
+            "ucb" => AgentSpec::Ucb { c: self.required(self.c, "c")? },
+            "softmax" => AgentSpec::Softmax { tau: self.required(self.tau, "tau")? },
+            "cvar" => AgentSpec::Cvar {
+                level: self.required(self.cvar_level, "cvar_level")?,
+                c: self.required(self.c, "c")?,
+                history: self.history,
+            },
+            "bootstrap" => AgentSpec::Bootstrap { replicates: self.replicates.unwrap_or(100) },
+            "meta" => {
+                let selector = match self.selector {
+                    Some(ref selector) => selector.spec()?,
+                    None => return Err(format!("agent `{}`: selector is required for kind \
+                                                `meta`", self.name)),
+                };
+                let mut agents = vec![];
+                for agent in self.sub_agents.iter().flat_map(|agents| agents.iter()) {
+                    agents.push(agent.spec()?);
+                }
+                AgentSpec::Meta { selector: Box::new(selector), agents }
+            }
+            "forecast" => AgentSpec::Forecast {
+                alpha: self.required(self.smoothing, "smoothing")?,
+                beta: self.required(self.trend_smoothing, "trend_smoothing")?,
+                c: self.required(self.c, "c")?,
+            },
+            "moss" | "explore-commit" => {
+                if self.horizon.is_some() && self.doubling.is_some() {
+                    return Err(format!("agent `{}`: doubling only applies without a horizon",
+                                       self.name));
+                }
+                let reset = match self.doubling.as_ref().map(|s| &s[..]) {
+                    None | Some("rescale") => false,
+                    Some("reset") => true,
+                    Some(other) => {
+                        return Err(format!("agent `{}`: unknown doubling `{}`", self.name, other))
+                    }
+                };
+                let (horizon, history) = (self.horizon, self.history);
+                if self.kind == "moss" {
+                    AgentSpec::Moss { horizon, reset, history }
+                } else {
+                    AgentSpec::ExploreCommit { horizon, reset, history }
+                }
+            }
+            kind => AgentSpec::Registered { name: kind.to_string(),
+                                            params: string_params(&self.params) },
+        };
+        for wrapper in self.wrappers.iter().flat_map(|wrappers| wrappers.iter()) {
+            spec = wrapper.wrap(spec).map_err(|e| format!("agent `{}`: {}", self.name, e))?;
+        }
+        let preprocessing = preprocessing(&self.reward_clip, &self.reward_normalize)
+                                .map_err(|e| format!("agent `{}`: {}", self.name, e))?;
+        let spec = match preprocessing {
+            Some(preprocessing) => {
+                AgentSpec::Preprocessed { preprocessing, agent: Box::new(spec) }
+            }
+            None => spec,
+        };
+        spec.validate().map_err(|e| format!("agent `{}`: {}", self.name, e))?;
+        Ok(spec)
+    }
+
+    fn required(&self, value: Option<f64>, param: &str) -> Result<f64, String> {
+        value.ok_or(format!("agent `{}`: {} is required for kind `{}`",
+                            self.name, param, self.kind))
+    }
+
+    fn explore_policy(&self) -> Result<ExplorePolicy, String> {
+        let name = match self.explore_policy {
+            Some(ref name) => &name[..],
+            None => "uniform",
+        };
+        ExplorePolicy::from_name(name)
+            .ok_or(format!("agent `{}`: unknown explore_policy `{}`", self.name, name))
+    }
+
+    fn output_path(&self, format: Format, compress: bool) -> String {
+        match self.output {
+            Some(ref path) => path.clone(),
+            None => output::file_name(&self.name, format, compress),
+        }
+    }
+}
+
+impl WrapperConfig {
+    // Wraps `spec` in this wrapper. As with an agent, parameters the kind
+    // doesn't use must be left out.
+    fn wrap(&self, spec: AgentSpec) -> Result<AgentSpec, String> {
+        let given = [("beta", self.beta.is_some()),
+                     ("delta", self.delta.is_some()),
+                     ("threshold", self.threshold.is_some()),
+                     ("reward_clip", self.reward_clip.is_some()),
+                     ("reward_normalize", self.reward_normalize.is_some()),
+                     ("params", self.params.is_some())];
+        let used: &[&str] = match &self.kind[..] {
+            "preprocess" => &["reward_clip", "reward_normalize"],
+            "count-bonus" => &["beta"],
+            "change-reset" => &["delta", "threshold"],
+            kind if registry::is_registered_wrapper(kind) => &["params"],
+            kind => return Err(registry::unknown_wrapper(kind).to_string()),
+        };
+        for &(param, present) in &given {
+            if present && !used.contains(&param) {
+                return Err(format!("{} doesn't apply to wrapper `{}`", param, self.kind));
+            }
+        }
+
+        let wrapper = match &self.kind[..] {
+            "preprocess" => {
+                return match preprocessing(&self.reward_clip, &self.reward_normalize)? {
+                    Some(preprocessing) => Ok(AgentSpec::Preprocessed {
+                        preprocessing,
+                        agent: Box::new(spec),
+                    }),
+                    None => Err("wrapper `preprocess` needs reward_clip or reward_normalize"
+                                .to_string()),
+                };
+            }
+            "count-bonus" => WrapperSpec::CountBonus { beta: self.required(self.beta, "beta")? },
+            "change-reset" => WrapperSpec::ChangeReset {
+                delta: self.delta.unwrap_or(0.0),
+                threshold: self.required(self.threshold, "threshold")?,
+            },
+            kind => WrapperSpec::Registered { name: kind.to_string(),
+                                              params: string_params(&self.params) },
+        };
+        Ok(AgentSpec::Wrapped { wrapper, agent: Box::new(spec) })
+    }
+
+    fn required(&self, value: Option<f64>, param: &str) -> Result<f64, String> {
+        value.ok_or(format!("{} is required for wrapper `{}`", param, self.kind))
+    }
+}
+
+// Reward preprocessing from `reward_clip` and `reward_normalize`, if either
+// is given.
+fn preprocessing(clip: &Option<Vec<f64>>, normalize: &Option<String>)
+                 -> Result<Option<Preprocessing>, String> {
+    let clip = match *clip {
+        Some(ref range) if range.len() == 2 => Some((range[0], range[1])),
+        Some(_) => return Err("reward_clip must be [low, high]".to_string()),
+        None => None,
+    };
+    let normalize = match *normalize {
+        Some(ref name) => Some(Normalization::from_name(name)?),
+        None => None,
+    };
+    if clip.is_none() && normalize.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Preprocessing { clip, normalize }))
+}
+
+// A registered agent's or wrapper's parameters as the strings it's given.
+fn string_params(params: &Option<BTreeMap<String, toml::Value>>) -> Params {
+    params.iter().flat_map(|params| params.iter()).map(|(k, v)| {
+        let value = match *v {
+            toml::Value::String(ref s) => s.clone(),
+            ref other => other.to_string(),
+        };
+        (k.clone(), value)
+    }).collect()
+}
+
+impl ExperimentConfig {
+    pub fn from_file(path: &str) -> Result<ExperimentConfig, Error> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| Error::io(format!("read config `{}`", path), e))?;
+        ExperimentConfig::from_str(&contents)
+            .map_err(|e| Error::Invalid(format!("invalid config `{}`: {}", path, e)))
+    }
+
+    pub fn from_str(contents: &str) -> Result<ExperimentConfig, String> {
+        let config: ExperimentConfig = toml::from_str(contents).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn format(&self) -> Result<Format, String> {
+        match self.format {
+            Some(ref name) => Format::from_name(name),
+            None => Ok(Format::Dat),
+        }
+    }
+
+    fn compress(&self) -> Result<bool, String> {
+        match self.compress {
+            Some(ref value) => output::parse_compress(value),
+            None => Ok(false),
+        }
+    }
+
+    fn metrics(&self) -> Result<Vec<Metric>, String> {
+        match self.metrics {
+            Some(ref names) if names.is_empty() => Err("metrics must not be empty".to_string()),
+            Some(ref names) => names.iter().map(|name| Metric::from_name(name)).collect(),
+            None => Ok(ALL_METRICS.to_vec()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let format = self.format()?;
+        let compress = self.compress()?;
+        self.metrics()?;
+        let t = &self.testbed;
+        if t.arms == 0 || t.tasks == 0 || t.plays == 0 {
+            return Err("testbed arms, tasks and plays must all be positive".to_string());
+        }
+        t.testbed()?;
+        if self.agents.is_empty() {
+            return Err("at least one [[agents]] entry is required".to_string());
+        }
+        for (i, agent) in self.agents.iter().enumerate() {
+            agent.spec()?;
+            for other in &self.agents[..i] {
+                if other.name == agent.name {
+                    return Err(format!("agent name `{}` is used twice", agent.name));
+                }
+                if other.output_path(format, compress) == agent.output_path(format, compress) {
+                    return Err(format!("agents `{}` and `{}` write to the same file",
+                                       other.name, agent.name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs every agent in the plan in order and writes its average-reward
+    // curve to its output path, then prints a summary of every agent. With
+    // `checkpoint`, each agent's run is checkpointed to "<file>.<name>";
+    // when resuming, agents without a checkpoint start from scratch.
+    pub fn run(&self, checkpoint: Option<&CheckpointFlags>) -> Result<(), Error> {
+        let format = self.format()?;
+        let compress = self.compress()?;
+        let t = &self.testbed;
+        let testbed = t.testbed()?;
+        if let Some(ref path) = t.save {
+            let seed = t.seed.ok_or("testbed save needs a seed to draw the tasks from")?;
+            let seeds = task_seeds(&mut seeded_rng(seed), t.tasks);
+            SavedTasks::capture(&testbed, t.arms, &seeds)?.save(path)?;
+            println!("Saved the {} tasks drawn from seed {} to `{}`", t.tasks, seed, path);
+        }
+        let mut builder = ExperimentBuilder::new()
+            .arms(t.arms)
+            .tasks(t.tasks)
+            .plays(t.plays)
+            .testbed(testbed)
+            .format(format)
+            .compress(compress);
+        for agent in &self.agents {
+            builder = match agent.output {
+                Some(ref path) => builder.agent_with_output(&agent.name, agent.spec()?, path),
+                None => builder.agent(&agent.name, agent.spec()?),
+            };
+        }
+        if let Some(seed) = t.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(ref path) = self.warm_start {
+            let history = WarmStart::read(path, t.arms)?;
+            println!("Warm-starting every agent from {} observations in `{}`",
+                     history.observations(), path);
+            builder = builder.warm_start(history, path);
+        }
+        if let Some(every) = self.partial_every {
+            builder = builder.partial_every(every);
+        }
+        if let Some(ref dir) = self.raw_output {
+            builder = builder.raw_output(dir);
+        }
+        if let Some(ref name) = self.events {
+            builder = builder.events(EventFormat::from_name(name)?);
+        }
+        if let Some(ref value) = self.histogram {
+            builder = builder.histogram(HistogramBins::parse(value)?);
+        }
+        if let Some(timing) = self.timing {
+            builder = builder.timing(timing);
+        }
+        if let Some(c) = checkpoint {
+            builder = builder.checkpoint(c.clone());
+        }
+        if let Some(ref path) = self.summary {
+            builder = builder.summary(path);
+        }
+        if let Some(ref path) = self.plot {
+            builder = builder.plot(path);
+        }
+        builder = builder.metrics(&self.metrics()?);
+        builder.build()?.run()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT: &str = "[[agents]]\nname = \"eps\"\nepsilon = 0.1\n";
+
+    // Plans that parse, with what they parse to.
+    #[test]
+    fn parses_valid_plans() {
+        let config = ExperimentConfig::from_str(&format!("[testbed]\n{}", AGENT)).unwrap();
+        let t = &config.testbed;
+        assert_eq!((t.arms, t.tasks, t.plays, t.seed), (10, 2000, 1000, None));
+        assert_eq!(t.testbed().unwrap(), Testbed::Gaussian { sd: None });
+        assert_eq!(config.agents[0].output_path(Format::Dat, false), "eps.dat");
+
+        let plans: &[(&str, Testbed, AgentSpec)] = &[
+            ("[testbed]\nkind = \"bernoulli\"\nprobs = [0.1, 0.2]\narms = 2\n\
+              [[agents]]\nname = \"ucb\"\nkind = \"ucb\"\nc = 2.0\n",
+             Testbed::Bernoulli { probs: Some(vec![0.1, 0.2]) }, AgentSpec::Ucb { c: 2.0 }),
+            ("[testbed]\nkind = \"random-walk\"\nwalk_sigma = 0.05\ndelay = 3\n\
+              [[agents]]\nname = \"soft\"\nkind = \"softmax\"\ntau = 0.2\n",
+             Testbed::Delayed {
+                 testbed: Box::new(Testbed::RandomWalk { sigma: 0.05 }),
+                 delay: Delay::Fixed(3),
+             },
+             AgentSpec::Softmax { tau: 0.2 }),
+        ];
+        for (plan, testbed, spec) in plans {
+            let config = ExperimentConfig::from_str(plan).unwrap();
+            assert_eq!(config.testbed.testbed().unwrap(), *testbed);
+            assert_eq!(config.agents[0].spec().unwrap(), *spec);
+        }
+    }
+
+    // Plans that don't, with a part of their error message. Every plan but
+    // those missing a section gets the agent section appended.
+    #[test]
+    fn rejects_invalid_plans() {
+        let plans: &[(&str, &str)] = &[
+            // unknown keys
+            ("colour = \"red\"\n[testbed]\n", "unknown field `colour`"),
+            ("[testbed]\nbandits = 10\n", "unknown field `bandits`"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nkind = \"ucb\"\nc = 1.0\nalpha = 0.1\n",
+             "unknown field `alpha`"),
+            // missing fields
+            ("[testbed]\n[[agents]]\nepsilon = 0.1\n", "missing field `name`"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\n", "epsilon is required"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nkind = \"softmax\"\n", "tau is required"),
+            // bad values
+            ("[testbed]\narms = -1\n", "invalid value"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nepsilon = \"lots\"\n", "invalid type"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nepsilon = 2.0\n", "epsilon"),
+            ("[testbed]\nplays = 0\n", "must all be positive"),
+            ("format = \"xml\"\n[testbed]\n", "xml"),
+            ("metrics = [\"happiness\"]\n[testbed]\n", "happiness"),
+            ("[testbed]\nkind = \"smooth\"\n", "smooth"),
+            ("[testbed]\nprobs = [0.5]\n", "only apply to kind `bernoulli`"),
+            ("[testbed]\ndelay = 1\nmax_delay = 2\n", "only one of delay and max_delay"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nepsilon = 0.1\nc = 2.0\n",
+             "c doesn't apply to kind `epsilon-greedy`"),
+            ("[testbed]\n[[agents]]\nname = \"b\"\nkind = \"nonsense\"\n", "unknown agent"),
+            ("[testbed]\n[[agents]]\nname = \"eps\"\nepsilon = 0.2\n", "used twice"),
+        ];
+        for &(plan, error) in plans {
+            let plan = format!("{}{}", plan, AGENT);
+            match ExperimentConfig::from_str(&plan) {
+                Ok(_) => panic!("accepted:\n{}", plan),
+                Err(e) => assert!(e.contains(error), "{:?} doesn't mention {:?}", e, error),
+            }
+        }
+
+        let missing: &[(&str, &str)] = &[
+            (AGENT, "missing field `testbed`"),
+            ("[testbed]\n", "missing field `agents`"),
+            ("agents = []\n[testbed]\n", "at least one [[agents]]"),
+        ];
+        for &(plan, error) in missing {
+            match ExperimentConfig::from_str(plan) {
+                Ok(_) => panic!("accepted:\n{}", plan),
+                Err(e) => assert!(e.contains(error), "{:?} doesn't mention {:?}", e, error),
+            }
+        }
+    }
+}
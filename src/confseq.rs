@@ -0,0 +1,90 @@
+// Anytime-valid confidence sequences for per-arm means.
+//
+// An ordinary confidence interval is only valid at one fixed sample size; if
+// it's recomputed after every reward and the experiment is stopped whenever
+// it looks conclusive, the error rate is far above alpha. A confidence
+// sequence instead holds for *every* sample size simultaneously: with
+// probability at least 1 - alpha, the true mean lies inside the interval at
+// all times. It can therefore be monitored continuously, or used by an agent
+// to decide which arms are still worth playing.
+//
+// This uses the two-sided normal-mixture boundary (Robbins; Howard et al.,
+// "Time-uniform, nonparametric, nonasymptotic confidence sequences"). For
+// rewards with sub-Gaussian noise of variance sigma^2, after k rewards the
+// sum S_k satisfies, uniformly over k,
+//
+//     |S_k - k*mu| < sqrt((V + rho) * ln((V + rho) / (rho * alpha^2)))
+//
+// where V = sigma^2 * k and rho > 0 sets the sample size at which the
+// boundary is tightest.
+
+use crate::stats::ArmSummary;
+
+pub struct ConfidenceSequence {
+    pub alpha: f64,
+    pub sigma: f64,
+    pub rho: f64,
+}
+
+impl ConfidenceSequence {
+    pub fn new(alpha: f64, sigma: f64, rho: f64) -> ConfidenceSequence {
+        assert!(alpha > 0.0 && alpha < 1.0);
+        assert!(sigma > 0.0 && rho > 0.0);
+        ConfidenceSequence {
+            alpha,
+            sigma,
+            rho,
+        }
+    }
+
+    // Chooses rho so the boundary is (approximately) tightest after
+    // `target` rewards, following Howard et al.
+    pub fn tuned_for(alpha: f64, sigma: f64, target: usize) -> ConfidenceSequence {
+        let l = -2.0 * alpha.ln();
+        let v = sigma * sigma * (target as f64);
+        ConfidenceSequence::new(alpha, sigma, v / (l + (l + 1.0).ln()))
+    }
+
+    // Half-width of the interval for an arm's mean after `count` rewards.
+    // With no rewards the mean is unconstrained.
+    pub fn radius(&self, count: usize) -> f64 {
+        if count == 0 {
+            return f64::INFINITY;
+        }
+        let v = self.sigma * self.sigma * (count as f64);
+        let sum_radius = ((v + self.rho)
+                          * ((v + self.rho) / (self.rho * self.alpha * self.alpha)).ln()).sqrt();
+        sum_radius / (count as f64)
+    }
+
+    pub fn interval(&self, arm: &ArmSummary) -> (f64, f64) {
+        let r = self.radius(arm.count);
+        (arm.mean - r, arm.mean + r)
+    }
+
+    pub fn intervals(&self, arms: &[ArmSummary]) -> Vec<(f64, f64)> {
+        let mut intervals = vec![];
+        for arm in arms {
+            intervals.push(self.interval(arm));
+        }
+        intervals
+    }
+
+    // For each arm, whether its upper bound lies below some other arm's
+    // lower bound. Such an arm is not the best arm, at every point in time,
+    // with probability at least 1 - alpha (per arm), so an elimination-style
+    // agent can stop playing it.
+    pub fn dominated(&self, arms: &[ArmSummary]) -> Vec<bool> {
+        let intervals = self.intervals(arms);
+        let mut best_lower = f64::NEG_INFINITY;
+        for &(lower, _) in &intervals {
+            best_lower = best_lower.max(lower);
+        }
+
+        let mut dominated = vec![];
+        for &(_, upper) in &intervals {
+            dominated.push(upper < best_lower);
+        }
+        dominated
+    }
+}
@@ -0,0 +1,406 @@
+// Continuum-armed bandits. In the `continuum` mode an action is a point x of
+// [0, 1] rather than an arm index, and playing x pays f(x) plus Gaussian
+// noise with standard deviation `noise-sd`, for a smooth reward function
+// drawn for every task as a sum of Gaussian bumps,
+//
+//     f(x) = sum over k of h_k exp(-(x - c_k)^2 / (2 w^2))
+//
+// with heights h_k and centres c_k uniform in [0, 1] and width w = `width`.
+// A play's regret is max f - f(x), with the maximum found on a fine grid.
+//
+// Two agents play every task, with the same rewards noise: ZoomingAgent
+// (Kleinberg, Slivkins & Upfal, 2008), which keeps a set of active points
+// and adds new ones where its confidence is lowest, so it ends up with a fine
+// grid only around the peaks, and UCB on a fixed uniform grid of
+// K = ceil((T / ln T)^(1/3)) points for T plays. Their average cumulative
+// regrets over the tasks are written for every play:
+//
+//     step,zooming_regret,grid_regret
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+use rand::distributions::IndependentSample;
+use rand::distributions::normal::Normal;
+
+use crate::output::OutputFile;
+use crate::progress;
+use crate::ucb::UcbBandit;
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, Agent};
+
+pub struct Options {
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub bumps: usize,
+    pub width: f64,
+    pub noise_sd: f64,
+
+    // how fast the zooming agent assumes f can change: |f(x) - f(y)| is at
+    // most lipschitz * |x - y|
+    pub lipschitz: f64,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            num_tasks: 200,
+            num_plays: 2000,
+            seed: None,
+            bumps: 3,
+            width: 0.1,
+            noise_sd: 0.1,
+            lipschitz: 5.0,
+            output: "continuum.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "bumps" => opts.bumps = parse_value(key, value)?,
+                "width" => opts.width = parse_value(key, value)?,
+                "noise-sd" => opts.noise_sd = parse_value(key, value)?,
+                "lipschitz" => opts.lipschitz = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.num_tasks == 0 || opts.num_plays < 2 || opts.bumps == 0 {
+            return Err("tasks and bumps must be positive, and there must be at least 2 plays"
+                       .to_string());
+        }
+        if !(opts.width > 0.0 && opts.noise_sd > 0.0 && opts.lipschitz > 0.0) {
+            return Err("width, noise-sd and lipschitz must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// A smooth reward function on [0, 1] with noisy evaluations.
+pub struct BumpFunction {
+    // (height, centre) of every bump
+    bumps: Vec<(f64, f64)>,
+    width: f64,
+    noise: Normal,
+    max: f64,
+}
+
+impl BumpFunction {
+    pub fn draw<R: Rng>(num_bumps: usize, width: f64, noise_sd: f64, rng: &mut R)
+                        -> BumpFunction {
+        let bumps = (0..num_bumps).map(|_| (rng.next_f64(), rng.next_f64())).collect();
+        let mut f = BumpFunction {
+            bumps,
+            width,
+            noise: Normal::new(0.0, noise_sd),
+            max: 0.0,
+        };
+        let points = 10000;
+        f.max = (0..points + 1).map(|i| f.value(i as f64 / points as f64))
+                               .fold(f64::NEG_INFINITY, |acc, v| acc.max(v));
+        f
+    }
+
+    pub fn value(&self, x: f64) -> f64 {
+        self.bumps.iter().fold(0.0, |acc, &(h, c)| {
+            acc + h * (-(x - c) * (x - c) / (2.0 * self.width * self.width)).exp()
+        })
+    }
+
+    pub fn step<R: Rng>(&self, x: f64, rng: &mut R) -> f64 {
+        self.value(x) + self.noise.ind_sample(rng)
+    }
+
+    pub fn regret(&self, x: f64) -> f64 {
+        self.max - self.value(x)
+    }
+}
+
+// Anything that can play a continuum-armed task: it picks a point of [0, 1]
+// each play and is told the reward there.
+pub trait ContinuumAgent {
+    fn choose_point(&mut self, rng: &mut dyn Rng) -> f64;
+    fn receive_reward(&mut self, reward: f64, x: f64);
+}
+
+struct ActivePoint {
+    x: f64,
+    count: usize,
+    sum: f64,
+}
+
+// The zooming algorithm for a known horizon T. Every active point x has a
+// confidence radius r(x) = sqrt(2 ln T / (N(x) + 1)) and covers the points
+// within r(x) / lipschitz of it. Before every play any point of [0, 1] left
+// uncovered is activated; then the active point maximizing mean(x) + 2 r(x)
+// is played.
+pub struct ZoomingAgent {
+    points: Vec<ActivePoint>,
+    horizon: usize,
+    lipschitz: f64,
+}
+
+impl ZoomingAgent {
+    pub fn new(horizon: usize, lipschitz: f64) -> ZoomingAgent {
+        assert!(horizon > 1 && lipschitz > 0.0);
+        ZoomingAgent { points: vec![], horizon, lipschitz }
+    }
+
+    pub fn num_active(&self) -> usize {
+        self.points.len()
+    }
+
+    fn radius(&self, point: &ActivePoint) -> f64 {
+        (2.0 * (self.horizon as f64).ln() / (point.count as f64 + 1.0)).sqrt()
+    }
+
+    // The middle of the first gap in the coverage of [0, 1], if there is one.
+    fn uncovered(&self) -> Option<f64> {
+        let mut balls: Vec<(f64, f64)> = self.points.iter().map(|p| {
+            let reach = self.radius(p) / self.lipschitz;
+            (p.x - reach, p.x + reach)
+        }).collect();
+        balls.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut covered = 0.0;
+        for &(left, right) in &balls {
+            if left > covered {
+                return Some((covered + left.min(1.0)) / 2.0);
+            }
+            covered = f64::max(covered, right);
+            if covered >= 1.0 {
+                return None;
+            }
+        }
+        Some((covered + 1.0) / 2.0)
+    }
+}
+
+impl ContinuumAgent for ZoomingAgent {
+    fn choose_point(&mut self, mut rng: &mut dyn Rng) -> f64 {
+        let rng = &mut rng;
+        while let Some(x) = self.uncovered() {
+            self.points.push(ActivePoint { x, count: 0, sum: 0.0 });
+        }
+
+        let mut best = vec![];
+        let mut best_value = f64::NEG_INFINITY;
+        for (i, p) in self.points.iter().enumerate() {
+            let mean = if p.count > 0 { p.sum / p.count as f64 } else { 0.0 };
+            let value = mean + 2.0 * self.radius(p);
+            if value > best_value {
+                best = vec![i];
+                best_value = value;
+            } else if value == best_value {
+                best.push(i);
+            }
+        }
+        self.points[best[rng.gen_range(0, best.len())]].x
+    }
+
+    fn receive_reward(&mut self, reward: f64, x: f64) {
+        if let Some(p) = self.points.iter_mut().find(|p| p.x == x) {
+            p.count += 1;
+            p.sum += reward;
+        }
+    }
+}
+
+// A bandit agent over k evenly spaced points, the middles of k equal cells.
+pub struct Discretized {
+    agent: Box<dyn Agent>,
+    k: usize,
+}
+
+impl Discretized {
+    pub fn new(agent: Box<dyn Agent>, k: usize) -> Discretized {
+        assert!(k > 0);
+        Discretized { agent, k }
+    }
+}
+
+impl ContinuumAgent for Discretized {
+    fn choose_point(&mut self, rng: &mut dyn Rng) -> f64 {
+        (self.agent.choose_action(rng) as f64 + 0.5) / self.k as f64
+    }
+
+    fn receive_reward(&mut self, reward: f64, x: f64) {
+        let arm = ((x * self.k as f64) as usize).min(self.k - 1);
+        self.agent.receive_reward(reward, arm);
+    }
+}
+
+// Plays every play, returning the cumulative regret after each.
+fn play_continuum<R: Rng>(f: &BumpFunction, agent: &mut dyn ContinuumAgent, num_plays: usize,
+                          rng: &mut R) -> Vec<f64> {
+    let mut total = 0.0;
+    (0..num_plays).map(|_| {
+        let x = agent.choose_point(rng);
+        agent.receive_reward(f.step(x, rng), x);
+        total += f.regret(x);
+        total
+    }).collect()
+}
+
+// Sums over tasks of both agents' cumulative regrets, and of the number of
+// points the zooming agent ended up with.
+struct ContinuumSums {
+    zooming: Vec<f64>,
+    grid: Vec<f64>,
+    active_points: usize,
+}
+
+impl ContinuumSums {
+    fn new(num_plays: usize) -> ContinuumSums {
+        ContinuumSums {
+            zooming: vec![0.0; num_plays],
+            grid: vec![0.0; num_plays],
+            active_points: 0,
+        }
+    }
+
+    fn add(&mut self, other: &ContinuumSums) {
+        for t in 0..self.zooming.len() {
+            self.zooming[t] += other.zooming[t];
+            self.grid[t] += other.grid[t];
+        }
+        self.active_points += other.active_points;
+    }
+}
+
+fn grid_size(num_plays: usize) -> usize {
+    let t = num_plays as f64;
+    (t / t.ln()).powf(1.0 / 3.0).ceil() as usize
+}
+
+fn run_tasks<R: Rng>(rng: &mut R, opts: &Options) -> ContinuumSums {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let k = grid_size(opts.num_plays);
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = ContinuumSums::new(opts.num_plays);
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let f = BumpFunction::draw(opts.bumps, opts.width, opts.noise_sd, &mut rng);
+            let play_seed: usize = rng.gen();
+
+            let mut zooming = ZoomingAgent::new(opts.num_plays, opts.lipschitz);
+            let regrets = play_continuum(&f, &mut zooming, opts.num_plays,
+                                         &mut seeded_rng(play_seed));
+            let mut grid = Discretized::new(Box::new(UcbBandit::new(k, 2.0)), k);
+            let grid_regrets = play_continuum(&f, &mut grid, opts.num_plays,
+                                              &mut seeded_rng(play_seed));
+            for t in 0..opts.num_plays {
+                sums.zooming[t] += regrets[t];
+                sums.grid[t] += grid_regrets[t];
+            }
+            sums.active_points += zooming.num_active();
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut sums = ContinuumSums::new(opts.num_plays);
+    for chunk in &chunks {
+        sums.add(chunk);
+    }
+    sums
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let sums = match opts.seed {
+        Some(seed) => run_tasks(&mut seeded_rng(seed), opts),
+        None => run_tasks(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let last = opts.num_plays - 1;
+    println!("{} bumps of width {}, noise sd {}: {} tasks x {} plays",
+             opts.bumps, opts.width, opts.noise_sd, opts.num_tasks, opts.num_plays);
+    println!("{:<32} {:>12} {:>8}", "agent", "total regret", "points");
+    println!("{:<32} {:>12.4} {:>8.1}", format!("zooming(lipschitz={})", opts.lipschitz),
+             sums.zooming[last] / tasks, sums.active_points as f64 / tasks);
+    println!("{:<32} {:>12.4} {:>8}", "ucb(c=2) on a uniform grid", sums.grid[last] / tasks,
+             grid_size(opts.num_plays));
+    Ok(())
+}
+
+fn write_csv(sums: &ContinuumSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let tasks = num_tasks as f64;
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,zooming_regret,grid_regret")?;
+    for t in 0..sums.zooming.len() {
+        writeln!(f, "{},{},{}", t, sums.zooming[t] / tasks, sums.grid[t] / tasks)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_bump(height: f64, centre: f64) -> BumpFunction {
+        BumpFunction {
+            bumps: vec![(height, centre)],
+            width: 0.1,
+            noise: Normal::new(0.0, 0.1),
+            max: height,
+        }
+    }
+
+    #[test]
+    fn bump_values_by_hand() {
+        let f = one_bump(0.8, 0.3);
+        assert_eq!(f.value(0.3), 0.8);
+        assert!((f.value(0.4) - 0.8 * (-0.5f64).exp()).abs() < 1e-12);
+        assert!((f.value(0.1) - f.value(0.5)).abs() < 1e-12);
+        assert_eq!(f.regret(0.3), 0.0);
+
+        // the maximum is found on the grid
+        let drawn = BumpFunction::draw(3, 0.1, 0.1, &mut seeded_rng(1));
+        let peak = (0..1001).map(|i| drawn.value(i as f64 / 1000.0))
+                            .fold(f64::NEG_INFINITY, f64::max);
+        assert!(drawn.max >= peak && drawn.max - peak < 1e-4);
+    }
+
+    // The active points always cover [0, 1], and play closes in on the peak.
+    #[test]
+    fn zooming_covers_the_interval_and_finds_the_peak() {
+        let f = one_bump(1.0, 0.7);
+        let mut rng = seeded_rng(2);
+        let mut agent = ZoomingAgent::new(2000, 5.0);
+        let mut regrets = vec![];
+        for _ in 0..2000 {
+            let x = agent.choose_point(&mut rng);
+            assert!(agent.uncovered().is_none());
+            agent.receive_reward(f.step(x, &mut rng), x);
+            regrets.push(f.regret(x));
+        }
+        let last: f64 = regrets[1500..].iter().sum::<f64>() / 500.0;
+        let first: f64 = regrets[..500].iter().sum::<f64>() / 500.0;
+        assert!(last < 0.1 && last < first / 4.0, "{} {}", first, last);
+    }
+
+    #[test]
+    fn grid_points_map_back_to_their_arms() {
+        let mut grid = Discretized::new(Box::new(UcbBandit::new(4, 2.0)), 4);
+        let mut rng = seeded_rng(3);
+        for _ in 0..8 {
+            let x = grid.choose_point(&mut rng);
+            assert!([0.125, 0.375, 0.625, 0.875].contains(&x));
+            grid.receive_reward(x, x);
+        }
+        for (arm, summary) in grid.agent.arm_summaries().iter().enumerate() {
+            assert_eq!(summary.count, 2);
+            assert_eq!(summary.mean, (arm as f64 + 0.5) / 4.0);
+        }
+        grid.receive_reward(1.0, 1.0);
+        assert_eq!(grid.agent.arm_summaries()[3].count, 3);
+    }
+}
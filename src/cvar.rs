@@ -0,0 +1,175 @@
+// Risk-averse agent. Instead of the mean it ranks arms by their conditional
+// value-at-risk at level alpha, the mean of the worst alpha fraction of
+// their rewards, so of two arms with the same mean it prefers the one whose
+// bad rewards aren't as bad. Each arm's most recent rewards (at most
+// `history` of them; see ring) are also kept sorted, which makes its
+// empirical lower quantiles, and so its CVaR estimate
+//
+//     CVaR_t(a) = mean of the ceil(alpha M) smallest of a's last M rewards
+//
+// cheap to read off. The arm played is the one maximizing
+//
+//     CVaR_t(a) + c * sqrt(ln t / N_t(a))
+//
+// where N_t(a) counts every reward of a, kept or not, and arms that haven't
+// been played yet count as maximizing, as in UCB. A prior says nothing about
+// an arm's tail, so arms added with one start out unplayed all the same.
+
+use rand::Rng;
+
+use crate::ring::RingBuffer;
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct CvarBandit {
+    stats: ArmStats,
+
+    // each arm's most recent rewards, in the order received and in
+    // increasing order
+    recent: Vec<RingBuffer<f64>>,
+    sorted_rewards: Vec<Vec<f64>>,
+    history: usize,
+
+    // fraction of the worst rewards averaged
+    level: f64,
+
+    // degree of exploration
+    c: f64,
+    t: usize,
+}
+
+impl CvarBandit {
+    pub fn new(n: usize, level: f64, c: f64, history: usize) -> CvarBandit {
+        assert!(n > 0);
+        assert!(level > 0.0 && level <= 1.0);
+        assert!(c >= 0.0);
+        CvarBandit {
+            stats: ArmStats::new(n),
+            recent: vec![RingBuffer::new(history); n],
+            sorted_rewards: vec![vec![]; n],
+            history,
+            level,
+            c,
+            t: 0,
+        }
+    }
+
+    pub fn cvar(&self, action: usize) -> f64 {
+        empirical_cvar(&self.sorted_rewards[action], self.level)
+    }
+
+    fn score(&self, action: usize) -> f64 {
+        let count = self.stats.count(action) as f64;
+        if count == 0.0 {
+            return f64::INFINITY;
+        }
+        self.cvar(action) + self.c * ((self.t as f64).max(1.0).ln() / count).sqrt()
+    }
+}
+
+// Mean of the worst `level` fraction of rewards sorted in increasing order,
+// and 0 if there are none.
+pub fn empirical_cvar(sorted: &[f64], level: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let m = ((level * sorted.len() as f64).ceil() as usize).max(1).min(sorted.len());
+    sorted[..m].iter().fold(0.0, |acc, r| acc + r) / m as f64
+}
+
+impl Agent for CvarBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active = self.stats.active();
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.score(active[0]);
+        for &i in &active[1..] {
+            let value = self.score(i);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+        let rewards = &mut self.sorted_rewards[action];
+        if let Some(old) = self.recent[action].push(reward) {
+            if let Ok(i) = rewards.binary_search_by(|r| r.partial_cmp(&old).unwrap()) {
+                rewards.remove(i);
+            }
+        }
+        let i = match rewards.binary_search_by(|r| r.partial_cmp(&reward).unwrap()) {
+            Ok(i) | Err(i) => i,
+        };
+        rewards.insert(i, reward);
+        self.t += 1;
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, _prior: Option<ArmPrior>) -> usize {
+        self.recent.push(RingBuffer::new(self.history));
+        self.sorted_rewards.push(vec![]);
+        self.stats.add_arm(None)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::normal::StandardNormal;
+
+    use crate::seeded_rng;
+
+    #[test]
+    fn cvar_of_the_kept_rewards() {
+        let sorted: Vec<f64> = (1..11).map(|r| r as f64).collect();
+        // ceil(0.25 * 10) = 3 worst rewards
+        assert_eq!(empirical_cvar(&sorted, 0.25), 2.0);
+        assert_eq!(empirical_cvar(&sorted, 0.01), 1.0);
+        assert_eq!(empirical_cvar(&sorted, 1.0), 5.5);
+        assert_eq!(empirical_cvar(&[], 0.5), 0.0);
+
+        // the oldest reward leaves the window
+        let mut agent = CvarBandit::new(1, 0.5, 1.0, 4);
+        for &r in &[-10.0, 4.0, 1.0, 3.0, 2.0] {
+            agent.receive_reward(r, 0);
+        }
+        assert_eq!(agent.sorted_rewards[0], [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(agent.cvar(0), 1.5);
+    }
+
+    // Two arms with the same mean: the agent settles on the one whose bad
+    // rewards aren't as bad.
+    #[test]
+    fn prefers_the_safer_of_equal_means() {
+        let mut rng = seeded_rng(2);
+        let mut agent = CvarBandit::new(2, 0.1, 0.5, 200);
+        let sds = [3.0, 0.2];
+        let mut plays = [0, 0];
+        for _ in 0..2000 {
+            let action = agent.choose_action(&mut rng);
+            let StandardNormal(z) = rng.gen();
+            agent.receive_reward(sds[action] * z, action);
+            plays[action] += 1;
+        }
+        assert!(plays[1] > 1800, "{:?}", plays);
+    }
+}
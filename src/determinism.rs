@@ -0,0 +1,179 @@
+// Results determinism tests. A fixed set of seeded experiments covering
+// every testbed kind is run and a few totals of each are compared against
+// reference values recorded on x86_64 Linux. Seeded runs are meant to give
+// the same results on every machine, so a mismatch on another target
+// (aarch64, macOS, ...) points at platform-dependent float or RNG behaviour
+// somewhere in the simulation pipeline, e.g. a libm function that rounds
+// differently. They are also meant to give the same results however many
+// threads run the tasks.
+//
+// A failure prints the totals that differ with full precision, so after an
+// intended change to the results the REFERENCE table can be updated from
+// it.
+
+use rayon::ThreadPoolBuilder;
+
+use crate::environment::{ArmDist, ArmSd, GapDist, Testbed};
+use crate::explore_pool::ExplorePolicy;
+use crate::spec::AgentSpec;
+use crate::{run_experiment_with, seeded_rng, Curves, RunConfig};
+
+const ARMS: usize = 10;
+const TASKS: usize = 50;
+const PLAYS: usize = 300;
+const SEED: usize = 2024;
+
+// largest relative difference from a reference total that still passes
+const TOLERANCE: f64 = 1e-12;
+
+// Sum of avg_reward, sum of pct_optimal and the final cum_regret of each
+// scenario, in the order of `scenarios()`.
+const REFERENCE: &[(&str, [f64; 3])] = &[
+    ("gaussian", [367.4440763051388, 18946.0, 91.29406396598907]),
+    ("gaussian-arm-sd", [372.0587957721165, 18336.0, 95.00813865800137]),
+    ("bernoulli", [236.20000000000027, 10488.0, 32.3775031475399]),
+    ("random-walk", [15.141972822619021, 5666.0, 38.249877109727116]),
+    ("difficulty", [-24.73244189072962, 8820.0, 31.272874552004673]),
+    ("heavy-tailed", [328.06972681900095, 13820.0, 140.24091074347905]),
+    ("mixed", [174.69291284623478, 15896.0, 39.472000000000094]),
+    ("switching", [91.30000000000004, 9130.0, 208.7]),
+    ("correlated", [232.66324504649762, 15352.0, 66.18546399513171]),
+    ("mortal", [283.6223285509845, 13446.0, 181.86674997502666]),
+    ("forecast", [371.1342359059869, 15806.0, 93.30059804802761]),
+];
+
+fn epsilon_greedy(epsilon: f64) -> AgentSpec {
+    AgentSpec::EpsilonGreedy {
+        epsilon,
+        step_size: None,
+        tie_tolerance: 0.0,
+        min_samples: 0,
+        mom_groups: None,
+        kernel_width: None,
+        history: None,
+    }
+}
+
+fn scenarios() -> Vec<(&'static str, Testbed, AgentSpec)> {
+    vec![
+        ("gaussian", Testbed::Gaussian { sd: None }, epsilon_greedy(0.1)),
+        ("gaussian-arm-sd",
+         Testbed::Gaussian { sd: Some(ArmSd::Uniform { low: 0.5, high: 2.0 }) },
+         AgentSpec::Ucb { c: 2.0 }),
+        ("bernoulli", Testbed::Bernoulli { probs: None }, AgentSpec::Softmax { tau: 0.1 }),
+        ("random-walk", Testbed::RandomWalk { sigma: 0.01 },
+         AgentSpec::EpsilonGreedy {
+             epsilon: 0.1,
+             step_size: Some(0.1),
+             tie_tolerance: 0.0,
+             min_samples: 0,
+             mom_groups: None,
+             kernel_width: None,
+             history: None,
+         }),
+        ("difficulty",
+         Testbed::Difficulty {
+             near_optimal: 3,
+             gap: 0.5,
+             gap_dist: GapDist::Exponential,
+             noise_to_gap: 2.0,
+         },
+         AgentSpec::ExplorePool { fraction: 0.1, policy: ExplorePolicy::Uniform }),
+        ("heavy-tailed", Testbed::HeavyTailed { dof: 2.0 },
+         AgentSpec::EpsilonGreedy {
+             epsilon: 0.1,
+             step_size: None,
+             tie_tolerance: 0.0,
+             min_samples: 0,
+             mom_groups: Some(5),
+             kernel_width: None,
+             history: None,
+         }),
+        ("mixed",
+         Testbed::Mixed {
+             arms: vec![ArmDist::Constant(0.5),
+                        ArmDist::Gaussian { mean: 0.6, sd: 2.0 },
+                        ArmDist::Bernoulli { p: 0.4 },
+                        ArmDist::Exponential { mean: 0.3 },
+                        ArmDist::Uniform { low: 0.0, high: 0.8 },
+                        ArmDist::Constant(-0.5),
+                        ArmDist::Gaussian { mean: 0.0, sd: 0.5 },
+                        ArmDist::Bernoulli { p: 0.7 },
+                        ArmDist::Exponential { mean: 0.1 },
+                        ArmDist::Uniform { low: -1.0, high: 1.0 }],
+         },
+         epsilon_greedy(0.1)),
+        ("switching", Testbed::switching(ARMS, 50),
+         AgentSpec::EpsilonGreedy {
+             epsilon: 0.1,
+             step_size: Some(0.2),
+             tie_tolerance: 0.0,
+             min_samples: 0,
+             mom_groups: None,
+             kernel_width: None,
+             history: None,
+         }),
+        ("correlated", Testbed::Correlated { length_scale: 2.0 },
+         AgentSpec::EpsilonGreedy {
+             epsilon: 0.1,
+             step_size: None,
+             tie_tolerance: 0.0,
+             min_samples: 0,
+             mom_groups: None,
+             kernel_width: Some(1.0),
+             history: None,
+         }),
+        ("mortal", Testbed::Mortal { lifetime: 50.0 }, AgentSpec::Ucb { c: 1.0 }),
+        ("forecast", Testbed::Gaussian { sd: None },
+         AgentSpec::Forecast { alpha: 0.1, beta: 0.05, c: 0.5 }),
+    ]
+}
+
+fn totals(curves: &Curves) -> [f64; 3] {
+    [curves.avg_reward.iter().fold(0.0, |acc, x| acc + x),
+     curves.pct_optimal.iter().fold(0.0, |acc, x| acc + x),
+     curves.cum_regret[curves.cum_regret.len() - 1]]
+}
+
+fn matches(expected: f64, got: f64) -> bool {
+    (expected - got).abs() <= TOLERANCE * expected.abs().max(1.0)
+}
+
+fn run(testbed: &Testbed, spec: &AgentSpec) -> Curves {
+    let make_agent = || spec.build(ARMS);
+    let run = RunConfig::new(testbed, ARMS, TASKS, PLAYS);
+    run_experiment_with(&mut seeded_rng(SEED), &run, &make_agent)
+}
+
+#[test]
+fn seeded_results_match_the_reference() {
+    let mut failed = vec![];
+    for (i, (name, testbed, spec)) in scenarios().into_iter().enumerate() {
+        let got = totals(&run(&testbed, &spec));
+        let ok = match REFERENCE.get(i) {
+            Some(&(ref_name, expected)) if ref_name == name => {
+                (0..3).all(|k| matches(expected[k], got[k]))
+            }
+            _ => false,
+        };
+        if !ok {
+            failed.push(format!("    (\"{}\", [{:?}, {:?}, {:?}]),", name, got[0], got[1],
+                                got[2]));
+        }
+    }
+    assert!(failed.is_empty(), "seeded results differ from the reference:\n{}",
+            failed.join("\n"));
+}
+
+// Task i's seed depends only on the run's seed and i, and chunk results
+// are combined in task order, so the thread count can't change anything.
+#[test]
+fn seeded_results_ignore_the_thread_count() {
+    for (name, testbed, spec) in scenarios() {
+        let on_threads = |threads: usize| {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            serde_json::to_string(&pool.install(|| run(&testbed, &spec))).unwrap()
+        };
+        assert!(on_threads(1) == on_threads(4), "{} depends on the thread count", name);
+    }
+}
@@ -0,0 +1,56 @@
+// Exploration diagnostics. The entropy of the empirical action-selection
+// distribution over the last `window` plays,
+//
+//     H_t = -sum_a p_t(a) log2 p_t(a),
+//
+// where p_t(a) is the fraction of those plays that chose a, is a single
+// number for "is this agent still exploring?": it is log2(n) bits when every
+// arm is played equally often and 0 once the agent always plays the same
+// arm. Before `window` plays have happened, all plays so far are used.
+//
+// How exploration was spread over the arms shows in the number of plays of
+// each arm by the end of a task, ranked by true value, so the best arm's
+// share can be compared across agents whatever arm it happened to be.
+
+use std::cmp;
+
+// H_t for every play of a task. The sum of c * log2(c) over the window's
+// counts is kept up to date as plays enter and leave the window, so this is
+// linear in the number of plays whatever the number of arms.
+pub fn window_entropy(actions: &[usize], num_arms: usize, window: usize) -> Vec<f64> {
+    assert!(window > 0);
+    let c_log_c = |c: usize| if c == 0 { 0.0 } else { (c as f64) * (c as f64).log2() };
+
+    let mut counts = vec![0; num_arms];
+    let mut sum = 0.0;
+    let mut entropy = vec![];
+    for t in 0..actions.len() {
+        let a = actions[t];
+        sum += c_log_c(counts[a] + 1) - c_log_c(counts[a]);
+        counts[a] += 1;
+        if t >= window {
+            let old = actions[t - window];
+            sum += c_log_c(counts[old] - 1) - c_log_c(counts[old]);
+            counts[old] -= 1;
+        }
+
+        // H = log2(m) - sum(c log2 c) / m for m plays in the window; the
+        // running sum leaves rounding residue where H should be exactly 0
+        let m = cmp::min(t + 1, window) as f64;
+        let h = m.log2() - sum / m;
+        entropy.push(if h < 1e-9 { 0.0 } else { h });
+    }
+    entropy
+}
+
+// How many times each arm was played over a task, ordered by the arms' true
+// values: entry 0 is the best arm, entry 1 the second best and so on.
+pub fn pulls_by_rank(actions: &[usize], q_star: &[f64]) -> Vec<usize> {
+    let mut counts = vec![0; q_star.len()];
+    for &a in actions {
+        counts[a] += 1;
+    }
+    let mut arms: Vec<usize> = (0..q_star.len()).collect();
+    arms.sort_by(|&a, &b| q_star[b].partial_cmp(&q_star[a]).unwrap_or(cmp::Ordering::Equal));
+    arms.iter().map(|&a| counts[a]).collect()
+}
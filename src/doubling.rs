@@ -0,0 +1,214 @@
+// The doubling trick, for running agents that need to know the horizon T
+// (MOSS, explore-then-commit) when the number of plays isn't known in
+// advance. Play is split into epochs of T_0, 2 T_0, 4 T_0, ... rewards, and
+// every epoch is played by a fresh agent built for that epoch's length. The
+// new agent either starts from nothing (`reset`), as in the textbook
+// version, or is shown the rewards so far before it plays, so only its
+// horizon changes and what was learned carries over. Only the last
+// `history` plays are kept for that (see ring), so past that many plays the
+// new agent learns from the most recent ones.
+
+use rand::Rng;
+
+use crate::ring::RingBuffer;
+use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+use crate::Agent;
+
+// Everything but the agent builder, which can't be saved.
+#[derive(Serialize, Deserialize)]
+struct DoublingState {
+    agent: serde_json::Value,
+    reset: bool,
+    n: usize,
+    added: Vec<Option<ArmPrior>>,
+    retired: Vec<bool>,
+    history: RingBuffer<(usize, f64)>,
+    epoch: usize,
+    left: usize,
+}
+
+pub struct DoublingTrick {
+    // builds an agent for n arms and horizon T
+    make: Box<dyn Fn(usize, usize) -> Box<dyn Agent>>,
+    agent: Box<dyn Agent>,
+    reset: bool,
+
+    // arms the agent was built with, and the priors of arms added since
+    n: usize,
+    added: Vec<Option<ArmPrior>>,
+    retired: Vec<bool>,
+
+    // (action, reward) of the most recent plays, unless resetting
+    history: RingBuffer<(usize, f64)>,
+
+    // length of the current epoch and plays left in it
+    epoch: usize,
+    left: usize,
+}
+
+impl DoublingTrick {
+    pub fn new(n: usize, first_epoch: usize, reset: bool, history: usize,
+               make: Box<dyn Fn(usize, usize) -> Box<dyn Agent>>) -> DoublingTrick {
+        assert!(n > 0);
+        assert!(first_epoch > 0);
+        DoublingTrick {
+            agent: make(n, first_epoch),
+            make,
+            reset,
+            n,
+            added: vec![],
+            retired: vec![false; n],
+            history: RingBuffer::new(history),
+            epoch: first_epoch,
+            left: first_epoch,
+        }
+    }
+
+    // Rebuilds a wrapper from its state(), restoring the agent of the
+    // current epoch with `restore`.
+    pub fn from_state(make: Box<dyn Fn(usize, usize) -> Box<dyn Agent>>, state: serde_json::Value,
+                      restore: &dyn Fn(serde_json::Value) -> Result<Box<dyn Agent>, String>)
+                      -> Result<DoublingTrick, String> {
+        let state: DoublingState = serde_json::from_value(state).map_err(|e| e.to_string())?;
+        Ok(DoublingTrick {
+            make,
+            agent: restore(state.agent)?,
+            reset: state.reset,
+            n: state.n,
+            added: state.added,
+            retired: state.retired,
+            history: state.history,
+            epoch: state.epoch,
+            left: state.left,
+        })
+    }
+
+    fn next_epoch(&mut self) {
+        self.epoch *= 2;
+        self.left = self.epoch;
+        let mut agent = (self.make)(self.n, self.epoch);
+        for &prior in &self.added {
+            agent.add_arm(prior);
+        }
+        if !self.reset {
+            agent.receive_rewards(&self.history.to_vec());
+        }
+        for (arm, &retired) in self.retired.iter().enumerate() {
+            if retired {
+                agent.set_retired(arm, true);
+            }
+        }
+        self.agent = agent;
+    }
+}
+
+impl Agent for DoublingTrick {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        self.agent.choose_action(rng)
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.agent.receive_reward(reward, action);
+        if !self.reset {
+            self.history.push((action, reward));
+        }
+        self.left -= 1;
+        if self.left == 0 {
+            self.next_epoch();
+        }
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.agent.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.added.push(prior);
+        self.retired.push(false);
+        self.agent.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+        self.agent.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.agent.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(DoublingState {
+            agent: self.agent.state(),
+            reset: self.reset,
+            n: self.n,
+            added: self.added.clone(),
+            retired: self.retired.clone(),
+            history: self.history.clone(),
+            epoch: self.epoch,
+            left: self.left,
+        }).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::moss::MossBandit;
+    use crate::seeded_rng;
+
+    // A doubling trick over MOSS that records the horizon of every agent it
+    // builds.
+    fn doubling(reset: bool) -> (DoublingTrick, Rc<RefCell<Vec<usize>>>) {
+        let horizons = Rc::new(RefCell::new(vec![]));
+        let built = horizons.clone();
+        let make = Box::new(move |n, horizon| {
+            built.borrow_mut().push(horizon);
+            Box::new(MossBandit::new(n, horizon)) as Box<dyn Agent>
+        });
+        (DoublingTrick::new(2, 2, reset, 100, make), horizons)
+    }
+
+    fn counts(agent: &dyn Agent) -> Vec<usize> {
+        agent.arm_summaries().iter().map(|arm| arm.count).collect()
+    }
+
+    #[test]
+    fn epochs_double_and_carry_what_was_learned() {
+        for &reset in &[false, true] {
+            let (mut agent, horizons) = doubling(reset);
+            let mut rng = seeded_rng(1);
+            agent.add_arm(None);
+            agent.set_retired(0, true);
+            for _ in 0..13 {
+                let action = agent.choose_action(&mut rng);
+                assert!(action != 0);
+                agent.receive_reward(action as f64, action);
+            }
+            // epochs of 2, 4 and 8 plays, and 1 of the next 16
+            assert_eq!(*horizons.borrow(), [2, 4, 8]);
+            agent.receive_reward(1.0, 1);
+            assert_eq!(*horizons.borrow(), [2, 4, 8, 16]);
+            let counts = counts(&agent);
+            assert_eq!(counts.len(), 3);
+            let total: usize = counts.iter().sum();
+            assert_eq!(total, if reset { 0 } else { 14 });
+        }
+    }
+
+    // Past `history` plays, a new epoch's agent learns from the latest ones.
+    #[test]
+    fn history_bounds_what_a_new_epoch_is_shown() {
+        let make = Box::new(|n, horizon| Box::new(MossBandit::new(n, horizon)) as Box<dyn Agent>);
+        let mut agent = DoublingTrick::new(2, 4, false, 3, make);
+        for (action, reward) in [(0, 5.0), (0, 5.0), (1, 1.0), (1, 2.0)] {
+            agent.receive_reward(reward, action);
+        }
+        let arms = agent.arm_summaries();
+        assert_eq!((arms[0].count, arms[0].mean), (1, 5.0));
+        assert_eq!((arms[1].count, arms[1].mean), (2, 1.5));
+    }
+}
@@ -0,0 +1,333 @@
+// Drift injection for robustness testing. DriftInjector wraps any
+// environment and applies scheduled perturbations to it:
+//
+//     shift@200:arm=3:delta=-2       from play 200 on, arm 3's mean moves by
+//                                    -2 (without `arm`, every arm moves)
+//     spike@500:duration=100:factor=4
+//                                    for 100 plays, rewards deviate from the
+//                                    mean 4 times as much as usual
+//     outage@800:duration=50         for 50 plays, no rewards are produced
+//
+// The `drift` mode runs an agent on the Gaussian testbed with a schedule of
+// such events (comma-separated) and writes the average reward at each play,
+// and the % of tasks playing an optimal arm there, with a marker column
+// naming the events that start or end there, so the curves can be plotted
+// with the perturbations lined up.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{self, Environment, GaussianTestbed};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Perturbation {
+    // permanent change to one arm's mean, or every arm's if `arm` is None
+    MeanShift { arm: Option<usize>, delta: f64 },
+
+    // rewards deviate from their mean `factor` times as much as usual
+    VarianceSpike { factor: f64, duration: usize },
+
+    // rewards aren't produced at all
+    Outage { duration: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DriftEvent {
+    pub at: usize,
+    pub perturbation: Perturbation,
+}
+
+impl DriftEvent {
+    fn start_marker(&self) -> String {
+        match self.perturbation {
+            Perturbation::MeanShift { arm: Some(arm), delta } => {
+                format!("shift(arm={} delta={})", arm, delta)
+            }
+            Perturbation::MeanShift { arm: None, delta } => format!("shift(delta={})", delta),
+            Perturbation::VarianceSpike { factor, .. } => format!("spike(factor={})", factor),
+            Perturbation::Outage { .. } => "outage".to_string(),
+        }
+    }
+
+    // The first play after the event is over, for events with a duration.
+    fn end(&self) -> Option<usize> {
+        match self.perturbation {
+            Perturbation::MeanShift { .. } => None,
+            Perturbation::VarianceSpike { duration, .. } |
+            Perturbation::Outage { duration } => Some(self.at + duration),
+        }
+    }
+
+    fn end_marker(&self) -> String {
+        match self.perturbation {
+            Perturbation::VarianceSpike { .. } => "spike-end".to_string(),
+            _ => "outage-end".to_string(),
+        }
+    }
+
+    fn active(&self, t: usize) -> bool {
+        t >= self.at && self.end().is_none_or(|end| t < end)
+    }
+}
+
+// Parses a comma-separated schedule like "shift@200:delta=-1,outage@500:duration=20".
+pub fn parse_schedule(value: &str, num_arms: usize) -> Result<Vec<DriftEvent>, String> {
+    let mut events = vec![];
+    for part in value.split(',') {
+        let mut fields = part.trim().split(':');
+        let head = fields.next().unwrap();
+        let at_sign = head.find('@').ok_or(format!("drift event `{}` has no @play", part))?;
+        let kind = &head[..at_sign];
+        let at: usize = parse_value("drift", &head[at_sign + 1..])?;
+
+        let mut params = HashMap::new();
+        for field in fields {
+            let eq = field.find('=').ok_or(format!("invalid drift parameter `{}`", field))?;
+            params.insert(&field[..eq], &field[eq + 1..]);
+        }
+        let required = |name: &str| {
+            params.get(name).copied()
+                  .ok_or(format!("drift event `{}` needs `{}`", part, name))
+        };
+        let allowed: &[&str] = match kind {
+            "shift" => &["arm", "delta"],
+            "spike" => &["duration", "factor"],
+            "outage" => &["duration"],
+            _ => return Err(format!("unknown drift event `{}`", kind)),
+        };
+        for name in params.keys() {
+            if !allowed.contains(name) {
+                return Err(format!("`{}` doesn't apply to drift event `{}`", name, kind));
+            }
+        }
+
+        let perturbation = match kind {
+            "shift" => {
+                let arm = match params.get("arm") {
+                    Some(arm) => {
+                        let arm: usize = parse_value("drift", arm)?;
+                        if arm >= num_arms {
+                            return Err(format!("drift event `{}`: no arm {}", part, arm));
+                        }
+                        Some(arm)
+                    }
+                    None => None,
+                };
+                Perturbation::MeanShift {
+                    arm,
+                    delta: parse_value("drift", required("delta")?)?,
+                }
+            }
+            "spike" => {
+                let factor: f64 = parse_value("drift", required("factor")?)?;
+                if factor.is_nan() || factor < 0.0 {
+                    return Err(format!("drift event `{}`: factor must be non-negative", part));
+                }
+                Perturbation::VarianceSpike {
+                    factor,
+                    duration: parse_value("drift", required("duration")?)?,
+                }
+            }
+            _ => Perturbation::Outage { duration: parse_value("drift", required("duration")?)? },
+        };
+        events.push(DriftEvent { at, perturbation });
+    }
+    Ok(events)
+}
+
+pub struct DriftInjector {
+    inner: Box<dyn Environment>,
+    events: Vec<DriftEvent>,
+
+    // current play
+    t: usize,
+}
+
+impl DriftInjector {
+    pub fn new(inner: Box<dyn Environment>, events: Vec<DriftEvent>) -> DriftInjector {
+        DriftInjector {
+            inner,
+            events,
+            t: 0,
+        }
+    }
+
+    // total mean shift of each arm that applies at the current play
+    fn shifts(&self) -> Vec<f64> {
+        let mut shifts = vec![0.0; self.inner.num_arms()];
+        for event in &self.events {
+            if let Perturbation::MeanShift { arm, delta } = event.perturbation {
+                if event.active(self.t) {
+                    match arm {
+                        Some(arm) => shifts[arm] += delta,
+                        None => for s in shifts.iter_mut() { *s += delta },
+                    }
+                }
+            }
+        }
+        shifts
+    }
+}
+
+impl Environment for DriftInjector {
+    fn num_arms(&self) -> usize {
+        self.inner.num_arms()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        let mut means = self.inner.means();
+        let shifts = self.shifts();
+        for i in 0..means.len() {
+            means[i] += shifts[i];
+        }
+        means
+    }
+
+    fn step(&mut self, action: usize, rng: &mut dyn Rng) -> Option<f64> {
+        let mut factor = 1.0;
+        let mut outage = false;
+        for event in &self.events {
+            if event.active(self.t) {
+                match event.perturbation {
+                    Perturbation::VarianceSpike { factor: f, .. } => factor *= f,
+                    Perturbation::Outage { .. } => outage = true,
+                    Perturbation::MeanShift { .. } => {}
+                }
+            }
+        }
+
+        let inner_mean = self.inner.means()[action];
+        let shift = self.shifts()[action];
+        let reward = self.inner.step(action, rng);
+        self.t += 1;
+        if outage {
+            return None;
+        }
+        reward.map(|r| inner_mean + shift + factor * (r - inner_mean))
+    }
+}
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+    pub events: Vec<DriftEvent>,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+            events: vec![],
+            output: "drift.csv".to_string(),
+        };
+        let mut schedule = None;
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "output" => opts.output = value.clone(),
+                "drift" => schedule = Some(value.clone()),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 {
+            return Err("arms and tasks must be positive".to_string());
+        }
+        match schedule {
+            Some(schedule) => opts.events = parse_schedule(&schedule, opts.n)?,
+            None => return Err("`--drift` schedule is required".to_string()),
+        }
+        Ok(opts)
+    }
+}
+
+// Averages over all tasks of the reward and of the % of tasks playing an
+// optimal arm at each play.
+fn run_drift<R: Rng>(rng: &mut R, opts: &Options) -> (Vec<f64>, Vec<f64>) {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunk_sums = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = vec![0.0; opts.num_plays];
+        let mut optimal = vec![0.0; opts.num_plays];
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let q_star = BanditTask::new(opts.n).draw_q_star(&mut rng);
+            let mut env = DriftInjector::new(Box::new(GaussianTestbed::new(q_star)),
+                                             opts.events.clone());
+            let mut agent = opts.agent.build(opts.n);
+            let outcome = environment::play(&mut env, &mut *agent, opts.num_plays, &mut rng);
+            for t in 0..opts.num_plays {
+                sums[t] += outcome.rewards[t];
+                if outcome.optimal[t] {
+                    optimal[t] += 100.0;
+                }
+            }
+            progress::task_done(i);
+        }
+        (sums, optimal)
+    });
+
+    let mut avg_rewards = vec![0.0; opts.num_plays];
+    let mut pct_optimal = vec![0.0; opts.num_plays];
+    for (sums, optimal) in chunk_sums {
+        for t in 0..opts.num_plays {
+            avg_rewards[t] += sums[t];
+            pct_optimal[t] += optimal[t];
+        }
+    }
+    for t in 0..opts.num_plays {
+        avg_rewards[t] /= opts.num_tasks as f64;
+        pct_optimal[t] /= opts.num_tasks as f64;
+    }
+    (avg_rewards, pct_optimal)
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let (avg_rewards, pct_optimal) = match opts.seed {
+        Some(seed) => run_drift(&mut seeded_rng(seed), opts),
+        None => run_drift(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&avg_rewards, &pct_optimal, &opts.events, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))
+}
+
+fn write_csv(avg_rewards: &[f64], pct_optimal: &[f64], events: &Vec<DriftEvent>,
+             file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,avg_reward,pct_optimal,marker")?;
+    for t in 0..avg_rewards.len() {
+        let mut markers = vec![];
+        for event in events {
+            if event.at == t {
+                markers.push(event.start_marker());
+            }
+            if event.end() == Some(t) {
+                markers.push(event.end_marker());
+            }
+        }
+        writeln!(f, "{},{},{},{}", t, avg_rewards[t], pct_optimal[t], markers.join(";"))?;
+    }
+    f.finish()
+}
@@ -0,0 +1,288 @@
+// Dueling bandits. In the `dueling` mode the agent picks a pair of arms
+// every round and only learns which of the two won, not any reward. Arm i
+// beats arm j with probability
+//
+//     P(i, j) = 1 / (1 + exp(-(Q^*(i) - Q^*(j))))
+//
+// for true values Q^*(a) drawn from a standard normal as in the usual
+// testbed, so the arm with the highest true value beats every other arm
+// more often than not (it is the Condorcet winner). A duel of arms c and d
+// costs the average of P(w, c) - 1/2 and P(w, d) - 1/2 for the Condorcet
+// winner w, which is 0 only when both are w. Averages over the tasks are
+// written for every round:
+//
+//     step,avg_regret,cum_regret,pct_winner
+//
+// where pct_winner is the percentage of tasks in which both arms of the duel
+// were the Condorcet winner.
+//
+// The agent is Relative UCB (Zoghi et al., 2014): it keeps the wins W(i, j)
+// of every arm over every other, and upper confidence bounds
+//
+//     U(i, j) = W(i, j) / N(i, j) + sqrt(alpha ln t / N(i, j))
+//
+// on P(i, j), where N(i, j) = W(i, j) + W(j, i) (U is 1 if they haven't met,
+// and U(i, i) = 1/2). A candidate c is drawn from the arms whose bounds are
+// at least 1/2 against every arm (or from all arms if there are none), and
+// it duels the arm d most likely to beat it, the one maximizing U(d, c).
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::output::OutputFile;
+use crate::progress;
+use crate::{best_arm, map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_rounds: usize,
+    pub seed: Option<usize>,
+    pub alpha: f64,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 500,
+            num_rounds: 1000,
+            seed: None,
+            alpha: 0.51,
+            output: "dueling.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_rounds = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "alpha" => opts.alpha = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n < 2 || opts.num_tasks == 0 || opts.num_rounds == 0 {
+            return Err("there must be at least 2 arms, and tasks and plays must be positive"
+                       .to_string());
+        }
+        if opts.alpha.is_nan() || opts.alpha <= 0.5 {
+            return Err("alpha must be greater than 1/2".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Which of two arms wins a duel, from the true values.
+pub struct PreferenceMatrix {
+    p: Vec<Vec<f64>>,
+    winner: usize,
+}
+
+impl PreferenceMatrix {
+    pub fn new(q_star: &[f64]) -> PreferenceMatrix {
+        let p = q_star.iter().map(|&qi| {
+            q_star.iter().map(|&qj| 1.0 / (1.0 + (qj - qi).exp())).collect()
+        }).collect();
+        PreferenceMatrix { p, winner: best_arm(q_star) }
+    }
+
+    // Whether arm i beats arm j.
+    pub fn duel<R: Rng>(&self, i: usize, j: usize, rng: &mut R) -> bool {
+        rng.next_f64() < self.p[i][j]
+    }
+
+    pub fn regret(&self, c: usize, d: usize) -> f64 {
+        (self.p[self.winner][c] + self.p[self.winner][d] - 1.0) / 2.0
+    }
+}
+
+pub struct Rucb {
+    // wins[i][j] is how often i has beaten j
+    wins: Vec<Vec<f64>>,
+    alpha: f64,
+    t: usize,
+}
+
+impl Rucb {
+    pub fn new(n: usize, alpha: f64) -> Rucb {
+        Rucb { wins: vec![vec![0.0; n]; n], alpha, t: 0 }
+    }
+
+    fn upper_bound(&self, i: usize, j: usize) -> f64 {
+        if i == j {
+            return 0.5;
+        }
+        let duels = self.wins[i][j] + self.wins[j][i];
+        if duels == 0.0 {
+            return 1.0;
+        }
+        let t = (self.t as f64).max(1.0);
+        self.wins[i][j] / duels + (self.alpha * t.ln() / duels).sqrt()
+    }
+
+    pub fn choose_pair<R: Rng>(&mut self, rng: &mut R) -> (usize, usize) {
+        self.t += 1;
+        let n = self.wins.len();
+        let mut candidates: Vec<usize> = (0..n).filter(|&c| {
+            (0..n).all(|j| self.upper_bound(c, j) >= 0.5)
+        }).collect();
+        if candidates.is_empty() {
+            candidates = (0..n).collect();
+        }
+        let c = candidates[rng.gen_range(0, candidates.len())];
+
+        let mut best = vec![c];
+        let mut best_value = self.upper_bound(c, c);
+        for d in 0..n {
+            let value = self.upper_bound(d, c);
+            if value > best_value {
+                best = vec![d];
+                best_value = value;
+            } else if value == best_value && d != c {
+                best.push(d);
+            }
+        }
+        (c, best[rng.gen_range(0, best.len())])
+    }
+
+    pub fn record(&mut self, winner: usize, loser: usize) {
+        if winner != loser {
+            self.wins[winner][loser] += 1.0;
+        }
+    }
+}
+
+// Sums over tasks of every round's metrics.
+struct DuelSums {
+    regret: Vec<f64>,
+    cum_regret: Vec<f64>,
+    winner: Vec<f64>,
+}
+
+impl DuelSums {
+    fn new(num_rounds: usize) -> DuelSums {
+        DuelSums {
+            regret: vec![0.0; num_rounds],
+            cum_regret: vec![0.0; num_rounds],
+            winner: vec![0.0; num_rounds],
+        }
+    }
+
+    fn add(&mut self, other: &DuelSums) {
+        for t in 0..self.regret.len() {
+            self.regret[t] += other.regret[t];
+            self.cum_regret[t] += other.cum_regret[t];
+            self.winner[t] += other.winner[t];
+        }
+    }
+}
+
+fn run_duels<R: Rng>(rng: &mut R, opts: &Options) -> DuelSums {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = DuelSums::new(opts.num_rounds);
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let prefs = PreferenceMatrix::new(&BanditTask::new(opts.n).draw_q_star(&mut rng));
+            let mut agent = Rucb::new(opts.n, opts.alpha);
+            let mut total = 0.0;
+            for t in 0..opts.num_rounds {
+                let (c, d) = agent.choose_pair(&mut rng);
+                if prefs.duel(c, d, &mut rng) {
+                    agent.record(c, d);
+                } else {
+                    agent.record(d, c);
+                }
+                let regret = prefs.regret(c, d);
+                total += regret;
+                sums.regret[t] += regret;
+                sums.cum_regret[t] += total;
+                if c == prefs.winner && d == prefs.winner {
+                    sums.winner[t] += 100.0;
+                }
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut sums = DuelSums::new(opts.num_rounds);
+    for chunk in &chunks {
+        sums.add(chunk);
+    }
+    sums
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let sums = match opts.seed {
+        Some(seed) => run_duels(&mut seeded_rng(seed), opts),
+        None => run_duels(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let last = opts.num_rounds - 1;
+    println!("rucb(alpha={}), {} arms: {} tasks x {} rounds",
+             opts.alpha, opts.n, opts.num_tasks, opts.num_rounds);
+    println!("final round: regret {:.4}, Condorcet winner dueling itself in {:.1}% of tasks",
+             sums.regret[last] / tasks, sums.winner[last] / tasks);
+    println!("total regret {:.4}", sums.cum_regret[last] / tasks);
+    Ok(())
+}
+
+fn write_csv(sums: &DuelSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let tasks = num_tasks as f64;
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,avg_regret,cum_regret,pct_winner")?;
+    for t in 0..sums.regret.len() {
+        writeln!(f, "{},{},{},{}", t, sums.regret[t] / tasks, sums.cum_regret[t] / tasks,
+                 sums.winner[t] / tasks)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences_favour_the_condorcet_winner() {
+        let prefs = PreferenceMatrix::new(&[0.3, 1.2, -0.5]);
+        assert_eq!(prefs.winner, 1);
+        for i in 0..3 {
+            assert_eq!(prefs.p[i][i], 0.5);
+            assert!(prefs.p[1][i] >= 0.5);
+            for j in 0..3 {
+                assert!((prefs.p[i][j] + prefs.p[j][i] - 1.0).abs() < 1e-12);
+            }
+        }
+        assert_eq!(prefs.regret(1, 1), 0.0);
+        assert!(prefs.regret(0, 2) > prefs.regret(0, 1));
+    }
+
+    // RUCB learns to duel the Condorcet winner against itself, so its
+    // regret per round falls off.
+    #[test]
+    fn rucb_settles_on_the_condorcet_winner() {
+        let opts = Options {
+            n: 5,
+            num_tasks: 20,
+            num_rounds: 2000,
+            seed: None,
+            alpha: 0.51,
+            output: String::new(),
+        };
+        let sums = run_duels(&mut seeded_rng(3), &opts);
+        let last = opts.num_rounds - 1;
+        assert!(sums.regret[last] < sums.regret[0] / 4.0);
+        assert!(sums.winner[last] / opts.num_tasks as f64 >= 50.0);
+    }
+}
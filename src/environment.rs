@@ -0,0 +1,992 @@
+// Environments an agent can play against. BanditTask is the fixed Gaussian
+// testbed; the Environment trait lets runners and wrappers work with any
+// reward process, including ones whose arm means change over time.
+//
+// A Testbed names the kind of task a run draws:
+//
+//     gaussian     Q^*(a) from a standard normal, rewards normal around it
+//                  with variance 1 (the original testbed), or with standard
+//                  deviations given per arm or drawn per task (see ArmSd)
+//     bernoulli    arm a pays 1 with probability p_a and 0 otherwise, with
+//                  the p_a drawn uniformly from [0, 1] for every task, or
+//                  fixed if given
+//     random-walk  every Q^*(a) starts at 0 and takes an independent normal
+//                  step with standard deviation sigma after every play;
+//                  rewards are normal around the current Q^*(a) with
+//                  variance 1
+//     difficulty   built from how hard the task should be rather than from
+//                  distribution parameters: the best arm, near_optimal - 1
+//                  arms within a tenth of `gap` of it, and the rest `gap`
+//                  below it on average (exactly, or spread per gap_dist);
+//                  reward noise has standard deviation noise_to_gap * gap.
+//                  The arms are shuffled, so the best one's index is random.
+//     heavy-tailed Q^*(a) as for gaussian, but the reward noise is Student-t
+//                  with `dof` degrees of freedom, whose moments of order dof
+//                  and above are infinite, so occasional huge rewards throw
+//                  off sample averages
+//     mixed        every arm has its own reward distribution (see ArmDist),
+//                  fixed for every task, e.g. one deterministic arm against
+//                  nine noisy ones
+//     scripted     every arm's reward at every play is given in advance, by
+//                  the user or a generator (see Testbed::switching), and
+//                  is the same for every task; the best arm is the one with
+//                  the highest reward at that play
+//     replay       logged (arm, reward) events from a previous run or another
+//                  system, one per play: a play is rewarded with the logged
+//                  reward if the agent picks the logged arm and gets no
+//                  feedback otherwise (see LoggedReplay)
+//     correlated   Q^*(a) jointly normal, each with variance 1, with arms d
+//                  indices apart correlated exp(-d^2 / (2 length_scale^2)),
+//                  so neighbouring arms are alike; rewards normal around it
+//                  with variance 1
+//     mortal       n arms alive at a time, with Q^*(a) as for gaussian; every
+//                  play each arm dies with probability 1 / lifetime and is
+//                  replaced by a new arm with a fresh Q^*(a)
+//     saved        the tasks of another testbed, read back from a file with
+//                  every task's true values (see saved_testbed); task i of
+//                  the run is task i of the file
+//
+// Any of them can be wrapped in Testbed::Delayed, which holds every reward
+// back from the agent for some number of plays, and in Testbed::Sleeping,
+// which only lets the agent play a random subset of the arms each play.
+
+use std::cmp;
+
+use rand::Rng;
+use rand::distributions::normal::{Normal, StandardNormal};
+use rand::distributions::{IndependentSample, Range, StudentT};
+
+use crate::saved_testbed::{SavedRewards, SavedTasks};
+use crate::{Agent, BanditTask, TaskOutcome};
+
+// The number of arms can grow during a task; new arms are added to the
+// agent before its next choice.
+pub trait Environment {
+    fn num_arms(&self) -> usize;
+
+    // Expected reward of every arm at the current play.
+    fn means(&self) -> Vec<f64>;
+
+    // Plays `action` and moves on to the next play. Returns the reward, or
+    // None if no reward was produced (the agent gets no feedback).
+    fn step(&mut self, action: usize, rng: &mut dyn Rng) -> Option<f64>;
+
+    // Whether step's reward is only what the play earned, with the agent
+    // told about it later, through late_rewards, instead of straight away.
+    fn delays_feedback(&self) -> bool {
+        false
+    }
+
+    // Rewards of earlier plays that reach the agent after the current one,
+    // as (action, reward) pairs, oldest first.
+    fn late_rewards(&mut self) -> Vec<(usize, f64)> {
+        vec![]
+    }
+
+    // Which arms can be played at the current play, or None if all of them
+    // can. At least one always can.
+    fn available(&self) -> Option<Vec<bool>> {
+        None
+    }
+}
+
+// The testbed's reward process for given true values: playing arm a gives
+// a reward drawn from a normal with mean Q^*(a) and variance 1 (or standard
+// deviation `sd`, or `sds[a]`).
+pub struct GaussianTestbed {
+    q_star: Vec<f64>,
+    sds: Vec<f64>,
+}
+
+impl GaussianTestbed {
+    pub fn new(q_star: Vec<f64>) -> GaussianTestbed {
+        GaussianTestbed::with_sd(q_star, 1.0)
+    }
+
+    pub fn with_sd(q_star: Vec<f64>, sd: f64) -> GaussianTestbed {
+        let sds = vec![sd; q_star.len()];
+        GaussianTestbed::with_sds(q_star, sds)
+    }
+
+    pub fn with_sds(q_star: Vec<f64>, sds: Vec<f64>) -> GaussianTestbed {
+        assert!(sds.len() == q_star.len() && sds.iter().all(|&sd| sd > 0.0));
+        GaussianTestbed { q_star, sds }
+    }
+}
+
+impl Environment for GaussianTestbed {
+    fn num_arms(&self) -> usize {
+        self.q_star.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.q_star.clone()
+    }
+
+    fn step(&mut self, action: usize, mut rng: &mut dyn Rng) -> Option<f64> {
+        let normal = Normal::new(self.q_star[action], self.sds[action]);
+        Some(normal.ind_sample(&mut rng))
+    }
+}
+
+// Rewards of 0 or 1: arm a pays 1 with probability p[a].
+pub struct BernoulliTestbed {
+    p: Vec<f64>,
+}
+
+impl BernoulliTestbed {
+    pub fn new(p: Vec<f64>) -> BernoulliTestbed {
+        assert!(p.iter().all(|&p| (0.0..=1.0).contains(&p)));
+        BernoulliTestbed { p }
+    }
+}
+
+impl Environment for BernoulliTestbed {
+    fn num_arms(&self) -> usize {
+        self.p.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.p.clone()
+    }
+
+    fn step(&mut self, action: usize, rng: &mut dyn Rng) -> Option<f64> {
+        Some(if rng.next_f64() < self.p[action] { 1.0 } else { 0.0 })
+    }
+}
+
+// Fixed true values with Student-t reward noise.
+pub struct HeavyTailedTestbed {
+    q_star: Vec<f64>,
+    noise: StudentT,
+}
+
+impl HeavyTailedTestbed {
+    pub fn new(q_star: Vec<f64>, dof: f64) -> HeavyTailedTestbed {
+        HeavyTailedTestbed { q_star, noise: StudentT::new(dof) }
+    }
+}
+
+impl Environment for HeavyTailedTestbed {
+    fn num_arms(&self) -> usize {
+        self.q_star.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.q_star.clone()
+    }
+
+    fn step(&mut self, action: usize, mut rng: &mut dyn Rng) -> Option<f64> {
+        Some(self.q_star[action] + self.noise.ind_sample(&mut rng))
+    }
+}
+
+// The reward distribution of one arm.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum ArmDist {
+    Gaussian { mean: f64, sd: f64 },
+    Bernoulli { p: f64 },
+    Exponential { mean: f64 },
+    Uniform { low: f64, high: f64 },
+    Constant(f64),
+}
+
+impl ArmDist {
+    pub fn mean(&self) -> f64 {
+        match *self {
+            ArmDist::Gaussian { mean, .. } => mean,
+            ArmDist::Bernoulli { p } => p,
+            ArmDist::Exponential { mean } => mean,
+            ArmDist::Uniform { low, high } => (low + high) / 2.0,
+            ArmDist::Constant(value) => value,
+        }
+    }
+
+    fn sample(&self, mut rng: &mut dyn Rng) -> f64 {
+        match *self {
+            ArmDist::Gaussian { mean, sd } => Normal::new(mean, sd).ind_sample(&mut rng),
+            ArmDist::Bernoulli { p } => if rng.next_f64() < p { 1.0 } else { 0.0 },
+            ArmDist::Exponential { mean } => -mean * (1.0 - rng.next_f64()).ln(),
+            ArmDist::Uniform { low, high } => low + (high - low) * rng.next_f64(),
+            ArmDist::Constant(value) => value,
+        }
+    }
+}
+
+// Arms with independently chosen reward distributions.
+pub struct MixedTestbed {
+    arms: Vec<ArmDist>,
+}
+
+impl MixedTestbed {
+    pub fn new(arms: Vec<ArmDist>) -> MixedTestbed {
+        MixedTestbed { arms }
+    }
+}
+
+impl Environment for MixedTestbed {
+    fn num_arms(&self) -> usize {
+        self.arms.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.arms.iter().map(|arm| arm.mean()).collect()
+    }
+
+    fn step(&mut self, action: usize, rng: &mut dyn Rng) -> Option<f64> {
+        Some(self.arms[action].sample(rng))
+    }
+}
+
+// The nonstationary testbed: the true values wander off independently.
+pub struct RandomWalkTestbed {
+    q: Vec<f64>,
+    walk: Normal,
+}
+
+impl RandomWalkTestbed {
+    pub fn new(n: usize, sigma: f64) -> RandomWalkTestbed {
+        assert!(sigma >= 0.0);
+        RandomWalkTestbed { q: vec![0.0; n], walk: Normal::new(0.0, sigma) }
+    }
+}
+
+impl Environment for RandomWalkTestbed {
+    fn num_arms(&self) -> usize {
+        self.q.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.q.clone()
+    }
+
+    fn step(&mut self, action: usize, mut rng: &mut dyn Rng) -> Option<f64> {
+        let reward = Normal::new(self.q[action], 1.0).ind_sample(&mut rng);
+        for q in self.q.iter_mut() {
+            *q += self.walk.ind_sample(&mut rng);
+        }
+        Some(reward)
+    }
+}
+
+// How the gaps of the arms that aren't near-optimal are spread around `gap`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum GapDist {
+    // exactly gap
+    Fixed,
+
+    // uniform on [gap / 2, 3 gap / 2]
+    Uniform,
+
+    // gap / 2 plus an exponential with mean gap / 2
+    Exponential,
+}
+
+impl GapDist {
+    pub fn from_name(name: &str) -> Option<GapDist> {
+        match name {
+            "fixed" => Some(GapDist::Fixed),
+            "uniform" => Some(GapDist::Uniform),
+            "exponential" => Some(GapDist::Exponential),
+            _ => None,
+        }
+    }
+
+    fn sample<R: Rng>(&self, gap: f64, rng: &mut R) -> f64 {
+        match *self {
+            GapDist::Fixed => gap,
+            GapDist::Uniform => gap * (0.5 + rng.next_f64()),
+            GapDist::Exponential => gap * 0.5 * (1.0 - (1.0 - rng.next_f64()).ln()),
+        }
+    }
+}
+
+// The reward noise standard deviation of each arm of a Gaussian testbed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum ArmSd {
+    // the same for every arm
+    Fixed(f64),
+
+    // one per arm
+    PerArm(Vec<f64>),
+
+    // drawn uniformly from [low, high] for every arm of every task
+    Uniform { low: f64, high: f64 },
+}
+
+impl ArmSd {
+    pub fn draw<R: Rng>(&self, n: usize, rng: &mut R) -> Vec<f64> {
+        match *self {
+            ArmSd::Fixed(sd) => vec![sd; n],
+            ArmSd::PerArm(ref sds) => sds.clone(),
+            ArmSd::Uniform { low, high } => {
+                (0..n).map(|_| low + (high - low) * rng.next_f64()).collect()
+            }
+        }
+    }
+}
+
+// True values for the correlated testbed: L z for standard normal z, where
+// L L^T is the covariance (the Cholesky factor).
+fn correlated_q_star<R: Rng>(n: usize, length_scale: f64, rng: &mut R) -> Vec<f64> {
+    // a little jitter on the diagonal keeps the factorization stable when
+    // neighbouring arms are almost perfectly correlated
+    let cov = |i: usize, j: usize| {
+        let d = i as f64 - j as f64;
+        (-d * d / (2.0 * length_scale * length_scale)).exp() + if i == j { 1e-9 } else { 0.0 }
+    };
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..i + 1 {
+            let dot = (0..j).fold(0.0, |acc, k| acc + l[i][k] * l[j][k]);
+            if i == j {
+                l[i][i] = (cov(i, i) - dot).max(0.0).sqrt();
+            } else {
+                l[i][j] = if l[j][j] > 0.0 { (cov(i, j) - dot) / l[j][j] } else { 0.0 };
+            }
+        }
+    }
+    let z = BanditTask::new(n).draw_q_star(rng);
+    (0..n).map(|i| (0..i + 1).fold(0.0, |acc, k| acc + l[i][k] * z[k])).collect()
+}
+
+// Arms within this fraction of the gap of the best arm are near-optimal.
+const NEAR_OPTIMAL_FRACTION: f64 = 0.1;
+
+// True values for the difficulty testbed, with the best arm at 0.
+fn difficulty_q_star<R: Rng>(n: usize, near_optimal: usize, gap: f64, gap_dist: GapDist,
+                             rng: &mut R) -> Vec<f64> {
+    let mut q_star = vec![0.0];
+    for i in 1..n {
+        if i < near_optimal {
+            q_star.push(-gap * NEAR_OPTIMAL_FRACTION * rng.next_f64());
+        } else {
+            q_star.push(-gap_dist.sample(gap, rng));
+        }
+    }
+    rng.shuffle(&mut q_star);
+    q_star
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Testbed {
+    // reward noise standard deviations, or None for variance 1 everywhere
+    Gaussian { sd: Option<ArmSd> },
+
+    // success probabilities, or None to draw them for every task
+    Bernoulli { probs: Option<Vec<f64>> },
+
+    // standard deviation of each play's step
+    RandomWalk { sigma: f64 },
+
+    // near_optimal counts the best arm
+    Difficulty { near_optimal: usize, gap: f64, gap_dist: GapDist, noise_to_gap: f64 },
+
+    // degrees of freedom (tail index) of the noise; the mean needs dof > 1
+    HeavyTailed { dof: f64 },
+
+    // one distribution per arm
+    Mixed { arms: Vec<ArmDist> },
+
+    // rewards[t][a], repeated once every row has been played
+    Scripted { rewards: Vec<Vec<f64>> },
+
+    // logged (arm, reward) events, replayed from the start in every task
+    Replay { log: Vec<(usize, f64)> },
+
+    // how many arms apart the true values stay alike
+    Correlated { length_scale: f64 },
+
+    // mean lifetime of an arm, in plays
+    Mortal { lifetime: f64 },
+
+    // every task's true values, saved from another run
+    Saved { tasks: SavedTasks },
+
+    // another testbed whose rewards reach the agent late
+    Delayed { testbed: Box<Testbed>, delay: Delay },
+
+    // another testbed whose arms are each awake with probability `awake`
+    Sleeping { testbed: Box<Testbed>, awake: f64 },
+}
+
+impl Testbed {
+    // A scripted testbed whose best arm changes every `every` plays: arm
+    // (t / every) mod n pays 1 at play t and the others pay 0.
+    pub fn switching(n: usize, every: usize) -> Testbed {
+        assert!(n > 0 && every > 0);
+        let rewards = (0..n * every).map(|t| {
+            (0..n).map(|a| if a == t / every { 1.0 } else { 0.0 }).collect()
+        }).collect();
+        Testbed::Scripted { rewards }
+    }
+
+    // Draws task number `task` on `n` arms.
+    pub fn draw<R: Rng>(&self, n: usize, task: usize, rng: &mut R) -> Box<dyn Environment> {
+        match *self {
+            Testbed::Gaussian { sd: None } => {
+                Box::new(GaussianTestbed::new(BanditTask::new(n).draw_q_star(rng)))
+            }
+            Testbed::Gaussian { sd: Some(ref sd) } => {
+                let q_star = BanditTask::new(n).draw_q_star(rng);
+                Box::new(GaussianTestbed::with_sds(q_star, sd.draw(n, rng)))
+            }
+            Testbed::Bernoulli { probs: Some(ref probs) } => {
+                Box::new(BernoulliTestbed::new(probs.clone()))
+            }
+            Testbed::Bernoulli { probs: None } => {
+                let uniform = Range::new(0.0, 1.0);
+                Box::new(BernoulliTestbed::new((0..n).map(|_| uniform.ind_sample(rng)).collect()))
+            }
+            Testbed::RandomWalk { sigma } => Box::new(RandomWalkTestbed::new(n, sigma)),
+            Testbed::Difficulty { near_optimal, gap, gap_dist, noise_to_gap } => {
+                let q_star = difficulty_q_star(n, near_optimal, gap, gap_dist, rng);
+                Box::new(GaussianTestbed::with_sd(q_star, noise_to_gap * gap))
+            }
+            Testbed::HeavyTailed { dof } => {
+                Box::new(HeavyTailedTestbed::new(BanditTask::new(n).draw_q_star(rng), dof))
+            }
+            Testbed::Mixed { ref arms } => Box::new(MixedTestbed::new(arms.clone())),
+            Testbed::Scripted { ref rewards } => Box::new(ScriptedRewards::new(rewards.clone())),
+            Testbed::Replay { ref log } => Box::new(LoggedReplay::new(n, log.clone())),
+            Testbed::Correlated { length_scale } => {
+                Box::new(GaussianTestbed::new(correlated_q_star(n, length_scale, rng)))
+            }
+            Testbed::Mortal { lifetime } => {
+                Box::new(MortalArms::new(BanditTask::new(n).draw_q_star(rng), lifetime))
+            }
+            Testbed::Saved { ref tasks } => {
+                let q_star = tasks.q_stars[task].clone();
+                match tasks.rewards {
+                    SavedRewards::Gaussian { sd } => Box::new(GaussianTestbed::with_sd(q_star, sd)),
+                    SavedRewards::Bernoulli => Box::new(BernoulliTestbed::new(q_star)),
+                }
+            }
+            Testbed::Delayed { ref testbed, ref delay } => {
+                Box::new(DelayedFeedback::new(testbed.draw(n, task, rng), delay.clone()))
+            }
+            Testbed::Sleeping { ref testbed, awake } => {
+                let inner = testbed.draw(n, task, rng);
+                Box::new(SleepingArms::new(inner, awake, rng))
+            }
+        }
+    }
+
+    // Draws a task and plays it with `agent`, returning what happened and the
+    // arms' true values. The original Gaussian testbed is played by
+    // BanditTask, whose oracle is paid a reward drawn for the best arm
+    // rather than the best arm's mean.
+    pub fn run_task<R: Rng>(&self, n: usize, task: usize, agent: &mut dyn Agent,
+                            num_plays: usize, rng: &mut R) -> (TaskOutcome, Vec<f64>) {
+        if *self == (Testbed::Gaussian { sd: None }) {
+            return BanditTask::new(n).run_task(agent, num_plays, rng);
+        }
+        let mut env = self.draw(n, task, rng);
+        let mut q_star = env.means();
+        let outcome = play(&mut *env, agent, num_plays, rng);
+        // arms that joined during the task are ranked by their means at the end
+        let means = env.means();
+        q_star.extend_from_slice(&means[q_star.len()..]);
+        (outcome, q_star)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Testbed::Gaussian { .. } => "gaussian",
+            Testbed::Bernoulli { .. } => "bernoulli",
+            Testbed::RandomWalk { .. } => "random-walk",
+            Testbed::Difficulty { .. } => "difficulty",
+            Testbed::HeavyTailed { .. } => "heavy-tailed",
+            Testbed::Mixed { .. } => "mixed",
+            Testbed::Scripted { .. } => "scripted",
+            Testbed::Replay { .. } => "replay",
+            Testbed::Correlated { .. } => "correlated",
+            Testbed::Mortal { .. } => "mortal",
+            Testbed::Saved { .. } => "saved",
+            Testbed::Delayed { ref testbed, .. } => testbed.label(),
+            Testbed::Sleeping { ref testbed, .. } => testbed.label(),
+        }
+    }
+}
+
+// A testbed whose rewards were all drawn in advance: `rewards[t][a]` is the
+// reward for playing arm a at play t. Agents played against copies of it see
+// exactly the same noise, so differences between them come from their
+// choices alone.
+pub struct FixedRewards {
+    q_star: Vec<f64>,
+    rewards: Vec<Vec<f64>>,
+    t: usize,
+}
+
+impl FixedRewards {
+    // Draws the rewards of `num_plays` plays of the testbed with true values
+    // `q_star`, as Q^*(a) plus standard normal noise (what Normal would give).
+    pub fn draw<R: Rng>(q_star: Vec<f64>, num_plays: usize, rng: &mut R) -> FixedRewards {
+        let mut rewards = Vec::with_capacity(num_plays);
+        for _ in 0..num_plays {
+            let row: Vec<f64> = q_star.iter().map(|&q| {
+                let StandardNormal(noise) = rng.gen();
+                q + noise
+            }).collect();
+            rewards.push(row);
+        }
+        FixedRewards { q_star, rewards, t: 0 }
+    }
+
+    // Draws the rewards of `num_plays` plays of bernoulli arms that pay 1
+    // with probabilities `p` (what BernoulliTestbed would give).
+    pub fn draw_bernoulli<R: Rng>(p: Vec<f64>, num_plays: usize, rng: &mut R) -> FixedRewards {
+        assert!(p.iter().all(|&p| (0.0..=1.0).contains(&p)));
+        let rewards = (0..num_plays).map(|_| {
+            p.iter().map(|&p| if rng.next_f64() < p { 1.0 } else { 0.0 }).collect()
+        }).collect();
+        FixedRewards { q_star: p, rewards, t: 0 }
+    }
+
+    // Starts over from the first play, for the next agent.
+    pub fn rewind(&mut self) {
+        self.t = 0;
+    }
+}
+
+impl Environment for FixedRewards {
+    fn num_arms(&self) -> usize {
+        self.q_star.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.q_star.clone()
+    }
+
+    fn step(&mut self, action: usize, _rng: &mut dyn Rng) -> Option<f64> {
+        let reward = self.rewards[self.t][action];
+        self.t += 1;
+        Some(reward)
+    }
+}
+
+// Rewards that follow a script: `rewards[t][a]` is the reward for playing
+// arm a at play t, starting over from the first row when the script runs
+// out. The rewards are what they are, so they are also the arms' means.
+pub struct ScriptedRewards {
+    rewards: Vec<Vec<f64>>,
+    t: usize,
+}
+
+impl ScriptedRewards {
+    pub fn new(rewards: Vec<Vec<f64>>) -> ScriptedRewards {
+        assert!(!rewards.is_empty() && rewards.iter().all(|row| row.len() == rewards[0].len()));
+        ScriptedRewards { rewards, t: 0 }
+    }
+}
+
+impl Environment for ScriptedRewards {
+    fn num_arms(&self) -> usize {
+        self.rewards[0].len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.rewards[self.t % self.rewards.len()].clone()
+    }
+
+    fn step(&mut self, action: usize, _rng: &mut dyn Rng) -> Option<f64> {
+        let reward = self.rewards[self.t % self.rewards.len()][action];
+        self.t += 1;
+        Some(reward)
+    }
+}
+
+// Replays a log of (arm, reward) events, one per play: when the agent plays
+// the logged arm it gets the logged reward, and otherwise the event is
+// skipped and it gets nothing. Once the log runs out no play is rewarded.
+// If the log's arms were chosen uniformly at random, the rewarded plays are
+// an unbiased sample of how the agent would have done online, so a run's
+// average reward is over those alone, with the number of tasks rewarded at
+// each play in the `rewarded` series.
+//
+// An arm's mean is its average logged reward. Arms the log never shows are
+// given the lowest mean of the others, as nothing can be learned about them.
+pub struct LoggedReplay {
+    log: Vec<(usize, f64)>,
+    means: Vec<f64>,
+    next: usize,
+}
+
+impl LoggedReplay {
+    pub fn new(n: usize, log: Vec<(usize, f64)>) -> LoggedReplay {
+        assert!(!log.is_empty() && log.iter().all(|&(arm, _)| arm < n));
+        let mut sums = vec![0.0; n];
+        let mut counts = vec![0; n];
+        for &(arm, reward) in &log {
+            sums[arm] += reward;
+            counts[arm] += 1;
+        }
+        let mut means: Vec<f64> = (0..n).map(|a| sums[a] / counts[a] as f64).collect();
+        let lowest = means.iter().filter(|m| !m.is_nan())
+                          .fold(f64::INFINITY, |acc, &m| acc.min(m));
+        for a in 0..n {
+            if counts[a] == 0 {
+                means[a] = lowest;
+            }
+        }
+        LoggedReplay { log, means, next: 0 }
+    }
+}
+
+impl Environment for LoggedReplay {
+    fn num_arms(&self) -> usize {
+        self.means.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.means.clone()
+    }
+
+    fn step(&mut self, action: usize, _rng: &mut dyn Rng) -> Option<f64> {
+        let event = self.log.get(self.next).cloned();
+        self.next += 1;
+        match event {
+            Some((arm, reward)) if arm == action => Some(reward),
+            _ => None,
+        }
+    }
+}
+
+// How many plays a reward is held back for.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Delay {
+    Fixed(usize),
+
+    // uniform on 0..=max, drawn for every reward
+    Uniform { max: usize },
+}
+
+// Wraps an environment so that the reward of a play reaches the agent only
+// after the play `delay` plays later (a delay of 0 is no delay at all).
+// Rewards still held back when the task ends are never delivered.
+pub struct DelayedFeedback {
+    inner: Box<dyn Environment>,
+    delay: Delay,
+
+    // (play the reward is due after, action, reward)
+    pending: Vec<(usize, usize, f64)>,
+    t: usize,
+}
+
+impl DelayedFeedback {
+    pub fn new(inner: Box<dyn Environment>, delay: Delay) -> DelayedFeedback {
+        DelayedFeedback { inner, delay, pending: vec![], t: 0 }
+    }
+}
+
+impl Environment for DelayedFeedback {
+    fn num_arms(&self) -> usize {
+        self.inner.num_arms()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.inner.means()
+    }
+
+    fn step(&mut self, action: usize, mut rng: &mut dyn Rng) -> Option<f64> {
+        let reward = self.inner.step(action, rng);
+        if let Some(reward) = reward {
+            let delay = match self.delay {
+                Delay::Fixed(d) => d,
+                Delay::Uniform { max } => Range::new(0, max + 1).ind_sample(&mut rng),
+            };
+            self.pending.push((self.t + delay, action, reward));
+        }
+        self.t += 1;
+        reward
+    }
+
+    fn delays_feedback(&self) -> bool {
+        true
+    }
+
+    fn available(&self) -> Option<Vec<bool>> {
+        self.inner.available()
+    }
+
+    fn late_rewards(&mut self) -> Vec<(usize, f64)> {
+        let t = self.t;
+        let mut due: Vec<(usize, usize, f64)> = self.pending.iter().cloned()
+                                                    .filter(|&(at, _, _)| at < t).collect();
+        self.pending.retain(|&(at, _, _)| at >= t);
+        due.sort_by_key(|&(at, _, _)| at);
+        due.into_iter().map(|(_, action, reward)| (action, reward)).collect()
+    }
+}
+
+// Wraps an environment so that each arm is awake (can be played) at a play
+// with probability `awake`, independently of the others and of earlier
+// plays. An arm the wrapped environment doesn't make available (a mortal
+// arm that has died, say) is never awake, and arms it adds are covered from
+// the play they appear at. If no arm is awake, one of the available ones,
+// picked at random, is woken up.
+pub struct SleepingArms {
+    inner: Box<dyn Environment>,
+    awake: f64,
+    mask: Vec<bool>,
+}
+
+impl SleepingArms {
+    pub fn new<R: Rng>(inner: Box<dyn Environment>, awake: f64, rng: &mut R) -> SleepingArms {
+        assert!(awake > 0.0 && awake <= 1.0);
+        let n = inner.num_arms();
+        let mut sleeping = SleepingArms { inner, awake, mask: vec![true; n] };
+        sleeping.draw_mask(rng);
+        sleeping
+    }
+
+    fn draw_mask<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let n = self.inner.num_arms();
+        let available = self.inner.available().unwrap_or(vec![true; n]);
+        self.mask.resize(n, false);
+        for (mask, &available) in self.mask.iter_mut().zip(&available) {
+            // draw for every arm, so the draws don't depend on availability
+            let awake = rng.next_f64() < self.awake;
+            *mask = awake && available;
+        }
+        if !self.mask.iter().any(|&awake| awake) {
+            let candidates: Vec<usize> = (0..n).filter(|&a| available[a]).collect();
+            let i = (rng.next_f64() * candidates.len() as f64) as usize;
+            self.mask[candidates[cmp::min(i, candidates.len() - 1)]] = true;
+        }
+    }
+}
+
+impl Environment for SleepingArms {
+    fn num_arms(&self) -> usize {
+        self.inner.num_arms()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.inner.means()
+    }
+
+    fn step(&mut self, action: usize, rng: &mut dyn Rng) -> Option<f64> {
+        assert!(self.mask[action], "arm {} is asleep", action);
+        let reward = self.inner.step(action, rng);
+        self.draw_mask(rng);
+        reward
+    }
+
+    fn delays_feedback(&self) -> bool {
+        self.inner.delays_feedback()
+    }
+
+    fn late_rewards(&mut self) -> Vec<(usize, f64)> {
+        self.inner.late_rewards()
+    }
+
+    fn available(&self) -> Option<Vec<bool>> {
+        Some(self.mask.clone())
+    }
+}
+
+// Arms with limited lifetimes: `n` arms are alive at any time, and after
+// every play each of them dies with probability 1 / lifetime, so lifetimes
+// are geometric with mean `lifetime`. Every arm that dies is replaced by a
+// new one (added after all the earlier arms) whose true value is drawn from
+// a standard normal; rewards are normal around it with variance 1. Dead arms
+// stay in the arm list but are never available again.
+pub struct MortalArms {
+    q_star: Vec<f64>,
+    alive: Vec<bool>,
+    lifetime: f64,
+}
+
+impl MortalArms {
+    pub fn new(q_star: Vec<f64>, lifetime: f64) -> MortalArms {
+        assert!(lifetime >= 1.0);
+        let n = q_star.len();
+        MortalArms { q_star, alive: vec![true; n], lifetime }
+    }
+}
+
+impl Environment for MortalArms {
+    fn num_arms(&self) -> usize {
+        self.q_star.len()
+    }
+
+    fn means(&self) -> Vec<f64> {
+        self.q_star.clone()
+    }
+
+    fn step(&mut self, action: usize, mut rng: &mut dyn Rng) -> Option<f64> {
+        assert!(self.alive[action], "arm {} is dead", action);
+        let reward = Normal::new(self.q_star[action], 1.0).ind_sample(&mut rng);
+        let standard = Normal::new(0.0, 1.0);
+        for a in 0..self.alive.len() {
+            if self.alive[a] && rng.next_f64() < 1.0 / self.lifetime {
+                self.alive[a] = false;
+                self.q_star.push(standard.ind_sample(&mut rng));
+                self.alive.push(true);
+            }
+        }
+        Some(reward)
+    }
+
+    fn available(&self) -> Option<Vec<bool>> {
+        Some(self.alive.clone())
+    }
+}
+
+// Plays `num_plays` plays of `env` with `agent`. A play without a reward is
+// recorded as a reward of 0 and isn't passed on to the agent, and rewards
+// the environment holds back are passed on after the play they're due at
+// (but still recorded at the play that earned them). Arms the environment
+// adds are added to the agent too, without a prior. Arms that aren't
+// available at a play are retired in the agent for that play. Whether an
+// action was optimal, and its regret, are judged by the means of the arms
+// available at the time it was taken. Environments don't show the rewards of
+// arms that weren't played, so the oracle is credited with the best arm's
+// mean.
+pub fn play<R: Rng>(env: &mut dyn Environment, agent: &mut dyn Agent, num_plays: usize,
+                    rng: &mut R) -> TaskOutcome {
+    play_observed(env, agent, num_plays, rng, &mut |_, _| {})
+}
+
+// Same as play, but `observe` is shown the agent after every play (with the
+// play's number), e.g. to record its estimates.
+pub fn play_observed<R: Rng>(env: &mut dyn Environment, agent: &mut dyn Agent, num_plays: usize,
+                             rng: &mut R, observe: &mut dyn FnMut(usize, &dyn Agent))
+                             -> TaskOutcome {
+    let mut rewards = vec![];
+    let mut rewarded = vec![];
+    let mut oracle_rewards = vec![];
+    let mut actions = vec![];
+    let mut optimal = vec![];
+    let mut regrets = vec![];
+    let mut num_arms = env.num_arms();
+
+    for t in 0..num_plays {
+        while num_arms < env.num_arms() {
+            agent.add_arm(None);
+            num_arms += 1;
+        }
+        let available = env.available();
+        if let Some(ref available) = available {
+            for (a, &available) in available.iter().enumerate() {
+                agent.set_retired(a, !available);
+            }
+        }
+        let action = agent.choose_action(rng);
+        let means = env.means();
+        let best = (0..means.len())
+            .filter(|&a| available.as_ref().is_none_or(|available| available[a]))
+            .fold(f64::NEG_INFINITY, |acc, a| acc.max(means[a]));
+        optimal.push(means[action] == best);
+        regrets.push(best - means[action]);
+        oracle_rewards.push(best);
+        match env.step(action, rng) {
+            Some(reward) => {
+                rewards.push(reward);
+                rewarded.push(true);
+                if !env.delays_feedback() {
+                    agent.receive_reward(reward, action);
+                }
+            }
+            None => {
+                rewards.push(0.0);
+                rewarded.push(false);
+            }
+        }
+        let late = env.late_rewards();
+        if !late.is_empty() {
+            agent.receive_rewards(&late);
+        }
+        actions.push(action);
+        observe(t, agent);
+    }
+    TaskOutcome {
+        rewards,
+        rewarded,
+        oracle_rewards,
+        actions,
+        optimal,
+        regrets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_experiment_with, seeded_rng, EpsilonGreedyBandit, RunConfig};
+
+    // Sleeping arms over mortal arms: the mask has to follow the arms that
+    // are added, and never wake a dead one.
+    #[test]
+    fn sleeping_mortal_arms() {
+        let mut rng = seeded_rng(1);
+        let mortal = MortalArms::new(vec![0.0; 5], 2.0);
+        let mut env = SleepingArms::new(Box::new(mortal), 0.3, &mut rng);
+        let mut agent = EpsilonGreedyBandit::new(5, 0.1);
+        let outcome = play(&mut env, &mut agent, 500, &mut rng);
+        assert_eq!(outcome.rewards.len(), 500);
+        assert!(env.num_arms() > 5);
+        let alive = env.inner.available().unwrap();
+        let awake = env.available().unwrap();
+        assert_eq!(awake.len(), env.num_arms());
+        assert!((0..awake.len()).all(|a| !awake[a] || alive[a]));
+        assert!(awake.iter().any(|&awake| awake));
+    }
+
+    // Every task rewarded at a play gets that play's logged reward, so the
+    // average over the rewarded tasks is exactly the log's, however many
+    // tasks the agent's choice missed it in, and plays past the end of the
+    // log aren't rewarded at all.
+    #[test]
+    fn replay_averages_over_rewarded_plays() {
+        let log = vec![(0, 1.0), (1, 3.0), (2, -2.0), (0, 5.0)];
+        let testbed = Testbed::Replay { log: log.clone() };
+        let tasks = 200;
+        let run = RunConfig::new(&testbed, 3, tasks, 6);
+        let uniform = || -> Box<dyn Agent> { Box::new(EpsilonGreedyBandit::new(3, 1.0)) };
+        let curves = run_experiment_with(&mut seeded_rng(4), &run, &uniform);
+        for (t, &(_, reward)) in log.iter().enumerate() {
+            assert_eq!(curves.avg_reward[t], reward);
+            assert_eq!(curves.reward_se[t], 0.0);
+            assert!(curves.rewarded[t] > 0.0 && curves.rewarded[t] < tasks as f64);
+        }
+        assert_eq!(curves.rewarded[4..], [0.0, 0.0]);
+        assert_eq!(curves.avg_reward[4..], [0.0, 0.0]);
+    }
+
+    // Plays arms 0 and 1 in turn on a script, returning the rewards the
+    // agent is told about after each play.
+    fn late_after_each_play(delay: Delay, plays: usize) -> Vec<Vec<(usize, f64)>> {
+        let script = (1..5).map(|t| vec![t as f64, 10.0 * t as f64]).collect();
+        let mut env = DelayedFeedback::new(Box::new(ScriptedRewards::new(script)), delay);
+        let mut rng = seeded_rng(2);
+        (0..plays).map(|t| {
+            env.step(t % 2, &mut rng);
+            env.late_rewards()
+        }).collect()
+    }
+
+    // A reward reaches the agent `delay` plays after it was earned, oldest
+    // first, and a delay of 0 is no delay at all.
+    #[test]
+    fn delayed_rewards_arrive_in_order() {
+        assert_eq!(late_after_each_play(Delay::Fixed(0), 3),
+                   [vec![(0, 1.0)], vec![(1, 20.0)], vec![(0, 3.0)]]);
+        assert_eq!(late_after_each_play(Delay::Fixed(2), 4),
+                   [vec![], vec![], vec![(0, 1.0)], vec![(1, 20.0)]]);
+
+        // Random delays of up to 3 plays: everything earned but the last 3
+        // plays' rewards has arrived, each reward once.
+        let late = late_after_each_play(Delay::Uniform { max: 3 }, 40);
+        let delivered: Vec<(usize, f64)> = late.iter().flat_map(|l| l.iter().cloned()).collect();
+        assert!((37..=40).contains(&delivered.len()));
+        assert!(late.iter().any(|l| l.len() > 1));
+        for t in 0..4 {
+            let earned = (t % 2, (t + 1) as f64 * [1.0, 10.0][t % 2]);
+            let count = delivered.iter().filter(|&&l| l == earned).count();
+            assert!((9..=10).contains(&count));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Errors. The modes report failures as messages, which is all a command
+// line needs, but the parts of the crate other code builds on (the agents'
+// checked calls, plans, saved agents, checkpoints and saved testbeds, and
+// the C ABI) return an Error, so a caller can tell a bad arm from a bad
+// file and handle it instead of panicking. An Error converts into its
+// message, so `?` works in either kind of function.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    // an arm the agent doesn't have
+    InvalidArm { arm: usize, arms: usize },
+
+    // no arm to choose from
+    NoActiveArms,
+
+    // a reward that is NaN or infinite
+    InvalidReward(f64),
+
+    // bad flags, a bad config, or a file with bad contents
+    Invalid(String),
+
+    // reading or writing a file failed; `what` is e.g. "read config `plan.toml`"
+    Io { what: String, error: io::Error },
+}
+
+impl Error {
+    pub fn io(what: String, error: io::Error) -> Error {
+        Error::Io { what, error }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidArm { arm, arms } => {
+                write!(f, "arm {} is out of range for an agent with {} arms", arm, arms)
+            }
+            Error::NoActiveArms => write!(f, "the agent has no arms to choose from"),
+            Error::InvalidReward(reward) => write!(f, "reward {} isn't finite", reward),
+            Error::Invalid(ref msg) => write!(f, "{}", msg),
+            Error::Io { ref what, ref error } => write!(f, "couldn't {}: {}", what, error),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Error {
+        Error::Invalid(msg)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(msg: &'a str) -> Error {
+        Error::Invalid(msg.to_string())
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> String {
+        e.to_string()
+    }
+}
@@ -0,0 +1,332 @@
+// Per-play event logs, for offline evaluation and for finding out why an
+// agent got stuck on a poor arm. With `--events csv` (or a config's
+// `events = "csv"`) every play of every task is written to
+// "<path>.events.csv":
+//
+//     task,step,action,reward,was_exploratory,estimate_of_chosen
+//
+// and with `jsonl` to "<path>.events.jsonl", one JSON object per play with
+// the same fields. The estimate is what the agent's arm_summaries said about
+// the arm it chose just before choosing it, and a play is exploratory when
+// some other active arm's estimate was higher, whatever made the agent
+// choose it (epsilon, an upper confidence bound, a posterior draw). Tasks
+// run in parallel, but their plays are written in task order, so a seeded
+// run's log is the same however many threads run it. A resumed run (see
+// checkpoint) keeps the logged plays of the tasks its checkpoint covers,
+// dropping any of later tasks, which it runs again, and appends its own.
+// Like the pull counts, the log is compressed along with a compressed series
+// ("<path>.events.csv.gz" for "<path>.gz").
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::Mutex;
+
+use rand::Rng;
+
+use crate::output::{self, OutputFile};
+use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+use crate::{Agent, TaskOutcome};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EventFormat {
+    Csv,
+    JsonLines,
+}
+
+impl EventFormat {
+    pub fn from_name(name: &str) -> Result<EventFormat, String> {
+        match name {
+            "csv" => Ok(EventFormat::Csv),
+            "jsonl" => Ok(EventFormat::JsonLines),
+            _ => Err(format!("unknown event format `{}` (expected csv or jsonl)", name)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            EventFormat::Csv => "csv",
+            EventFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Event {
+    task: usize,
+    step: usize,
+    action: usize,
+    reward: f64,
+    was_exploratory: bool,
+    estimate_of_chosen: f64,
+}
+
+// What the agent knew when it made a choice.
+pub struct Choice {
+    exploratory: bool,
+    estimate: f64,
+}
+
+pub struct EventLog {
+    pub path: String,
+    format: EventFormat,
+    file: Mutex<OrderedFile>,
+}
+
+// The log file, and the chunks of tasks that finished before the tasks
+// ahead of them, keyed by their first task, until those are written too.
+struct OrderedFile {
+    file: OutputFile,
+    next_task: usize,
+    waiting: BTreeMap<usize, (usize, Vec<u8>)>,
+}
+
+impl EventLog {
+    // Creates "<path>.events.<csv or jsonl>" for the series at `path`. A run
+    // resumed with `tasks_done` tasks done appends to the log instead.
+    // `finish` must be called once the run is done.
+    pub fn create(path: &str, format: EventFormat, tasks_done: usize)
+                  -> Result<EventLog, String> {
+        let path = output::companion(path, &format!(".events.{}", format.extension()));
+        let open = || -> io::Result<OutputFile> {
+            if tasks_done > 0 && keep_tasks_before(&path, format, tasks_done)? {
+                return OutputFile::append(&path);
+            }
+            if tasks_done > 0 {
+                warn!("`{}` is missing; it only logs the tasks from {} on", path, tasks_done);
+            }
+            let mut f = OutputFile::create(&path)?;
+            if format == EventFormat::Csv {
+                writeln!(f, "task,step,action,reward,was_exploratory,estimate_of_chosen")?;
+            }
+            Ok(f)
+        };
+        let file = open().map_err(|e| format!("couldn't write `{}`: {}", path, e))?;
+        Ok(EventLog {
+            path,
+            format,
+            file: Mutex::new(OrderedFile {
+                file,
+                next_task: tasks_done,
+                waiting: BTreeMap::new(),
+            }),
+        })
+    }
+
+    // Adds task `task`'s plays to `buf`, to be written by write_tasks.
+    pub fn format_task(&self, task: usize, outcome: &TaskOutcome, choices: &[Choice],
+                       buf: &mut Vec<u8>) {
+        for (t, choice) in choices.iter().enumerate().take(outcome.actions.len()) {
+            let event = Event {
+                task,
+                step: t,
+                action: outcome.actions[t],
+                reward: outcome.rewards[t],
+                was_exploratory: choice.exploratory,
+                estimate_of_chosen: choice.estimate,
+            };
+            // writes to a Vec can't fail
+            match self.format {
+                EventFormat::Csv => {
+                    writeln!(buf, "{},{},{},{},{},{}", event.task, event.step, event.action,
+                             event.reward, event.was_exploratory, event.estimate_of_chosen)
+                        .unwrap();
+                }
+                EventFormat::JsonLines => {
+                    serde_json::to_writer(&mut *buf, &event).unwrap();
+                    writeln!(buf).unwrap();
+                }
+            }
+        }
+    }
+
+    // Writes the plays of `tasks`, formatted into `buf`, once every earlier
+    // task's are written. Like raw results, a failed write is only
+    // reported, so the run itself still finishes.
+    pub fn write_tasks(&self, tasks: Range<usize>, buf: Vec<u8>) {
+        let mut f = self.file.lock().unwrap();
+        f.waiting.insert(tasks.start, (tasks.end, buf));
+        let mut write = || -> io::Result<()> {
+            loop {
+                let next = f.next_task;
+                let (end, buf) = match f.waiting.remove(&next) {
+                    Some(chunk) => chunk,
+                    None => break,
+                };
+                f.file.write_all(&buf)?;
+                f.next_task = end;
+            }
+            f.file.flush()
+        };
+        if let Err(e) = write() {
+            warn!("couldn't write events to `{}`: {}", self.path, e);
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        let path = self.path;
+        self.file.into_inner().unwrap().file.finish()
+            .map_err(|e| format!("couldn't write `{}`: {}", path, e))
+    }
+}
+
+// Drops the plays of tasks from `tasks_done` on from the log at `path`,
+// which an interrupted run may have written after its last checkpoint.
+// Returns false if there is no log.
+fn keep_tasks_before(path: &str, format: EventFormat, tasks_done: usize) -> io::Result<bool> {
+    let contents = match output::read_output(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let tmp = output::companion(path, ".tmp");
+    {
+        let mut f = OutputFile::create(&tmp)?;
+        for (i, line) in contents.lines().enumerate() {
+            // a line cut short by the interruption doesn't parse, and goes
+            let task = match format {
+                EventFormat::Csv if i == 0 => Some(0),
+                EventFormat::Csv => line.split(',').next().and_then(|t| t.parse().ok()),
+                EventFormat::JsonLines => {
+                    serde_json::from_str::<serde_json::Value>(line).ok()
+                        .and_then(|event| event["task"].as_u64()).map(|t| t as usize)
+                }
+            };
+            if task.is_some_and(|task| task < tasks_done) {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        f.finish()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(true)
+}
+
+// Plays `inner` and notes what it knew at every choice. Everything else is
+// passed straight through, so the agent plays exactly as it would alone.
+pub struct ChoiceRecorder<'a> {
+    inner: &'a mut dyn Agent,
+    retired: Vec<bool>,
+    pub choices: Vec<Choice>,
+}
+
+impl<'a> ChoiceRecorder<'a> {
+    pub fn new(inner: &'a mut dyn Agent) -> ChoiceRecorder<'a> {
+        let n = inner.arm_summaries().len();
+        ChoiceRecorder { inner, retired: vec![false; n], choices: vec![] }
+    }
+}
+
+impl<'a> Agent for ChoiceRecorder<'a> {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        let summaries = self.inner.arm_summaries();
+        let action = self.inner.choose_action(rng);
+        let best = (0..summaries.len()).filter(|&a| !self.retired[a])
+                                       .fold(f64::NEG_INFINITY,
+                                             |acc, a| acc.max(summaries[a].mean));
+        self.choices.push(Choice {
+            exploratory: summaries[action].mean < best,
+            estimate: summaries[action].mean,
+        });
+        action
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.inner.receive_reward(reward, action);
+    }
+
+    fn receive_rewards(&mut self, rewards: &[(usize, f64)]) {
+        self.inner.receive_rewards(rewards);
+    }
+
+    fn warm_start(&mut self, arms: &[ArmSummary]) {
+        self.inner.warm_start(arms);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.inner.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.retired.push(false);
+        self.inner.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+        self.inner.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.inner.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        self.inner.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn outcome(reward: f64) -> TaskOutcome {
+        TaskOutcome {
+            rewards: vec![reward],
+            rewarded: vec![true],
+            oracle_rewards: vec![1.0],
+            actions: vec![0],
+            optimal: vec![true],
+            regrets: vec![0.0],
+        }
+    }
+
+    fn lines(path: &str) -> Vec<String> {
+        let contents = output::read_output(path).unwrap();
+        contents.lines().map(|line| line.to_string()).collect()
+    }
+
+    // Chunks finishing out of order are still written in task order, and a
+    // resumed log drops the tasks past the checkpoint before appending,
+    // compressed or not.
+    #[test]
+    fn task_order_and_resume() {
+        for compressed in &["", ".gz"] {
+            let stem = env::temp_dir().join(format!("events-test-{}{}", ::std::process::id(),
+                                                    compressed));
+            check_task_order_and_resume(stem.to_str().unwrap());
+        }
+    }
+
+    fn check_task_order_and_resume(stem: &str) {
+        let choice = || vec![Choice { exploratory: false, estimate: 0.0 }];
+        let log = EventLog::create(stem, EventFormat::Csv, 0).unwrap();
+        for &(first, end) in &[(2, 4), (0, 2), (4, 5)] {
+            let mut buf = vec![];
+            for task in first..end {
+                log.format_task(task, &outcome(task as f64), &choice(), &mut buf);
+            }
+            log.write_tasks(first..end, buf);
+        }
+        let tasks = |lines: Vec<String>| -> Vec<String> {
+            lines[1..].iter().map(|line| line.split(',').next().unwrap().to_string()).collect()
+        };
+        let path = log.path.clone();
+        log.finish().unwrap();
+        assert_eq!(tasks(lines(&path)), vec!["0", "1", "2", "3", "4"]);
+
+        let resumed = EventLog::create(stem, EventFormat::Csv, 3).unwrap();
+        let mut buf = vec![];
+        resumed.format_task(3, &outcome(9.0), &choice(), &mut buf);
+        resumed.write_tasks(3..4, buf);
+        let path = resumed.path.clone();
+        resumed.finish().unwrap();
+        let written = lines(&path);
+        assert_eq!(written[0], "task,step,action,reward,was_exploratory,estimate_of_chosen");
+        assert_eq!(tasks(written.clone()), vec!["0", "1", "2", "3"]);
+        assert_eq!(written[4], "3,0,0,9,false,0");
+        fs::remove_file(&path).unwrap();
+    }
+}
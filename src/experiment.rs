@@ -0,0 +1,447 @@
+// A single simulated online experiment: one bandit plays one task until a
+// stopping rule declares a winner (or the play budget runs out), and the
+// outcome is written to a manifest file alongside the configuration that
+// produced it.
+//
+// With `--add-arm-at T`, a new arm joins the task at play T. `--similar`
+// names the existing arms it resembles ("0:1,3:0.5": arm 0 with similarity
+// 1, arm 3 with 0.5) and the bandit bootstraps the new arm from them, with
+// at most `--prior-cap` pseudo-observations (0 starts it cold).
+//
+// With `--stop convergence` the task instead ends once the bandit's
+// estimates have settled (see stopping): the greedy arm unchanged and no
+// estimate moving more than `--threshold` (0.01 by default) for
+// `--patience` consecutive checks (100 by default), with a check after
+// every play unless `--check-every` says otherwise. The play it stopped at
+// is the manifest's `plays`.
+//
+// With `--stop pac` it ends once the leading arm is within `--threshold`
+// (0.1 by default) of the best with probability at least 1 - `--delta`
+// (0.05 by default), by LUCB-style confidence bounds (see stopping); the
+// manifest's `plays` is then the sample complexity of the run.
+//
+// With `--retire-after N`, arms whose confidence interval has been entirely
+// below the leader's for N consecutive checks are retired (see the retire
+// module); retirements and reinstatements are written to
+// `--retirement-log` if given.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::coldstart::ArmArrival;
+use crate::confseq::ConfidenceSequence;
+use crate::output::OutputFile;
+use crate::retire::{RetirementEvent, RetirementPolicy};
+use crate::stats::ArmSummary;
+use crate::stopping::{Convergence, ExpectedLoss, PacBestArm, ProbabilityBest, StoppingRule};
+use crate::{parse_value, seeded_rng, Agent, BanditTask, EpsilonGreedyBandit, StopCheck};
+
+pub struct Options {
+    pub n: usize,
+    pub epsilon: f64,
+    pub max_plays: usize,
+    pub seed: Option<usize>,
+
+    // "prob-best", "expected-loss", "pac" or "convergence"
+    pub rule: String,
+
+    // probability threshold for prob-best, loss epsilon for expected-loss,
+    // epsilon for pac, largest estimate change for convergence
+    pub threshold: f64,
+
+    // pac's error probability
+    pub delta: f64,
+
+    // settled checks in a row before convergence stops the task
+    pub patience: usize,
+
+    // posterior draws per check
+    pub samples: usize,
+    pub check_every: usize,
+    pub manifest: String,
+
+    // If set, the anytime-valid confidence interval of every arm is written
+    // here at each check, with coverage 1 - cs_alpha, along with whether the
+    // arm is already ruled out as the best one.
+    pub monitor: Option<String>,
+    pub cs_alpha: f64,
+
+    // an arm joining the task part way through
+    pub arrival: Option<ArmArrival>,
+
+    // consecutive dominated checks before an arm is retired
+    pub retire_after: Option<usize>,
+    pub retirement_log: Option<String>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            epsilon: 0.1,
+            max_plays: 10000,
+            seed: None,
+            rule: "prob-best".to_string(),
+            threshold: 0.95,
+            delta: 0.05,
+            patience: 100,
+            samples: 1000,
+            check_every: 100,
+            manifest: "manifest.txt".to_string(),
+            monitor: None,
+            cs_alpha: 0.05,
+            arrival: None,
+            retire_after: None,
+            retirement_log: None,
+        };
+        let mut threshold_given = false;
+        let mut add_arm_at = None;
+        let mut similar = None;
+        let mut prior_cap = 10.0;
+        let mut spread = 0.5;
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "epsilon" => opts.epsilon = parse_value(key, value)?,
+                "max-plays" => opts.max_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "stop" => opts.rule = value.clone(),
+                "threshold" => {
+                    opts.threshold = parse_value(key, value)?;
+                    threshold_given = true;
+                }
+                "delta" => opts.delta = parse_value(key, value)?,
+                "patience" => opts.patience = parse_value(key, value)?,
+                "samples" => opts.samples = parse_value(key, value)?,
+                "check-every" => opts.check_every = parse_value(key, value)?,
+                "manifest" => opts.manifest = value.clone(),
+                "monitor" => opts.monitor = Some(value.clone()),
+                "cs-alpha" => opts.cs_alpha = parse_value(key, value)?,
+                "add-arm-at" => add_arm_at = Some(parse_value(key, value)?),
+                "similar" => similar = Some(value.clone()),
+                "prior-cap" => prior_cap = parse_value(key, value)?,
+                "new-arm-spread" => spread = parse_value(key, value)?,
+                "retire-after" => opts.retire_after = Some(parse_value(key, value)?),
+                "retirement-log" => opts.retirement_log = Some(value.clone()),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        match &opts.rule[..] {
+            "prob-best" => {}
+            "expected-loss" => {
+                if !threshold_given {
+                    opts.threshold = 0.01;
+                }
+            }
+            "pac" => {
+                if !threshold_given {
+                    opts.threshold = 0.1;
+                }
+                if !(opts.threshold >= 0.0 && opts.delta > 0.0 && opts.delta < 1.0) {
+                    return Err("the pac threshold must be non-negative and delta strictly \
+                                between 0 and 1".to_string());
+                }
+            }
+            "convergence" => {
+                if !threshold_given {
+                    opts.threshold = 0.01;
+                }
+                if !flags.contains_key("check-every") {
+                    opts.check_every = 1;
+                }
+                if opts.threshold.is_nan() || opts.threshold < 0.0 || opts.patience == 0 {
+                    return Err("the convergence threshold must be non-negative and patience \
+                                positive".to_string());
+                }
+            }
+            rule => return Err(format!("unknown stopping rule `{}`", rule)),
+        }
+        if opts.rule != "convergence" && flags.contains_key("patience") {
+            return Err("`--patience` only applies with `--stop convergence`".to_string());
+        }
+        if opts.rule != "pac" && flags.contains_key("delta") {
+            return Err("`--delta` only applies with `--stop pac`".to_string());
+        }
+        if opts.n == 0 || opts.max_plays == 0 || opts.samples == 0 || opts.check_every == 0 {
+            return Err("arms, max-plays, samples and check-every must all be positive"
+                       .to_string());
+        }
+        if opts.cs_alpha <= 0.0 || opts.cs_alpha >= 1.0 {
+            return Err("cs-alpha must be strictly between 0 and 1".to_string());
+        }
+
+        if opts.retire_after == Some(0) {
+            return Err("retire-after must be positive".to_string());
+        }
+        if opts.retirement_log.is_some() && opts.retire_after.is_none() {
+            return Err("`--retirement-log` needs `--retire-after`".to_string());
+        }
+
+        match add_arm_at {
+            Some(at) => {
+                if !(prior_cap >= 0.0 && spread >= 0.0) {
+                    return Err("prior-cap and new-arm-spread must be non-negative".to_string());
+                }
+                let similar = match similar {
+                    Some(ref value) => ArmArrival::parse_similar(value, opts.n)?,
+                    None => vec![],
+                };
+                opts.arrival = Some(ArmArrival {
+                    at,
+                    similar,
+                    spread,
+                    max_weight: prior_cap,
+                });
+            }
+            None => {
+                for key in &["similar", "prior-cap", "new-arm-spread"] {
+                    if flags.contains_key(*key) {
+                        return Err(format!("`--{}` needs `--add-arm-at`", key));
+                    }
+                }
+            }
+        }
+        Ok(opts)
+    }
+
+    fn stopping_rule(&self) -> Box<dyn StoppingRule> {
+        match &self.rule[..] {
+            "expected-loss" => {
+                Box::new(ExpectedLoss { epsilon: self.threshold, samples: self.samples })
+            }
+            "pac" => Box::new(PacBestArm {
+                epsilon: self.threshold,
+                delta: self.delta,
+                target: self.max_plays / self.n,
+            }),
+            "convergence" => Box::new(Convergence::new(self.threshold, self.patience)),
+            _ => Box::new(ProbabilityBest { threshold: self.threshold, samples: self.samples }),
+        }
+    }
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    // Without an explicit seed, pick one so the manifest can still record
+    // how to reproduce the run.
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => rand::thread_rng().gen(),
+    };
+    let mut rng = seeded_rng(seed);
+    let rule = opts.stopping_rule();
+
+    // The monitor file and the confidence sequence its intervals come from,
+    // tightest around the plays each arm would get in a round-robin.
+    let mut monitor = match opts.monitor {
+        Some(ref path) => {
+            let mut f = OutputFile::create(path)
+                .map_err(|e| format!("couldn't create monitor file `{}`: {}", path, e))?;
+            writeln!(f, "plays,arm,count,mean,lower,upper,dominated")
+                .map_err(|e| format!("couldn't write monitor file `{}`: {}", path, e))?;
+            let cs = ConfidenceSequence::tuned_for(opts.cs_alpha, 1.0,
+                                                   (opts.max_plays / opts.n).max(1));
+            Some((f, cs))
+        }
+        None => None,
+    };
+    let mut monitor_error = None;
+    let mut retirement = opts.retire_after.map(|windows| {
+        RetirementPolicy::new(ConfidenceSequence::tuned_for(opts.cs_alpha, 1.0,
+                                                            (opts.max_plays / opts.n).max(1)),
+                              windows)
+    });
+    let mut events = vec![];
+
+    let mut task = BanditTask::new(opts.n);
+    let mut bandit = EpsilonGreedyBandit::new(opts.n, opts.epsilon);
+    let stopped = {
+        let mut on_check = |plays: usize, arms: &[ArmSummary], bandit: &mut dyn Agent| {
+            if let Some((ref mut f, ref cs)) = monitor {
+                if monitor_error.is_none() {
+                    if let Err(e) = write_intervals(f, plays, arms, cs) {
+                        monitor_error = Some(e);
+                    }
+                }
+            }
+            if let Some(ref mut policy) = retirement {
+                for event in policy.review(plays, arms, bandit) {
+                    println!("after {} plays: arm {} {}", event.plays, event.arm,
+                             if event.retired { "retired" } else { "reinstated" });
+                    events.push(event);
+                }
+            }
+        };
+        let stop = StopCheck {
+            rule: &*rule,
+            check_every: opts.check_every,
+            max_plays: opts.max_plays,
+        };
+        task.run_until_stopped(&mut bandit, &stop, opts.arrival.as_ref(), &mut on_check,
+                               &mut rng)
+    };
+    let (rewards, decision, q_star) = (stopped.rewards, stopped.decision, stopped.q_star);
+    let monitor_error = match (monitor, monitor_error) {
+        (Some((f, _)), None) => f.finish().err(),
+        (_, error) => error,
+    };
+    if let Some(e) = monitor_error {
+        return Err(format!("couldn't write monitor file: {}", e));
+    }
+    if let Some(ref path) = opts.retirement_log {
+        write_retirements(&events, path)
+            .map_err(|e| format!("couldn't write retirement log `{}`: {}", path, e))?;
+    }
+
+    let mut best_arm = 0;
+    for i in 1..q_star.len() {
+        if q_star[i] > q_star[best_arm] {
+            best_arm = i;
+        }
+    }
+
+    let mut manifest = vec![
+        ("arms".to_string(), opts.n.to_string()),
+        ("epsilon".to_string(), opts.epsilon.to_string()),
+        ("max_plays".to_string(), opts.max_plays.to_string()),
+        ("seed".to_string(), seed.to_string()),
+        ("stopping_rule".to_string(), rule.name()),
+        ("threshold".to_string(), opts.threshold.to_string()),
+        ("posterior_samples".to_string(), opts.samples.to_string()),
+        ("check_every".to_string(), opts.check_every.to_string()),
+        ("plays".to_string(), rewards.len().to_string()),
+        ("true_best_arm".to_string(), best_arm.to_string()),
+    ];
+    if opts.rule == "pac" {
+        manifest.push(("delta".to_string(), opts.delta.to_string()));
+    }
+    if opts.rule == "convergence" {
+        manifest.push(("patience".to_string(), opts.patience.to_string()));
+    }
+    if let Some(ref path) = opts.monitor {
+        manifest.push(("monitor".to_string(), path.clone()));
+        manifest.push(("cs_alpha".to_string(), opts.cs_alpha.to_string()));
+    }
+    if let Some(ref policy) = retirement {
+        manifest.push(("retire_after".to_string(), opts.retire_after.unwrap().to_string()));
+        manifest.push(("retirement_events".to_string(), events.len().to_string()));
+        manifest.push(("retired_arms".to_string(), policy.num_retired().to_string()));
+    }
+    if let Some(ref arrival) = opts.arrival {
+        let similar: Vec<String> =
+            arrival.similar.iter().map(|&(arm, w)| format!("{}:{}", arm, w)).collect();
+        manifest.push(("new_arm_at".to_string(), arrival.at.to_string()));
+        manifest.push(("new_arm_similar".to_string(), similar.join(",")));
+        manifest.push(("new_arm_spread".to_string(), arrival.spread.to_string()));
+        manifest.push(("prior_cap".to_string(), arrival.max_weight.to_string()));
+        if arrival.at < rewards.len() {
+            manifest.push(("new_arm_value".to_string(), q_star[opts.n].to_string()));
+            match stopped.new_arm_prior {
+                Some(prior) => {
+                    manifest.push(("new_arm_prior_mean".to_string(), prior.mean.to_string()));
+                    manifest.push(("new_arm_prior_weight".to_string(),
+                                   prior.weight.to_string()));
+                }
+                None => manifest.push(("new_arm_prior".to_string(), "none".to_string())),
+            }
+        }
+    }
+    match decision {
+        Some(ref d) => {
+            println!("{} fired after {} plays: arm {} wins ({} = {:.4})",
+                     d.rule, d.plays, d.winner, d.rule, d.value);
+            manifest.push(("decision".to_string(), "stopped".to_string()));
+            manifest.push(("winner".to_string(), d.winner.to_string()));
+            manifest.push(("decision_value".to_string(), d.value.to_string()));
+            manifest.push(("winner_is_best".to_string(), (d.winner == best_arm).to_string()));
+        }
+        None => {
+            println!("no decision after {} plays", rewards.len());
+            manifest.push(("decision".to_string(), "budget-exhausted".to_string()));
+        }
+    }
+
+    write_manifest(&manifest, &opts.manifest)
+        .map_err(|e| format!("couldn't write manifest `{}`: {}", opts.manifest, e))
+}
+
+fn write_intervals(f: &mut OutputFile, plays: usize, arms: &[ArmSummary],
+                   cs: &ConfidenceSequence) -> io::Result<()> {
+    let intervals = cs.intervals(arms);
+    let dominated = cs.dominated(arms);
+    for i in 0..arms.len() {
+        let (lower, upper) = intervals[i];
+        writeln!(f, "{},{},{},{},{},{},{}", plays, i, arms[i].count, arms[i].mean, lower, upper,
+                 dominated[i])?;
+    }
+    Ok(())
+}
+
+fn write_retirements(events: &Vec<RetirementEvent>, file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "plays,arm,event")?;
+    for e in events {
+        writeln!(f, "{},{},{}", e.plays, e.arm, if e.retired { "retire" } else { "reinstate" })?;
+    }
+    f.finish()
+}
+
+// One `key = value` line per entry.
+fn write_manifest(entries: &Vec<(String, String)>, file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    for (key, value) in entries {
+        writeln!(f, "{} = {}", key, value)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("{}-{}", name, ::std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn options(args: &[(&str, &str)]) -> Result<Options, String> {
+        let flags = args.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        Options::from_flags(&flags)
+    }
+
+    #[test]
+    fn rejects_an_empty_play_budget() {
+        assert!(options(&[("max-plays", "0")]).is_err());
+        assert!(options(&[("max-plays", "5")]).is_ok());
+    }
+
+    // Fewer plays than arms leave a round-robin nothing per arm, which
+    // mustn't stop the run with a monitor, retirements or neither.
+    #[test]
+    fn runs_with_fewer_plays_than_arms() {
+        let manifest = temp_path("experiment-short-manifest");
+        let monitor = temp_path("experiment-short-monitor");
+        for with_monitor in &[false, true] {
+            let mut args = vec![("max-plays", "5"), ("seed", "3"), ("check-every", "1"),
+                                ("manifest", &manifest[..])];
+            if *with_monitor {
+                args.push(("monitor", &monitor[..]));
+            }
+            run(&options(&args).unwrap()).unwrap();
+            let written = fs::read_to_string(&manifest).unwrap();
+            assert!(written.contains("plays = 5\n"));
+        }
+        let args = [("max-plays", "5"), ("seed", "3"), ("check-every", "1"),
+                    ("retire-after", "1"), ("manifest", &manifest[..])];
+        run(&options(&args).unwrap()).unwrap();
+        assert!(fs::read_to_string(&manifest).unwrap().contains("retire_after = 1\n"));
+        assert!(fs::read_to_string(&monitor).unwrap().lines().count() > 1);
+        fs::remove_file(&manifest).unwrap();
+        fs::remove_file(&monitor).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+// Explore-then-commit for a known horizon T: every active arm is played
+// until it has m = ceil((T / K)^(2/3)) rewards, for K active arms, fewest
+// rewards first; then the arm with the highest estimate is played for the
+// rest of the task. A prior's weight counts toward an arm's m rewards. An arm
+// added later is explored like the others before committing again.
+// Without a horizon it is run by the doubling trick (see doubling).
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct ExploreCommitBandit {
+    stats: ArmStats,
+    horizon: usize,
+    committed: Option<usize>,
+}
+
+impl ExploreCommitBandit {
+    pub fn new(n: usize, horizon: usize) -> ExploreCommitBandit {
+        assert!(n > 0);
+        assert!(horizon > 0);
+        ExploreCommitBandit { stats: ArmStats::new(n), horizon, committed: None }
+    }
+
+    // rewards every arm gets before committing
+    fn explore_length(&self, num_active: usize) -> f64 {
+        (self.horizon as f64 / num_active as f64).powf(2.0 / 3.0).ceil()
+    }
+}
+
+impl Agent for ExploreCommitBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active = self.stats.active();
+        let m = self.explore_length(active.len());
+        let least = active.iter().map(|&i| self.stats.weight(i))
+                          .fold(f64::INFINITY, f64::min);
+        if least < m {
+            self.committed = None;
+            let fewest: Vec<usize> = active.into_iter()
+                                           .filter(|&i| self.stats.weight(i) == least)
+                                           .collect();
+            return fewest[rng.gen_range(0, fewest.len())];
+        }
+
+        match self.committed {
+            Some(arm) if !self.stats.is_retired(arm) => arm,
+            _ => {
+                let arm = self.stats.choose_greedy(rng);
+                self.committed = Some(arm);
+                arm
+            }
+        }
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+
+    // With horizon 16 and 2 arms, every arm is explored ceil(8^(2/3)) = 4
+    // times before committing to the better one, and an arm added later is
+    // explored before committing again.
+    #[test]
+    fn explores_then_commits() {
+        let mut agent = ExploreCommitBandit::new(2, 16);
+        let mut rng = seeded_rng(1);
+        let mut play = |agent: &mut ExploreCommitBandit, plays: usize| -> Vec<usize> {
+            (0..plays).map(|_| {
+                let action = agent.choose_action(&mut rng);
+                agent.receive_reward(action as f64, action);
+                action
+            }).collect()
+        };
+        let explored = play(&mut agent, 8);
+        assert_eq!(explored.iter().filter(|&&a| a == 1).count(), 4);
+        assert_eq!(play(&mut agent, 8), [1; 8]);
+
+        agent.add_arm(None);
+        // m = ceil((16 / 3)^(2/3)) = 4 for three arms
+        assert_eq!(play(&mut agent, 4), [2; 4]);
+        assert_eq!(play(&mut agent, 4), [2; 4]);
+    }
+}
@@ -0,0 +1,135 @@
+// "Explore pool" agent. Production systems often don't randomize every
+// decision; instead a fixed slice of traffic is set aside for exploration
+// and served by its own policy, while all other traffic is served greedily.
+// Both pools record their rewards into the same per-arm statistics, so what
+// the exploration traffic learns immediately benefits the greedy traffic.
+//
+// Unlike epsilon-greedy, the split is a budget rather than a coin flip: out
+// of every t plays, exactly floor(t * explore_fraction) go to the explore
+// pool.
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExplorePolicy {
+    // any arm, uniformly at random
+    #[default]
+    Uniform,
+
+    // cycle through the arms in order
+    RoundRobin,
+
+    // the arm with the fewest observations so far, ties broken at random
+    LeastPulled,
+}
+
+impl ExplorePolicy {
+    pub fn from_name(name: &str) -> Option<ExplorePolicy> {
+        match name {
+            "uniform" => Some(ExplorePolicy::Uniform),
+            "round-robin" => Some(ExplorePolicy::RoundRobin),
+            "least-pulled" => Some(ExplorePolicy::LeastPulled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExplorePoolBandit {
+    n: usize,
+
+    // statistics shared by both pools
+    stats: ArmStats,
+
+    explore_fraction: f64,
+    policy: ExplorePolicy,
+
+    // next arm for the round-robin policy
+    next_arm: usize,
+
+    // number of plays served by each pool so far
+    pub explore_plays: usize,
+    pub exploit_plays: usize,
+}
+
+impl ExplorePoolBandit {
+    pub fn new(n: usize, explore_fraction: f64, policy: ExplorePolicy) -> ExplorePoolBandit {
+        assert!(n > 0);
+        assert!((0.0..=1.0).contains(&explore_fraction));
+        ExplorePoolBandit {
+            n,
+            stats: ArmStats::new(n),
+            explore_fraction,
+            policy,
+            next_arm: 0,
+            explore_plays: 0,
+            exploit_plays: 0,
+        }
+    }
+
+    // Whether the next play belongs to the explore pool, keeping the explore
+    // pool's share of all plays as close as possible to explore_fraction.
+    fn next_is_exploration(&self) -> bool {
+        let t = (self.explore_plays + self.exploit_plays) as f64;
+        ((t + 1.0) * self.explore_fraction).floor() > self.explore_plays as f64
+    }
+
+    fn choose_exploratory<R: Rng>(&mut self, rng: &mut R) -> usize {
+        let active = self.stats.active();
+        match self.policy {
+            ExplorePolicy::Uniform => active[rng.gen_range(0, active.len())],
+            ExplorePolicy::RoundRobin => {
+                // skip over retired arms
+                while self.stats.is_retired(self.next_arm % self.n) {
+                    self.next_arm += 1;
+                }
+                let action = self.next_arm % self.n;
+                self.next_arm = (action + 1) % self.n;
+                action
+            }
+            ExplorePolicy::LeastPulled => {
+                let fewest = active.iter().map(|&i| self.stats.count(i)).min().unwrap();
+                let candidates: Vec<usize> =
+                    active.into_iter().filter(|&i| self.stats.count(i) == fewest).collect();
+                candidates[rng.gen_range(0, candidates.len())]
+            }
+        }
+    }
+}
+
+impl Agent for ExplorePoolBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        if self.next_is_exploration() {
+            self.explore_plays += 1;
+            self.choose_exploratory(&mut rng)
+        } else {
+            self.exploit_plays += 1;
+            self.stats.choose_greedy(&mut rng)
+        }
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.n += 1;
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
@@ -0,0 +1,289 @@
+// Arms described by feature vectors. In the feature testbed every arm a has
+// a feature vector x_a drawn from a standard normal in d dimensions, and its
+// true value is linear in the features,
+//
+//     Q^*(a) = theta . x_a,
+//
+// for a weight vector theta drawn per task from a normal with variance 1/d
+// (so Q^*(a) has variance 1, as on the usual testbed). With many arms,
+// estimating every arm independently means most are never played; the
+// LinearBandit instead fits a single ridge regression of reward on features,
+//
+//     theta_hat = (lambda I + sum_t x_t x_t^T)^-1 sum_t r_t x_t,
+//
+// so every reward informs the estimate of every arm. Actions are chosen
+// epsilon-greedily on theta_hat . x_a.
+//
+// The `features` mode runs it on this testbed and writes the usual per-play
+// series as CSV.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+use rand::distributions::normal::{Normal, StandardNormal};
+use rand::distributions::IndependentSample;
+
+use crate::environment::{self, GaussianTestbed};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::stats::{ArmPrior, ArmSummary};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, Agent, Curves};
+
+// Feature vectors of `n` arms in `d` dimensions and the true values they
+// give under a freshly drawn theta.
+pub fn draw_testbed<R: Rng>(n: usize, d: usize, rng: &mut R) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let weight = Normal::new(0.0, 1.0 / (d as f64).sqrt());
+    let theta: Vec<f64> = (0..d).map(|_| weight.ind_sample(rng)).collect();
+    let mut features = vec![];
+    let mut q_star = vec![];
+    for _ in 0..n {
+        let x: Vec<f64> = (0..d).map(|_| { let StandardNormal(z) = rng.gen(); z }).collect();
+        q_star.push(dot(&theta, &x));
+        features.push(x);
+    }
+    (features, q_star)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).fold(0.0, |acc, (x, y)| acc + x * y)
+}
+
+// Solves the symmetric positive definite system m x = v by Cholesky
+// decomposition.
+fn solve_spd(m: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    let d = v.len();
+    let mut l = vec![vec![0.0; d]; d];
+    for i in 0..d {
+        for j in 0..i + 1 {
+            let mut sum = m[i][j];
+            for (a, b) in l[i][..j].iter().zip(&l[j][..j]) {
+                sum -= a * b;
+            }
+            l[i][j] = if i == j { sum.max(1e-12).sqrt() } else { sum / l[j][j] };
+        }
+    }
+    // L y = v, then L^T x = y
+    let mut y = vec![0.0; d];
+    for i in 0..d {
+        let mut sum = v[i];
+        for k in 0..i {
+            sum -= l[i][k] * y[k];
+        }
+        y[i] = sum / l[i][i];
+    }
+    let mut x = vec![0.0; d];
+    for i in (0..d).rev() {
+        let mut sum = y[i];
+        for k in i + 1..d {
+            sum -= l[k][i] * x[k];
+        }
+        x[i] = sum / l[i][i];
+    }
+    x
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LinearBandit {
+    features: Vec<Vec<f64>>,
+    counts: Vec<usize>,
+    retired: Vec<bool>,
+    epsilon: f64,
+
+    // lambda I + sum x x^T and sum r x
+    gram: Vec<Vec<f64>>,
+    moment: Vec<f64>,
+
+    // ridge estimate of theta, refreshed after every reward
+    theta: Vec<f64>,
+}
+
+impl LinearBandit {
+    pub fn new(features: Vec<Vec<f64>>, epsilon: f64, lambda: f64) -> LinearBandit {
+        assert!(!features.is_empty());
+        assert!((0.0..=1.0).contains(&epsilon));
+        assert!(lambda > 0.0);
+        let n = features.len();
+        let d = features[0].len();
+        let mut gram = vec![vec![0.0; d]; d];
+        for (i, row) in gram.iter_mut().enumerate() {
+            row[i] = lambda;
+        }
+        LinearBandit {
+            features,
+            counts: vec![0; n],
+            retired: vec![false; n],
+            epsilon,
+            gram,
+            moment: vec![0.0; d],
+            theta: vec![0.0; d],
+        }
+    }
+
+    fn estimate(&self, action: usize) -> f64 {
+        dot(&self.theta, &self.features[action])
+    }
+}
+
+impl Agent for LinearBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active: Vec<usize> = (0..self.features.len()).filter(|&i| !self.retired[i])
+                                                          .collect();
+        if rng.next_f64() < self.epsilon {
+            return active[rng.gen_range(0, active.len())];
+        }
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.estimate(active[0]);
+        for &i in &active[1..] {
+            let value = self.estimate(i);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        let x = &self.features[action];
+        for i in 0..x.len() {
+            for j in 0..x.len() {
+                self.gram[i][j] += x[i] * x[j];
+            }
+            self.moment[i] += reward * x[i];
+        }
+        self.counts[action] += 1;
+        self.theta = solve_spd(&self.gram, &self.moment);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        (0..self.features.len()).map(|i| ArmSummary {
+            count: self.counts[i],
+            mean: self.estimate(i),
+        }).collect()
+    }
+
+    // A new arm's features aren't known, so it gets all-zero features: the
+    // model predicts 0 for it and learns nothing from playing it. Its prior
+    // isn't used.
+    fn add_arm(&mut self, _prior: Option<ArmPrior>) -> usize {
+        let d = self.moment.len();
+        self.features.push(vec![0.0; d]);
+        self.counts.push(0);
+        self.retired.push(false);
+        self.features.len() - 1
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+pub struct Options {
+    pub n: usize,
+    pub dims: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub epsilon: f64,
+
+    // ridge penalty
+    pub lambda: f64,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10000,
+            dims: 5,
+            num_tasks: 200,
+            num_plays: 1000,
+            seed: None,
+            epsilon: 0.1,
+            lambda: 1.0,
+            output: "features.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "dims" => opts.dims = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "epsilon" => opts.epsilon = parse_value(key, value)?,
+                "ridge" => opts.lambda = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.dims == 0 || opts.num_tasks == 0 {
+            return Err("arms, dims and tasks must all be positive".to_string());
+        }
+        if !(opts.epsilon >= 0.0 && opts.epsilon <= 1.0) {
+            return Err("epsilon must be in [0, 1]".to_string());
+        }
+        if opts.lambda.is_nan() || opts.lambda <= 0.0 {
+            return Err("ridge must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+fn run_features<R: Rng>(rng: &mut R, opts: &Options) -> Curves {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunk_sums = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = Curves::new(opts.num_plays);
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let (features, q_star) = draw_testbed(opts.n, opts.dims, &mut rng);
+            let mut agent = LinearBandit::new(features, opts.epsilon, opts.lambda);
+            let mut env = GaussianTestbed::new(q_star.clone());
+            let outcome = environment::play(&mut env, &mut agent, opts.num_plays, &mut rng);
+            sums.add_task(&outcome, &q_star);
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut curves = Curves::new(opts.num_plays);
+    for sums in &chunk_sums {
+        curves.add(sums);
+    }
+    curves.average(opts.num_tasks);
+    curves
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let curves = match opts.seed {
+        Some(seed) => run_features(&mut seeded_rng(seed), opts),
+        None => run_features(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&curves, &opts.output).map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))
+}
+
+fn write_csv(curves: &Curves, file_name: &str) -> io::Result<()> {
+    let columns = curves.columns();
+    let mut f = OutputFile::create(file_name)?;
+    let names: Vec<&str> = columns.iter().map(|&(name, _)| name).collect();
+    writeln!(f, "step,{}", names.join(","))?;
+    for t in 0..curves.avg_reward.len() {
+        let values: Vec<String> = columns.iter().map(|&(_, v)| v[t].to_string()).collect();
+        writeln!(f, "{},{}", t, values.join(","))?;
+    }
+    f.finish()
+}
@@ -0,0 +1,320 @@
+// A C ABI for the agents, so services in C, C++, Go and the like can run
+// them without a rewrite. The crate also builds as a shared library
+// (cdylib) exporting, as declared in include/bandit.h:
+//
+//     BanditAgent *bandit_new(const char *spec, size_t arms, uint64_t seed);
+//     size_t bandit_choose(BanditAgent *agent);
+//     int bandit_reward(BanditAgent *agent, size_t arm, double reward);
+//     void bandit_free(BanditAgent *agent);
+//
+// and a step-by-step simulator of one task of the usual gaussian testbed,
+// to play an agent against without a system of one's own:
+//
+//     BanditSim *bandit_sim_new(size_t arms, uint64_t seed);
+//     double bandit_sim_play(BanditSim *sim, size_t arm);
+//     double bandit_sim_mean(const BanditSim *sim, size_t arm);
+//     size_t bandit_sim_best(const BanditSim *sim);
+//     void bandit_sim_free(BanditSim *sim);
+//
+// The spec is an agent spec as JSON, as in a saved agent (see agent_state),
+// e.g. {"kind": "ucb", "c": 2.0}, or NULL for epsilon-greedy with epsilon
+// 0.1. Only "kind" is required, and the parameters left out take the
+// defaults of the command-line flags, so {"kind": "ucb"} is UCB with c = 2;
+// the exceptions are a registered agent's "name", a meta agent's
+// "selector" and "agents", a wrapped agent's "wrapper" and "agent", and a
+// preprocessed agent's "preprocessing" and "agent". Each agent draws its
+// random choices from its own RNG seeded with `seed`, so a seeded agent
+// makes the same choices given the same rewards.
+//
+// bandit_new returns NULL if the spec is invalid, bandit_choose returns
+// SIZE_MAX for a NULL agent, and bandit_reward returns 0, or -1 for a NULL
+// agent, an arm out of range or a reward that isn't finite. A panic inside
+// an agent never crosses the boundary; the call fails the same way instead.
+// An agent must only be used from one thread at a time, and a spec must be
+// NULL or a C string.
+//
+// bandit_sim_new returns NULL if arms is 0, and bandit_sim_play and
+// bandit_sim_mean return NaN, and bandit_sim_best SIZE_MAX, for a NULL
+// simulator or an arm out of range. Nothing here reads files, the clock or
+// an unseeded RNG, so the same functions can be exported by a build for a
+// target without them, such as a browser's WebAssembly, where everything
+// but a spec is a plain number and a NULL spec needs no memory from the
+// caller.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use rand::StdRng;
+use serde_json;
+
+use crate::environment::{Environment, Testbed};
+use crate::error::Error;
+use crate::spec::AgentSpec;
+use crate::{best_arm, seeded_rng, Agent};
+
+pub struct BanditAgent {
+    agent: Box<dyn Agent>,
+    rng: StdRng,
+}
+
+unsafe fn new_agent(spec: *const c_char, arms: usize, seed: u64)
+                    -> Result<BanditAgent, Error> {
+    let spec = if spec.is_null() {
+        AgentSpec::EpsilonGreedy {
+            epsilon: 0.1,
+            step_size: None,
+            tie_tolerance: 0.0,
+            min_samples: 0,
+            mom_groups: None,
+            kernel_width: None,
+            history: None,
+        }
+    } else {
+        let json = CStr::from_ptr(spec).to_str().map_err(|e| Error::Invalid(e.to_string()))?;
+        serde_json::from_str(json).map_err(|e| Error::Invalid(e.to_string()))?
+    };
+    if arms == 0 {
+        return Err(Error::Invalid("arms must be positive".to_string()));
+    }
+    spec.validate()?;
+    Ok(BanditAgent { agent: spec.build(arms), rng: seeded_rng(seed as usize) })
+}
+
+/// Builds an agent with `arms` arms from the JSON `spec`, or NULL if the
+/// spec is invalid. Free it with `bandit_free`.
+///
+/// # Safety
+///
+/// `spec` must be NULL or point to a NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_new(spec: *const c_char, arms: usize, seed: u64)
+                                    -> *mut BanditAgent {
+    match panic::catch_unwind(|| new_agent(spec, arms, seed)) {
+        Ok(Ok(agent)) => Box::into_raw(Box::new(agent)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// The arm `agent` chooses to play next, or SIZE_MAX on failure.
+///
+/// # Safety
+///
+/// `agent` must be NULL or a pointer from `bandit_new` not yet freed, used by
+/// no other thread during the call.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_choose(agent: *mut BanditAgent) -> usize {
+    let agent = match agent.as_mut() {
+        Some(agent) => agent,
+        None => return usize::MAX,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let BanditAgent { ref mut agent, ref mut rng, .. } = *agent;
+        agent.try_choose_action(rng)
+    }));
+    match result {
+        Ok(Ok(action)) => action,
+        _ => usize::MAX,
+    }
+}
+
+/// Shows `agent` the reward of a play of `arm`; 0 on success, -1 otherwise.
+///
+/// # Safety
+///
+/// `agent` must be NULL or a pointer from `bandit_new` not yet freed, used by
+/// no other thread during the call.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_reward(agent: *mut BanditAgent, arm: usize, reward: f64)
+                                       -> c_int {
+    let agent = match agent.as_mut() {
+        Some(agent) => agent,
+        None => return -1,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| agent.agent.try_receive_reward(reward, arm))) {
+        Ok(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Frees an agent made by `bandit_new`; NULL is ignored.
+///
+/// # Safety
+///
+/// `agent` must be NULL or a pointer from `bandit_new` not yet freed, and
+/// must not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_free(agent: *mut BanditAgent) {
+    if !agent.is_null() {
+        drop(Box::from_raw(agent));
+    }
+}
+
+pub struct BanditSim {
+    env: Box<dyn Environment>,
+    rng: StdRng,
+}
+
+/// Draws a task of the gaussian testbed with `arms` arms, or NULL if `arms`
+/// is 0. Free it with `bandit_sim_free`.
+#[no_mangle]
+pub extern "C" fn bandit_sim_new(arms: usize, seed: u64) -> *mut BanditSim {
+    if arms == 0 {
+        return ptr::null_mut();
+    }
+    let mut rng = seeded_rng(seed as usize);
+    let env = Testbed::Gaussian { sd: None }.draw(arms, 0, &mut rng);
+    Box::into_raw(Box::new(BanditSim { env, rng }))
+}
+
+/// Plays `arm` and returns its reward, or NaN on failure.
+///
+/// # Safety
+///
+/// `sim` must be NULL or a pointer from `bandit_sim_new` not yet freed, used
+/// by no other thread during the call.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_sim_play(sim: *mut BanditSim, arm: usize) -> f64 {
+    match sim.as_mut() {
+        Some(sim) if arm < sim.env.num_arms() => {
+            let BanditSim { ref mut env, ref mut rng } = *sim;
+            env.step(arm, rng).unwrap_or(f64::NAN)
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// The true value of `arm`, or NaN on failure.
+///
+/// # Safety
+///
+/// `sim` must be NULL or a pointer from `bandit_sim_new` not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_sim_mean(sim: *const BanditSim, arm: usize) -> f64 {
+    match sim.as_ref() {
+        Some(sim) if arm < sim.env.num_arms() => sim.env.means()[arm],
+        _ => f64::NAN,
+    }
+}
+
+/// The arm with the highest true value, or SIZE_MAX for a NULL simulator.
+///
+/// # Safety
+///
+/// `sim` must be NULL or a pointer from `bandit_sim_new` not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_sim_best(sim: *const BanditSim) -> usize {
+    match sim.as_ref() {
+        Some(sim) => best_arm(&sim.env.means()),
+        None => usize::MAX,
+    }
+}
+
+/// Frees a simulator made by `bandit_sim_new`; NULL is ignored.
+///
+/// # Safety
+///
+/// `sim` must be NULL or a pointer from `bandit_sim_new` not yet freed, and
+/// must not be used again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bandit_sim_free(sim: *mut BanditSim) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn new(spec: Option<&str>, arms: usize, seed: u64) -> *mut BanditAgent {
+        let spec = spec.map(|spec| CString::new(spec).unwrap());
+        unsafe { bandit_new(spec.as_ref().map_or(ptr::null(), |spec| spec.as_ptr()), arms, seed) }
+    }
+
+    // Plays `plays` plays through the C functions, arm a paying a.
+    fn choices(agent: *mut BanditAgent, plays: usize) -> Vec<usize> {
+        (0..plays).map(|_| unsafe {
+            let arm = bandit_choose(agent);
+            assert_eq!(bandit_reward(agent, arm, arm as f64), 0);
+            arm
+        }).collect()
+    }
+
+    #[test]
+    fn drives_agents_through_the_c_functions() {
+        for spec in &[None, Some(r#"{"kind": "ucb"}"#), Some(r#"{"kind": "epsilon-greedy"}"#),
+                      Some(r#"{"kind": "softmax", "tau": 0.5}"#),
+                      Some(r#"{"kind": "moss"}"#), Some(r#"{"kind": "explore-pool"}"#)] {
+            let (a, b) = (new(*spec, 4, 9), new(*spec, 4, 9));
+            assert!(!a.is_null() && !b.is_null(), "{:?}", spec);
+            let played = choices(a, 200);
+            assert!(played.iter().all(|&arm| arm < 4));
+            // the best arm ends up played most
+            assert!(played[100..].iter().filter(|&&arm| arm == 3).count() > 50, "{:?}", spec);
+            // a seeded agent plays the same given the same rewards
+            assert_eq!(choices(b, 200), played);
+            unsafe {
+                assert_eq!(bandit_reward(a, 4, 1.0), -1);
+                assert_eq!(bandit_reward(a, 0, f64::NAN), -1);
+                bandit_free(a);
+                bandit_free(b);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_specs_and_null_agents() {
+        for spec in &["", "{", r#"{"c": 2.0}"#, r#"{"kind": "no-such-agent"}"#,
+                      r#"{"kind": "epsilon-greedy", "epsilon": 2.0}"#,
+                      r#"{"kind": "ucb", "c": "two"}"#, r#"{"kind": "meta"}"#] {
+            assert!(new(Some(spec), 4, 1).is_null(), "{}", spec);
+        }
+        assert!(new(None, 0, 1).is_null());
+        unsafe {
+            assert_eq!(bandit_choose(ptr::null_mut()), usize::MAX);
+            assert_eq!(bandit_reward(ptr::null_mut(), 0, 1.0), -1);
+            bandit_free(ptr::null_mut());
+        }
+    }
+
+    // An agent learns the simulator's best arm through the C functions, and
+    // the same seed gives the same task and rewards.
+    #[test]
+    fn plays_agents_against_the_simulator() {
+        let run = || unsafe {
+            let sim = bandit_sim_new(5, 3);
+            let agent = new(Some(r#"{"kind": "ucb"}"#), 5, 3);
+            let mut plays = vec![];
+            for _ in 0..2000 {
+                let arm = bandit_choose(agent);
+                let reward = bandit_sim_play(sim, arm);
+                assert!(reward.is_finite());
+                assert_eq!(bandit_reward(agent, arm, reward), 0);
+                plays.push((arm, reward));
+            }
+            let best = bandit_sim_best(sim);
+            let means: Vec<f64> = (0..5).map(|arm| bandit_sim_mean(sim, arm)).collect();
+            assert_eq!(best, best_arm(&means));
+            assert!(plays[1000..].iter().filter(|&&(arm, _)| arm == best).count() > 800);
+            bandit_free(agent);
+            bandit_sim_free(sim);
+            plays
+        };
+        assert_eq!(run(), run());
+
+        assert!(bandit_sim_new(0, 1).is_null());
+        unsafe {
+            let sim = bandit_sim_new(3, 1);
+            assert!(bandit_sim_play(sim, 3).is_nan());
+            assert!(bandit_sim_mean(sim, 3).is_nan());
+            bandit_sim_free(sim);
+            assert!(bandit_sim_play(ptr::null_mut(), 0).is_nan());
+            assert!(bandit_sim_mean(ptr::null(), 0).is_nan());
+            assert_eq!(bandit_sim_best(ptr::null()), usize::MAX);
+            bandit_sim_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Forecast-assisted agent for drifting environments. Each arm's rewards are
+// tracked with Holt's linear exponential smoothing, a level L and a trend B
+// updated on every reward r for the arm:
+//
+//     L' = alpha * r + (1 - alpha) * (L + B * k)
+//     B' = beta * (L' - L) / k + (1 - beta) * B
+//
+// where k is the number of plays since the arm was last played. The arm
+// played is the one maximizing its forecast for the current play plus an
+// uncertainty bonus,
+//
+//     L + B * k + c * sqrt(V * (1 + k * beta))
+//
+// where V is an exponentially smoothed squared forecast error, so arms that
+// haven't been looked at for a while, or whose rewards are hard to predict,
+// get revisited. Arms that haven't been played yet count as maximizing.
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+struct ArmForecast {
+    count: usize,
+    level: f64,
+    trend: f64,
+
+    // smoothed squared one-step forecast error
+    error_var: f64,
+
+    // play at which the arm was last played
+    last_play: usize,
+
+    // whether the level has a value yet (from a reward or a prior)
+    started: bool,
+}
+
+impl ArmForecast {
+    fn new(prior: Option<ArmPrior>, now: usize) -> ArmForecast {
+        let started = prior.is_some_and(|p| p.weight > 0.0);
+        ArmForecast {
+            count: 0,
+            level: if started { prior.unwrap().mean } else { 0.0 },
+            trend: 0.0,
+            error_var: 1.0,
+            last_play: now,
+            started,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ForecastBandit {
+    arms: Vec<ArmForecast>,
+    retired: Vec<bool>,
+
+    // smoothing of the level and of the trend
+    alpha: f64,
+    beta: f64,
+
+    // weight of the uncertainty bonus
+    c: f64,
+
+    // plays so far; the current play is numbered t
+    t: usize,
+}
+
+impl ForecastBandit {
+    pub fn new(n: usize, alpha: f64, beta: f64, c: f64) -> ForecastBandit {
+        assert!(n > 0);
+        assert!(alpha > 0.0 && alpha <= 1.0);
+        assert!((0.0..=1.0).contains(&beta));
+        assert!(c >= 0.0);
+        ForecastBandit {
+            arms: (0..n).map(|_| ArmForecast::new(None, 0)).collect(),
+            retired: vec![false; n],
+            alpha,
+            beta,
+            c,
+            t: 0,
+        }
+    }
+
+    // The arm's forecast for play `play`.
+    fn forecast(&self, action: usize, play: usize) -> f64 {
+        let arm = &self.arms[action];
+        arm.level + arm.trend * (play - arm.last_play) as f64
+    }
+
+    fn score(&self, action: usize) -> f64 {
+        let arm = &self.arms[action];
+        if !arm.started {
+            return f64::INFINITY;
+        }
+        let k = (self.t - arm.last_play) as f64;
+        self.forecast(action, self.t) + self.c * (arm.error_var * (1.0 + k * self.beta)).sqrt()
+    }
+}
+
+impl Agent for ForecastBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let mut max_actions = vec![];
+        let mut max_value = f64::NEG_INFINITY;
+        for i in 0..self.arms.len() {
+            if self.retired[i] {
+                continue;
+            }
+            let value = self.score(i);
+            if max_actions.is_empty() || value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        self.t += 1;
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    // The reward is for the play just chosen, t - 1.
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        let play = self.t - 1;
+        let forecast = self.forecast(action, play);
+        let (alpha, beta) = (self.alpha, self.beta);
+        let arm = &mut self.arms[action];
+        if arm.started {
+            // an arm added with a prior can be played on the play it joined
+            let k = ((play - arm.last_play) as f64).max(1.0);
+            let error = reward - forecast;
+            let level = alpha * reward + (1.0 - alpha) * forecast;
+            arm.trend = beta * (level - arm.level) / k + (1.0 - beta) * arm.trend;
+            arm.level = level;
+            arm.error_var = alpha * error * error + (1.0 - alpha) * arm.error_var;
+        } else {
+            arm.level = reward;
+            arm.started = true;
+        }
+        arm.count += 1;
+        arm.last_play = play;
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        (0..self.arms.len()).map(|i| ArmSummary {
+            count: self.arms[i].count,
+            mean: self.forecast(i, self.t),
+        }).collect()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.arms.push(ArmForecast::new(prior, self.t));
+        self.retired.push(false);
+        self.arms.len() - 1
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
@@ -0,0 +1,457 @@
+// Hyperparameter grid search. Each agent family gets a list (or range) of
+// values for its hyperparameters:
+//
+//     --epsilon 0,0.01,0.1     epsilon-greedy
+//     --alpha avg,0.1          step sizes for epsilon-greedy ("avg" means
+//                              sample averages); crossed with --epsilon
+//     --ucb-c 0.25:4:x2        UCB exploration constant
+//     --tau 0.1:0.5:0.1        softmax temperature
+//
+// A range is `start:end:step`, or `start:end:xfactor` for a geometric one.
+// Every configuration is run on the same testbed (each task's q* is shared
+// by all of them) and summarized by its mean reward over all plays, its mean
+// reward over the final plays and its cumulative regret. With
+// `--discount gamma`, the discounted cumulative reward sum_t gamma^t R_t is
+// reported as well, for applications that value early rewards more.
+//
+// Because every configuration plays the same tasks, they can also be
+// compared head to head: configuration A beats B on a task if it collected
+// more total reward there (a tie counts as half a win). The table of
+// empirical win probabilities is printed after the summary, and written as
+// CSV with `--win-matrix`.
+//
+// The results can be stored as a baseline with `--save-baseline`, and later
+// runs checked against one with `--baseline` (see the baseline module).
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process;
+
+use rand::Rng;
+
+use crate::baseline::{self, Baseline};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+
+    // number of final plays averaged for the "final reward" column
+    pub window: usize,
+
+    // discount factor for the discounted-reward column, if wanted
+    pub discount: Option<f64>,
+    pub configs: Vec<AgentSpec>,
+
+    // optional CSV copy of the summary table
+    pub output: Option<String>,
+
+    // optional CSV copy of the pairwise win probabilities
+    pub win_matrix: Option<String>,
+
+    // baseline to store the results as, or to check them against
+    pub save_baseline: Option<String>,
+    pub baseline: Option<String>,
+
+    // allowed relative regression of each metric
+    pub tolerance: f64,
+
+    // file regression alerts are appended to
+    pub alerts: Option<String>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            window: 100,
+            discount: None,
+            configs: vec![],
+            output: None,
+            win_matrix: None,
+            save_baseline: None,
+            baseline: None,
+            tolerance: 0.05,
+            alerts: None,
+        };
+        let mut epsilons = vec![];
+        let mut step_sizes = vec![None];
+        let mut cs = vec![];
+        let mut taus = vec![];
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "window" => opts.window = parse_value(key, value)?,
+                "discount" => opts.discount = Some(parse_value(key, value)?),
+                "output" => opts.output = Some(value.clone()),
+                "win-matrix" => opts.win_matrix = Some(value.clone()),
+                "save-baseline" => opts.save_baseline = Some(value.clone()),
+                "baseline" => opts.baseline = Some(value.clone()),
+                "tolerance" => opts.tolerance = parse_value(key, value)?,
+                "alerts" => opts.alerts = Some(value.clone()),
+                "epsilon" => epsilons = parse_grid(key, value)?,
+                "ucb-c" => cs = parse_grid(key, value)?,
+                "tau" => taus = parse_grid(key, value)?,
+                "alpha" => {
+                    step_sizes = vec![];
+                    for part in value.split(',') {
+                        if part.trim() == "avg" {
+                            step_sizes.push(None);
+                        } else {
+                            for alpha in parse_grid(key, part)? {
+                                step_sizes.push(Some(alpha));
+                            }
+                        }
+                    }
+                }
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if flags.contains_key("alpha") && epsilons.is_empty() {
+            return Err("`--alpha` needs `--epsilon` values to cross with".to_string());
+        }
+        for &epsilon in &epsilons {
+            for &step_size in &step_sizes {
+                opts.configs.push(AgentSpec::EpsilonGreedy {
+                    epsilon,
+                    step_size,
+                    tie_tolerance: 0.0,
+                    min_samples: 0,
+                    mom_groups: None,
+                    kernel_width: None,
+                    history: None,
+                });
+            }
+        }
+        for &c in &cs {
+            opts.configs.push(AgentSpec::Ucb { c });
+        }
+        for &tau in &taus {
+            opts.configs.push(AgentSpec::Softmax { tau });
+        }
+
+        if opts.configs.is_empty() {
+            return Err("no hyperparameter values given (see --epsilon, --ucb-c, --tau)"
+                       .to_string());
+        }
+        for spec in &opts.configs {
+            spec.validate().map_err(|e| format!("{}: {}", spec.label(), e))?;
+        }
+        if opts.n == 0 || opts.num_tasks == 0 {
+            return Err("arms and tasks must be positive".to_string());
+        }
+        if opts.window == 0 || opts.window > opts.num_plays {
+            return Err("window must be between 1 and the number of plays".to_string());
+        }
+        if let Some(gamma) = opts.discount {
+            if !(gamma > 0.0 && gamma <= 1.0) {
+                return Err("discount must be in (0, 1]".to_string());
+            }
+        }
+        if opts.tolerance.is_nan() || opts.tolerance < 0.0 {
+            return Err("tolerance must be non-negative".to_string());
+        }
+        if opts.baseline.is_none() && (flags.contains_key("tolerance") || opts.alerts.is_some()) {
+            return Err("`--tolerance` and `--alerts` need `--baseline`".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Parses a comma-separated list of values and ranges.
+pub fn parse_grid(key: &str, value: &str) -> Result<Vec<f64>, String> {
+    let mut values = vec![];
+    for part in value.split(',') {
+        let bounds: Vec<&str> = part.split(':').collect();
+        if bounds.len() == 1 {
+            values.push(parse_value(key, part)?);
+            continue;
+        }
+        if bounds.len() != 3 {
+            return Err(format!("invalid range `{}` for `--{}`", part, key));
+        }
+
+        let start: f64 = parse_value(key, bounds[0])?;
+        let end: f64 = parse_value(key, bounds[1])?;
+        let step = bounds[2].trim();
+        // a little slack so the end point survives rounding
+        let limit = end + 1e-9 * end.abs().max(1.0);
+        if let Some(factor) = step.strip_prefix('x') {
+            let factor: f64 = parse_value(key, factor)?;
+            if !(factor > 1.0 && start > 0.0) {
+                return Err(format!("geometric range `{}` needs start > 0 and factor > 1", part));
+            }
+            let mut x = start;
+            while x <= limit {
+                values.push(x);
+                x *= factor;
+            }
+        } else {
+            let step: f64 = parse_value(key, step)?;
+            if step.is_nan() || step <= 0.0 {
+                return Err(format!("range `{}` needs a positive step", part));
+            }
+            let mut i = 0;
+            while start + (i as f64) * step <= limit {
+                values.push(start + (i as f64) * step);
+                i += 1;
+            }
+        }
+    }
+    Ok(values)
+}
+
+pub struct GridResult {
+    pub spec: AgentSpec,
+
+    // all averaged over tasks
+    pub mean_reward: f64,
+    pub final_reward: f64,
+    pub cumulative_regret: f64,
+    pub discounted_reward: Option<f64>,
+
+    // probability of beating each configuration (including itself, 0.5) on a
+    // random task
+    pub win_prob: Vec<f64>,
+}
+
+// Per-configuration totals over some of the tasks.
+struct GridSums {
+    reward: Vec<f64>,
+    final_reward: Vec<f64>,
+    regret: Vec<f64>,
+    discounted: Vec<f64>,
+
+    // wins[a][b]: tasks on which a beat b
+    wins: Vec<Vec<f64>>,
+}
+
+pub fn run_grid<R: Rng>(rng: &mut R, opts: &Options) -> Vec<GridResult> {
+    let num_configs = opts.configs.len();
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunk_sums = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = GridSums {
+            reward: vec![0.0; num_configs],
+            final_reward: vec![0.0; num_configs],
+            regret: vec![0.0; num_configs],
+            discounted: vec![0.0; num_configs],
+            wins: vec![vec![0.0; num_configs]; num_configs],
+        };
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let mut task = BanditTask::new(opts.n);
+            let q_star = task.draw_q_star(&mut rng);
+            let best = q_star.iter().fold(f64::NEG_INFINITY, |acc, &q| acc.max(q));
+
+            let mut task_rewards = vec![0.0; num_configs];
+            for (c, config) in opts.configs.iter().enumerate() {
+                let mut agent = config.build(opts.n);
+                let outcome = task.play_task_on(&q_star, &mut *agent, opts.num_plays, &mut rng);
+                for t in 0..opts.num_plays {
+                    task_rewards[c] += outcome.rewards[t];
+                    if t >= opts.num_plays - opts.window {
+                        sums.final_reward[c] += outcome.rewards[t];
+                    }
+                    sums.regret[c] += best - q_star[outcome.actions[t]];
+                }
+                sums.reward[c] += task_rewards[c];
+                if let Some(gamma) = opts.discount {
+                    sums.discounted[c] += discounted_sum(&outcome.rewards, gamma);
+                }
+            }
+
+            for a in 0..num_configs {
+                for b in 0..num_configs {
+                    if task_rewards[a] > task_rewards[b] {
+                        sums.wins[a][b] += 1.0;
+                    } else if task_rewards[a] == task_rewards[b] {
+                        sums.wins[a][b] += 0.5;
+                    }
+                }
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut total_reward = vec![0.0; num_configs];
+    let mut final_reward = vec![0.0; num_configs];
+    let mut regret = vec![0.0; num_configs];
+    let mut discounted = vec![0.0; num_configs];
+    let mut wins = vec![vec![0.0; num_configs]; num_configs];
+    for sums in chunk_sums {
+        for c in 0..num_configs {
+            total_reward[c] += sums.reward[c];
+            discounted[c] += sums.discounted[c];
+            final_reward[c] += sums.final_reward[c];
+            regret[c] += sums.regret[c];
+            for (win, &w) in wins[c].iter_mut().zip(&sums.wins[c]) {
+                *win += w;
+            }
+        }
+    }
+
+    let tasks = opts.num_tasks as f64;
+    let mut results = vec![];
+    for c in 0..num_configs {
+        results.push(GridResult {
+            spec: opts.configs[c].clone(),
+            mean_reward: total_reward[c] / (tasks * opts.num_plays as f64),
+            final_reward: final_reward[c] / (tasks * opts.window as f64),
+            cumulative_regret: regret[c] / tasks,
+            discounted_reward: opts.discount.map(|_| discounted[c] / tasks),
+            win_prob: wins[c].iter().map(|w| w / tasks).collect(),
+        });
+    }
+    results
+}
+
+// sum_t gamma^t * rewards[t]
+fn discounted_sum(rewards: &Vec<f64>, gamma: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut weight = 1.0;
+    for &r in rewards {
+        sum += weight * r;
+        weight *= gamma;
+    }
+    sum
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    // Check the baseline before spending time on the run.
+    let baseline = match opts.baseline {
+        Some(ref path) => {
+            let baseline = Baseline::load(path)?;
+            baseline.check_comparable(opts.n, opts.num_plays, opts.window)
+                    .map_err(|e| format!("`{}`: {}", path, e))?;
+            Some(baseline)
+        }
+        None => None,
+    };
+
+    let results = match opts.seed {
+        Some(seed) => run_grid(&mut seeded_rng(seed), opts),
+        None => run_grid(&mut rand::thread_rng(), opts),
+    };
+
+    for r in &results {
+        progress::config_done(&r.spec.label());
+    }
+
+    let mut best = 0;
+    for i in 1..results.len() {
+        if results[i].mean_reward > results[best].mean_reward {
+            best = i;
+        }
+    }
+
+    println!("grid search: {} configurations, {} tasks x {} plays, {} arms",
+             results.len(), opts.num_tasks, opts.num_plays, opts.n);
+    print!("{:<40} {:>12} {:>12} {:>12}", "config", "mean reward", "final reward", "regret");
+    if let Some(gamma) = opts.discount {
+        print!(" {:>14}", format!("disc({})", gamma));
+    }
+    println!();
+    for (i, r) in results.iter().enumerate() {
+        print!("{:<40} {:>12.4} {:>12.4} {:>12.2}", r.spec.label(), r.mean_reward,
+               r.final_reward, r.cumulative_regret);
+        if let Some(d) = r.discounted_reward {
+            print!(" {:>14.4}", d);
+        }
+        println!("{}", if i == best { " *" } else { "" });
+    }
+
+    println!();
+    println!("win probability of row against column, per task:");
+    print!("{:>4}", "");
+    for j in 0..results.len() {
+        print!(" {:>6}", format!("[{}]", j));
+    }
+    println!();
+    for (i, r) in results.iter().enumerate() {
+        print!("{:>4}", format!("[{}]", i));
+        for j in 0..results.len() {
+            print!(" {:>6.3}", r.win_prob[j]);
+        }
+        println!("  {}", r.spec.label());
+    }
+
+    if let Some(ref path) = opts.output {
+        write_csv(&results, path).map_err(|e| format!("couldn't write `{}`: {}", path, e))?;
+    }
+    if let Some(ref path) = opts.win_matrix {
+        write_win_matrix(&results, path)
+            .map_err(|e| format!("couldn't write `{}`: {}", path, e))?;
+    }
+    if let Some(ref path) = opts.save_baseline {
+        Baseline::from_results(&results, opts.n, opts.num_tasks, opts.num_plays, opts.window)
+            .save(path)
+            .map_err(|e| format!("couldn't write baseline `{}`: {}", path, e))?;
+        println!("baseline saved to {}", path);
+    }
+
+    if let Some(baseline) = baseline {
+        let alerts = baseline.regressions(&results, opts.tolerance);
+        if let Some(ref path) = opts.alerts {
+            baseline::append_alerts(&alerts, path)
+                .map_err(|e| format!("couldn't write alerts `{}`: {}", path, e))?;
+        }
+        if alerts.is_empty() {
+            println!("no regressions against the baseline");
+        } else {
+            for a in &alerts {
+                let _ = writeln!(io::stderr(), "regression: {} {} went from {:.4} to {:.4}",
+                                 a.config, a.metric, a.baseline, a.current);
+            }
+            // distinct from the usage errors' status 2
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn write_csv(results: &Vec<GridResult>, file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    let discounted = !results.is_empty() && results[0].discounted_reward.is_some();
+    write!(f, "config,mean_reward,final_reward,cumulative_regret")?;
+    writeln!(f, "{}", if discounted { ",discounted_reward" } else { "" })?;
+    for r in results {
+        write!(f, "\"{}\",{},{},{}", r.spec.label(), r.mean_reward, r.final_reward,
+               r.cumulative_regret)?;
+        match r.discounted_reward {
+            Some(d) => writeln!(f, ",{}", d)?,
+            None => writeln!(f)?,
+        }
+    }
+    f.finish()
+}
+
+// One row per configuration: its label followed by its win probability
+// against every configuration, in the same order as the columns.
+fn write_win_matrix(results: &[GridResult], file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    let labels: Vec<String> = results.iter().map(|r| format!("\"{}\"", r.spec.label())).collect();
+    writeln!(f, "config,{}", labels.join(","))?;
+    for (i, r) in results.iter().enumerate() {
+        let probs: Vec<String> = r.win_prob.iter().map(|p| p.to_string()).collect();
+        writeln!(f, "{},{}", labels[i], probs.join(","))?;
+    }
+    f.finish()
+}
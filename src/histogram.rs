@@ -0,0 +1,143 @@
+// Histograms of the rewards every arm actually paid out over a run, to see
+// the reward distributions the agent was shown (an arm it gave up on early
+// has few rewards, and a noisy arm's tail may never have been seen) next to
+// what the testbed draws from. As with the pull counts, arms are identified
+// by rank, best first, since a task's arm indices don't mean anything
+// across tasks. `--histogram low:high:bins` (`histogram = "low:high:bins"`
+// in a config plan) splits [low, high] into `bins` equal bins, with rewards
+// outside it counted in an extra bin at either end, and writes
+// "<output>.hist" as CSV (compressed along with a compressed series):
+//
+//     rank,true_mean,low,high,count,fraction
+//
+// one line per rank and bin, with the rank's true value averaged over tasks
+// and the fraction of that rank's rewards in the bin.
+
+use std::cmp;
+use std::io::{self, Write};
+
+use crate::output::{self, OutputFile};
+use crate::TaskOutcome;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBins {
+    pub low: f64,
+    pub high: f64,
+    pub bins: usize,
+}
+
+impl HistogramBins {
+    // Parses "low:high:bins".
+    pub fn parse(value: &str) -> Result<HistogramBins, String> {
+        let bad = || format!("invalid histogram `{}` (expected low:high:bins)", value);
+        let parts: Vec<&str> = value.split(':').collect();
+        if parts.len() != 3 {
+            return Err(bad());
+        }
+        let bins = HistogramBins {
+            low: parts[0].trim().parse().map_err(|_| bad())?,
+            high: parts[1].trim().parse().map_err(|_| bad())?,
+            bins: parts[2].trim().parse().map_err(|_| bad())?,
+        };
+        if !(bins.low.is_finite() && bins.high.is_finite() && bins.low < bins.high) ||
+           bins.bins == 0 {
+            return Err(format!("histogram `{}` needs finite low < high and at least one bin",
+                               value));
+        }
+        Ok(bins)
+    }
+
+    // Index of the bin `reward` falls in: 0 below low, bins + 1 above high.
+    fn index(&self, reward: f64) -> usize {
+        if reward < self.low {
+            return 0;
+        }
+        if reward > self.high {
+            return self.bins + 1;
+        }
+        let width = (self.high - self.low) / self.bins as f64;
+        1 + cmp::min(((reward - self.low) / width) as usize, self.bins - 1)
+    }
+
+    // Bounds of bin `i`, as numbered by index().
+    fn bounds(&self, i: usize) -> (f64, f64) {
+        let width = (self.high - self.low) / self.bins as f64;
+        match i {
+            0 => (f64::NEG_INFINITY, self.low),
+            i if i > self.bins => (self.high, f64::INFINITY),
+            i => (self.low + (i - 1) as f64 * width, self.low + i as f64 * width),
+        }
+    }
+}
+
+// Totals over tasks: the rewards counted per rank and bin, and the sum of
+// each rank's true value.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RewardHistograms {
+    pub bins: HistogramBins,
+    counts: Vec<Vec<u64>>,
+    true_means: Vec<f64>,
+    tasks: usize,
+}
+
+impl RewardHistograms {
+    pub fn new(bins: HistogramBins) -> RewardHistograms {
+        RewardHistograms { bins, counts: vec![], true_means: vec![], tasks: 0 }
+    }
+
+    fn grow(&mut self, num_arms: usize) {
+        if self.counts.len() < num_arms {
+            self.counts.resize(num_arms, vec![0; self.bins.bins + 2]);
+            self.true_means.resize(num_arms, 0.0);
+        }
+    }
+
+    pub fn add_task(&mut self, outcome: &TaskOutcome, q_star: &[f64]) {
+        let mut arms: Vec<usize> = (0..q_star.len()).collect();
+        arms.sort_by(|&a, &b| q_star[b].partial_cmp(&q_star[a]).unwrap_or(cmp::Ordering::Equal));
+        let mut rank = vec![0; q_star.len()];
+        for (r, &a) in arms.iter().enumerate() {
+            rank[a] = r;
+        }
+        self.grow(q_star.len());
+        for (&a, &reward) in outcome.actions.iter().zip(&outcome.rewards) {
+            self.counts[rank[a]][self.bins.index(reward)] += 1;
+        }
+        for (r, &a) in arms.iter().enumerate() {
+            self.true_means[r] += q_star[a];
+        }
+        self.tasks += 1;
+    }
+
+    pub fn add(&mut self, other: &RewardHistograms) {
+        self.grow(other.counts.len());
+        for r in 0..other.counts.len() {
+            for i in 0..other.counts[r].len() {
+                self.counts[r][i] += other.counts[r][i];
+            }
+            self.true_means[r] += other.true_means[r];
+        }
+        self.tasks += other.tasks;
+    }
+
+    // Writes the histograms to "<path>.hist", next to the series written to
+    // `path`.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let hist_path = output::companion(path, ".hist");
+        let write = || -> io::Result<()> {
+            let mut f = OutputFile::create(&hist_path)?;
+            writeln!(f, "rank,true_mean,low,high,count,fraction")?;
+            for (r, counts) in self.counts.iter().enumerate() {
+                let total = counts.iter().sum::<u64>();
+                let true_mean = self.true_means[r] / cmp::max(self.tasks, 1) as f64;
+                for (i, &count) in counts.iter().enumerate() {
+                    let (low, high) = self.bins.bounds(i);
+                    let fraction = if total > 0 { count as f64 / total as f64 } else { 0.0 };
+                    writeln!(f, "{},{},{},{},{},{}", r, true_mean, low, high, count, fraction)?;
+                }
+            }
+            f.finish()
+        };
+        write().map_err(|e| format!("couldn't write `{}`: {}", hist_path, e))
+    }
+}
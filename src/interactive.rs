@@ -0,0 +1,146 @@
+// Live decisions. In the `interactive` mode there is no simulator: the agent
+// (given with the usual agent flags) writes the arm it plays to stdout, one
+// index per line, and reads that arm's reward back from stdin, so any
+// outside system or script can drive it:
+//
+//     out: 3
+//     in:  0.71
+//     out: 0
+//     in:  -0.2
+//     ...
+//
+// until stdin ends, a line reads `quit`, or `--plays` plays are done. A line
+// that isn't a number is reported on stderr and read again. At the end a
+// summary of every arm goes to stderr, keeping stdout to the protocol; with
+// `--save-agent file` the agent is saved too, and `--load-agent file` starts
+// from a saved one instead (see agent_state), so a live agent can be
+// stopped and picked up again.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use rand::Rng;
+
+use crate::agent_state::SavedAgent;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{parse_value, seeded_rng, Agent};
+
+pub struct Options {
+    pub n: Option<usize>,
+    pub num_plays: Option<usize>,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+    pub save_agent: Option<String>,
+    pub load_agent: Option<SavedAgent>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        if flags.contains_key("load-agent") {
+            if let Some(key) = flags.keys().find(|k| is_agent_flag(k)) {
+                return Err(format!("`--{}` doesn't apply to a loaded agent", key));
+            }
+        }
+        let mut opts = Options {
+            n: None,
+            num_plays: None,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+            save_agent: None,
+            load_agent: None,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = Some(parse_value(key, value)?),
+                "plays" => opts.num_plays = Some(parse_value(key, value)?),
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "save-agent" => opts.save_agent = Some(value.clone()),
+                "load-agent" => {
+                    let saved = SavedAgent::load(value)?;
+                    opts.agent = saved.spec.clone();
+                    opts.load_agent = Some(saved);
+                }
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == Some(0) {
+            return Err("arms must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Reads lines until one is a reward, returning None at the end of the input
+// or on `quit`.
+fn read_reward(input: &mut dyn BufRead) -> Result<Option<f64>, String> {
+    loop {
+        let mut line = String::new();
+        let read = input.read_line(&mut line)
+                        .map_err(|e| format!("couldn't read a reward: {}", e))?;
+        let line = line.trim();
+        if read == 0 || line == "quit" {
+            return Ok(None);
+        }
+        match line.parse::<f64>() {
+            Ok(reward) if reward.is_finite() => return Ok(Some(reward)),
+            _ => {
+                let _ = writeln!(io::stderr(), "expected a reward, got `{}`", line);
+            }
+        }
+    }
+}
+
+fn serve<R: Rng>(rng: &mut R, agent: &mut dyn Agent, num_plays: Option<usize>)
+                 -> Result<usize, String> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut plays = 0;
+    while num_plays.is_none_or(|max| plays < max) {
+        let action = agent.try_choose_action(rng)?;
+        {
+            let mut out = stdout.lock();
+            writeln!(out, "{}", action).and_then(|_| out.flush())
+                .map_err(|e| format!("couldn't write an arm: {}", e))?;
+        }
+        match read_reward(&mut input)? {
+            Some(reward) => agent.try_receive_reward(reward, action)?,
+            None => break,
+        }
+        plays += 1;
+    }
+    Ok(plays)
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let mut agent = match opts.load_agent {
+        Some(ref saved) => {
+            let agent = saved.agent()?;
+            let arms = agent.arm_summaries().len();
+            if opts.n.is_some_and(|n| n != arms) {
+                return Err(format!("the loaded agent has {} arms, not {}", arms,
+                                   opts.n.unwrap()));
+            }
+            agent
+        }
+        None => opts.agent.build(opts.n.unwrap_or(10)),
+    };
+    let plays = match opts.seed {
+        Some(seed) => serve(&mut seeded_rng(seed), &mut *agent, opts.num_plays)?,
+        None => serve(&mut rand::thread_rng(), &mut *agent, opts.num_plays)?,
+    };
+
+    let mut stderr = io::stderr();
+    let _ = writeln!(stderr, "{}: {} plays", opts.agent.label(), plays);
+    for (arm, summary) in agent.arm_summaries().iter().enumerate() {
+        let _ = writeln!(stderr, "arm {}: {} plays, estimate {:.4}", arm, summary.count,
+                         summary.mean);
+    }
+    if let Some(ref path) = opts.save_agent {
+        SavedAgent::new(&opts.agent, &*agent).save(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,1409 @@
+extern crate flate2;
+#[macro_use]
+extern crate log;
+extern crate plotters;
+extern crate rand;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+use log::Level;
+use rand::{Rng, SeedableRng, StdRng};
+use rand::distributions::normal::StandardNormal;
+use rand::distributions::{IndependentSample, Range};
+use rayon::prelude::*;
+use std::cmp;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::ops;
+use std::process;
+use std::time::Instant;
+
+use crate::argmax::MaxTree;
+use crate::builder::ExperimentBuilder;
+use crate::checkpoint::{CheckpointFlags, Checkpointer};
+use crate::coldstart::ArmArrival;
+use crate::environment::Testbed;
+use crate::events::{ChoiceRecorder, EventFormat, EventLog};
+use crate::histogram::{HistogramBins, RewardHistograms};
+use crate::output::{PartialWriter, RawWriter};
+use crate::ring::RingBuffer;
+use crate::stopping::{Decision, StoppingRule};
+use crate::timing::{secs, PhaseTimer, PhaseTimes, TimedAgent};
+
+// What an agent registered from another crate (see registry) is made of.
+pub use crate::error::Error;
+pub use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+
+mod ab_test;
+mod agent_state;
+mod argmax;
+mod baseline;
+#[doc(hidden)]
+pub mod bench;
+mod bootstrap;
+mod builder;
+mod budget;
+mod checkpoint;
+mod coldstart;
+mod config;
+mod confseq;
+mod continuum;
+mod cvar;
+#[cfg(test)]
+mod determinism;
+mod diagnostics;
+mod doubling;
+mod drift;
+mod dueling;
+mod environment;
+mod error;
+mod events;
+mod experiment;
+mod explore_commit;
+mod explore_pool;
+mod features;
+pub mod ffi;
+mod forecast;
+mod grid;
+mod histogram;
+mod interactive;
+mod logging;
+mod meta;
+mod moss;
+mod offline;
+mod output;
+mod paired;
+mod pareto;
+mod plot;
+mod preprocess;
+mod progress;
+pub mod registry;
+mod retire;
+mod ring;
+mod risk;
+mod saved_testbed;
+mod seed_report;
+mod sensitivity;
+mod significance;
+mod slate;
+mod soak;
+mod softmax;
+mod spec;
+mod stats;
+mod stopping;
+mod summary;
+mod timing;
+mod tournament;
+mod trace;
+mod sweep;
+mod tune;
+mod ucb;
+mod warm_start;
+mod wrappers;
+
+// Anything that can play a bandit task: it picks one of the arms each play,
+// is told the reward for that arm, and can summarize what it has learned
+// about every arm so far. Arms can be added while the task is running; a new
+// arm starts from `prior` if one is given (see coldstart) and returns its
+// index, which is always the previous number of arms. Arms can also be
+// retired, which keeps them out of action selection until they're
+// reinstated; at least one arm must stay active. Rewards that arrive late
+// come in batches of (action, reward) pairs, oldest first.
+//
+// For multiple plays, choose_actions picks `k` distinct arms at once; by
+// default it picks them one at a time, keeping the arms already picked out
+// of the later choices, so there must be at least k active arms.
+//
+// state() is everything the agent has learned and been configured with, as
+// JSON; AgentSpec::restore turns it back into an agent (see agent_state).
+//
+// An agent can be warm-started before its first play from history: logged
+// (action, reward) pairs go to receive_rewards, and a count and mean reward
+// per arm to warm_start, which by default tells the agent `count` rewards
+// equal to the mean (see warm_start).
+//
+// choose_action and receive_reward trust their caller and panic on an arm
+// the agent doesn't have. Callers outside the simulator, which can't vouch
+// for their input, use try_choose_action and try_receive_reward, which
+// return an Error instead (see error).
+pub trait Agent {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize;
+    fn try_choose_action(&mut self, rng: &mut dyn Rng) -> Result<usize, Error> {
+        if self.arm_summaries().is_empty() {
+            return Err(Error::NoActiveArms);
+        }
+        Ok(self.choose_action(rng))
+    }
+    fn choose_actions(&mut self, k: usize, rng: &mut dyn Rng) -> Vec<usize> {
+        let mut actions = vec![];
+        for _ in 0..k {
+            let action = self.choose_action(rng);
+            self.set_retired(action, true);
+            actions.push(action);
+        }
+        for &action in &actions {
+            self.set_retired(action, false);
+        }
+        actions
+    }
+    fn receive_reward(&mut self, reward: f64, action: usize);
+    fn try_receive_reward(&mut self, reward: f64, action: usize) -> Result<(), Error> {
+        let arms = self.arm_summaries().len();
+        if action >= arms {
+            return Err(Error::InvalidArm { arm: action, arms });
+        }
+        if !reward.is_finite() {
+            return Err(Error::InvalidReward(reward));
+        }
+        self.receive_reward(reward, action);
+        Ok(())
+    }
+    fn receive_rewards(&mut self, rewards: &[(usize, f64)]) {
+        for &(action, reward) in rewards {
+            self.receive_reward(reward, action);
+        }
+    }
+    fn warm_start(&mut self, arms: &[ArmSummary]) {
+        for (action, arm) in arms.iter().enumerate() {
+            for _ in 0..arm.count {
+                self.receive_reward(arm.mean, action);
+            }
+        }
+    }
+    fn arm_summaries(&self) -> Vec<ArmSummary>;
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize;
+    fn set_retired(&mut self, arm: usize, retired: bool);
+
+    // Every arm's posterior, for agents that keep one.
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        None
+    }
+    fn state(&self) -> serde_json::Value;
+}
+
+#[derive(Serialize, Deserialize)]
+struct EpsilonGreedyBandit {
+    // number of arms
+    n: usize,
+
+    // Actions are a_0 through a_{n-1}. For each we keep the number and
+    // sum of the rewards received when choosing it, whose average is the
+    // estimate of Q_t(a), the value of taking action a at time t, which is
+    // not known with certainty.
+    counts: Vec<usize>,
+    sums: Vec<f64>,
+
+    // with a step size, each action's recency-weighted estimate Q_k
+    weighted: Vec<f64>,
+
+    // parameter for the greediness of the bandit
+    epsilon: f64,
+
+    // If set, estimates are exponential recency-weighted averages with this
+    // constant step size instead of sample averages.
+    step_size: Option<f64>,
+
+    // Pseudo-observations of arms added with a prior. With a step size the
+    // prior mean is used as Q_0 instead.
+    priors: Vec<Option<ArmPrior>>,
+
+    // arms kept out of action selection
+    retired: Vec<bool>,
+
+    // Estimates within this much of the maximum count as tied for it, so
+    // rounding in the estimates doesn't decide ties. 0 means exact equality.
+    tie_tolerance: f64,
+
+    // Arms with fewer rewards than this are played (uniformly among them)
+    // before any action is chosen by estimate, so a lucky early draw can't
+    // lock in an arm.
+    min_samples: usize,
+
+    // If set, estimates are medians of the means of this many groups of
+    // consecutive rewards, which a few huge rewards can't drag around the
+    // way they do a sample average. Those need the rewards themselves, so
+    // each action's most recent ones are kept (see ring).
+    mom_groups: Option<usize>,
+    recent: Vec<RingBuffer<f64>>,
+
+    // If set, every arm's estimate also draws on the rewards of the other
+    // arms, weighted by exp(-d^2 / (2 width^2)) for arms d indices away, for
+    // testbeds where neighbouring arms are alike.
+    kernel_width: Option<f64>,
+
+    // With a kernel, every arm's kernel-weighted sums of the rewards and
+    // of their counts, which a reward only adds to within the few widths
+    // the kernel doesn't underflow to 0 over (see kernel_neighbours). Built
+    // from `sums` and `counts` when missing.
+    #[serde(default)]
+    kernel_totals: Option<Vec<(f64, f64)>>,
+
+    // Every action's current estimate, -inf for retired ones, kept up to
+    // date as rewards arrive so choosing greedily doesn't recompute and
+    // rescan all of them (see argmax). Rebuilt from the rest when missing,
+    // e.g. in an agent restored from its state.
+    #[serde(skip)]
+    estimates: Option<MaxTree>,
+}
+
+impl EpsilonGreedyBandit {
+    fn new(n: usize, epsilon: f64) -> EpsilonGreedyBandit {
+        EpsilonGreedyBandit {
+            n,
+            counts: vec![0; n],
+            sums: vec![0.0; n],
+            weighted: vec![0.0; n],
+            epsilon,
+            step_size: None,
+            priors: vec![None; n],
+            retired: vec![false; n],
+            tie_tolerance: 0.0,
+            min_samples: 0,
+            mom_groups: None,
+            recent: vec![],
+            kernel_width: None,
+            kernel_totals: None,
+            estimates: None,
+        }
+    }
+
+    fn set_tie_tolerance(&mut self, tolerance: f64) {
+        assert!(tolerance >= 0.0);
+        self.tie_tolerance = tolerance;
+    }
+
+    fn set_min_samples(&mut self, min_samples: usize) {
+        self.min_samples = min_samples;
+    }
+
+    // Medians of means over the last `history` rewards of each action.
+    fn set_median_of_means(&mut self, groups: usize, history: usize) {
+        assert!(groups > 0 && self.step_size.is_none());
+        self.mom_groups = Some(groups);
+        self.recent = vec![RingBuffer::new(history); self.n];
+    }
+
+    fn set_kernel_width(&mut self, width: f64) {
+        assert!(width > 0.0 && self.step_size.is_none() && self.mom_groups.is_none());
+        self.kernel_width = Some(width);
+    }
+
+    fn with_step_size(n: usize, epsilon: f64, step_size: f64) -> EpsilonGreedyBandit {
+        assert!(step_size > 0.0 && step_size <= 1.0);
+        let mut bandit = EpsilonGreedyBandit::new(n, epsilon);
+        bandit.step_size = Some(step_size);
+        bandit
+    }
+
+    // The estimate kept for `action`: -inf if it's retired.
+    fn kept_estimate(&self, action: usize) -> f64 {
+        if self.retired[action] { f64::NEG_INFINITY } else { self.calculate_estimate(action) }
+    }
+
+    // Brings the kept estimates up to date after a reward for `action`.
+    // With a kernel, the estimates of the arms it reaches move too.
+    fn update_estimates(&mut self, action: usize) {
+        if self.estimates.is_none() {
+            return;
+        }
+        let mut changed = vec![];
+        match self.kernel_width {
+            Some(width) => kernel_neighbours(self.n, action, width, |i, _| changed.push(i)),
+            None => changed.push(action),
+        }
+        for i in changed {
+            let estimate = self.kept_estimate(i);
+            self.estimates.as_mut().unwrap().set(i, estimate);
+        }
+    }
+
+    // Adds a reward for `action` to the kernel totals, once it's in `sums`
+    // and `counts`.
+    fn update_kernel_totals(&mut self, action: usize, reward: f64, width: f64) {
+        if self.kernel_totals.is_none() {
+            let totals = (0..self.n).map(|i| self.kernel_total(i, width)).collect();
+            self.kernel_totals = Some(totals);
+            return;
+        }
+        let totals = self.kernel_totals.as_mut().unwrap();
+        kernel_neighbours(self.n, action, width, |i, k| {
+            totals[i].0 += k * reward;
+            totals[i].1 += k;
+        });
+    }
+
+    // The kernel-weighted sums of the rewards and counts around `action`.
+    fn kernel_total(&self, action: usize, width: f64) -> (f64, f64) {
+        let (mut sum, mut weight) = (0.0, 0.0);
+        kernel_neighbours(self.n, action, width, |other, k| {
+            sum += k * self.sums[other];
+            weight += k * self.counts[other] as f64;
+        });
+        (sum, weight)
+    }
+
+    fn calculate_estimate(&self, action: usize) -> f64 {
+        let num_actions = self.counts.len();
+        assert!(action < num_actions);
+
+        let num_past_rewards = self.counts[action];
+        let prior = self.priors[action].unwrap_or(ArmPrior { mean: 0.0, weight: 0.0 });
+
+        if let Some(width) = self.kernel_width {
+            let (sum, weight) = match self.kernel_totals {
+                Some(ref totals) => totals[action],
+                None => self.kernel_total(action, width),
+            };
+            let sum = prior.weight * prior.mean + sum;
+            let weight = prior.weight + weight;
+            return if weight > 0.0 { sum / weight } else { 0.0 };
+        }
+
+        if num_past_rewards == 0 && prior.weight == 0.0 { return 0.0 }
+
+        if self.step_size.is_some() {
+            return self.weighted[action];
+        }
+
+        if let Some(groups) = self.mom_groups {
+            if num_past_rewards > 0 {
+                return median_of_means(&self.recent[action].to_vec(), groups);
+            }
+        }
+
+        (prior.weight * prior.mean + self.sums[action]) / (num_past_rewards as f64 + prior.weight)
+    }
+}
+
+impl Agent for EpsilonGreedyBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        // Rng's generic methods need a sized receiver
+        let rng = &mut rng;
+
+        // It doesn't make sense if there are no possible actions.
+        // If there's only one possible action, the whole exercise is
+        // pointless, but we still allow it.
+        assert!(self.n > 0);
+
+        if self.min_samples > 0 {
+            let under_sampled: Vec<usize> = (0..self.n).filter(|&i| {
+                !self.retired[i] && self.counts[i] < self.min_samples
+            }).collect();
+            if !under_sampled.is_empty() {
+                let action = under_sampled[rng.gen_range(0, under_sampled.len())];
+                debug!("arm {} has fewer than {} rewards", action, self.min_samples);
+                return action;
+            }
+        }
+
+        // estimate "true values" for each action; retired actions can never
+        // be the max
+        if self.estimates.is_none() {
+            let estimates: Vec<f64> = (0..self.n).map(|i| self.kept_estimate(i)).collect();
+            self.estimates = Some(MaxTree::new(&estimates));
+        }
+        let tree = self.estimates.as_ref().unwrap();
+
+        // Pick a random number uniformly between 0 and 1 to see
+        // if it's > epsilon (and so pick a greedy action)
+        // or <= (and so pick a non-greedy move)
+        let between = Range::new(0f64, 1.);
+        let x = between.ind_sample(rng);
+
+        if x > self.epsilon {
+            // choose an action with a max value
+            let max_actions = tree.at_least(tree.max() - self.tie_tolerance);
+            assert!(!max_actions.is_empty());
+            let k = rng.gen_range(0, max_actions.len());
+            debug!("exploiting: arm {} (estimate {:.4})", max_actions[k],
+                   tree.get(max_actions[k]));
+            max_actions[k]
+        } else {
+            // choose a non-max action, an active one not tied for the max.
+            // Most arms usually are, so arms are drawn until one is, and
+            // only if that keeps failing are they all looked through.
+            let threshold = tree.max() - self.tie_tolerance;
+            let reaches = |i: usize| tree.get(i).partial_cmp(&threshold)
+                                                .is_some_and(cmp::Ordering::is_ge);
+            let non_max = |i: usize| !self.retired[i] && !reaches(i);
+            for _ in 0..EXPLORE_DRAWS {
+                let i = rng.gen_range(0, self.n);
+                if non_max(i) {
+                    debug!("exploring: arm {} (estimate {:.4})", i, tree.get(i));
+                    return i;
+                }
+            }
+            let non_max_actions: Vec<usize> = (0..self.n).filter(|&i| non_max(i)).collect();
+            if !non_max_actions.is_empty() {
+                let k = rng.gen_range(0, non_max_actions.len());
+                debug!("exploring: arm {} (estimate {:.4})", non_max_actions[k],
+                       tree.get(non_max_actions[k]));
+                non_max_actions[k]
+            } else {
+                let max_actions = tree.at_least(threshold);
+                let k = rng.gen_range(0, max_actions.len());
+                debug!("exploring, but every active arm is tied for the maximum: arm {}",
+                       max_actions[k]);
+                max_actions[k]
+            }
+        }
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.counts[action] += 1;
+        self.sums[action] += reward;
+        if let Some(alpha) = self.step_size {
+            // Q_{k+1} = Q_k + alpha * (R_k - Q_k)
+            self.weighted[action] += alpha * (reward - self.weighted[action]);
+        }
+        if self.mom_groups.is_some() {
+            self.recent[action].push(reward);
+        }
+        if let Some(width) = self.kernel_width {
+            self.update_kernel_totals(action, reward, width);
+        }
+        self.update_estimates(action);
+        if log_enabled!(Level::Debug) {
+            debug!("arm {}: reward {:.4}, estimate now {:.4} over {} rewards", action, reward,
+                   self.calculate_estimate(action), self.counts[action]);
+        }
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        let mut summaries = vec![];
+        for i in 0..self.n {
+            summaries.push(ArmSummary {
+                count: self.counts[i],
+                mean: self.calculate_estimate(i),
+            });
+        }
+        summaries
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        // with a step size, Q_0 is the prior mean
+        let q_0 = match prior {
+            Some(prior) if prior.weight > 0.0 => prior.mean,
+            _ => 0.0,
+        };
+        self.counts.push(0);
+        self.sums.push(0.0);
+        self.weighted.push(q_0);
+        if self.mom_groups.is_some() {
+            let history = self.recent[0].capacity();
+            self.recent.push(RingBuffer::new(history));
+        }
+        self.priors.push(prior);
+        self.retired.push(false);
+        self.n += 1;
+        if let (Some(width), Some(_)) = (self.kernel_width, self.kernel_totals.as_ref()) {
+            let total = self.kernel_total(self.n - 1, width);
+            self.kernel_totals.as_mut().unwrap().push(total);
+        }
+        let estimate = self.kept_estimate(self.n - 1);
+        if let Some(ref mut estimates) = self.estimates {
+            estimates.push(estimate);
+        }
+        self.n - 1
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+        let estimate = self.kept_estimate(arm);
+        if let Some(ref mut estimates) = self.estimates {
+            estimates.set(arm, estimate);
+        }
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+// Explorative draws of a random arm before EpsilonGreedyBandit falls back
+// to listing the arms it may explore.
+const EXPLORE_DRAWS: usize = 32;
+
+// Calls `f(other, k)` for every arm `other` of `n` that the kernel of
+// `width` around `arm` gives a weight k = exp(-d^2 / (2 width^2)) other
+// than 0, nearest first, d being how many arms apart they are. The kernel
+// underflows to 0 within a few dozen widths, so a reward only reaches that
+// many arms however many there are.
+fn kernel_neighbours<F: FnMut(usize, f64)>(n: usize, arm: usize, width: f64, mut f: F) {
+    for d in 0..n {
+        let dist = d as f64;
+        let k = (-dist * dist / (2.0 * width * width)).exp();
+        if k == 0.0 || (d > arm && arm + d >= n) {
+            break;
+        }
+        if d <= arm {
+            f(arm - d, k);
+        }
+        if d > 0 && arm + d < n {
+            f(arm + d, k);
+        }
+    }
+}
+
+// Median of the means of `groups` groups of consecutive values (fewer if
+// there aren't that many values), as equal in size as possible.
+fn median_of_means(xs: &[f64], groups: usize) -> f64 {
+    let groups = cmp::min(groups, xs.len());
+    let mut means = vec![];
+    for g in 0..groups {
+        let group = &xs[g * xs.len() / groups..(g + 1) * xs.len() / groups];
+        means.push(group.iter().fold(0.0, |acc, x| acc + x) / group.len() as f64);
+    }
+    means.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+    if groups % 2 == 1 {
+        means[groups / 2]
+    } else {
+        (means[groups / 2 - 1] + means[groups / 2]) / 2.0
+    }
+}
+
+struct BanditTask {
+    n: usize,
+}
+
+// What happened during one task: the reward received, the action taken,
+// whether it was an optimal one (an arm with the highest true value at that
+// play) and the regret of taking it (the best true value minus the true value
+// of the action) at each play, along with what an oracle always playing an
+// optimal arm would have received instead. A play the environment didn't
+// reward (a replayed log event for another arm) has reward 0 and isn't
+// `rewarded`.
+struct TaskOutcome {
+    rewards: Vec<f64>,
+    rewarded: Vec<bool>,
+    oracle_rewards: Vec<f64>,
+    actions: Vec<usize>,
+    optimal: Vec<bool>,
+    regrets: Vec<f64>,
+}
+
+impl BanditTask {
+    fn new(n: usize) -> BanditTask {
+        BanditTask {
+            n,
+        }
+    }
+
+    // Draws Q^*(j), the "true value" of each action j for one task, from a
+    // standard normal dist.
+    fn draw_q_star<R: Rng>(&self, rng: &mut R) -> Vec<f64> {
+        let mut q_star: Vec<f64> = vec![];
+        for _ in 0..self.n {
+            let StandardNormal(true_value) = rng.gen();
+            q_star.push(true_value);
+        }
+        q_star
+    }
+
+    // The reward for selecting an action with true value `q` is chosen from
+    // a normal with mean q and variance 1, as q plus standard normal noise
+    // (exactly what Normal::new(q, 1.0) gives, without building a Normal for
+    // every play). Rewards are drawn only for the arms that are played, so a
+    // play costs the same however many arms there are.
+    fn sample_reward<R: Rng>(&self, q: f64, rng: &mut R) -> f64 {
+        let StandardNormal(noise) = rng.gen();
+        q + noise
+    }
+
+    // Returns the reward and action at each stage, along with the task's
+    // true values
+    fn run_task<R: Rng>(&mut self, bandit: &mut dyn Agent, num_plays: usize,
+                        rng: &mut R) -> (TaskOutcome, Vec<f64>) {
+        let q_star = self.draw_q_star(rng);
+        let outcome = self.play_task_on(&q_star, bandit, num_plays, rng);
+        (outcome, q_star)
+    }
+
+    // Same as run_task, but against given true values, so several bandits
+    // can be compared on the same task. The oracle's reward is drawn for the
+    // best arm after the bandit's, or is the bandit's when it played the
+    // best arm.
+    fn play_task_on<R: Rng>(&mut self, q_star: &[f64], bandit: &mut dyn Agent,
+                            num_plays: usize, rng: &mut R) -> TaskOutcome {
+        let mut rewards = vec![];
+        let mut rewarded = vec![];
+        let mut oracle_rewards = vec![];
+        let mut actions = vec![];
+        let mut optimal = vec![];
+        let mut regrets = vec![];
+        let best = best_arm(q_star);
+
+        for _ in 0..num_plays {
+            // Bandit is prompted to choose an action, 
+            let action = bandit.choose_action(rng);
+            let reward = self.sample_reward(q_star[action], rng);
+            let oracle_reward = if action == best {
+                reward
+            } else {
+                self.sample_reward(q_star[best], rng)
+            };
+            rewards.push(reward);
+            rewarded.push(true);
+            oracle_rewards.push(oracle_reward);
+            actions.push(action);
+            optimal.push(q_star[action] == q_star[best]);
+            regrets.push(q_star[best] - q_star[action]);
+            bandit.receive_reward(reward, action);
+        }
+        TaskOutcome {
+            rewards,
+            rewarded,
+            oracle_rewards,
+            actions,
+            optimal,
+            regrets,
+        }
+    }
+
+    // Like run_task, but the stopping rule is consulted as `stop` says and
+    // the task ends as soon as it declares a winner. `on_check` sees the arm
+    // statistics at each check, for monitoring, and may adjust the bandit
+    // (e.g. retire arms) before the rule runs. If `arrival` is given, a new
+    // arm joins the task at that play and the bandit bootstraps it from the
+    // arms it is similar to.
+    fn run_until_stopped<R: Rng>(&mut self, bandit: &mut dyn Agent, stop: &StopCheck,
+                                 arrival: Option<&ArmArrival>, on_check: &mut OnCheck<'_>,
+                                 rng: &mut R) -> StoppedTask {
+        let StopCheck { rule, check_every, max_plays } = *stop;
+        let mut stopped = StoppedTask {
+            rewards: vec![],
+            decision: None,
+            q_star: self.draw_q_star(rng),
+            new_arm_prior: None,
+        };
+
+        for t in 0..max_plays {
+            if let Some(arrival) = arrival {
+                if arrival.at == t {
+                    let q = arrival.true_value(&stopped.q_star, rng);
+                    stopped.q_star.push(q);
+                    stopped.new_arm_prior = arrival.prior(&bandit.arm_summaries());
+                    bandit.add_arm(stopped.new_arm_prior);
+                }
+            }
+
+            let action = bandit.choose_action(rng);
+            let reward = self.sample_reward(stopped.q_star[action], rng);
+            stopped.rewards.push(reward);
+            bandit.receive_reward(reward, action);
+
+            if (t + 1) % check_every == 0 {
+                let arms = bandit.arm_summaries();
+                on_check(t + 1, &arms, bandit);
+                if let Some(decision) = rule.check(&arms, t + 1, rng) {
+                    stopped.decision = Some(decision);
+                    return stopped;
+                }
+            }
+        }
+        stopped
+    }
+}
+
+// How a task run until stopped checks for a winner: with `rule`, every
+// `check_every` plays, for at most `max_plays` plays.
+struct StopCheck<'a> {
+    rule: &'a dyn StoppingRule,
+    check_every: usize,
+    max_plays: usize,
+}
+
+// Called at every check of a task run until stopped, with the plays so far,
+// the arm statistics and the bandit.
+type OnCheck<'a> = dyn FnMut(usize, &[ArmSummary], &mut dyn Agent) + 'a;
+
+// What happened during a task run until stopped: the rewards received, the
+// decision (if any), the task's q_star (including any arm that joined) and
+// the prior the joining arm was given.
+struct StoppedTask {
+    rewards: Vec<f64>,
+    decision: Option<Decision>,
+    q_star: Vec<f64>,
+    new_arm_prior: Option<ArmPrior>,
+}
+
+fn dump_vec_to_file(v: &[f64], file_name: &str) -> io::Result<()> {
+    let mut f = File::create(file_name)?;
+    for x in v {
+        let s = format!("{:?}", x);
+        f.write_all(s.as_bytes())?;
+        f.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// Index of the arm with the highest true value.
+fn best_arm(q_star: &[f64]) -> usize {
+    let mut best = 0;
+    for i in 1..q_star.len() {
+        if q_star[i] > q_star[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+// Plays covered by the action-selection entropy (see diagnostics).
+const ENTROPY_WINDOW: usize = 100;
+
+// Per-play averages over all the tasks of a run.
+#[derive(Clone, Serialize, Deserialize)]
+struct Curves {
+    // over the tasks in which the play was rewarded
+    avg_reward: Vec<f64>,
+
+    // Standard error of avg_reward across tasks, from the running sums of
+    // squared rewards.
+    reward_se: Vec<f64>,
+    reward_sq: Vec<f64>,
+
+    // average reward of always playing the best arm, on the same draws
+    oracle_reward: Vec<f64>,
+
+    // number of tasks in which the play was rewarded: all of them, but for
+    // replayed logs, where only plays matching the logged arm are
+    rewarded: Vec<f64>,
+
+    // percentage of tasks in which the best arm was played
+    pct_optimal: Vec<f64>,
+
+    // entropy in bits of the actions over the last ENTROPY_WINDOW plays
+    entropy: Vec<f64>,
+
+    // regret accumulated over the plays so far
+    cum_regret: Vec<f64>,
+
+    // plays per task of the arm with the i-th highest true value (not per
+    // play, so it isn't one of the columns)
+    pulls_by_rank: Vec<f64>,
+
+    // every rank's observed rewards, if wanted (totals, never averaged)
+    #[serde(default)]
+    histograms: Option<RewardHistograms>,
+}
+
+impl Curves {
+    fn new(num_plays: usize) -> Curves {
+        Curves {
+            avg_reward: vec![0.0; num_plays],
+            reward_se: vec![0.0; num_plays],
+            reward_sq: vec![0.0; num_plays],
+            oracle_reward: vec![0.0; num_plays],
+            rewarded: vec![0.0; num_plays],
+            pct_optimal: vec![0.0; num_plays],
+            entropy: vec![0.0; num_plays],
+            cum_regret: vec![0.0; num_plays],
+            pulls_by_rank: vec![],
+            histograms: None,
+        }
+    }
+
+    // Adds one task, with true values `q_star`, to the running totals.
+    fn add_task(&mut self, outcome: &TaskOutcome, q_star: &[f64]) {
+        let entropy = diagnostics::window_entropy(&outcome.actions, q_star.len(),
+                                                  ENTROPY_WINDOW);
+        let pulls = diagnostics::pulls_by_rank(&outcome.actions, q_star);
+        self.grow_pulls(pulls.len());
+        for (total, &count) in self.pulls_by_rank.iter_mut().zip(&pulls) {
+            *total += count as f64;
+        }
+        if let Some(ref mut histograms) = self.histograms {
+            histograms.add_task(outcome, q_star);
+        }
+        let mut regret = 0.0;
+        for (t, &entropy) in entropy.iter().enumerate().take(self.avg_reward.len()) {
+            if outcome.rewarded[t] {
+                self.avg_reward[t] += outcome.rewards[t];
+                self.reward_sq[t] += outcome.rewards[t] * outcome.rewards[t];
+                self.rewarded[t] += 1.0;
+            }
+            self.oracle_reward[t] += outcome.oracle_rewards[t];
+            if outcome.optimal[t] {
+                self.pct_optimal[t] += 100.0;
+            }
+            self.entropy[t] += entropy;
+            regret += outcome.regrets[t];
+            self.cum_regret[t] += regret;
+        }
+    }
+
+    fn add(&mut self, other: &Curves) {
+        for t in 0..self.avg_reward.len() {
+            self.avg_reward[t] += other.avg_reward[t];
+            self.reward_sq[t] += other.reward_sq[t];
+            self.oracle_reward[t] += other.oracle_reward[t];
+            self.rewarded[t] += other.rewarded[t];
+            self.pct_optimal[t] += other.pct_optimal[t];
+            self.entropy[t] += other.entropy[t];
+            self.cum_regret[t] += other.cum_regret[t];
+        }
+        self.grow_pulls(other.pulls_by_rank.len());
+        for i in 0..other.pulls_by_rank.len() {
+            self.pulls_by_rank[i] += other.pulls_by_rank[i];
+        }
+        if let (Some(ref mut histograms), Some(ref other)) = (&mut self.histograms,
+                                                               &other.histograms) {
+            histograms.add(other);
+        }
+    }
+
+    // Makes room for pull counts of `num_arms` arms.
+    fn grow_pulls(&mut self, num_arms: usize) {
+        if self.pulls_by_rank.len() < num_arms {
+            self.pulls_by_rank.resize(num_arms, 0.0);
+        }
+    }
+
+    // Turns totals over `num_tasks` tasks into averages, and the reward's
+    // sample variance across tasks into a standard error. The reward is
+    // averaged over the tasks in which the play was rewarded, and is 0 if
+    // there were none.
+    fn average(&mut self, num_tasks: usize) {
+        let n = num_tasks as f64;
+        for t in 0..self.avg_reward.len() {
+            let m = self.rewarded[t];
+            if m > 0.0 {
+                self.avg_reward[t] /= m;
+            }
+            if m > 1.0 {
+                let mean = self.avg_reward[t];
+                let var = ((self.reward_sq[t] - m * mean * mean) / (m - 1.0)).max(0.0);
+                self.reward_se[t] = (var / m).sqrt();
+            }
+            self.oracle_reward[t] /= n;
+            self.pct_optimal[t] /= num_tasks as f64;
+            self.entropy[t] /= num_tasks as f64;
+            self.cum_regret[t] /= num_tasks as f64;
+        }
+        for x in self.pulls_by_rank.iter_mut() {
+            *x /= num_tasks as f64;
+        }
+    }
+
+    // The series written to output files, in column order.
+    fn columns(&self) -> Vec<(&str, &Vec<f64>)> {
+        vec![("avg_reward", &self.avg_reward), ("reward_se", &self.reward_se),
+             ("oracle_reward", &self.oracle_reward), ("pct_optimal", &self.pct_optimal),
+             ("entropy", &self.entropy), ("cum_regret", &self.cum_regret),
+             ("rewarded", &self.rewarded)]
+    }
+}
+
+// Runs `num_tasks` independent tasks with a fresh bandit each and returns
+// the reward at each play (and how often the best arm was played) averaged
+// over all tasks.
+fn run_experiment<R: Rng>(rng: &mut R, n: usize, num_tasks: usize, num_plays: usize,
+                          epsilon: f64, verbose: bool) -> Curves {
+    let run = RunConfig {
+        verbose,
+        ..RunConfig::new(&Testbed::Gaussian { sd: None }, n, num_tasks, num_plays)
+    };
+    run_experiment_with(rng, &run, &|| Box::new(EpsilonGreedyBandit::new(n, epsilon)))
+}
+
+// What run_experiment_with runs: `num_tasks` tasks of `num_plays` plays with
+// `n` arms on `testbed`. With `partial`, the averages over the tasks
+// finished so far are written out as the run goes, with `raw`, every task's
+// own rewards, with `checkpoint`, the totals so far, from which the run can
+// be resumed (see checkpoint), and with `events`, every play. With `timer`,
+// the time spent in every phase of the run is added to it (see timing).
+#[derive(Clone, Copy)]
+struct RunConfig<'a> {
+    testbed: &'a Testbed,
+    n: usize,
+    num_tasks: usize,
+    num_plays: usize,
+    verbose: bool,
+    partial: Option<&'a PartialWriter>,
+    raw: Option<&'a RawWriter>,
+    checkpoint: Option<&'a Checkpointer>,
+    events: Option<&'a EventLog>,
+    histogram: Option<&'a HistogramBins>,
+    timer: Option<&'a PhaseTimer>,
+}
+
+impl<'a> RunConfig<'a> {
+    // A quiet run with none of the optional outputs.
+    fn new(testbed: &'a Testbed, n: usize, num_tasks: usize, num_plays: usize) -> RunConfig<'a> {
+        RunConfig {
+            testbed,
+            n,
+            num_tasks,
+            num_plays,
+            verbose: false,
+            partial: None,
+            raw: None,
+            checkpoint: None,
+            events: None,
+            histogram: None,
+            timer: None,
+        }
+    }
+}
+
+// Same as run_experiment, for any kind of agent and testbed. `make_agent` is
+// called once per task to get a fresh agent.
+fn run_experiment_with<R: Rng>(rng: &mut R, run: &RunConfig,
+                               make_agent: &(dyn Fn() -> Box<dyn Agent> + Sync)) -> Curves {
+    let RunConfig { testbed, n, num_tasks, num_plays, verbose, partial, raw, checkpoint, events,
+                    histogram, timer } = *run;
+    let seeds = task_seeds(rng, num_tasks);
+    progress::start(num_tasks);
+    let batch = cmp::min(partial.map_or(num_tasks, |p| p.batch_size()),
+                         checkpoint.map_or(num_tasks, |c| c.batch_size()));
+    let (mut curves, mut done) = match checkpoint.and_then(|c| c.resumed.as_ref()) {
+        Some(saved) => {
+            progress::resumed(saved.tasks_done);
+            (saved.totals.clone(), saved.tasks_done)
+        }
+        None => (Curves::new(num_plays), 0),
+    };
+    let new_sums = || {
+        let mut sums = Curves::new(num_plays);
+        sums.histograms = histogram.map(|&bins| RewardHistograms::new(bins));
+        sums
+    };
+    if let Some(&bins) = histogram {
+        if curves.histograms.as_ref().map(|h| h.bins) != Some(bins) {
+            if done > 0 {
+                warn!("the checkpoint has no histograms with these bins; they only cover the \
+                       tasks from {} on", done);
+            }
+            curves.histograms = Some(RewardHistograms::new(bins));
+        }
+    }
+    let mut checkpointed = done;
+
+    while done < num_tasks {
+        let end = cmp::min(done + batch, num_tasks);
+        let chunk_sums = map_task_chunks(done..end, |tasks| {
+            let mut sums = new_sums();
+            let mut times = PhaseTimes::default();
+            let mut chunk_events = vec![];
+            for i in tasks.clone() {
+                if verbose && progress::task_lines() {
+                    info!("Task #{}", i);
+                }
+                let mut rng = seeded_rng(seeds[i]);
+                let mut bandit = make_agent();
+                let mut play = |agent: &mut dyn Agent, rng: &mut StdRng| {
+                    let started = Instant::now();
+                    match events {
+                        Some(events) => {
+                            let mut recorder = ChoiceRecorder::new(agent);
+                            let result = testbed.run_task(n, i, &mut recorder, num_plays, rng);
+                            let played = started.elapsed();
+                            events.format_task(i, &result.0, &recorder.choices,
+                                               &mut chunk_events);
+                            (result, played)
+                        }
+                        None => {
+                            let result = testbed.run_task(n, i, agent, num_plays, rng);
+                            (result, started.elapsed())
+                        }
+                    }
+                };
+                let ((outcome, q_star), _) = match timer {
+                    Some(_) => {
+                        let mut timed = TimedAgent::new(&mut *bandit);
+                        let result = play(&mut timed, &mut rng);
+                        let (selection, update) = (secs(timed.selection), secs(timed.update));
+                        times.selection += selection;
+                        times.update += update;
+                        times.environment += secs(result.1) - selection - update;
+                        result
+                    }
+                    None => play(&mut *bandit, &mut rng),
+                };
+                if let Some(raw) = raw {
+                    raw.write(i, &outcome);
+                }
+                let started = Instant::now();
+                sums.add_task(&outcome, &q_star);
+                times.aggregation += secs(started.elapsed());
+                progress::task_done(i);
+            }
+            if let Some(events) = events {
+                events.write_tasks(tasks, chunk_events);
+            }
+            if let Some(timer) = timer {
+                timer.add(&times);
+            }
+            sums
+        });
+        let started = Instant::now();
+        for sums in &chunk_sums {
+            curves.add(sums);
+        }
+        if let Some(timer) = timer {
+            timer.add(&PhaseTimes { aggregation: secs(started.elapsed()), ..Default::default() });
+        }
+        done = end;
+
+        if let Some(partial) = partial {
+            let mut so_far = curves.clone();
+            so_far.average(done);
+            partial.write(&so_far, done, num_tasks);
+        }
+        if let Some(checkpoint) = checkpoint {
+            if done - checkpointed >= checkpoint.batch_size() || done == num_tasks {
+                checkpoint.write(&curves, done, num_tasks);
+                checkpointed = done;
+            }
+        }
+    }
+    let started = Instant::now();
+    curves.average(num_tasks);
+    if let Some(timer) = timer {
+        timer.add(&PhaseTimes { aggregation: secs(started.elapsed()), ..Default::default() });
+    }
+    curves
+}
+
+fn seeded_rng(seed: usize) -> StdRng {
+    let seed: &[_] = &[seed];
+    SeedableRng::from_seed(seed)
+}
+
+// Tasks run in parallel, each with its own RNG. A single master seed is
+// drawn from the caller's RNG and task i's seed is derived from it and i
+// alone, so a seeded run is reproducible however the tasks end up scheduled
+// and however many threads there are.
+fn task_seeds<R: Rng>(rng: &mut R, num_tasks: usize) -> Vec<usize> {
+    let master: u64 = rng.gen();
+    let mut seeds = vec![];
+    for i in 0..num_tasks {
+        seeds.push(task_seed(master, i as u64) as usize);
+    }
+    seeds
+}
+
+// SplitMix64 output for the master seed advanced `index + 1` times, which
+// spreads consecutive indices over unrelated seeds.
+fn task_seed(master: u64, index: u64) -> u64 {
+    let mut z = master.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Sizes the thread pool used for tasks; by default there is one thread per
+// CPU.
+fn set_threads(value: &str) -> Result<(), String> {
+    let threads: usize = parse_value("threads", value)?;
+    if threads == 0 {
+        return Err("threads must be positive".to_string());
+    }
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()
+        .map_err(|e| format!("couldn't start {} threads: {}", threads, e))
+}
+
+// Tasks are handed to the thread pool in chunks of this many.
+const TASK_CHUNK: usize = 16;
+
+// Calls `f` with consecutive ranges of the task indices in `tasks`, in
+// parallel, and returns the results in task order. Callers accumulate within
+// a chunk and then combine the chunk results in order, so floating point
+// sums come out the same no matter how many threads there are.
+fn map_task_chunks<T, F>(tasks: ops::Range<usize>, f: F) -> Vec<T>
+    where T: Send, F: Fn(ops::Range<usize>) -> T + Sync
+{
+    let start = tasks.start;
+    let num_chunks = (tasks.end - start).div_ceil(TASK_CHUNK);
+    (0..num_chunks).into_par_iter()
+                   .map(|c| f(start + c * TASK_CHUNK..
+                              cmp::min(start + (c + 1) * TASK_CHUNK, tasks.end)))
+                   .collect()
+}
+
+// Flags that take no value; they're collected with the value "true".
+const SWITCHES: &[&str] = &["quiet", "timing"];
+
+// Collects `--key value` pairs. Anything that isn't a flag followed by a
+// value, or a switch, is an error.
+fn parse_flags(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut flags = HashMap::new();
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            return Err(format!("unexpected argument `{}`", args[i]));
+        }
+        if SWITCHES.contains(&&args[i][2..]) {
+            flags.insert(args[i][2..].to_string(), "true".to_string());
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() {
+            return Err(format!("missing value for `{}`", args[i]));
+        }
+        flags.insert(args[i][2..].to_string(), args[i + 1].clone());
+        i += 2;
+    }
+    Ok(flags)
+}
+
+fn parse_value<T: ::std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("invalid value `{}` for `--{}`", value, key))
+}
+
+fn usage_error(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "error: {}", msg);
+    let _ = writeln!(io::stderr(), "usage: n-armed-bandit-greedy [MODE] [--flag value]...");
+    let _ = writeln!(io::stderr(),
+                     "modes: run (default), seed-report, experiment, sweep, grid, drift, paired, \
+                      tournament, features, tune, trace, trace-query, offline-eval, budget, \
+                      slate, dueling, pareto, risk, continuum, interactive, sensitivity, \
+                      ab-test, soak");
+    let _ = writeln!(io::stderr(),
+                     "every mode accepts --threads N, --progress-format text|json and --quiet");
+    process::exit(2);
+}
+
+// The original hard-coded run, or a whole plan read from `--config`.
+fn run(flags: &HashMap<String, String>) -> Result<(), String> {
+    for key in flags.keys() {
+        if !["config", "seed", "format", "compress", "plot", "partial-every", "raw-output",
+              "checkpoint", "checkpoint-every", "resume", "events", "histogram", "timing"]
+               .contains(&&key[..]) {
+            return Err(format!("unknown flag `--{}`", key));
+        }
+    }
+    let checkpoint_flags = CheckpointFlags::from_flags(flags)?;
+    if let Some(path) = flags.get("config") {
+        for key in &["seed", "format", "compress", "plot", "partial-every", "raw-output",
+                     "events", "histogram", "timing"] {
+            if flags.contains_key(*key) {
+                return Err(format!("with `--config`, `{}` is set in the config file", key));
+            }
+        }
+        let plan = config::ExperimentConfig::from_file(path)?;
+        return Ok(plan.run(checkpoint_flags.as_ref())?);
+    }
+
+    let spec = spec::AgentSpec::EpsilonGreedy {
+        epsilon: 0.2,
+        step_size: None,
+        tie_tolerance: 0.0,
+        min_samples: 0,
+        mom_groups: None,
+        kernel_width: None,
+        history: None,
+    };
+    let format = match flags.get("format") {
+        Some(name) => output::Format::from_name(name)?,
+        None => output::Format::Dat,
+    };
+    let compress = match flags.get("compress") {
+        Some(value) => output::parse_compress(value)?,
+        None => false,
+    };
+    let mut builder = ExperimentBuilder::new()
+        .agent("eps_0_2", spec)
+        .format(format)
+        .compress(compress)
+        .files_per_agent(false)
+        .verbose(true);
+    if let Some(seed) = flags.get("seed") {
+        builder = builder.seed(parse_value("seed", seed)?);
+    }
+
+    // With `--partial-every K`, the averages so far are written to
+    // "<path>.partial" every K tasks.
+    if let Some(every) = flags.get("partial-every") {
+        builder = builder.partial_every(parse_value("partial-every", every)?);
+    }
+    if let Some(dir) = flags.get("raw-output") {
+        builder = builder.raw_output(dir);
+    }
+    if let Some(name) = flags.get("events") {
+        builder = builder.events(EventFormat::from_name(name)?);
+    }
+    if let Some(path) = flags.get("plot") {
+        builder = builder.plot(path);
+    }
+    if let Some(value) = flags.get("histogram") {
+        builder = builder.histogram(HistogramBins::parse(value)?);
+    }
+    if flags.contains_key("timing") {
+        builder = builder.timing(true);
+    }
+    if let Some(c) = checkpoint_flags {
+        builder = builder.checkpoint(c);
+    }
+    builder.build()?.run()?;
+    Ok(())
+}
+
+// The command line; the binary in main.rs only calls this.
+pub fn main() {
+    if let Err(e) = logging::init() {
+        usage_error(&e);
+    }
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // The first argument names the mode, unless it's already a flag, in
+    // which case all the flags belong to the default run.
+    let (mode, rest) = if !args.is_empty() && !args[0].starts_with("--") {
+        (&args[0][..], &args[1..])
+    } else {
+        ("run", &args[..])
+    };
+    let mut flags = match parse_flags(rest) {
+        Ok(flags) => flags,
+        Err(e) => usage_error(&e),
+    };
+
+    // `--threads`, `--progress-format` and `--quiet` apply to every mode.
+    if let Some(threads) = flags.remove("threads") {
+        if let Err(e) = set_threads(&threads) {
+            usage_error(&e);
+        }
+    }
+    if let Some(format) = flags.remove("progress-format") {
+        if let Err(e) = progress::set_format(&format) {
+            usage_error(&e);
+        }
+    }
+    if flags.remove("quiet").is_some() {
+        progress::set_quiet();
+    }
+
+    let result = match mode {
+        "run" => run(&flags),
+        "seed-report" => seed_report::Options::from_flags(&flags).map(|o| seed_report::run(&o)),
+        "experiment" => experiment::Options::from_flags(&flags).and_then(|o| experiment::run(&o)),
+        "sweep" => sweep::Options::from_flags(&flags).and_then(|o| sweep::run(&o)),
+        "grid" => grid::Options::from_flags(&flags).and_then(|o| grid::run(&o)),
+        "sensitivity" => {
+            sensitivity::Options::from_flags(&flags).and_then(|o| sensitivity::run(&o))
+        }
+        "drift" => drift::Options::from_flags(&flags).and_then(|o| drift::run(&o)),
+        "paired" => paired::Options::from_flags(&flags).and_then(|o| paired::run(&o)),
+        "tournament" => {
+            tournament::Options::from_flags(&flags).and_then(|o| tournament::run(&o))
+        }
+        "features" => features::Options::from_flags(&flags).and_then(|o| features::run(&o)),
+        "tune" => tune::Options::from_flags(&flags).and_then(|o| tune::run(&o)),
+        "trace" => trace::Options::from_flags(&flags).and_then(|o| trace::run(&o)),
+        "trace-query" => trace::QueryOptions::from_flags(&flags).and_then(|o| trace::query(&o)),
+        "slate" => slate::Options::from_flags(&flags).and_then(|o| slate::run(&o)),
+        "continuum" => {
+            continuum::Options::from_flags(&flags).and_then(|o| continuum::run(&o))
+        }
+        "risk" => risk::Options::from_flags(&flags).and_then(|o| risk::run(&o)),
+        "interactive" => {
+            interactive::Options::from_flags(&flags).and_then(|o| interactive::run(&o))
+        }
+        "pareto" => pareto::Options::from_flags(&flags).and_then(|o| pareto::run(&o)),
+        "dueling" => dueling::Options::from_flags(&flags).and_then(|o| dueling::run(&o)),
+        "ab-test" => ab_test::Options::from_flags(&flags).and_then(|o| ab_test::run(&o)),
+        "budget" => budget::Options::from_flags(&flags).and_then(|o| budget::run(&o)),
+        "offline-eval" => offline::Options::from_flags(&flags).and_then(|o| offline::run(&o)),
+        "soak" => soak::Options::from_flags(&flags).and_then(|o| soak::run(&o)),
+        mode => Err(format!("unknown mode `{}`", mode)),
+    };
+    if let Err(e) = result {
+        usage_error(&e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A task's seed depends on the run's seed and its index alone, so a
+    // longer run starts with the same tasks.
+    #[test]
+    fn task_seeds_depend_on_the_index_alone() {
+        let short = task_seeds(&mut seeded_rng(5), 10);
+        let long = task_seeds(&mut seeded_rng(5), 1000);
+        assert_eq!(short[..], long[..10]);
+        let mut distinct = long.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), long.len());
+        assert!(task_seeds(&mut seeded_rng(6), 10) != short);
+    }
+
+    // The kernel totals kept as rewards arrive give the estimates a full
+    // pass over every arm does, arms added along the way included.
+    #[test]
+    fn kernel_estimates_match_a_full_recomputation() {
+        let mut rng = seeded_rng(11);
+        let width = 2.5;
+        let mut bandit = EpsilonGreedyBandit::new(300, 0.1);
+        bandit.set_kernel_width(width);
+        for t in 0..2000 {
+            if t == 1000 {
+                bandit.add_arm(Some(ArmPrior { mean: 1.0, weight: 2.0 }));
+            }
+            let action = bandit.choose_action(&mut rng);
+            let StandardNormal(reward) = rng.gen();
+            bandit.receive_reward(reward, action);
+        }
+        for arm in 0..bandit.n {
+            let prior = bandit.priors[arm].unwrap_or(ArmPrior { mean: 0.0, weight: 0.0 });
+            let (mut sum, mut weight) = (prior.weight * prior.mean, prior.weight);
+            for other in 0..bandit.n {
+                let d = other as f64 - arm as f64;
+                let k = (-d * d / (2.0 * width * width)).exp();
+                sum += k * bandit.sums[other];
+                weight += k * bandit.counts[other] as f64;
+            }
+            let full = if weight > 0.0 { sum / weight } else { 0.0 };
+            assert!((bandit.calculate_estimate(arm) - full).abs() < 1e-9, "arm {}", arm);
+            assert_eq!(bandit.estimates.as_ref().unwrap().get(arm),
+                       bandit.calculate_estimate(arm));
+        }
+    }
+
+    // Exploring never picks an arm tied for the max or a retired one, and
+    // falls back to the tied arms when there's nothing else.
+    #[test]
+    fn exploring_avoids_the_max_and_retired_arms() {
+        let mut rng = seeded_rng(12);
+        let mut bandit = EpsilonGreedyBandit::new(1000, 1.0);
+        bandit.set_tie_tolerance(0.5);
+        for arm in 0..1000 {
+            let reward = if arm < 3 { 2.0 } else if arm == 3 { 1.6 } else { 1.0 };
+            bandit.receive_reward(reward, arm);
+        }
+        bandit.set_retired(998, true);
+        let mut seen = vec![false; 1000];
+        for _ in 0..20000 {
+            seen[bandit.choose_action(&mut rng)] = true;
+        }
+        assert!(seen[..4].iter().all(|&s| !s) && !seen[998]);
+        assert!(seen[4..998].iter().all(|&s| s) && seen[999]);
+
+        for arm in 4..1000 {
+            bandit.set_retired(arm, true);
+        }
+        for _ in 0..100 {
+            assert!(bandit.choose_action(&mut rng) < 4);
+        }
+    }
+
+    #[test]
+    fn task_chunks_come_back_in_order() {
+        let chunks = map_task_chunks(3..70, |tasks| tasks);
+        assert_eq!(chunks.first().map(|c| c.start), Some(3));
+        assert_eq!(chunks.last().map(|c| c.end), Some(70));
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert!(pair[0].len() == TASK_CHUNK);
+        }
+        assert!(map_task_chunks(4..4, |tasks| tasks).is_empty());
+    }
+}
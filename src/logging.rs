@@ -0,0 +1,102 @@
+// Diagnostics go through the `log` facade and are written to stderr. What
+// gets written is set by RUST_LOG, as with env_logger: a level (error, warn,
+// info, debug, trace or off) for everything, and/or `module=level` for one
+// module, e.g.
+//
+//     RUST_LOG=debug                 every agent's choices and updates
+//     RUST_LOG=warn,ucb=debug        only UCB's, and no per-task lines
+//
+// where modules are named as in the source (main for main.rs, which has the
+// epsilon-greedy agent), with or without the crate's name in front.
+//
+// The default is info, which is the per-task lines and warnings. At debug
+// level agents log the actions they choose and why (exploring, exploiting,
+// an arm's upper bound) and their estimates as rewards come in; tasks run in
+// parallel, so `--threads 1` keeps one task's lines together.
+
+use std::env;
+use std::io::{self, Write};
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger {
+    default: LevelFilter,
+
+    // (module, level) overrides, by module path within the crate
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl StderrLogger {
+    // The level of the last override for the target's module or one
+    // enclosing it, named with or without the crate.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let module = module_of(target);
+        let within = |path: &str, name: &str| {
+            path == name || path.starts_with(&format!("{}::", name))
+        };
+        self.modules.iter()
+            .filter(|&(name, _)| within(target, name) || within(module, name))
+            .map(|&(_, level)| level)
+            .next_back()
+            .unwrap_or(self.default)
+    }
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = match record.level() {
+            Level::Error => writeln!(stderr, "error: {}", record.args()),
+            Level::Warn => writeln!(stderr, "warning: {}", record.args()),
+            Level::Info => writeln!(stderr, "{}", record.args()),
+            level => {
+                writeln!(stderr, "[{} {}] {}", level.to_string().to_lowercase(),
+                         module_of(record.target()), record.args())
+            }
+        };
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+// A target's module path within the crate; main.rs is "main".
+fn module_of(target: &str) -> &str {
+    target.split_once("::").map(|x| x.1).unwrap_or("main")
+}
+
+fn parse_level(value: &str) -> Result<LevelFilter, String> {
+    value.parse().map_err(|_| {
+        format!("unknown log level `{}` (expected error, warn, info, debug, trace or off)", value)
+    })
+}
+
+// Installs the logger, configured from RUST_LOG.
+pub fn init() -> Result<(), String> {
+    let mut logger = StderrLogger { default: LevelFilter::Info, modules: vec![] };
+    if let Ok(spec) = env::var("RUST_LOG") {
+        for directive in spec.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            match directive.find('=') {
+                Some(i) => {
+                    let level = parse_level(&directive[i + 1..])?;
+                    logger.modules.push((directive[..i].to_string(), level));
+                }
+                None => logger.default = parse_level(directive)?,
+            }
+        }
+    }
+    let max = logger.modules.iter().fold(logger.default, |acc, &(_, level)| acc.max(level));
+    log::set_logger(Box::leak(Box::new(logger)))
+        .map_err(|e| format!("couldn't start logging: {}", e))?;
+    log::set_max_level(max);
+    Ok(())
+}
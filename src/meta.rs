@@ -0,0 +1,259 @@
+// A bandit over bandits, for algorithm selection: a selector agent treats
+// every one of several sub-agents as an arm. Each play the selector picks a
+// sub-agent, the sub-agent picks the arm that's played, and the reward is
+// credited to both, the selector for having picked that sub-agent and the
+// sub-agent for its arm. Only the sub-agent that chose a play learns from
+// it, so each one learns from the plays it made itself, and the selector
+// ends up favouring the sub-agent that has done best so far. Rewards that
+// follow no choice (a warm start's, say) are shown to every sub-agent.
+//
+//     --agent meta --meta-agents "epsilon-greedy(epsilon=0.01),epsilon-greedy(epsilon=0.1)"
+//                  --meta-selector "ucb(c=2)"
+//
+// Sub-agents are given as a kind followed by its parameters, by flag name,
+// in parentheses (see parse_agents); the selector defaults to epsilon-greedy
+// with epsilon = 0.1. In a config plan a meta agent is configured as
+//
+//     [[agents]]
+//     name = "meta"
+//     kind = "meta"
+//     selector = { name = "ucb", kind = "ucb", c = 2.0 }
+//     sub_agents = [{ name = "eps-0.01", epsilon = 0.01 },
+//                   { name = "eps-0.1", epsilon = 0.1 }]
+
+use std::collections::{HashMap, VecDeque};
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::spec::AgentSpec;
+use crate::Agent;
+
+// Parses a comma-separated list of agents, each a kind with its parameters
+// in parentheses, e.g. "ucb(c=2),epsilon-greedy(epsilon=0.1,step-size=0.1)".
+// The parameters are those of `--agent.<param>` (see spec).
+pub fn parse_agents(value: &str) -> Result<Vec<AgentSpec>, String> {
+    let mut agents = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(format!("unbalanced `)` in `{}`", value)),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                agents.push(parse_agent(&value[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(format!("unbalanced `(` in `{}`", value));
+    }
+    agents.push(parse_agent(&value[start..])?);
+    Ok(agents)
+}
+
+// Parses one agent, "kind" or "kind(param=value,...)".
+pub fn parse_agent(value: &str) -> Result<AgentSpec, String> {
+    let value = value.trim();
+    let (kind, params) = match value.find('(') {
+        Some(open) if value.ends_with(')') => (&value[..open], &value[open + 1..value.len() - 1]),
+        Some(_) => return Err(format!("invalid agent `{}` (expected kind(param=value,...))",
+                                      value)),
+        None => (value, ""),
+    };
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(format!("invalid agent `{}`: no kind", value));
+    }
+    let mut flags = HashMap::new();
+    flags.insert("agent".to_string(), kind.to_string());
+    for param in params.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let mut parts = param.splitn(2, '=');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim()),
+            _ => return Err(format!("invalid parameter `{}` (expected param=value)", param)),
+        };
+        flags.insert(format!("agent.{}", key), value.to_string());
+    }
+    AgentSpec::from_flags(&flags)
+}
+
+#[derive(Serialize, Deserialize)]
+struct MetaState {
+    selector: serde_json::Value,
+    agents: Vec<serde_json::Value>,
+    pending: VecDeque<(usize, usize)>,
+    stats: ArmStats,
+}
+
+pub struct MetaAgent {
+    selector: Box<dyn Agent>,
+    agents: Vec<Box<dyn Agent>>,
+
+    // the sub-agent and arm of every play still waiting for its reward,
+    // oldest first
+    pending: VecDeque<(usize, usize)>,
+
+    // every arm's rewards, whichever sub-agent played it
+    stats: ArmStats,
+}
+
+impl MetaAgent {
+    // `selector` must have one arm per sub-agent, and the sub-agents `n`
+    // arms each.
+    pub fn new(n: usize, selector: Box<dyn Agent>, agents: Vec<Box<dyn Agent>>) -> MetaAgent {
+        MetaAgent {
+            selector,
+            agents,
+            pending: VecDeque::new(),
+            stats: ArmStats::new(n),
+        }
+    }
+
+    // Rebuilds a meta agent from its state(), restoring the selector with
+    // `restore_selector` and sub-agent i with `restore_agent(i, state)`.
+    pub fn from_state(state: serde_json::Value,
+                      restore_selector: &dyn Fn(serde_json::Value)
+                                                -> Result<Box<dyn Agent>, String>,
+                      restore_agent: &dyn Fn(usize, serde_json::Value)
+                                             -> Result<Box<dyn Agent>, String>)
+                      -> Result<MetaAgent, String> {
+        let state: MetaState = serde_json::from_value(state).map_err(|e| e.to_string())?;
+        let mut agents = vec![];
+        for (i, agent) in state.agents.into_iter().enumerate() {
+            agents.push(restore_agent(i, agent)?);
+        }
+        Ok(MetaAgent {
+            selector: restore_selector(state.selector)?,
+            agents,
+            pending: state.pending,
+            stats: state.stats,
+        })
+    }
+}
+
+impl Agent for MetaAgent {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        let agent = self.selector.choose_action(rng);
+        let action = self.agents[agent].choose_action(rng);
+        self.pending.push_back((agent, action));
+        action
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+        match self.pending.iter().position(|&(_, a)| a == action) {
+            Some(i) => {
+                let (agent, _) = self.pending.remove(i).unwrap();
+                self.selector.receive_reward(reward, agent);
+                self.agents[agent].receive_reward(reward, action);
+            }
+            None => {
+                for agent in &mut self.agents {
+                    agent.receive_reward(reward, action);
+                }
+            }
+        }
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        for agent in &mut self.agents {
+            agent.add_arm(prior);
+        }
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        for agent in &mut self.agents {
+            agent.set_retired(arm, retired);
+        }
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(MetaState {
+            selector: self.selector.state(),
+            agents: self.agents.iter().map(|agent| agent.state()).collect(),
+            pending: self.pending.clone(),
+            stats: self.stats.clone(),
+        }).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{self, GaussianTestbed};
+    use crate::{seeded_rng, EpsilonGreedyBandit};
+
+    fn counts(agent: &dyn Agent) -> Vec<usize> {
+        agent.arm_summaries().iter().map(|arm| arm.count).collect()
+    }
+
+    fn greedy_selector() -> Box<dyn Agent> {
+        Box::new(EpsilonGreedyBandit::new(2, 0.1))
+    }
+
+    #[test]
+    fn parses_agents_with_parameters() {
+        let agents = parse_agents("ucb(c=2), epsilon-greedy(epsilon=0.1,step-size=0.5),softmax")
+                         .unwrap();
+        assert_eq!(agents.len(), 3);
+        assert_eq!(agents[0], AgentSpec::Ucb { c: 2.0 });
+        assert_eq!(agents[1].label(), "eps-greedy(epsilon=0.1,alpha=0.5)");
+        for bad in &["ucb(c=2", "ucb)c=2(", "ucb(c)", "(c=2)", "ucb(nonsense=1)", "nonsense"] {
+            assert!(parse_agents(bad).is_err(), "{}", bad);
+        }
+    }
+
+    // A reward for a play goes to the sub-agent that chose it and to the
+    // selector; one that follows no choice goes to every sub-agent.
+    #[test]
+    fn credits_the_sub_agent_that_chose() {
+        let greedy = || Box::new(EpsilonGreedyBandit::new(3, 0.0)) as Box<dyn Agent>;
+        let mut meta = MetaAgent::new(3, greedy_selector(), vec![greedy(), greedy()]);
+        meta.receive_reward(1.0, 2);
+        assert_eq!(counts(&*meta.agents[0]), [0, 0, 1]);
+        assert_eq!(counts(&*meta.agents[1]), [0, 0, 1]);
+        assert_eq!(counts(&*meta.selector), [0, 0]);
+
+        let mut rng = seeded_rng(1);
+        let action = meta.choose_action(&mut rng);
+        let (agent, _) = meta.pending[0];
+        meta.receive_reward(0.5, action);
+        assert_eq!(meta.agents[agent].arm_summaries()[action].count,
+                   1 + usize::from(action == 2));
+        assert_eq!(counts(&*meta.agents[1 - agent]), [0, 0, 1]);
+        assert_eq!(counts(&*meta.selector)[agent], 1);
+        assert!(meta.pending.is_empty());
+        assert_eq!(counts(&meta)[action], 1 + usize::from(action == 2));
+
+        assert_eq!(meta.add_arm(None), 3);
+        assert!(meta.agents.iter().all(|agent| agent.arm_summaries().len() == 4));
+        for arm in 0..3 {
+            meta.set_retired(arm, true);
+        }
+        assert!((0..20).all(|_| meta.choose_action(&mut rng) == 3));
+    }
+
+    // The selector ends up favouring the sub-agent that learns over one
+    // that plays at random.
+    #[test]
+    fn favours_the_better_sub_agent() {
+        let mut rng = seeded_rng(2);
+        let mut env = GaussianTestbed::new(vec![0.0, 0.0, 3.0]);
+        let agents = vec![AgentSpec::Ucb { c: 1.0 }.build(3),
+                          Box::new(EpsilonGreedyBandit::new(3, 1.0)) as Box<dyn Agent>];
+        let mut meta = MetaAgent::new(3, greedy_selector(), agents);
+        environment::play(&mut env, &mut meta, 1000, &mut rng);
+        let picked = counts(&*meta.selector);
+        assert!(picked[0] > 4 * picked[1]);
+    }
+}
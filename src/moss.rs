@@ -0,0 +1,96 @@
+// MOSS (Minimax Optimal Strategy in the Stochastic case, Audibert & Bubeck,
+// 2009), a UCB variant for a known horizon T that plays the arm maximizing
+//
+//     Q_t(a) + sqrt(max(0, ln(T / (K N_t(a)))) / N_t(a))
+//
+// for K active arms. Arms that haven't been played yet count as maximizing.
+// Without a horizon it is run by the doubling trick (see doubling).
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct MossBandit {
+    stats: ArmStats,
+    horizon: usize,
+}
+
+impl MossBandit {
+    pub fn new(n: usize, horizon: usize) -> MossBandit {
+        assert!(n > 0);
+        assert!(horizon > 0);
+        MossBandit { stats: ArmStats::new(n), horizon }
+    }
+
+    fn index(&self, action: usize, num_active: usize) -> f64 {
+        let weight = self.stats.weight(action);
+        if weight == 0.0 {
+            return f64::INFINITY;
+        }
+        let log_term = (self.horizon as f64 / (num_active as f64 * weight)).ln().max(0.0);
+        self.stats.estimate(action) + (log_term / weight).sqrt()
+    }
+}
+
+impl Agent for MossBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active = self.stats.active();
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.index(active[0], active.len());
+        for &i in &active[1..] {
+            let value = self.index(i, active.len());
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unplayed arms come first, and an arm with at least T / K rewards has
+    // no bonus left.
+    #[test]
+    fn index_by_hand() {
+        let mut agent = MossBandit::new(2, 20);
+        assert_eq!(agent.index(0, 2), f64::INFINITY);
+        for _ in 0..4 {
+            agent.receive_reward(1.0, 0);
+        }
+        assert_eq!(agent.index(0, 2), 1.0 + ((20.0f64 / 8.0).ln() / 4.0).sqrt());
+        for _ in 0..6 {
+            agent.receive_reward(1.0, 0);
+        }
+        assert_eq!(agent.index(0, 2), 1.0);
+    }
+}
@@ -0,0 +1,273 @@
+// Offline policy evaluation. The `offline-eval` mode estimates how much
+// reward an agent would collect per play without running it live, from a
+// log of another (logging) policy's interactions that records the
+// probability with which the logged arm was chosen:
+//
+//     step,arm,reward,propensity
+//     0,3,0.52,0.25
+//     ...
+//
+// The agent is shown the logged events in order and picks an arm for each;
+// when it picks the logged arm it learns the logged reward, weighted by one
+// over the propensity. The inverse-propensity-scored (IPS) estimate is the
+// average weighted reward over all events, which is unbiased as long as
+// every arm the agent can pick had a positive propensity. The
+// self-normalized (SNIPS) estimate divides by the total weight instead of
+// the number of events: slightly biased, but with much less variance when
+// propensities are small. Since the agent's picks are random, the estimates
+// are averaged over several runs.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use rand::Rng;
+
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{parse_value, seeded_rng, task_seeds, Agent};
+
+pub struct Options {
+    pub log: String,
+    pub n: usize,
+    pub num_runs: usize,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            log: String::new(),
+            n: 10,
+            num_runs: 20,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "log" => opts.log = value.clone(),
+                "arms" => opts.n = parse_value(key, value)?,
+                "runs" => opts.num_runs = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.log.is_empty() {
+            return Err("`--log` is required".to_string());
+        }
+        if opts.n == 0 || opts.num_runs == 0 {
+            return Err("arms and runs must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// One logged interaction.
+pub struct LoggedEvent {
+    pub arm: usize,
+    pub reward: f64,
+
+    // probability the logging policy gave `arm`
+    pub propensity: f64,
+}
+
+// Reads a log with step, arm, reward and propensity columns, in step order.
+pub fn read_log(path: &str, n: usize) -> Result<Vec<LoggedEvent>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("couldn't read log `{}`: {}", path, e))?;
+    let mut lines = text.lines().enumerate()
+                        .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => line.split(',').map(|c| c.trim().to_string()).collect(),
+        None => return Err(format!("log `{}` is empty", path)),
+    };
+    let column = |name: &str| {
+        header.iter().position(|c| c == name)
+              .ok_or(format!("log `{}` has no `{}` column", path, name))
+    };
+    let (step_col, arm_col) = (column("step")?, column("arm")?);
+    let (reward_col, propensity_col) = (column("reward")?, column("propensity")?);
+
+    let mut events = vec![];
+    for (i, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let bad = || format!("log `{}` line {}: expected {} columns of numbers",
+                             path, i + 1, header.len());
+        if fields.len() != header.len() {
+            return Err(bad());
+        }
+        let step: usize = fields[step_col].parse().map_err(|_| bad())?;
+        let event = LoggedEvent {
+            arm: fields[arm_col].parse().map_err(|_| bad())?,
+            reward: fields[reward_col].parse().map_err(|_| bad())?,
+            propensity: fields[propensity_col].parse().map_err(|_| bad())?,
+        };
+        if event.arm >= n {
+            return Err(format!("log `{}` line {}: arm {} but there are {} arms",
+                               path, i + 1, event.arm, n));
+        }
+        if !(event.propensity > 0.0 && event.propensity <= 1.0) {
+            return Err(format!("log `{}` line {}: propensity must be in (0, 1]", path, i + 1));
+        }
+        events.push((step, event));
+    }
+    if events.is_empty() {
+        return Err(format!("log `{}` has no events", path));
+    }
+    events.sort_by_key(|&(step, _)| step);
+    Ok(events.into_iter().map(|(_, event)| event).collect())
+}
+
+pub struct Estimate {
+    pub ips: f64,
+    pub snips: f64,
+
+    // logged events where the agent picked the logged arm
+    pub matched: usize,
+}
+
+// Runs a fresh agent from `spec` through the log once.
+pub fn evaluate<R: Rng>(spec: &AgentSpec, n: usize, log: &[LoggedEvent], rng: &mut R)
+                        -> Estimate {
+    evaluate_agent(&mut *spec.build(n), log, rng)
+}
+
+// Runs `agent` through the log once. The log's propensities must be
+// positive, as read_log makes sure.
+fn evaluate_agent<R: Rng>(agent: &mut dyn Agent, log: &[LoggedEvent], rng: &mut R) -> Estimate {
+    let mut weighted_reward = 0.0;
+    let mut total_weight = 0.0;
+    let mut matched = 0;
+    for event in log {
+        if agent.choose_action(rng) != event.arm {
+            continue;
+        }
+        let weight = 1.0 / event.propensity;
+        weighted_reward += weight * event.reward;
+        total_weight += weight;
+        matched += 1;
+        agent.receive_reward(event.reward, event.arm);
+    }
+    Estimate {
+        ips: weighted_reward / log.len() as f64,
+        snips: if total_weight > 0.0 { weighted_reward / total_weight } else { 0.0 },
+        matched,
+    }
+}
+
+fn mean_and_se(xs: &[f64]) -> (f64, f64) {
+    let len = xs.len() as f64;
+    let mean = xs.iter().fold(0.0, |acc, x| acc + x) / len;
+    if xs.len() < 2 {
+        return (mean, 0.0);
+    }
+    let var = xs.iter().fold(0.0, |acc, x| acc + (x - mean) * (x - mean)) / (len - 1.0);
+    (mean, (var / len).sqrt())
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let log = read_log(&opts.log, opts.n)?;
+    let mut rng = match opts.seed {
+        Some(seed) => seeded_rng(seed),
+        None => seeded_rng(rand::thread_rng().gen()),
+    };
+    let seeds = task_seeds(&mut rng, opts.num_runs);
+    let estimates: Vec<Estimate> = seeds.iter().map(|&seed| {
+        evaluate(&opts.agent, opts.n, &log, &mut seeded_rng(seed))
+    }).collect();
+
+    let ips: Vec<f64> = estimates.iter().map(|e| e.ips).collect();
+    let snips: Vec<f64> = estimates.iter().map(|e| e.snips).collect();
+    let matched: Vec<f64> = estimates.iter().map(|e| e.matched as f64).collect();
+    let logged = log.iter().fold(0.0, |acc, e| acc + e.reward) / log.len() as f64;
+
+    println!("offline evaluation of {} on `{}`: {} events, {} runs",
+             opts.agent.label(), opts.log, log.len(), opts.num_runs);
+    println!("{:<16} {:>10} {:>10}", "estimate", "value", "se");
+    let (mean, se) = mean_and_se(&ips);
+    println!("{:<16} {:>10.4} {:>10.4}", "ips", mean, se);
+    let (mean, se) = mean_and_se(&snips);
+    println!("{:<16} {:>10.4} {:>10.4}", "snips", mean, se);
+    println!("{:<16} {:>10.4}", "logging policy", logged);
+    println!("{:<16} {:>10.1}", "matched events", mean_and_se(&matched).0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    use crate::stats::{ArmPrior, ArmSummary};
+
+    // Plays the same arm every time, so the target policy's probability of
+    // every logged arm is 1 or 0.
+    struct Always(usize);
+
+    impl Agent for Always {
+        fn choose_action(&mut self, _rng: &mut dyn Rng) -> usize {
+            self.0
+        }
+
+        fn receive_reward(&mut self, _reward: f64, _action: usize) {}
+
+        fn arm_summaries(&self) -> Vec<ArmSummary> {
+            vec![]
+        }
+
+        fn add_arm(&mut self, _prior: Option<ArmPrior>) -> usize {
+            0
+        }
+
+        fn set_retired(&mut self, _arm: usize, _retired: bool) {}
+
+        fn state(&self) -> serde_json::Value {
+            serde_json::Value::Null
+        }
+    }
+
+    fn event(arm: usize, reward: f64, propensity: f64) -> LoggedEvent {
+        LoggedEvent { arm, reward, propensity }
+    }
+
+    // Always playing arm 0 matches the first and third events, weighted 1 /
+    // 0.5 and 1 / 0.2; the others, which it never plays, count for nothing
+    // but the IPS denominator.
+    #[test]
+    fn ips_and_snips_by_hand() {
+        let log = vec![event(0, 1.0, 0.5), event(1, 2.0, 0.25), event(0, 3.0, 0.2),
+                       event(2, 4.0, 0.5)];
+        let mut rng = seeded_rng(1);
+        let estimate = evaluate_agent(&mut Always(0), &log, &mut rng);
+        assert_eq!(estimate.matched, 2);
+        assert_eq!(estimate.ips, (2.0 * 1.0 + 5.0 * 3.0) / 4.0);
+        assert_eq!(estimate.snips, (2.0 * 1.0 + 5.0 * 3.0) / (2.0 + 5.0));
+
+        let estimate = evaluate_agent(&mut Always(3), &log, &mut rng);
+        assert_eq!((estimate.matched, estimate.ips, estimate.snips), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_logs_without_positive_propensities() {
+        let path = env::temp_dir().join(format!("offline-log-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let read = |contents: &str| {
+            fs::write(path, contents).unwrap();
+            read_log(path, 3)
+        };
+        let events = read("step,arm,reward,propensity\n1,2,0.5,0.25\n0,1,1.5,1\n").unwrap();
+        assert_eq!(events.iter().map(|e| e.arm).collect::<Vec<_>>(), [1, 2]);
+        assert!(read("step,arm,reward,propensity\n0,1,1.5,0\n").is_err());
+        assert!(read("step,arm,reward,propensity\n0,1,1.5,-0.5\n").is_err());
+        assert!(read("step,arm,reward,propensity\n0,1,1.5,\n").is_err());
+        assert!(read("step,arm,reward,propensity\n0,1,1.5\n").is_err());
+        assert!(read("step,arm,reward\n0,1,1.5\n").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
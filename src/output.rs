@@ -0,0 +1,346 @@
+// Writers for per-play result series. The original `.dat` format is one bare
+// average reward per line; the CSV format has a header row and a step
+// column, followed by one column per series, so files load directly into
+// pandas or R:
+//
+//     step,avg_reward
+//     0,0.0123
+//     1,0.1936
+//
+// The JSON format is a single self-describing document: the run's metadata
+// (agent, testbed size, seed, start and finish times) with the series
+// alongside, keyed by name.
+//
+// Any of these is gzip-compressed when its file name ends in `.gz`, which
+// `--compress gzip` adds to the default file names. So is every file written
+// alongside a compressed series (pulls, histograms, event logs, partial and
+// raw results), with the `.gz` moved to the end of its name: "eps.csv.gz"
+// comes with "eps.csv.pulls.gz".
+//
+// Alongside the series, "<path>.pulls" records how many times each arm was
+// played per task on average, ranked from the best arm down.
+//
+// With `--raw-output dir` (or a config's `raw_output`), every task's rewards
+// and actions are also written to "dir/task_<i>.csv", for statistics the
+// averages can't give, such as medians or bootstrap intervals.
+//
+// Long runs can also stream their results: a PartialWriter rewrites a CSV
+// of the averages over the tasks finished so far every so many tasks,
+// headed by a comment line with the task counter, so a crash late in a run
+// doesn't lose everything and progress can be inspected while it runs.
+
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::timing::Timing;
+use crate::{dump_vec_to_file, Curves, TaskOutcome, TASK_CHUNK};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Dat,
+    Csv,
+    Json,
+}
+
+// What produced a set of series, for formats that record it.
+#[derive(Serialize)]
+pub struct RunInfo {
+    pub agent_label: String,
+    pub agent: AgentSpec,
+    pub arms: usize,
+    pub tasks: usize,
+    pub plays: usize,
+    pub seed: usize,
+
+    // seconds since the Unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+
+    // how long the run took (see timing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+}
+
+impl RunInfo {
+    // Metadata for a run of `agent` that started at `started_at`, finishing
+    // now.
+    pub fn new(agent: &AgentSpec, arms: usize, tasks: usize, plays: usize, seed: usize,
+               started_at: u64) -> RunInfo {
+        RunInfo {
+            agent_label: agent.label(),
+            agent: agent.clone(),
+            arms,
+            tasks,
+            plays,
+            seed,
+            started_at,
+            finished_at: unix_time(),
+            timing: None,
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Whether `--compress` (or a config's `compress`) asks for compression.
+pub fn parse_compress(value: &str) -> Result<bool, String> {
+    match value {
+        "gzip" => Ok(true),
+        "none" => Ok(false),
+        _ => Err(format!("unknown compression `{}` (expected gzip or none)", value)),
+    }
+}
+
+// "<stem>.<extension>", plus ".gz" when compressing.
+pub fn file_name(stem: &str, format: Format, compress: bool) -> String {
+    format!("{}.{}{}", stem, format.extension(), if compress { ".gz" } else { "" })
+}
+
+// "<path><suffix>", for a file written alongside the series at `path`, with
+// a trailing `.gz` moved to the end so it's compressed like the series.
+pub fn companion(path: &str, suffix: &str) -> String {
+    if let Some(stem) = path.strip_suffix(".gz") {
+        format!("{}{}.gz", stem, suffix)
+    } else {
+        format!("{}{}", path, suffix)
+    }
+}
+
+// An output file, compressed if its name ends in `.gz`. `finish` must be
+// called to flush it (and write the gzip trailer).
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl OutputFile {
+    pub fn create(file_name: &str) -> io::Result<OutputFile> {
+        Ok(OutputFile::wrap(file_name, File::create(file_name)?))
+    }
+
+    // Opens the file to write at its end; a compressed one gets another
+    // gzip member, which readers take as a continuation.
+    pub fn append(file_name: &str) -> io::Result<OutputFile> {
+        Ok(OutputFile::wrap(file_name, OpenOptions::new().append(true).open(file_name)?))
+    }
+
+    fn wrap(file_name: &str, f: File) -> OutputFile {
+        let f = BufWriter::new(f);
+        if file_name.ends_with(".gz") {
+            OutputFile::Gzip(GzEncoder::new(f, Compression::default()))
+        } else {
+            OutputFile::Plain(f)
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(mut f) => f.flush(),
+            OutputFile::Gzip(gz) => gz.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            OutputFile::Plain(ref mut f) => f.write(buf),
+            OutputFile::Gzip(ref mut gz) => gz.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            OutputFile::Plain(ref mut f) => f.flush(),
+            OutputFile::Gzip(ref mut gz) => gz.flush(),
+        }
+    }
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, String> {
+        match name {
+            "dat" => Ok(Format::Dat),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown output format `{}` (expected dat, csv or json)", name)),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Dat => "dat",
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+// Reads back a file written by OutputFile, or as much of it as was
+// written: the end of a compressed file whose writer was interrupted is
+// left out.
+pub fn read_output(file_name: &str) -> io::Result<String> {
+    let mut bytes = vec![];
+    let f = File::open(file_name)?;
+    if file_name.ends_with(".gz") {
+        if let Err(e) = MultiGzDecoder::new(f).read_to_end(&mut bytes) {
+            if e.kind() != io::ErrorKind::UnexpectedEof {
+                return Err(e);
+            }
+        }
+    } else {
+        { f }.read_to_end(&mut bytes)?;
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Writes named series of equal length. The `.dat` format only has room for
+// the first one, and only the JSON format records `info`.
+pub fn write_series(file_name: &str, format: Format, info: &RunInfo,
+                    columns: &[(&str, &Vec<f64>)]) -> io::Result<()> {
+    assert!(!columns.is_empty());
+    let compressed = file_name.ends_with(".gz");
+    match format {
+        Format::Dat if !compressed => return dump_vec_to_file(columns[0].1, file_name),
+        Format::Dat => {
+            let mut f = OutputFile::create(file_name)?;
+            for x in columns[0].1 {
+                writeln!(f, "{:?}", x)?;
+            }
+            return f.finish();
+        }
+        Format::Json => return write_json(file_name, info, columns),
+        Format::Csv => {}
+    }
+
+    let mut f = OutputFile::create(file_name)?;
+    let names: Vec<&str> = columns.iter().map(|&(name, _)| name).collect();
+    writeln!(f, "step,{}", names.join(","))?;
+    for t in 0..columns[0].1.len() {
+        write!(f, "{}", t)?;
+        for &(_, values) in columns {
+            write!(f, ",{}", values[t])?;
+        }
+        writeln!(f)?;
+    }
+    f.finish()
+}
+
+fn write_json(file_name: &str, info: &RunInfo, columns: &[(&str, &Vec<f64>)]) -> io::Result<()> {
+    let mut series = serde_json::Map::new();
+    for &(name, values) in columns {
+        series.insert(name.to_string(), serde_json::to_value(values)?);
+    }
+    let mut doc = serde_json::to_value(info)?;
+    doc["series"] = serde_json::Value::Object(series);
+
+    let mut f = OutputFile::create(file_name)?;
+    serde_json::to_writer_pretty(&mut f, &doc)?;
+    writeln!(f)?;
+    f.finish()
+}
+
+// Writes the mean plays per task of each arm, best arm first, to
+// "<path>.pulls" as CSV, next to the series written to `path`.
+pub fn write_pulls(path: &str, pulls_by_rank: &[f64]) -> Result<(), String> {
+    let pulls_path = companion(path, ".pulls");
+    let write = || -> io::Result<()> {
+        let mut f = OutputFile::create(&pulls_path)?;
+        writeln!(f, "rank,mean_pulls")?;
+        for (rank, pulls) in pulls_by_rank.iter().enumerate() {
+            writeln!(f, "{},{}", rank, pulls)?;
+        }
+        f.finish()
+    };
+    write().map_err(|e| format!("couldn't write `{}`: {}", pulls_path, e))
+}
+
+pub struct PartialWriter {
+    pub path: String,
+
+    // tasks between writes
+    pub every: usize,
+}
+
+impl PartialWriter {
+    // Tasks to run between writes: `every` rounded up to whole chunks, so
+    // the final result doesn't depend on how often partial results were
+    // written.
+    pub fn batch_size(&self) -> usize {
+        cmp::max(1, self.every.div_ceil(TASK_CHUNK)) * TASK_CHUNK
+    }
+
+    // Replaces the file with `curves`, the averages over the first `done`
+    // tasks. Failing to write partial results doesn't stop the run, so
+    // errors are only reported.
+    pub fn write(&self, curves: &Curves, done: usize, num_tasks: usize) {
+        match self.try_write(curves, done, num_tasks) {
+            Ok(()) => progress::checkpoint_written(&self.path, done, num_tasks),
+            Err(e) => {
+                warn!("couldn't write partial results to `{}`: {}", self.path, e);
+            }
+        }
+    }
+
+    // Writes to a temporary file first and renames it into place, so readers
+    // never see a half-written file.
+    fn try_write(&self, curves: &Curves, done: usize, num_tasks: usize) -> io::Result<()> {
+        let tmp = companion(&self.path, ".tmp");
+        {
+            let mut f = OutputFile::create(&tmp)?;
+            writeln!(f, "# tasks_completed = {} of {}", done, num_tasks)?;
+            writeln!(f, "step,avg_reward,reward_se,oracle_reward,pct_optimal,entropy,cum_regret,\
+                         rewarded")?;
+            for t in 0..curves.avg_reward.len() {
+                writeln!(f, "{},{},{},{},{},{},{},{}", t, curves.avg_reward[t],
+                         curves.reward_se[t], curves.oracle_reward[t], curves.pct_optimal[t],
+                         curves.entropy[t], curves.cum_regret[t], curves.rewarded[t])?;
+            }
+            f.finish()?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+// Writes one CSV per task into `dir`, compressed (as "task_<i>.csv.gz") if
+// `compress`.
+pub struct RawWriter {
+    pub dir: String,
+    pub compress: bool,
+}
+
+impl RawWriter {
+    // Creates `dir` if needed, so a bad path is reported before the run.
+    pub fn new(dir: &str, compress: bool) -> Result<RawWriter, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("couldn't create `{}`: {}", dir, e))?;
+        Ok(RawWriter { dir: dir.to_string(), compress })
+    }
+
+    // Writes task `task`'s outcome. Like partial results, a failed write is
+    // only reported, so the run itself still finishes.
+    pub fn write(&self, task: usize, outcome: &TaskOutcome) {
+        let name = format!("task_{}.csv{}", task, if self.compress { ".gz" } else { "" });
+        let path = Path::new(&self.dir).join(name);
+        let result = OutputFile::create(&path.to_string_lossy()).and_then(|mut f| {
+            writeln!(f, "step,action,reward")?;
+            for t in 0..outcome.rewards.len() {
+                writeln!(f, "{},{},{}", t, outcome.actions[t], outcome.rewards[t])?;
+            }
+            f.finish()
+        });
+        if let Err(e) = result {
+            warn!("couldn't write raw results to `{}`: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// Paired comparison of two agents. On every task both agents play against
+// the same pre-drawn rewards (see environment::FixedRewards) and make their
+// random choices from the same seed, so the per-play reward difference
+// R_A(t) - R_B(t) only reflects how the agents differ. Its spread across
+// tasks is much smaller than that of two independent runs, so fewer tasks
+// are needed to tell the agents apart.
+//
+// The agents are given with the usual agent flags behind an `a-` or `b-`
+// prefix, e.g.
+//
+//     paired --a-agent ucb --a-c 2 --b-agent epsilon-greedy --b-epsilon 0.1
+//
+// The mean, standard deviation and standard error of the difference, and
+// the fraction of tasks where A did better, are written for every play. The
+// difference in total reward per task is tested for significance at the
+// end (see significance).
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{self, FixedRewards};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::significance::{format_p, PairedTest};
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub a: AgentSpec,
+    pub b: AgentSpec,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            a: agent_from_flags(flags, "a-")?,
+            b: agent_from_flags(flags, "b-")?,
+            output: "paired.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "output" => opts.output = value.clone(),
+                key if is_prefixed_agent_flag(key, "a-") || is_prefixed_agent_flag(key, "b-") => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks < 2 {
+            return Err("arms must be positive and there must be at least 2 tasks".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+fn is_prefixed_agent_flag(key: &str, prefix: &str) -> bool {
+    key.starts_with(prefix) && is_agent_flag(&key[prefix.len()..])
+}
+
+// The agent described by the flags starting with `prefix`.
+pub fn agent_from_flags(flags: &HashMap<String, String>, prefix: &str)
+                        -> Result<AgentSpec, String> {
+    let mut agent_flags = HashMap::new();
+    for (key, value) in flags {
+        if is_prefixed_agent_flag(key, prefix) {
+            agent_flags.insert(key[prefix.len()..].to_string(), value.clone());
+        }
+    }
+    AgentSpec::from_flags(&agent_flags).map_err(|e| format!("agent {}: {}", &prefix[..1], e))
+}
+
+// Sums over tasks of the per-play difference, its square and whether A did
+// better.
+struct DiffSums {
+    diff: Vec<f64>,
+    diff_sq: Vec<f64>,
+    a_better: Vec<f64>,
+
+    // per task, the difference in total reward
+    totals: Vec<f64>,
+}
+
+impl DiffSums {
+    fn new(num_plays: usize) -> DiffSums {
+        DiffSums {
+            diff: vec![0.0; num_plays],
+            diff_sq: vec![0.0; num_plays],
+            a_better: vec![0.0; num_plays],
+            totals: vec![],
+        }
+    }
+}
+
+fn run_paired<R: Rng>(rng: &mut R, opts: &Options) -> DiffSums {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunk_sums = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = DiffSums::new(opts.num_plays);
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let q_star = BanditTask::new(opts.n).draw_q_star(&mut rng);
+            let mut env = FixedRewards::draw(q_star, opts.num_plays, &mut rng);
+            let choice_seed: usize = rng.gen();
+
+            let mut a = opts.a.build(opts.n);
+            let outcome_a = environment::play(&mut env, &mut *a, opts.num_plays,
+                                              &mut seeded_rng(choice_seed));
+            env.rewind();
+            let mut b = opts.b.build(opts.n);
+            let outcome_b = environment::play(&mut env, &mut *b, opts.num_plays,
+                                              &mut seeded_rng(choice_seed));
+
+            let mut total = 0.0;
+            for t in 0..opts.num_plays {
+                let d = outcome_a.rewards[t] - outcome_b.rewards[t];
+                sums.diff[t] += d;
+                sums.diff_sq[t] += d * d;
+                if d > 0.0 {
+                    sums.a_better[t] += 1.0;
+                }
+                total += d;
+            }
+            sums.totals.push(total);
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut sums = DiffSums::new(opts.num_plays);
+    for chunk in chunk_sums {
+        for t in 0..opts.num_plays {
+            sums.diff[t] += chunk.diff[t];
+            sums.diff_sq[t] += chunk.diff_sq[t];
+            sums.a_better[t] += chunk.a_better[t];
+        }
+        sums.totals.extend(chunk.totals);
+    }
+    sums
+}
+
+// Sample mean and standard deviation from a sum and a sum of squares.
+pub fn mean_and_std_dev(sum: f64, sum_sq: f64, count: usize) -> (f64, f64) {
+    let n = count as f64;
+    let mean = sum / n;
+    let var = ((sum_sq - n * mean * mean) / (n - 1.0)).max(0.0);
+    (mean, var.sqrt())
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let sums = match opts.seed {
+        Some(seed) => run_paired(&mut seeded_rng(seed), opts),
+        None => run_paired(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let total_sq = sums.totals.iter().fold(0.0, |acc, x| acc + x * x);
+    let total_sum = sums.totals.iter().fold(0.0, |acc, x| acc + x);
+    let (mean, std_dev) = mean_and_std_dev(total_sum, total_sq, opts.num_tasks);
+    let wins = sums.totals.iter().filter(|&&d| d > 0.0).count();
+    println!("A = {}, B = {}: {} tasks x {} plays, {} arms",
+             opts.a.label(), opts.b.label(), opts.num_tasks, opts.num_plays, opts.n);
+    println!("total reward A - B per task: {:.4} (std err {:.4}); A better on {:.1}% of tasks",
+             mean, std_dev / (opts.num_tasks as f64).sqrt(),
+             100.0 * wins as f64 / opts.num_tasks as f64);
+    let test = PairedTest::of(&sums.totals);
+    println!("paired t-test: t = {:.3}, p = {}, Cohen's d = {:.3}",
+             test.t, format_p(test.t_p), test.cohens_d);
+    println!("Wilcoxon signed-rank: W+ = {}, z = {:.3}, p = {}, rank-biserial r = {:.3}",
+             test.w_plus, test.z, format_p(test.wilcoxon_p), test.rank_biserial);
+    Ok(())
+}
+
+fn write_csv(sums: &DiffSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,mean_diff,std_dev,std_err,frac_a_better")?;
+    for t in 0..sums.diff.len() {
+        let (mean, std_dev) = mean_and_std_dev(sums.diff[t], sums.diff_sq[t], num_tasks);
+        writeln!(f, "{},{},{},{},{}", t, mean, std_dev, std_dev / (num_tasks as f64).sqrt(),
+                 sums.a_better[t] / num_tasks as f64)?;
+    }
+    f.finish()
+}
@@ -0,0 +1,285 @@
+// Multi-objective bandits. In the `pareto` mode every arm pays a reward
+// vector with one entry per objective (e.g. revenue and minus latency): arm
+// a pays mu(a) plus standard normal noise in every entry, with the true mean
+// vectors mu(a) drawn from a standard normal. There is usually no single best
+// arm, only the Pareto front: the arms whose means aren't dominated, i.e.
+// beaten or matched in every objective and beaten in one, by another arm's.
+//
+// The Pareto regret of arm a (Drugan & Nowé, 2013) is the smallest amount
+// that, added to every objective of mu(a), puts it on the front:
+//
+//     Delta(a) = max over a' of max(0, min over objectives d of mu(a')_d - mu(a)_d)
+//
+// which is 0 for the arms on the front. Averages over the tasks are written
+// for every round:
+//
+//     step,avg_regret,cum_regret,pct_front
+//
+// where pct_front is the percentage of tasks that played an arm on the front.
+//
+// The agent is Pareto UCB1: it plays every arm once, then picks uniformly
+// among the arms whose vectors of upper confidence bounds
+//
+//     mu_hat(a)_d + sqrt(2 ln(t (D A)^(1/4)) / N_t(a))
+//
+// are on the front of those vectors, for D objectives and A arms.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+use rand::distributions::IndependentSample;
+use rand::distributions::normal::Normal;
+
+use crate::output::OutputFile;
+use crate::progress;
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds};
+
+pub struct Options {
+    pub n: usize,
+    pub num_objectives: usize,
+    pub num_tasks: usize,
+    pub num_rounds: usize,
+    pub seed: Option<usize>,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_objectives: 2,
+            num_tasks: 500,
+            num_rounds: 1000,
+            seed: None,
+            output: "pareto.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "objectives" => opts.num_objectives = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_rounds = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "output" => opts.output = value.clone(),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_objectives == 0 || opts.num_tasks == 0 || opts.num_rounds == 0 {
+            return Err("arms, objectives, tasks and plays must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Whether `a` is at least as good as `b` in every objective and better in one.
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x >= y) && a.iter().zip(b).any(|(x, y)| x > y)
+}
+
+// The indices of the vectors no other vector dominates.
+pub fn pareto_front(vectors: &[Vec<f64>]) -> Vec<usize> {
+    (0..vectors.len()).filter(|&i| {
+        !vectors.iter().any(|v| dominates(v, &vectors[i]))
+    }).collect()
+}
+
+// Arms paying vectors of Gaussian rewards.
+pub struct VectorTestbed {
+    means: Vec<Vec<f64>>,
+    front: Vec<usize>,
+}
+
+impl VectorTestbed {
+    pub fn draw<R: Rng>(n: usize, num_objectives: usize, rng: &mut R) -> VectorTestbed {
+        let standard = Normal::new(0.0, 1.0);
+        let means: Vec<Vec<f64>> = (0..n).map(|_| {
+            (0..num_objectives).map(|_| standard.ind_sample(rng)).collect()
+        }).collect();
+        let front = pareto_front(&means);
+        VectorTestbed { means, front }
+    }
+
+    pub fn step<R: Rng>(&self, action: usize, rng: &mut R) -> Vec<f64> {
+        self.means[action].iter().map(|&m| Normal::new(m, 1.0).ind_sample(rng)).collect()
+    }
+
+    pub fn regret(&self, action: usize) -> f64 {
+        let mu = &self.means[action];
+        self.means.iter().fold(0.0, |acc: f64, other| {
+            let margin = other.iter().zip(mu).fold(f64::INFINITY,
+                                                   |m: f64, (x, y)| m.min(x - y));
+            acc.max(margin)
+        })
+    }
+}
+
+pub struct ParetoUcb {
+    sums: Vec<Vec<f64>>,
+    pulls: Vec<usize>,
+    t: usize,
+}
+
+impl ParetoUcb {
+    pub fn new(n: usize, num_objectives: usize) -> ParetoUcb {
+        ParetoUcb { sums: vec![vec![0.0; num_objectives]; n], pulls: vec![0; n], t: 0 }
+    }
+
+    pub fn choose_action<R: Rng>(&self, rng: &mut R) -> usize {
+        let untried: Vec<usize> = (0..self.pulls.len()).filter(|&a| self.pulls[a] == 0).collect();
+        if !untried.is_empty() {
+            return untried[rng.gen_range(0, untried.len())];
+        }
+
+        let (n, d) = (self.pulls.len() as f64, self.sums[0].len() as f64);
+        let log_term = 2.0 * (self.t as f64 * (d * n).powf(0.25)).ln();
+        let bounds: Vec<Vec<f64>> = (0..self.pulls.len()).map(|a| {
+            let pulls = self.pulls[a] as f64;
+            let bonus = (log_term / pulls).sqrt();
+            self.sums[a].iter().map(|s| s / pulls + bonus).collect()
+        }).collect();
+        let front = pareto_front(&bounds);
+        front[rng.gen_range(0, front.len())]
+    }
+
+    pub fn receive_reward(&mut self, reward: &[f64], action: usize) {
+        for (sum, r) in self.sums[action].iter_mut().zip(reward) {
+            *sum += *r;
+        }
+        self.pulls[action] += 1;
+        self.t += 1;
+    }
+}
+
+// Sums over tasks of every round's metrics.
+struct ParetoSums {
+    regret: Vec<f64>,
+    cum_regret: Vec<f64>,
+    front: Vec<f64>,
+}
+
+impl ParetoSums {
+    fn new(num_rounds: usize) -> ParetoSums {
+        ParetoSums {
+            regret: vec![0.0; num_rounds],
+            cum_regret: vec![0.0; num_rounds],
+            front: vec![0.0; num_rounds],
+        }
+    }
+
+    fn add(&mut self, other: &ParetoSums) {
+        for t in 0..self.regret.len() {
+            self.regret[t] += other.regret[t];
+            self.cum_regret[t] += other.cum_regret[t];
+            self.front[t] += other.front[t];
+        }
+    }
+}
+
+fn run_tasks<R: Rng>(rng: &mut R, opts: &Options) -> (ParetoSums, usize) {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = ParetoSums::new(opts.num_rounds);
+        let mut front_size = 0;
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let env = VectorTestbed::draw(opts.n, opts.num_objectives, &mut rng);
+            let mut agent = ParetoUcb::new(opts.n, opts.num_objectives);
+            let mut total = 0.0;
+            for t in 0..opts.num_rounds {
+                let action = agent.choose_action(&mut rng);
+                let reward = env.step(action, &mut rng);
+                agent.receive_reward(&reward, action);
+                let regret = env.regret(action);
+                total += regret;
+                sums.regret[t] += regret;
+                sums.cum_regret[t] += total;
+                if env.front.contains(&action) {
+                    sums.front[t] += 100.0;
+                }
+            }
+            front_size += env.front.len();
+            progress::task_done(i);
+        }
+        (sums, front_size)
+    });
+
+    let mut sums = ParetoSums::new(opts.num_rounds);
+    let mut front_size = 0;
+    for &(ref chunk, size) in &chunks {
+        sums.add(chunk);
+        front_size += size;
+    }
+    (sums, front_size)
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let (sums, front_size) = match opts.seed {
+        Some(seed) => run_tasks(&mut seeded_rng(seed), opts),
+        None => run_tasks(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let last = opts.num_rounds - 1;
+    println!("pareto-ucb, {} arms x {} objectives: {} tasks x {} rounds",
+             opts.n, opts.num_objectives, opts.num_tasks, opts.num_rounds);
+    println!("{:.2} arms on the Pareto front on average", front_size as f64 / tasks);
+    println!("final round: Pareto regret {:.4}, {:.1}% of tasks on the front",
+             sums.regret[last] / tasks, sums.front[last] / tasks);
+    println!("total Pareto regret {:.4}", sums.cum_regret[last] / tasks);
+    Ok(())
+}
+
+fn write_csv(sums: &ParetoSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let tasks = num_tasks as f64;
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,avg_regret,cum_regret,pct_front")?;
+    for t in 0..sums.regret.len() {
+        writeln!(f, "{},{},{},{}", t, sums.regret[t] / tasks, sums.cum_regret[t] / tasks,
+                 sums.front[t] / tasks)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testbed(means: Vec<Vec<f64>>) -> VectorTestbed {
+        let front = pareto_front(&means);
+        VectorTestbed { means, front }
+    }
+
+    // The last arm is dominated by the first and third; it needs another
+    // 0.3 in both objectives to catch up with the third, which is the most
+    // any arm needs.
+    #[test]
+    fn front_and_regret_by_hand() {
+        let bed = testbed(vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5],
+                               vec![0.2, -0.1]]);
+        assert_eq!(bed.front, [0, 1, 2]);
+        for a in 0..3 {
+            assert_eq!(bed.regret(a), 0.0);
+        }
+        assert!((bed.regret(3) - 0.3).abs() < 1e-12);
+        assert_eq!(pareto_front(&[vec![1.0, 1.0], vec![1.0, 1.0], vec![1.0, 0.5]]), [0, 1]);
+    }
+
+    #[test]
+    fn pareto_ucb_plays_the_front() {
+        let bed = testbed(vec![vec![0.0, 0.0], vec![2.0, 1.5], vec![-1.0, 0.5]]);
+        let mut rng = seeded_rng(5);
+        let mut agent = ParetoUcb::new(3, 2);
+        for _ in 0..500 {
+            let action = agent.choose_action(&mut rng);
+            let reward = bed.step(action, &mut rng);
+            agent.receive_reward(&reward, action);
+        }
+        assert!(agent.pulls[1] > 400, "{:?}", agent.pulls);
+    }
+}
@@ -0,0 +1,171 @@
+// Renders the two standard bandit plots, average reward and % optimal action
+// against plays, one line per agent configuration. The average reward is
+// drawn with a shaded band of +/- 2 standard errors across tasks. The image
+// is SVG if the path ends in `.svg` and PNG otherwise. Parameter studies
+// get a plot of their own, average reward against the parameter.
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::Curves;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 900;
+
+// half-width of the average reward's error band, in standard errors
+const BAND: f64 = 2.0;
+
+pub fn plot_curves(path: &str, series: &[(String, &Curves)]) -> Result<(), String> {
+    assert!(!series.is_empty());
+    let result = if path.ends_with(".svg") {
+        draw(SVGBackend::new(path, (WIDTH, HEIGHT)).into_drawing_area(), series)
+    } else {
+        draw(BitMapBackend::new(path, (WIDTH, HEIGHT)).into_drawing_area(), series)
+    };
+    result.map_err(|e| format!("couldn't plot to `{}`: {}", path, e))
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>,
+                            series: &[(String, &Curves)]) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+    let areas = root.split_evenly((2, 1));
+
+    let mut low = f64::INFINITY;
+    let mut high = f64::NEG_INFINITY;
+    for &(_, curves) in series {
+        for t in 0..curves.avg_reward.len() {
+            low = low.min(curves.avg_reward[t] - BAND * curves.reward_se[t]);
+            high = high.max(curves.avg_reward[t] + BAND * curves.reward_se[t]);
+        }
+    }
+    let pad = 0.05 * (high - low).max(1e-3);
+
+    let panels = [("Average reward", low - pad, high + pad),
+                  ("% Optimal action", 0.0, 100.0)];
+    let num_plays = series[0].1.avg_reward.len();
+    for (p, &(title, y_min, y_max)) in panels.iter().enumerate() {
+        let mut chart = ChartBuilder::on(&areas[p])
+            .caption(title, ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(35)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0f64..num_plays as f64, y_min..y_max)
+            .map_err(|e| e.to_string())?;
+        chart.configure_mesh()
+             .x_desc("Plays")
+             .x_label_formatter(&|x| format!("{}", *x as usize))
+             .draw()
+             .map_err(|e| e.to_string())?;
+
+        for (i, &(ref label, curves)) in series.iter().enumerate() {
+            let values = if p == 0 { &curves.avg_reward } else { &curves.pct_optimal };
+            let color = Palette99::pick(i).to_rgba();
+            if p == 0 {
+                let upper = values.iter().zip(&curves.reward_se).enumerate()
+                                  .map(|(t, (&v, &se))| ((t + 1) as f64, v + BAND * se));
+                let lower = values.iter().zip(&curves.reward_se).enumerate().rev()
+                                  .map(|(t, (&v, &se))| ((t + 1) as f64, v - BAND * se));
+                chart.draw_series(::std::iter::once(
+                         Polygon::new(upper.chain(lower).collect::<Vec<_>>(), color.mix(0.2))))
+                     .map_err(|e| e.to_string())?;
+            }
+            chart.draw_series(LineSeries::new(
+                     values.iter().enumerate().map(|(t, &v)| ((t + 1) as f64, v)), color))
+                 .map_err(|e| e.to_string())?
+                 .label(label.clone())
+                 .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.configure_series_labels()
+             .background_style(WHITE.mix(0.8))
+             .border_style(BLACK)
+             .position(SeriesLabelPosition::LowerRight)
+             .draw()
+             .map_err(|e| e.to_string())?;
+    }
+    root.present().map_err(|e| e.to_string())
+}
+
+// A point of a parameter study: the parameter's value, and the average
+// reward there with its standard error.
+pub type StudyPoint = (f64, f64, f64);
+
+// Draws a parameter study: average reward against a hyperparameter, one
+// line per agent family, each point with +/- 2 standard errors. The
+// parameter axis is log2, labelled with the values themselves. `series`
+// holds each family's label and its points, whose values must be positive.
+pub fn plot_parameter_study(path: &str, series: &[(String, Vec<StudyPoint>)])
+                            -> Result<(), String> {
+    assert!(!series.is_empty());
+    let result = if path.ends_with(".svg") {
+        draw_study(SVGBackend::new(path, (WIDTH, HEIGHT / 2)).into_drawing_area(), series)
+    } else {
+        draw_study(BitMapBackend::new(path, (WIDTH, HEIGHT / 2)).into_drawing_area(), series)
+    };
+    result.map_err(|e| format!("couldn't plot to `{}`: {}", path, e))
+}
+
+fn draw_study<DB: DrawingBackend>(root: DrawingArea<DB, Shift>,
+                                  series: &[(String, Vec<StudyPoint>)])
+                                  -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+    let (mut x_min, mut x_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut low, mut high) = (f64::INFINITY, f64::NEG_INFINITY);
+    for (_, points) in series {
+        for &(value, mean, se) in points {
+            x_min = x_min.min(value.log2());
+            x_max = x_max.max(value.log2());
+            low = low.min(mean - BAND * se);
+            high = high.max(mean + BAND * se);
+        }
+    }
+    let x_pad = 0.05 * (x_max - x_min).max(1.0);
+    let pad = 0.05 * (high - low).max(1e-3);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Average reward vs parameter", ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .build_cartesian_2d(x_min - x_pad..x_max + x_pad, low - pad..high + pad)
+        .map_err(|e| e.to_string())?;
+    chart.configure_mesh()
+         .x_desc("Parameter")
+         .x_label_formatter(&power_of_two_label)
+         .draw()
+         .map_err(|e| e.to_string())?;
+
+    for (i, (label, points)) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        chart.draw_series(points.iter().map(|&(value, mean, se)| {
+                 PathElement::new(vec![(value.log2(), mean - BAND * se),
+                                       (value.log2(), mean + BAND * se)], color.mix(0.5))
+             }))
+             .map_err(|e| e.to_string())?;
+        chart.draw_series(LineSeries::new(
+                 points.iter().map(|&(value, mean, _)| (value.log2(), mean)), color))
+             .map_err(|e| e.to_string())?
+             .label(label.clone())
+             .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    chart.configure_series_labels()
+         .background_style(WHITE.mix(0.8))
+         .border_style(BLACK)
+         .position(SeriesLabelPosition::LowerRight)
+         .draw()
+         .map_err(|e| e.to_string())?;
+    root.present().map_err(|e| e.to_string())
+}
+
+// Labels log2 tick x with 2^x, as a fraction below 1, or nothing between
+// powers of two.
+fn power_of_two_label(x: &f64) -> String {
+    let k = x.round();
+    if (x - k).abs() > 1e-6 {
+        String::new()
+    } else if k >= 0.0 {
+        format!("{}", 2f64.powi(k as i32))
+    } else {
+        format!("1/{}", 2f64.powi(-k as i32))
+    }
+}
@@ -0,0 +1,295 @@
+// Reward preprocessing on the agent's side. Some agents assume rewards on a
+// known, bounded scale (softmax's temperature is in reward units, and
+// UCB-style bonuses assume rewards in [0, 1]), which the gaussian testbed
+// doesn't give them. Any agent can be wrapped so that every reward is, in
+// order,
+//
+//     --reward-clip low:high          clipped to [low, high]
+//     --reward-normalize minmax       mapped to [0, 1] by the smallest and
+//                                     largest reward seen so far (this one
+//                                     included), 0.5 until they differ
+//     --reward-normalize standardize  standardized by the running mean and
+//                                     standard deviation of the rewards so
+//                                     far (this one included), 0 until
+//                                     there are two that differ
+//
+// before the agent sees it (in a config plan, `reward_clip = [low, high]`
+// and `reward_normalize = "minmax"`). The statistics are shared by all
+// arms, so rewards stay comparable between them. The agent's estimates are
+// in the transformed units too; the runner still records the rewards the
+// testbed paid.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+use crate::Agent;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalization {
+    Minmax,
+    Standardize,
+}
+
+impl Normalization {
+    pub fn from_name(name: &str) -> Result<Normalization, String> {
+        match name {
+            "minmax" => Ok(Normalization::Minmax),
+            "standardize" => Ok(Normalization::Standardize),
+            _ => Err(format!("unknown reward normalization `{}` (expected minmax or \
+                              standardize)", name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preprocessing {
+    pub clip: Option<(f64, f64)>,
+    pub normalize: Option<Normalization>,
+}
+
+// Flags read by Preprocessing::from_flags; any agent takes them.
+pub const PREPROCESSING_FLAGS: &[&str] = &["reward-clip", "reward-normalize"];
+
+impl Preprocessing {
+    // Reads `--reward-clip` and `--reward-normalize`, if either is given.
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Option<Preprocessing>, String> {
+        let clip = match flags.get("reward-clip") {
+            Some(value) => Some(parse_clip(value)?),
+            None => None,
+        };
+        let normalize = match flags.get("reward-normalize") {
+            Some(name) => Some(Normalization::from_name(name)?),
+            None => None,
+        };
+        if clip.is_none() && normalize.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Preprocessing { clip, normalize }))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some((low, high)) = self.clip {
+            if !(low.is_finite() && high.is_finite() && low < high) {
+                return Err("reward clip range must be finite with low < high".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Appended to the label of the agent it wraps, e.g. "+clip[-1,1]+minmax".
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        if let Some((low, high)) = self.clip {
+            label.push_str(&format!("+clip[{},{}]", low, high));
+        }
+        match self.normalize {
+            Some(Normalization::Minmax) => label.push_str("+minmax"),
+            Some(Normalization::Standardize) => label.push_str("+standardize"),
+            None => {}
+        }
+        label
+    }
+}
+
+fn parse_clip(value: &str) -> Result<(f64, f64), String> {
+    let bad = || format!("invalid value `{}` for `--reward-clip` (expected low:high)", value);
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 2 {
+        return Err(bad());
+    }
+    let low = parts[0].trim().parse().map_err(|_| bad())?;
+    let high = parts[1].trim().parse().map_err(|_| bad())?;
+    Ok((low, high))
+}
+
+// Running statistics of the rewards seen so far.
+#[derive(Clone, Serialize, Deserialize)]
+struct RewardScale {
+    count: usize,
+    min: f64,
+    max: f64,
+
+    // Welford's running mean and sum of squared deviations
+    mean: f64,
+    m2: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PreprocessedState {
+    agent: serde_json::Value,
+    scale: RewardScale,
+}
+
+pub struct PreprocessedAgent {
+    preprocessing: Preprocessing,
+    agent: Box<dyn Agent>,
+    scale: RewardScale,
+}
+
+impl PreprocessedAgent {
+    pub fn new(preprocessing: Preprocessing, agent: Box<dyn Agent>) -> PreprocessedAgent {
+        PreprocessedAgent {
+            preprocessing,
+            agent,
+            scale: RewardScale { count: 0, min: 0.0, max: 0.0, mean: 0.0, m2: 0.0 },
+        }
+    }
+
+    // Rebuilds a wrapper from its state(), restoring the wrapped agent with
+    // `restore`.
+    pub fn from_state(preprocessing: Preprocessing, state: serde_json::Value,
+                      restore: &dyn Fn(serde_json::Value) -> Result<Box<dyn Agent>, String>)
+                      -> Result<PreprocessedAgent, String> {
+        let state: PreprocessedState = serde_json::from_value(state).map_err(|e| e.to_string())?;
+        Ok(PreprocessedAgent {
+            preprocessing,
+            agent: restore(state.agent)?,
+            scale: state.scale,
+        })
+    }
+
+    fn transform(&mut self, reward: f64) -> f64 {
+        let mut r = reward;
+        if let Some((low, high)) = self.preprocessing.clip {
+            r = r.max(low).min(high);
+        }
+        let normalize = match self.preprocessing.normalize {
+            Some(normalize) => normalize,
+            None => return r,
+        };
+
+        let s = &mut self.scale;
+        s.count += 1;
+        if s.count == 1 {
+            s.min = r;
+            s.max = r;
+        } else {
+            s.min = s.min.min(r);
+            s.max = s.max.max(r);
+        }
+        let delta = r - s.mean;
+        s.mean += delta / s.count as f64;
+        s.m2 += delta * (r - s.mean);
+
+        match normalize {
+            Normalization::Minmax if s.max > s.min => (r - s.min) / (s.max - s.min),
+            Normalization::Minmax => 0.5,
+            Normalization::Standardize if s.count > 1 && s.m2 > 0.0 => {
+                (r - s.mean) / (s.m2 / (s.count - 1) as f64).sqrt()
+            }
+            Normalization::Standardize => 0.0,
+        }
+    }
+}
+
+impl Agent for PreprocessedAgent {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        self.agent.choose_action(rng)
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        let reward = self.transform(reward);
+        self.agent.receive_reward(reward, action);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.agent.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.agent.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.agent.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.agent.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(PreprocessedState {
+            agent: self.agent.state(),
+            scale: self.scale.clone(),
+        }).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{seeded_rng, EpsilonGreedyBandit};
+
+    fn preprocessed(clip: Option<(f64, f64)>, normalize: Option<Normalization>)
+                    -> PreprocessedAgent {
+        let agent = Box::new(EpsilonGreedyBandit::new(3, 1.0));
+        PreprocessedAgent::new(Preprocessing { clip, normalize }, agent)
+    }
+
+    fn transformed(agent: &mut PreprocessedAgent, rewards: &[f64]) -> Vec<f64> {
+        rewards.iter().map(|&r| agent.transform(r)).collect()
+    }
+
+    // Clipping comes first; normalizing uses the rewards so far, this one
+    // included.
+    #[test]
+    fn transforms_by_hand() {
+        let mut agent = preprocessed(Some((-1.0, 1.0)), None);
+        assert_eq!(transformed(&mut agent, &[5.0, -3.0, 0.25]), [1.0, -1.0, 0.25]);
+        let mut agent = preprocessed(None, Some(Normalization::Minmax));
+        assert_eq!(transformed(&mut agent, &[2.0, 2.0, 4.0, 3.0, 0.0]),
+                   [0.5, 0.5, 1.0, 0.5, 0.0]);
+        let mut agent = preprocessed(Some((0.0, 2.0)), Some(Normalization::Minmax));
+        assert_eq!(transformed(&mut agent, &[1.0, 7.0, -7.0]), [0.5, 1.0, 0.0]);
+        let mut agent = preprocessed(None, Some(Normalization::Standardize));
+        let standardized = transformed(&mut agent, &[1.0, 1.0, 4.0]);
+        assert_eq!(standardized[..2], [0.0, 0.0]);
+        assert!((standardized[2] - 2.0 / 3.0f64.sqrt()).abs() < 1e-12);
+    }
+
+    // The wrapped agent learns from the transformed rewards, and arms added
+    // or retired through the wrapper are added or retired in it.
+    #[test]
+    fn forwards_to_the_wrapped_agent() {
+        let mut agent = preprocessed(None, Some(Normalization::Minmax));
+        agent.receive_reward(10.0, 0);
+        agent.receive_reward(20.0, 1);
+        agent.receive_reward(15.0, 1);
+        let means: Vec<f64> = agent.arm_summaries().iter().map(|arm| arm.mean).collect();
+        assert_eq!(means, [0.5, 0.75, 0.0]);
+
+        assert_eq!(agent.add_arm(None), 3);
+        assert_eq!(agent.arm_summaries().len(), 4);
+        agent.set_retired(0, true);
+        agent.set_retired(2, true);
+        let mut rng = seeded_rng(1);
+        for _ in 0..50 {
+            let action = agent.choose_action(&mut rng);
+            assert!(action == 1 || action == 3);
+        }
+    }
+
+    #[test]
+    fn reads_its_flags() {
+        let flags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(Preprocessing::from_flags(&flags(&[])).unwrap(), None);
+        let preprocessing = Preprocessing::from_flags(&flags(&[("reward-clip", "-1:2"),
+                                                              ("reward-normalize", "minmax")]))
+                                .unwrap().unwrap();
+        assert_eq!(preprocessing, Preprocessing {
+            clip: Some((-1.0, 2.0)),
+            normalize: Some(Normalization::Minmax),
+        });
+        assert_eq!(preprocessing.label(), "+clip[-1,2]+minmax");
+        assert!(Preprocessing::from_flags(&flags(&[("reward-clip", "1")])).is_err());
+        assert!(Preprocessing::from_flags(&flags(&[("reward-normalize", "log")])).is_err());
+        let backwards = Preprocessing::from_flags(&flags(&[("reward-clip", "2:1")]));
+        assert!(backwards.unwrap().unwrap().validate().is_err());
+    }
+}
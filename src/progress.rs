@@ -0,0 +1,174 @@
+// Machine-readable progress. With `--progress-format json` (any mode), an
+// event is written to stderr as one JSON object per line whenever a task
+// finishes, a configuration finishes or a partial-results checkpoint is
+// written:
+//
+//     {"event":"task-done","task":17}
+//     {"event":"config-done","config":"eps-greedy(epsilon=0.1)"}
+//     {"event":"checkpoint-written","path":"g.csv.partial","tasks_completed":400,"tasks":2000}
+//
+// so orchestration tools can follow long runs. The default `text` format
+// writes no events; the human-oriented logs on stdout are the same either
+// way.
+//
+// In the text format, when stderr is a terminal, a run's tasks are shown as
+// a progress bar there instead, with the tasks finished per second and the
+// time left:
+//
+//     [==============>               ] 950/2000 tasks, 41.3/s, ETA 0:25
+//
+// and the "Task #i" lines the modes otherwise print for every task are left
+// out. `--quiet` (any mode) turns off both.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static BAR: Mutex<Option<Bar>> = Mutex::new(None);
+
+const BAR_WIDTH: usize = 30;
+
+// the bar is redrawn at most this often, and when the last task finishes
+const REDRAW_MILLIS: u64 = 100;
+
+struct Bar {
+    total: usize,
+
+    // tasks finished, counting ones finished before a resume
+    done: usize,
+    resumed: usize,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Bar {
+    fn draw(&mut self) {
+        let now = Instant::now();
+        let finished = self.done >= self.total;
+        if let Some(drawn) = self.drawn {
+            if !finished && now.duration_since(drawn) < Duration::from_millis(REDRAW_MILLIS) {
+                return;
+            }
+        }
+        self.drawn = Some(now);
+
+        let filled = (BAR_WIDTH * self.done).checked_div(self.total).unwrap_or(BAR_WIDTH);
+        let mut bar: String = (0..filled).map(|_| '=').collect();
+        if filled < BAR_WIDTH {
+            bar.push('>');
+            bar.extend((filled + 1..BAR_WIDTH).map(|_| ' '));
+        }
+        let elapsed = duration_secs(now.duration_since(self.started));
+        let rate = (self.done - self.resumed) as f64 / elapsed.max(1e-9);
+        let eta = if finished {
+            format!("done in {}", clock(elapsed))
+        } else if self.done > self.resumed {
+            format!("ETA {}", clock((self.total - self.done) as f64 / rate))
+        } else {
+            "ETA -".to_string()
+        };
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = write!(stderr, "\r[{}] {}/{} tasks, {:.1}/s, {}\x1b[K", bar, self.done,
+                       self.total, rate, eta);
+        if finished {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+// Seconds as m:ss, or h:mm:ss past an hour.
+fn clock(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event<'a> {
+    TaskDone { task: usize },
+    ConfigDone { config: &'a str },
+    CheckpointWritten { path: &'a str, tasks_completed: usize, tasks: usize },
+}
+
+// Sets the format from `--progress-format`.
+pub fn set_format(value: &str) -> Result<(), String> {
+    match value {
+        "text" => JSON.store(false, Ordering::Relaxed),
+        "json" => JSON.store(true, Ordering::Relaxed),
+        _ => return Err(format!("unknown progress format `{}` (expected text or json)", value)),
+    }
+    Ok(())
+}
+
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+fn bar_shown() -> bool {
+    !QUIET.load(Ordering::Relaxed) && !JSON.load(Ordering::Relaxed) && io::stderr().is_terminal()
+}
+
+// Whether the modes should print a line for every task.
+pub fn task_lines() -> bool {
+    !QUIET.load(Ordering::Relaxed) && !bar_shown()
+}
+
+// Starts the bar for a run of `total` tasks, replacing any earlier run's.
+pub fn start(total: usize) {
+    if !bar_shown() {
+        return;
+    }
+    let mut bar = BAR.lock().unwrap();
+    *bar = Some(Bar { total, done: 0, resumed: 0, started: Instant::now(), drawn: None });
+}
+
+// Counts the tasks a resumed run already has as done, without counting them
+// towards its rate.
+pub fn resumed(done: usize) {
+    if let Some(ref mut bar) = *BAR.lock().unwrap() {
+        bar.done = done;
+        bar.resumed = done;
+    }
+}
+
+fn emit(event: &Event) {
+    if !JSON.load(Ordering::Relaxed) {
+        return;
+    }
+    // tasks finish on several threads; one locked write per line keeps the
+    // lines whole
+    if let Ok(line) = serde_json::to_string(event) {
+        let stderr = io::stderr();
+        let _ = writeln!(stderr.lock(), "{}", line);
+    }
+}
+
+pub fn task_done(task: usize) {
+    emit(&Event::TaskDone { task });
+    if let Some(ref mut bar) = *BAR.lock().unwrap() {
+        bar.done += 1;
+        bar.draw();
+    }
+}
+
+pub fn config_done(config: &str) {
+    emit(&Event::ConfigDone { config });
+}
+
+pub fn checkpoint_written(path: &str, tasks_completed: usize, tasks: usize) {
+    emit(&Event::CheckpointWritten { path, tasks_completed, tasks });
+}
@@ -0,0 +1,211 @@
+// Agents chosen by name. Besides the built-in kinds (see spec), any number
+// of agents can be registered under a name, each with a function that
+// builds one for a number of arms from its parameters, given as strings:
+//
+//     registry::register("sticky", |params, n| {
+//         let stickiness = registry::param(params, "stickiness", 0.5)?;
+//         Ok(Box::new(StickyBandit::new(n, stickiness)) as Box<dyn Agent>)
+//     })?;
+//
+// after which `--agent sticky --agent.stickiness 0.9` selects it wherever a
+// mode takes an agent, as does a config plan's
+//
+//     [[agents]]
+//     name = "sticky"
+//     kind = "sticky"
+//     params = { stickiness = 0.9 }
+//
+// `--agent.<param>` also sets a built-in kind's hyperparameters, e.g.
+// `--agent ucb --agent.c 2.0` is `--agent ucb --c 2.0`. A registered agent
+// is built afresh for every task, like any other, but isn't known to saved
+// agents or checkpoints' states, which can't restore it.
+//
+// Wrappers around agents (see wrappers) are registered the same way, with a
+// function that wraps an agent for a number of arms:
+//
+//     registry::register_wrapper("delayed", |params, agent, n| {
+//         let plays = registry::param(params, "plays", 1)?;
+//         Ok(Box::new(DelayedAgent::new(n, plays, agent)) as Box<dyn Agent>)
+//     })?;
+//
+// after which a config plan's `wrappers = [{ kind = "delayed", params = {
+// plays = 5 } }]` wraps an agent in it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::error::Error;
+use crate::spec::{AgentSpec, BUILTIN_AGENTS};
+use crate::wrappers::BUILTIN_WRAPPERS;
+use crate::Agent;
+
+// A registered agent's parameters, by name.
+pub type Params = BTreeMap<String, String>;
+
+type MakeAgent = dyn Fn(&Params, usize) -> Result<Box<dyn Agent>, String> + Send + Sync;
+
+static REGISTRY: RwLock<BTreeMap<String, Arc<MakeAgent>>> = RwLock::new(BTreeMap::new());
+
+type WrapAgent = dyn Fn(&Params, Box<dyn Agent>, usize) -> Result<Box<dyn Agent>, String>
+                     + Send + Sync;
+
+static WRAPPERS: RwLock<BTreeMap<String, Arc<WrapAgent>>> = RwLock::new(BTreeMap::new());
+
+// Registers `make` under `name`, which mustn't be taken already.
+pub fn register<F>(name: &str, make: F) -> Result<(), Error>
+    where F: Fn(&Params, usize) -> Result<Box<dyn Agent>, String> + Send + Sync + 'static
+{
+    if name.is_empty() || name.contains('.') {
+        return Err(Error::Invalid(format!("invalid agent name `{}`", name)));
+    }
+    let mut registry = REGISTRY.write().unwrap();
+    if BUILTIN_AGENTS.contains(&name) || registry.contains_key(name) {
+        return Err(Error::Invalid(format!("agent `{}` is already registered", name)));
+    }
+    registry.insert(name.to_string(), Arc::new(make));
+    Ok(())
+}
+
+pub fn is_registered(name: &str) -> bool {
+    REGISTRY.read().unwrap().contains_key(name)
+}
+
+// Every agent name, the built-in kinds first.
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_AGENTS.iter().map(|name| name.to_string()).collect();
+    names.extend(REGISTRY.read().unwrap().keys().cloned());
+    names
+}
+
+// Builds the agent named `name` with `n` arms. A built-in kind takes its
+// usual flags as parameters, without the dashes, and defaults.
+pub fn make(name: &str, params: &Params, n: usize) -> Result<Box<dyn Agent>, Error> {
+    if BUILTIN_AGENTS.contains(&name) {
+        let mut flags: HashMap<String, String> =
+            params.iter().map(|(key, value)| (format!("agent.{}", key), value.clone()))
+                  .collect();
+        flags.insert("agent".to_string(), name.to_string());
+        return Ok(AgentSpec::from_flags(&flags)?.build(n));
+    }
+    let make = match REGISTRY.read().unwrap().get(name) {
+        Some(make) => make.clone(),
+        None => return Err(unknown(name)),
+    };
+    make(params, n).map_err(|e| Error::Invalid(format!("agent `{}`: {}", name, e)))
+}
+
+pub fn unknown(name: &str) -> Error {
+    Error::Invalid(format!("unknown agent `{}` (expected one of {})", name,
+                           names().join(", ")))
+}
+
+// Registers the wrapper `wrap` under `name`, which mustn't be taken already.
+pub fn register_wrapper<F>(name: &str, wrap: F) -> Result<(), Error>
+    where F: Fn(&Params, Box<dyn Agent>, usize) -> Result<Box<dyn Agent>, String>
+                 + Send + Sync + 'static
+{
+    if name.is_empty() || name.contains('.') {
+        return Err(Error::Invalid(format!("invalid wrapper name `{}`", name)));
+    }
+    let mut wrappers = WRAPPERS.write().unwrap();
+    if BUILTIN_WRAPPERS.contains(&name) || wrappers.contains_key(name) {
+        return Err(Error::Invalid(format!("wrapper `{}` is already registered", name)));
+    }
+    wrappers.insert(name.to_string(), Arc::new(wrap));
+    Ok(())
+}
+
+pub fn is_registered_wrapper(name: &str) -> bool {
+    WRAPPERS.read().unwrap().contains_key(name)
+}
+
+// Every wrapper name, the built-in kinds first.
+pub fn wrapper_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_WRAPPERS.iter().map(|name| name.to_string()).collect();
+    names.extend(WRAPPERS.read().unwrap().keys().cloned());
+    names
+}
+
+// Wraps `agent`, which has `n` arms, in the wrapper registered as `name`.
+pub fn wrap(name: &str, params: &Params, agent: Box<dyn Agent>, n: usize)
+            -> Result<Box<dyn Agent>, Error> {
+    let wrap = match WRAPPERS.read().unwrap().get(name) {
+        Some(wrap) => wrap.clone(),
+        None => return Err(unknown_wrapper(name)),
+    };
+    wrap(params, agent, n).map_err(|e| Error::Invalid(format!("wrapper `{}`: {}", name, e)))
+}
+
+pub fn unknown_wrapper(name: &str) -> Error {
+    Error::Invalid(format!("unknown wrapper `{}` (expected one of {})", name,
+                           wrapper_names().join(", ")))
+}
+
+// Reads parameter `key`, or `default` if it isn't given.
+pub fn param<T: ::std::str::FromStr>(params: &Params, key: &str, default: T)
+                                     -> Result<T, String> {
+    match params.get(key) {
+        Some(value) => value.trim().parse()
+                            .map_err(|_| format!("invalid value `{}` for `{}`", value, key)),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EpsilonGreedyBandit;
+
+    fn flags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    // A registered name builds its agent, through make and through the
+    // agent flags, with its parameters checked; unknown and taken names are
+    // rejected.
+    #[test]
+    fn registered_agents_are_made_by_name() {
+        register("registry-test-greedy", |params, n| {
+            let epsilon = param(params, "epsilon", 0.1)?;
+            if !(0.0..=1.0).contains(&epsilon) {
+                return Err("epsilon must be in [0, 1]".to_string());
+            }
+            Ok(Box::new(EpsilonGreedyBandit::new(n, epsilon)) as Box<dyn Agent>)
+        }).unwrap();
+        assert!(is_registered("registry-test-greedy"));
+        assert!(names().contains(&"registry-test-greedy".to_string()));
+        assert!(register("registry-test-greedy", |_, n| {
+            Ok(Box::new(EpsilonGreedyBandit::new(n, 0.1)) as Box<dyn Agent>)
+        }).is_err());
+        assert!(register("ucb", |_, n| {
+            Ok(Box::new(EpsilonGreedyBandit::new(n, 0.1)) as Box<dyn Agent>)
+        }).is_err());
+
+        let mut params = Params::new();
+        assert_eq!(make("registry-test-greedy", &params, 4).unwrap().arm_summaries().len(), 4);
+        params.insert("epsilon".to_string(), "2".to_string());
+        assert!(make("registry-test-greedy", &params, 4).is_err());
+        params.insert("epsilon".to_string(), "lots".to_string());
+        assert!(make("registry-test-greedy", &params, 4).is_err());
+        assert!(make("registry-test-unknown", &Params::new(), 4).is_err());
+
+        let spec = AgentSpec::from_flags(&flags(&[("agent", "registry-test-greedy"),
+                                                  ("agent.epsilon", "0.5")])).unwrap();
+        assert_eq!(spec.build(3).arm_summaries().len(), 3);
+        assert!(AgentSpec::from_flags(&flags(&[("agent", "registry-test-greedy"),
+                                               ("agent.epsilon", "-1")])).is_err());
+        assert!(AgentSpec::from_flags(&flags(&[("agent", "registry-test-greedy"),
+                                               ("epsilon", "0.5")])).is_err());
+        assert!(AgentSpec::from_flags(&flags(&[("agent", "registry-test-unknown")])).is_err());
+    }
+
+    // A built-in kind takes its flags as parameters.
+    #[test]
+    fn builtin_agents_take_their_flags_as_params() {
+        let mut params = Params::new();
+        params.insert("c".to_string(), "2".to_string());
+        assert_eq!(make("ucb", &params, 5).unwrap().arm_summaries().len(), 5);
+        params.insert("no-such-flag".to_string(), "2".to_string());
+        assert!(make("ucb", &params, 5).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+// Automatic arm retirement for online experiments. At every check, each
+// arm's anytime-valid confidence interval is compared with the others: an
+// arm whose upper bound is below the best lower bound is dominated. An arm
+// that has been dominated at `windows` consecutive checks is retired, so the
+// agent stops spending plays on it. Retirement is reversible: a retired arm
+// that is no longer dominated (say the leader's lower bound fell back after
+// some unlucky rewards) is reinstated at the next check.
+//
+// The arm with the best lower bound is never dominated, so at least one arm
+// always stays active.
+
+use crate::confseq::ConfidenceSequence;
+use crate::stats::ArmSummary;
+use crate::Agent;
+
+pub struct RetirementEvent {
+    pub plays: usize,
+    pub arm: usize,
+
+    // false for a reinstatement
+    pub retired: bool,
+}
+
+pub struct RetirementPolicy {
+    cs: ConfidenceSequence,
+    windows: usize,
+
+    // consecutive checks at which each arm was dominated
+    streaks: Vec<usize>,
+    retired: Vec<bool>,
+}
+
+impl RetirementPolicy {
+    pub fn new(cs: ConfidenceSequence, windows: usize) -> RetirementPolicy {
+        assert!(windows > 0);
+        RetirementPolicy {
+            cs,
+            windows,
+            streaks: vec![],
+            retired: vec![],
+        }
+    }
+
+    // Reviews the arms after `plays` plays, retiring and reinstating arms of
+    // `agent` as needed, and returns what changed.
+    pub fn review(&mut self, plays: usize, arms: &[ArmSummary],
+                  agent: &mut dyn Agent) -> Vec<RetirementEvent> {
+        // arms may have been added since the last review
+        while self.streaks.len() < arms.len() {
+            self.streaks.push(0);
+            self.retired.push(false);
+        }
+
+        let dominated = self.cs.dominated(arms);
+        let mut events = vec![];
+        for (i, &dominated) in dominated.iter().enumerate() {
+            if dominated {
+                self.streaks[i] += 1;
+            } else {
+                self.streaks[i] = 0;
+            }
+
+            let retire = self.streaks[i] >= self.windows;
+            if retire != self.retired[i] {
+                self.retired[i] = retire;
+                agent.set_retired(i, retire);
+                events.push(RetirementEvent { plays, arm: i, retired: retire });
+            }
+        }
+        events
+    }
+
+    pub fn num_retired(&self) -> usize {
+        self.retired.iter().filter(|&&r| r).count()
+    }
+}
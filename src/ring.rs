@@ -0,0 +1,132 @@
+// Fixed-capacity histories. Most agents only need counts and sums of their
+// rewards (see stats), but a few estimators genuinely need the rewards
+// themselves: medians of means, CVaR's lower tail, and the doubling trick's
+// replay of past plays to a fresh agent. Those keep them in a ring buffer
+// that holds at most `capacity` values and drops the oldest to make room,
+// so their memory stays bounded however long a task runs: at most
+//
+//     capacity * arms * 8 bytes
+//
+// per agent for per-arm rewards (twice that for CVaR, which also keeps
+// them sorted), 80 KB per arm at the default capacity. Past the capacity
+// those estimators become windowed ones, over the most recent rewards.
+// `--history N` (`history = N` in a config plan) sets the capacity.
+
+use std::convert::TryFrom;
+use std::iter::Chain;
+use std::slice;
+
+// Capacity used when an agent isn't given one.
+pub const DEFAULT_HISTORY: usize = 10_000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "RingBufferState<T>")]
+pub struct RingBuffer<T> {
+    capacity: usize,
+
+    // once full, the oldest value is at `start` and the rest follow it
+    // around the end
+    values: Vec<T>,
+    start: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> RingBuffer<T> {
+        assert!(capacity > 0);
+        RingBuffer { capacity, values: vec![], start: 0 }
+    }
+
+    // Appends `value`, returning the oldest value if it had to be dropped.
+    pub fn push(&mut self, value: T) -> Option<T> {
+        if self.values.len() < self.capacity {
+            self.values.push(value);
+            return None;
+        }
+        let old = ::std::mem::replace(&mut self.values[self.start], value);
+        self.start = (self.start + 1) % self.capacity;
+        Some(old)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // The values from oldest to newest.
+    pub fn iter<'a>(&'a self) -> Chain<slice::Iter<'a, T>, slice::Iter<'a, T>> {
+        self.values[self.start..].iter().chain(self.values[..self.start].iter())
+    }
+}
+
+// A ring buffer as saved, checked before it's turned back into one (see
+// agent_state), since push and iter rely on its fields agreeing.
+#[derive(Deserialize)]
+struct RingBufferState<T> {
+    capacity: usize,
+    values: Vec<T>,
+    start: usize,
+}
+
+impl<T> TryFrom<RingBufferState<T>> for RingBuffer<T> {
+    type Error = String;
+
+    fn try_from(state: RingBufferState<T>) -> Result<RingBuffer<T>, String> {
+        if state.capacity == 0 {
+            return Err("ring buffer capacity must be positive".to_string());
+        }
+        if state.values.len() > state.capacity {
+            return Err(format!("ring buffer holds {} values, more than its capacity {}",
+                               state.values.len(), state.capacity));
+        }
+        let full = state.values.len() == state.capacity;
+        if (full && state.start >= state.capacity) || (!full && state.start != 0) {
+            return Err(format!("invalid ring buffer start {} for {} of {} values", state.start,
+                               state.values.len(), state.capacity));
+        }
+        Ok(RingBuffer { capacity: state.capacity, values: state.values, start: state.start })
+    }
+}
+
+impl<T: Clone> RingBuffer<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+
+    #[test]
+    fn drops_the_oldest() {
+        let mut ring = RingBuffer::new(3);
+        assert_eq!(ring.push(1), None);
+        assert_eq!(ring.push(2), None);
+        assert_eq!(ring.push(3), None);
+        assert_eq!(ring.push(4), Some(1));
+        assert_eq!(ring.push(5), Some(2));
+        assert_eq!(ring.to_vec(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut ring = RingBuffer::new(2);
+        for i in 0..5 {
+            ring.push(i);
+        }
+        let state = serde_json::to_string(&ring).unwrap();
+        let mut restored: RingBuffer<i32> = serde_json::from_str(&state).unwrap();
+        assert_eq!(restored.to_vec(), vec![3, 4]);
+        assert_eq!(restored.push(5), Some(3));
+    }
+
+    #[test]
+    fn rejects_malformed_states() {
+        for state in &[r#"{"capacity":0,"values":[],"start":0}"#,
+                       r#"{"capacity":2,"values":[1,2],"start":2}"#,
+                       r#"{"capacity":3,"values":[1],"start":1}"#,
+                       r#"{"capacity":1,"values":[1,2],"start":0}"#] {
+            assert!(serde_json::from_str::<RingBuffer<i32>>(state).is_err(), "{}", state);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+// Mean versus risk. In the `risk` mode every arm pays Gaussian rewards with
+// its own standard deviation, drawn for every task uniformly from
+// [min-sd, max-sd], so an arm with a high mean can also be the one with the
+// worst bad days. Besides the usual regret against the arm with the highest
+// mean, every play is scored against the arm with the highest conditional
+// value-at-risk at level alpha (the mean of its worst alpha fraction of
+// rewards), which for N(mu, sd) is
+//
+//     CVaR(a) = mu(a) - sd(a) phi(z) / alpha,    z = Phi^-1(alpha)
+//
+// The agent is given with the usual agent flags; alpha defaults to the
+// agent's own level when it is `cvar` and to 0.1 otherwise. Averages over the
+// tasks are written for every play:
+//
+//     step,avg_reward,mean_regret,cvar_regret,pct_cvar_optimal
+//
+// where the regrets are cumulative.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{play, ArmSd, GaussianTestbed};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{best_arm, map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub min_sd: f64,
+    pub max_sd: f64,
+
+    // alpha of the CVaR the plays are scored against
+    pub level: f64,
+    pub agent: AgentSpec,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let agent = AgentSpec::from_flags(flags)?;
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            min_sd: 0.5,
+            max_sd: 3.0,
+            level: match agent {
+                AgentSpec::Cvar { level, .. } => level,
+                _ => 0.1,
+            },
+            agent,
+            output: "risk.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "min-sd" => opts.min_sd = parse_value(key, value)?,
+                "max-sd" => opts.max_sd = parse_value(key, value)?,
+                "level" => opts.level = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 || opts.num_plays == 0 {
+            return Err("arms, tasks and plays must be positive".to_string());
+        }
+        if !(opts.min_sd > 0.0 && opts.min_sd <= opts.max_sd) {
+            return Err("sds must be positive with min-sd <= max-sd".to_string());
+        }
+        if !(opts.level > 0.0 && opts.level <= 1.0) {
+            return Err("level must be in (0, 1]".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+fn standard_density(z: f64) -> f64 {
+    (-z * z / 2.0).exp() / (2.0 * PI).sqrt()
+}
+
+// Phi(z) by Simpson's rule; the density is negligible below -12.
+fn standard_cdf(z: f64) -> f64 {
+    let low = -12.0;
+    if z <= low {
+        return 0.0;
+    }
+    let steps = 2000;
+    let h = (z - low) / steps as f64;
+    let mut sum = standard_density(low) + standard_density(z);
+    for i in 1..steps {
+        let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+        sum += weight * standard_density(low + i as f64 * h);
+    }
+    sum * h / 3.0
+}
+
+// phi(z) / alpha for z = Phi^-1(alpha), so that the CVaR of N(mu, sd) at
+// level alpha is mu - sd times this.
+pub fn gaussian_tail_factor(level: f64) -> f64 {
+    if level >= 1.0 {
+        return 0.0;
+    }
+    let (mut low, mut high) = (-12.0, 12.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if standard_cdf(mid) < level {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    standard_density((low + high) / 2.0) / level
+}
+
+// Sums over tasks of every play's metrics.
+struct RiskSums {
+    reward: Vec<f64>,
+    mean_regret: Vec<f64>,
+    cvar_regret: Vec<f64>,
+    cvar_optimal: Vec<f64>,
+
+    // tasks where the arm with the highest mean isn't the one with the
+    // highest CVaR
+    disagreements: usize,
+}
+
+impl RiskSums {
+    fn new(num_plays: usize) -> RiskSums {
+        RiskSums {
+            reward: vec![0.0; num_plays],
+            mean_regret: vec![0.0; num_plays],
+            cvar_regret: vec![0.0; num_plays],
+            cvar_optimal: vec![0.0; num_plays],
+            disagreements: 0,
+        }
+    }
+
+    fn add(&mut self, other: &RiskSums) {
+        for t in 0..self.reward.len() {
+            self.reward[t] += other.reward[t];
+            self.mean_regret[t] += other.mean_regret[t];
+            self.cvar_regret[t] += other.cvar_regret[t];
+            self.cvar_optimal[t] += other.cvar_optimal[t];
+        }
+        self.disagreements += other.disagreements;
+    }
+}
+
+fn run_tasks<R: Rng>(rng: &mut R, opts: &Options) -> RiskSums {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let arm_sd = ArmSd::Uniform { low: opts.min_sd, high: opts.max_sd };
+    let tail_factor = gaussian_tail_factor(opts.level);
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = RiskSums::new(opts.num_plays);
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let q_star = BanditTask::new(opts.n).draw_q_star(&mut rng);
+            let sds = arm_sd.draw(opts.n, &mut rng);
+            let cvars: Vec<f64> = (0..opts.n).map(|a| q_star[a] - sds[a] * tail_factor).collect();
+            let (best_mean, best_cvar) = (best_arm(&q_star), best_arm(&cvars));
+            if best_mean != best_cvar {
+                sums.disagreements += 1;
+            }
+
+            let mut env = GaussianTestbed::with_sds(q_star.clone(), sds);
+            let mut agent = opts.agent.build(opts.n);
+            let outcome = play(&mut env, &mut *agent, opts.num_plays, &mut rng);
+            let (mut mean_regret, mut cvar_regret) = (0.0, 0.0);
+            for t in 0..opts.num_plays {
+                let action = outcome.actions[t];
+                mean_regret += q_star[best_mean] - q_star[action];
+                cvar_regret += cvars[best_cvar] - cvars[action];
+                sums.reward[t] += outcome.rewards[t];
+                sums.mean_regret[t] += mean_regret;
+                sums.cvar_regret[t] += cvar_regret;
+                if action == best_cvar {
+                    sums.cvar_optimal[t] += 100.0;
+                }
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut sums = RiskSums::new(opts.num_plays);
+    for chunk in &chunks {
+        sums.add(chunk);
+    }
+    sums
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let sums = match opts.seed {
+        Some(seed) => run_tasks(&mut seeded_rng(seed), opts),
+        None => run_tasks(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let last = opts.num_plays - 1;
+    println!("{}, sds in [{}, {}], CVaR level {}: {} tasks x {} plays",
+             opts.agent.label(), opts.min_sd, opts.max_sd, opts.level, opts.num_tasks,
+             opts.num_plays);
+    println!("best mean and best CVaR arms differ in {:.1}% of tasks",
+             100.0 * sums.disagreements as f64 / tasks);
+    println!("final play: {:.1}% of tasks on the best CVaR arm", sums.cvar_optimal[last] / tasks);
+    println!("total regret: {:.4} against the best mean, {:.4} against the best CVaR",
+             sums.mean_regret[last] / tasks, sums.cvar_regret[last] / tasks);
+    Ok(())
+}
+
+fn write_csv(sums: &RiskSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let tasks = num_tasks as f64;
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,avg_reward,mean_regret,cvar_regret,pct_cvar_optimal")?;
+    for t in 0..sums.reward.len() {
+        writeln!(f, "{},{},{},{},{}", t, sums.reward[t] / tasks, sums.mean_regret[t] / tasks,
+                 sums.cvar_regret[t] / tasks, sums.cvar_optimal[t] / tasks)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // phi(Phi^-1(alpha)) / alpha from tables: the CVaR of a standard normal
+    // at levels 0.05, 0.1 and 0.5, and none at all at level 1.
+    #[test]
+    fn gaussian_tail_factors() {
+        for &(level, factor) in &[(0.05, 2.0627), (0.1, 1.7550), (0.5, 0.7979), (1.0, 0.0)] {
+            assert!((gaussian_tail_factor(level) - factor).abs() < 1e-3, "{}", level);
+        }
+    }
+}
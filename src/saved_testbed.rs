@@ -0,0 +1,117 @@
+// Testbeds saved to a file, so the very same tasks can be played by agents
+// run on other branches or machines, whatever their RNGs would draw. A saved
+// testbed is every task's true arm values, Q^*(a) or the arms' success
+// probabilities, and how rewards are drawn around them:
+//
+//     {"rewards": {"gaussian": {"sd": 1.0}}, "q_stars": [[0.3, -1.2, ...], ...]}
+//
+// or {"rewards": "bernoulli", ...}. Only the tasks are saved: the reward
+// noise is still drawn from each task's RNG. Testbeds whose tasks are fixed
+// by their true values can be saved: gaussian with the same noise for every
+// arm, bernoulli, difficulty, correlated, and saved ones.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+
+use crate::environment::{ArmSd, Testbed};
+use crate::error::Error;
+use crate::seeded_rng;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedRewards {
+    Gaussian { sd: f64 },
+    Bernoulli,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedTasks {
+    pub rewards: SavedRewards,
+    pub q_stars: Vec<Vec<f64>>,
+}
+
+impl SavedTasks {
+    // The tasks `testbed` draws on `n` arms from the given task seeds, which
+    // are the ones a run gets from the same seed.
+    pub fn capture(testbed: &Testbed, n: usize, seeds: &[usize]) -> Result<SavedTasks, String> {
+        let rewards = match *testbed {
+            Testbed::Gaussian { sd: None } | Testbed::Correlated { .. } => {
+                SavedRewards::Gaussian { sd: 1.0 }
+            }
+            Testbed::Gaussian { sd: Some(ArmSd::Fixed(sd)) } => SavedRewards::Gaussian { sd },
+            Testbed::Difficulty { gap, noise_to_gap, .. } => {
+                SavedRewards::Gaussian { sd: noise_to_gap * gap }
+            }
+            Testbed::Bernoulli { .. } => SavedRewards::Bernoulli,
+            Testbed::Saved { ref tasks } => tasks.rewards.clone(),
+            _ => {
+                return Err(format!("a {} testbed can't be saved: its tasks aren't just its \
+                                    arms' true values", testbed.label()));
+            }
+        };
+        let q_stars = seeds.iter().enumerate().map(|(i, &seed)| {
+            testbed.draw(n, i, &mut seeded_rng(seed)).means()
+        }).collect();
+        Ok(SavedTasks { rewards, q_stars })
+    }
+
+    pub fn load(path: &str, n: usize, num_tasks: usize) -> Result<SavedTasks, Error> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| Error::io(format!("read testbed `{}`", path), e))?;
+        let tasks: SavedTasks = serde_json::from_str(&contents)
+            .map_err(|e| Error::Invalid(format!("invalid testbed `{}`: {}", path, e)))?;
+        if tasks.q_stars.len() < num_tasks {
+            return Err(Error::Invalid(format!("testbed `{}` has {} tasks but the run has {}",
+                                              path, tasks.q_stars.len(), num_tasks)));
+        }
+        if let Some(q_star) = tasks.q_stars.iter().find(|q_star| q_star.len() != n) {
+            return Err(Error::Invalid(format!("testbed `{}` has a task with {} arms but the \
+                                               run has {}", path, q_star.len(), n)));
+        }
+        Ok(tasks)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let write = || -> io::Result<()> {
+            let mut f = File::create(path)?;
+            serde_json::to_writer(&mut f, self)?;
+            writeln!(f)
+        };
+        write().map_err(|e| Error::io(format!("write `{}`", path), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    // Saved tasks load back as they were, and the saved testbed plays task
+    // i with the true values the original drew for it.
+    #[test]
+    fn saved_tasks_replay_the_original_ones() {
+        let path = env::temp_dir().join(format!("saved-testbed-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let seeds = [3, 1, 4, 1, 5];
+        for testbed in &[Testbed::Gaussian { sd: None }, Testbed::Bernoulli { probs: None },
+                         Testbed::Correlated { length_scale: 2.0 }] {
+            let tasks = SavedTasks::capture(testbed, 6, &seeds).unwrap();
+            tasks.save(path).unwrap();
+            let loaded = SavedTasks::load(path, 6, 5).unwrap();
+            assert_eq!(loaded, tasks);
+            let saved = Testbed::Saved { tasks: loaded };
+            for (i, &seed) in seeds.iter().enumerate() {
+                assert_eq!(saved.draw(6, i, &mut seeded_rng(99)).means(),
+                           testbed.draw(6, i, &mut seeded_rng(seed)).means());
+            }
+            assert!(SavedTasks::load(path, 6, 6).is_err());
+            assert!(SavedTasks::load(path, 5, 5).is_err());
+        }
+        fs::remove_file(path).unwrap();
+        assert!(SavedTasks::capture(&Testbed::RandomWalk { sigma: 0.1 }, 6, &seeds).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+// Seed-robustness report: runs each configuration under many master seeds
+// and summarizes how much the final metrics move around. A configuration is
+// flagged when its rank among the other configurations isn't the same under
+// every seed, i.e. the comparison can't be trusted at this number of tasks.
+
+use std::collections::HashMap;
+
+use crate::{parse_value, run_experiment, seeded_rng};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub epsilons: Vec<f64>,
+    pub num_seeds: usize,
+
+    // number of final plays averaged to get the "final reward" metric
+    pub window: usize,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 200,
+            num_plays: 1000,
+            epsilons: vec![0.0, 0.01, 0.1],
+            num_seeds: 10,
+            window: 100,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seeds" => opts.num_seeds = parse_value(key, value)?,
+                "window" => opts.window = parse_value(key, value)?,
+                "epsilons" => {
+                    opts.epsilons = vec![];
+                    for part in value.split(',') {
+                        opts.epsilons.push(parse_value(key, part)?);
+                    }
+                }
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 || opts.num_seeds == 0 {
+            return Err("arms, tasks and seeds must all be positive".to_string());
+        }
+        if opts.window == 0 || opts.window > opts.num_plays {
+            return Err("window must be between 1 and the number of plays".to_string());
+        }
+        if opts.epsilons.is_empty() {
+            return Err("at least one epsilon is required".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+struct Summary {
+    mean: f64,
+    std_dev: f64,
+    min: f64,
+    max: f64,
+}
+
+fn summarize(xs: &[f64]) -> Summary {
+    let len = xs.len() as f64;
+    let mean = xs.iter().fold(0.0, |acc, x| acc + x) / len;
+    let var = if xs.len() > 1 {
+        xs.iter().fold(0.0, |acc, x| acc + (x - mean) * (x - mean)) / (len - 1.0)
+    } else {
+        0.0
+    };
+    Summary {
+        mean,
+        std_dev: var.sqrt(),
+        min: xs.iter().fold(f64::INFINITY, |acc, &x| acc.min(x)),
+        max: xs.iter().fold(f64::NEG_INFINITY, |acc, &x| acc.max(x)),
+    }
+}
+
+fn mean_of(xs: &[f64]) -> f64 {
+    xs.iter().fold(0.0, |acc, x| acc + x) / (xs.len() as f64)
+}
+
+pub fn run(opts: &Options) {
+    let num_configs = opts.epsilons.len();
+
+    // final_rewards[c][s] and overall_rewards[c][s] are the metrics for
+    // configuration c under master seed s
+    let mut final_rewards = vec![vec![]; num_configs];
+    let mut overall_rewards = vec![vec![]; num_configs];
+
+    for seed in 0..opts.num_seeds {
+        for c in 0..num_configs {
+            // Every configuration sees the same seed, so within one seed the
+            // configurations are compared on the same random stream.
+            let mut rng = seeded_rng(seed);
+            let avg_rewards = run_experiment(&mut rng, opts.n, opts.num_tasks, opts.num_plays,
+                                             opts.epsilons[c], false).avg_reward;
+            let tail = &avg_rewards[opts.num_plays - opts.window..];
+            final_rewards[c].push(mean_of(tail));
+            overall_rewards[c].push(mean_of(&avg_rewards));
+        }
+    }
+
+    // ranks[c] holds the rank (1 = best final reward) of configuration c
+    // under each seed
+    let mut ranks = vec![vec![]; num_configs];
+    for seed in 0..opts.num_seeds {
+        let finals: Vec<f64> = final_rewards.iter().map(|rewards| rewards[seed]).collect();
+        for (c, ranks) in ranks.iter_mut().enumerate() {
+            ranks.push(1 + finals.iter().filter(|&&other| other > finals[c]).count());
+        }
+    }
+
+    println!("seed-robustness report: {} seeds, {} tasks x {} plays, {} arms, final window {}",
+             opts.num_seeds, opts.num_tasks, opts.num_plays, opts.n, opts.window);
+    println!("{:<12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+             "config", "final", "std", "min", "max", "overall", "ranks");
+
+    let mut any_flagged = false;
+    for c in 0..num_configs {
+        let f = summarize(&final_rewards[c]);
+        let o = summarize(&overall_rewards[c]);
+        let best = *ranks[c].iter().min().unwrap();
+        let worst = *ranks[c].iter().max().unwrap();
+        let flagged = best != worst;
+        any_flagged = any_flagged || flagged;
+
+        let rank_range = if flagged {
+            format!("{}-{}", best, worst)
+        } else {
+            format!("{}", best)
+        };
+        println!("{:<12} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>8}{}",
+                 format!("eps={}", opts.epsilons[c]), f.mean, f.std_dev, f.min, f.max,
+                 o.mean, rank_range, if flagged { " *" } else { "" });
+    }
+
+    if any_flagged {
+        println!("* ranking is seed-dependent; consider more tasks per run");
+    }
+}
@@ -0,0 +1,302 @@
+// Parameter study: how sensitive each agent family is to its one
+// hyperparameter, summarized as in Sutton & Barto's Figure 2.6. Every value
+// of the parameter is run on the full testbed and reduced to a single
+// number, the average reward over all the plays (the first 1000 by
+// default), giving one curve of average reward against parameter per
+// family:
+//
+//     --epsilon 1/128..1/4     epsilon-greedy, sample averages
+//     --ucb-c 1/16..4          UCB exploration constant
+//     --tau 1/32..2            softmax temperature
+//     --alpha 1/32..1          constant step size of epsilon-greedy, with
+//                              epsilon --alpha-epsilon (0.1)
+//
+// Values take the grid mode's syntax (`0.01,0.1` or `start:end:xfactor`);
+// the ranges above, in powers of two, are used for every family when none
+// is given. As in the grid mode every value plays the same tasks. The
+// curves are written as CSV,
+//
+//     agent,parameter,value,avg_reward,std_err
+//
+// and with `--plot` drawn against a log2 axis (see plot).
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::grid::parse_grid;
+use crate::output::OutputFile;
+use crate::paired::mean_and_std_dev;
+use crate::plot::{self, StudyPoint};
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    EpsilonGreedy,
+    Ucb,
+    Softmax,
+    StepSize,
+}
+
+impl Family {
+    fn name(&self) -> &'static str {
+        match *self {
+            Family::EpsilonGreedy => "eps-greedy",
+            Family::Ucb => "ucb",
+            Family::Softmax => "softmax",
+            Family::StepSize => "eps-greedy-alpha",
+        }
+    }
+
+    fn parameter(&self) -> &'static str {
+        match *self {
+            Family::EpsilonGreedy => "epsilon",
+            Family::Ucb => "c",
+            Family::Softmax => "tau",
+            Family::StepSize => "alpha",
+        }
+    }
+
+    // log2 of the default range's ends, with a factor of 2 between values
+    fn default_range(&self) -> (i32, i32) {
+        match *self {
+            Family::EpsilonGreedy => (-7, -2),
+            Family::Ucb => (-4, 2),
+            Family::Softmax => (-5, 1),
+            Family::StepSize => (-5, 0),
+        }
+    }
+
+    fn spec(&self, value: f64, alpha_epsilon: f64) -> AgentSpec {
+        match *self {
+            Family::EpsilonGreedy | Family::StepSize => {
+                let (epsilon, step_size) = if *self == Family::StepSize {
+                    (alpha_epsilon, Some(value))
+                } else {
+                    (value, None)
+                };
+                AgentSpec::EpsilonGreedy {
+                    epsilon,
+                    step_size,
+                    tie_tolerance: 0.0,
+                    min_samples: 0,
+                    mom_groups: None,
+                    kernel_width: None,
+                    history: None,
+                }
+            }
+            Family::Ucb => AgentSpec::Ucb { c: value },
+            Family::Softmax => AgentSpec::Softmax { tau: value },
+        }
+    }
+}
+
+const FAMILIES: [(Family, &str); 4] = [(Family::EpsilonGreedy, "epsilon"),
+                                               (Family::Ucb, "ucb-c"),
+                                               (Family::Softmax, "tau"),
+                                               (Family::StepSize, "alpha")];
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+
+    // every family studied, with its parameter values in the order given
+    pub studies: Vec<(Family, Vec<f64>)>,
+
+    // epsilon of the step-size family
+    pub alpha_epsilon: f64,
+    pub output: String,
+
+    // image of the curves, if wanted
+    pub plot: Option<String>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            studies: vec![],
+            alpha_epsilon: 0.1,
+            output: "sensitivity.csv".to_string(),
+            plot: None,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "alpha-epsilon" => opts.alpha_epsilon = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                "plot" => opts.plot = Some(value.clone()),
+                "epsilon" | "ucb-c" | "tau" | "alpha" => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        // keep the families in a fixed order, whatever the flags' order
+        for &(family, flag) in FAMILIES.iter() {
+            if let Some(value) = flags.get(flag) {
+                opts.studies.push((family, parse_grid(flag, value)?));
+            }
+        }
+        if opts.studies.is_empty() {
+            for &(family, _) in FAMILIES.iter() {
+                let (low, high) = family.default_range();
+                opts.studies.push((family, (low..high + 1).map(|k| 2f64.powi(k)).collect()));
+            }
+        }
+
+        for &(family, ref values) in &opts.studies {
+            if values.is_empty() {
+                return Err(format!("no values given for {}", family.parameter()));
+            }
+            for &value in values {
+                let spec = family.spec(value, opts.alpha_epsilon);
+                spec.validate().map_err(|e| format!("{}: {}", spec.label(), e))?;
+            }
+        }
+        if opts.n == 0 || opts.num_plays == 0 || opts.num_tasks < 2 {
+            return Err("arms and plays must be positive and there must be at least 2 tasks"
+                       .to_string());
+        }
+        Ok(opts)
+    }
+
+    fn specs(&self) -> Vec<AgentSpec> {
+        let mut specs = vec![];
+        for &(family, ref values) in &self.studies {
+            for &value in values {
+                specs.push(family.spec(value, self.alpha_epsilon));
+            }
+        }
+        specs
+    }
+}
+
+// Sums over tasks of every configuration's average reward per play, and of
+// its square.
+struct StudySums {
+    reward: Vec<f64>,
+    reward_sq: Vec<f64>,
+}
+
+// Every configuration's mean over tasks of the average reward per play, and
+// its standard error, in the order of Options::specs.
+fn run_study<R: Rng>(rng: &mut R, opts: &Options) -> Vec<(f64, f64)> {
+    let specs = opts.specs();
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunk_sums = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = StudySums {
+            reward: vec![0.0; specs.len()],
+            reward_sq: vec![0.0; specs.len()],
+        };
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let mut task = BanditTask::new(opts.n);
+            let q_star = task.draw_q_star(&mut rng);
+            for (c, spec) in specs.iter().enumerate() {
+                let mut agent = spec.build(opts.n);
+                let outcome = task.play_task_on(&q_star, &mut *agent, opts.num_plays, &mut rng);
+                let average = outcome.rewards.iter().fold(0.0, |acc, r| acc + r) /
+                              opts.num_plays as f64;
+                sums.reward[c] += average;
+                sums.reward_sq[c] += average * average;
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut reward = vec![0.0; specs.len()];
+    let mut reward_sq = vec![0.0; specs.len()];
+    for sums in chunk_sums {
+        for c in 0..specs.len() {
+            reward[c] += sums.reward[c];
+            reward_sq[c] += sums.reward_sq[c];
+        }
+    }
+    (0..specs.len()).map(|c| {
+        let (mean, std_dev) = mean_and_std_dev(reward[c], reward_sq[c], opts.num_tasks);
+        (mean, std_dev / (opts.num_tasks as f64).sqrt())
+    }).collect()
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let results = match opts.seed {
+        Some(seed) => run_study(&mut seeded_rng(seed), opts),
+        None => run_study(&mut rand::thread_rng(), opts),
+    };
+    for spec in opts.specs() {
+        progress::config_done(&spec.label());
+    }
+
+    // results split up by family, as (value, mean, std err)
+    let mut curves = vec![];
+    let mut results = results.into_iter();
+    for &(family, ref values) in &opts.studies {
+        let points: Vec<StudyPoint> = values.iter().zip(results.by_ref())
+                                                 .map(|(&v, (mean, se))| (v, mean, se))
+                                                 .collect();
+        curves.push((family, points));
+    }
+
+    println!("parameter study: average reward over {} plays, {} tasks, {} arms",
+             opts.num_plays, opts.num_tasks, opts.n);
+    for &(family, ref points) in &curves {
+        let label = match family {
+            Family::StepSize => format!("{} (epsilon={})", family.name(), opts.alpha_epsilon),
+            _ => family.name().to_string(),
+        };
+        println!();
+        println!("{}", label);
+        println!("{:>12} {:>12} {:>10}", family.parameter(), "avg reward", "std err");
+        let mut best = 0;
+        for i in 1..points.len() {
+            if points[i].1 > points[best].1 {
+                best = i;
+            }
+        }
+        for (i, &(value, mean, se)) in points.iter().enumerate() {
+            println!("{:>12} {:>12.4} {:>10.4}{}", value, mean, se,
+                     if i == best { " *" } else { "" });
+        }
+    }
+
+    write_csv(&curves, &opts.output).map_err(|e| format!("couldn't write `{}`: {}",
+                                                         opts.output, e))?;
+    if let Some(ref path) = opts.plot {
+        let series: Vec<(String, Vec<StudyPoint>)> =
+            curves.iter()
+                  .map(|&(family, ref points)| {
+                      (format!("{} ({})", family.name(), family.parameter()), points.clone())
+                  })
+                  .collect();
+        plot::plot_parameter_study(path, &series)?;
+    }
+    Ok(())
+}
+
+fn write_csv(curves: &[(Family, Vec<StudyPoint>)], file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "agent,parameter,value,avg_reward,std_err")?;
+    for &(family, ref points) in curves {
+        for &(value, mean, se) in points {
+            writeln!(f, "{},{},{},{},{}", family.name(), family.parameter(), value, mean, se)?;
+        }
+    }
+    f.finish()
+}
@@ -0,0 +1,286 @@
+// Significance tests for paired comparisons: given every task's difference
+// in total reward between two agents that played the same tasks, whether A
+// really beats B or the difference could be noise.
+//
+//     paired t-test      t = mean / (sd / sqrt(n)) against Student's t with
+//                        n - 1 degrees of freedom; effect size Cohen's
+//                        d_z = mean / sd
+//     Wilcoxon           signed-rank test on the nonzero differences, by the
+//                        normal approximation with corrections for ties and
+//                        continuity; effect size the rank-biserial
+//                        correlation (W+ - W-) / (W+ + W-), from -1 (B
+//                        always better) to 1 (A always better)
+//
+// Both p-values are two-sided. The t-test assumes the mean difference is
+// roughly normal, which it is with the usual hundreds of tasks; the
+// Wilcoxon test doesn't, and is the one to trust when a few tasks have
+// extreme differences.
+
+use std::cmp::Ordering;
+
+pub struct PairedTest {
+    pub t: f64,
+    pub t_p: f64,
+    pub cohens_d: f64,
+
+    // sum of the ranks of the positive differences, and its z score
+    pub w_plus: f64,
+    pub z: f64,
+    pub wilcoxon_p: f64,
+    pub rank_biserial: f64,
+}
+
+impl PairedTest {
+    // Tests the differences `diffs`, of which there must be at least two.
+    pub fn of(diffs: &[f64]) -> PairedTest {
+        assert!(diffs.len() >= 2);
+        let n = diffs.len() as f64;
+        let mean = diffs.iter().fold(0.0, |acc, d| acc + d) / n;
+        let var = diffs.iter().fold(0.0, |acc, d| acc + (d - mean) * (d - mean)) / (n - 1.0);
+        let sd = var.sqrt();
+        let (t, t_p, cohens_d) = if sd > 0.0 {
+            let t = mean / (sd / n.sqrt());
+            (t, student_t_p(t, n - 1.0), mean / sd)
+        } else if mean == 0.0 {
+            (0.0, 1.0, 0.0)
+        } else {
+            // every task differs by the same amount
+            (mean.signum() * f64::INFINITY, 0.0, mean.signum() * f64::INFINITY)
+        };
+
+        let (w_plus, w_minus, z) = signed_ranks(diffs);
+        let wilcoxon_p = if z.is_nan() { 1.0 } else { erfc(z.abs() / 2f64.sqrt()) };
+        let rank_biserial = if w_plus + w_minus > 0.0 {
+            (w_plus - w_minus) / (w_plus + w_minus)
+        } else {
+            0.0
+        };
+        PairedTest {
+            t,
+            t_p,
+            cohens_d,
+            w_plus,
+            z,
+            wilcoxon_p,
+            rank_biserial,
+        }
+    }
+}
+
+// Formats a p-value to four places, or as an upper bound when it's
+// smaller than that.
+pub fn format_p(p: f64) -> String {
+    if p < 1e-4 {
+        "<0.0001".to_string()
+    } else {
+        format!("{:.4}", p)
+    }
+}
+
+// W+ and W-, the sums of the ranks of |d| over the positive and negative
+// differences (zeros dropped, ties given their average rank), and the z
+// score of W+; z is NaN if every difference is zero.
+fn signed_ranks(diffs: &[f64]) -> (f64, f64, f64) {
+    let mut nonzero: Vec<f64> = diffs.iter().cloned().filter(|&d| d != 0.0).collect();
+    nonzero.sort_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap_or(Ordering::Equal));
+    let m = nonzero.len();
+    let (mut w_plus, mut w_minus, mut ties) = (0.0, 0.0, 0.0);
+    let mut i = 0;
+    while i < m {
+        let mut j = i + 1;
+        while j < m && nonzero[j].abs() == nonzero[i].abs() {
+            j += 1;
+        }
+        // ranks i + 1 ..= j share their average
+        let rank = (i + 1 + j) as f64 / 2.0;
+        for d in &nonzero[i..j] {
+            if *d > 0.0 {
+                w_plus += rank;
+            } else {
+                w_minus += rank;
+            }
+        }
+        let t = (j - i) as f64;
+        ties += t * t * t - t;
+        i = j;
+    }
+
+    let m = m as f64;
+    let expected = m * (m + 1.0) / 4.0;
+    let var = m * (m + 1.0) * (2.0 * m + 1.0) / 24.0 - ties / 48.0;
+    if var <= 0.0 {
+        return (w_plus, w_minus, f64::NAN);
+    }
+    let diff = w_plus - expected;
+    let corrected = diff - 0.5 * diff.signum();
+    let z = if diff.abs() <= 0.5 { 0.0 } else { corrected / var.sqrt() };
+    (w_plus, w_minus, z)
+}
+
+// Two-sided p-value of t under Student's t with `df` degrees of freedom:
+// the regularized incomplete beta function I_x(df / 2, 1 / 2) at
+// x = df / (df + t^2).
+fn student_t_p(t: f64, df: f64) -> f64 {
+    if !t.is_finite() {
+        return 0.0;
+    }
+    incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+// The regularized incomplete beta function I_x(a, b), by its continued
+// fraction (Numerical Recipes, betai and betacf).
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln())
+                .exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+// ln Gamma(x) for x > 0, by the Lanczos approximation (g = 7, n = 9).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [0.999_999_999_999_809_9, 676.5203681218851, -1259.1392167224028,
+                              771.323_428_777_653_1, -176.615_029_162_140_6, 12.507343278686905,
+                              -0.13857109526572012, 9.984_369_578_019_572e-6,
+                              1.5056327351493116e-7];
+    if x < 0.5 {
+        // reflection, for the small shapes of few degrees of freedom
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFS[0];
+    for (i, &c) in COEFFS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// The complementary error function, to about 1e-7 relative error
+// (Numerical Recipes, erfcc).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223 +
+               t * (1.00002368 + t * (0.37409196 + t * (0.09678418 +
+               t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 +
+               t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let r = t * poly.exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    // Student's t has closed forms for one and two degrees of freedom.
+    #[test]
+    fn t_test_p_values() {
+        for &t in &[0.0, 0.5, 1.0, 2.0, -3.0, 10.0] {
+            let cauchy = 1.0 - 2.0 / std::f64::consts::PI * f64::atan(f64::abs(t));
+            assert!(close(student_t_p(t, 1.0), cauchy, 1e-9), "t = {}, df = 1", t);
+            let two = 1.0 - f64::abs(t) / (2.0 + t * t).sqrt();
+            assert!(close(student_t_p(t, 2.0), two, 1e-9), "t = {}, df = 2", t);
+        }
+
+        let test = PairedTest::of(&[1.0, 2.0, 6.0]);
+        let t = 3.0 / (7f64.sqrt() / 3f64.sqrt());
+        assert!(close(test.t, t, 1e-12));
+        assert!(close(test.t_p, 1.0 - t / (2.0 + t * t).sqrt(), 1e-9));
+        assert!(close(test.cohens_d, 3.0 / 7f64.sqrt(), 1e-12));
+    }
+
+    #[test]
+    fn degenerate_differences() {
+        let same = PairedTest::of(&[0.0, 0.0, 0.0]);
+        assert_eq!((same.t, same.t_p, same.wilcoxon_p, same.rank_biserial), (0.0, 1.0, 1.0, 0.0));
+        let constant = PairedTest::of(&[-0.5, -0.5]);
+        assert_eq!((constant.t, constant.t_p), (f64::NEG_INFINITY, 0.0));
+    }
+
+    #[test]
+    fn signed_rank_test() {
+        // W+ = 15 of m = 5, expected 7.5 with variance 13.75, and the
+        // continuity correction takes 0.5 off the difference
+        let test = PairedTest::of(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let z = 7.0 / 13.75f64.sqrt();
+        assert_eq!(test.w_plus, 15.0);
+        assert!(close(test.z, z, 1e-12));
+        assert!(close(test.wilcoxon_p, 0.05905, 1e-5));
+        assert_eq!(test.rank_biserial, 1.0);
+
+        // the tied |d| = 1 share rank 1.5, and the zero is dropped
+        assert_eq!(signed_ranks(&[1.0, -1.0, 0.0, 2.0]).0, 4.5);
+        assert_eq!(signed_ranks(&[1.0, -1.0, 0.0, 2.0]).1, 1.5);
+    }
+
+    #[test]
+    fn special_functions() {
+        for &(x, expected) in &[(0.0, 1.0), (0.5, 0.4795001222), (1.0, 0.1572992071),
+                                (-1.0, 1.8427007929), (2.0, 0.0046777350)] {
+            assert!(close(erfc(x), expected, 2e-7), "erfc({})", x);
+        }
+        for &(x, gamma) in &[(1.0, 1.0), (5.0, 24.0), (0.5, std::f64::consts::PI.sqrt()),
+                             (0.25, 3.6256099082)] {
+            assert!(close(ln_gamma(x), f64::ln(gamma), 1e-9), "ln_gamma({})", x);
+        }
+        assert_eq!(format_p(0.00001), "<0.0001");
+        assert_eq!(format_p(0.05), "0.0500");
+    }
+}
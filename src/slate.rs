@@ -0,0 +1,231 @@
+// Multiple-play bandits. In the `slate` mode the agent picks `k` distinct
+// arms every round (a slate, e.g. of recommendations) from a Gaussian
+// testbed and gets a reward for each. The best slate is the k arms with the
+// highest true values, so a round's regret is their total true value minus
+// that of the slate, and its "% top-k" is the share of the slate that is in
+// the best slate. Averages over the tasks are written for every round:
+//
+//     step,avg_reward,oracle_reward,pct_top_k,cum_regret
+//
+// where the rewards are totals over the slate.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{Environment, Testbed};
+use crate::output::OutputFile;
+use crate::progress;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, Agent};
+
+pub struct Options {
+    pub n: usize,
+    pub k: usize,
+    pub num_tasks: usize,
+    pub num_rounds: usize,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            k: 3,
+            num_tasks: 2000,
+            num_rounds: 1000,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+            output: "slate.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "k" => opts.k = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_rounds = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "output" => opts.output = value.clone(),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 {
+            return Err("arms and tasks must be positive".to_string());
+        }
+        if opts.k == 0 || opts.k > opts.n {
+            return Err("k must be between 1 and the number of arms".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// What happened in every round of one task.
+pub struct SlateOutcome {
+    pub rewards: Vec<f64>,
+    pub oracle_rewards: Vec<f64>,
+    pub top_k: Vec<usize>,
+    pub regrets: Vec<f64>,
+}
+
+// Plays `num_rounds` rounds of `k` arms each. The environment is stepped
+// once for every arm of the slate, in the order the agent picked them.
+pub fn play_slates<R: Rng>(env: &mut dyn Environment, agent: &mut dyn Agent, k: usize,
+                           num_rounds: usize, rng: &mut R) -> SlateOutcome {
+    let mut outcome = SlateOutcome {
+        rewards: vec![],
+        oracle_rewards: vec![],
+        top_k: vec![],
+        regrets: vec![],
+    };
+    for _ in 0..num_rounds {
+        let means = env.means();
+        let mut ranked: Vec<usize> = (0..means.len()).collect();
+        ranked.sort_by(|&a, &b| means[b].partial_cmp(&means[a]).unwrap());
+        let best = &ranked[..k];
+        let oracle = best.iter().fold(0.0, |acc, &a| acc + means[a]);
+
+        let slate = agent.choose_actions(k, rng);
+        let mut reward = 0.0;
+        for &action in &slate {
+            if let Some(r) = env.step(action, rng) {
+                reward += r;
+                agent.receive_reward(r, action);
+            }
+        }
+        outcome.rewards.push(reward);
+        outcome.oracle_rewards.push(oracle);
+        outcome.top_k.push(slate.iter().filter(|a| best.contains(a)).count());
+        outcome.regrets.push(oracle - slate.iter().fold(0.0, |acc, &a| acc + means[a]));
+    }
+    outcome
+}
+
+// Sums over tasks of every round's metrics.
+struct SlateSums {
+    reward: Vec<f64>,
+    oracle_reward: Vec<f64>,
+    top_k: Vec<f64>,
+    cum_regret: Vec<f64>,
+}
+
+impl SlateSums {
+    fn new(num_rounds: usize) -> SlateSums {
+        SlateSums {
+            reward: vec![0.0; num_rounds],
+            oracle_reward: vec![0.0; num_rounds],
+            top_k: vec![0.0; num_rounds],
+            cum_regret: vec![0.0; num_rounds],
+        }
+    }
+
+    fn add(&mut self, other: &SlateSums) {
+        for t in 0..self.reward.len() {
+            self.reward[t] += other.reward[t];
+            self.oracle_reward[t] += other.oracle_reward[t];
+            self.top_k[t] += other.top_k[t];
+            self.cum_regret[t] += other.cum_regret[t];
+        }
+    }
+}
+
+fn run_slates<R: Rng>(rng: &mut R, opts: &Options) -> SlateSums {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let testbed = Testbed::Gaussian { sd: None };
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut sums = SlateSums::new(opts.num_rounds);
+        for i in tasks {
+            let mut rng = seeded_rng(seeds[i]);
+            let mut env = testbed.draw(opts.n, i, &mut rng);
+            let mut agent = opts.agent.build(opts.n);
+            let outcome = play_slates(&mut *env, &mut *agent, opts.k, opts.num_rounds, &mut rng);
+            let mut regret = 0.0;
+            for t in 0..opts.num_rounds {
+                sums.reward[t] += outcome.rewards[t];
+                sums.oracle_reward[t] += outcome.oracle_rewards[t];
+                sums.top_k[t] += 100.0 * outcome.top_k[t] as f64 / opts.k as f64;
+                regret += outcome.regrets[t];
+                sums.cum_regret[t] += regret;
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut sums = SlateSums::new(opts.num_rounds);
+    for chunk in &chunks {
+        sums.add(chunk);
+    }
+    sums
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let sums = match opts.seed {
+        Some(seed) => run_slates(&mut seeded_rng(seed), opts),
+        None => run_slates(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&sums, opts.num_tasks, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let tasks = opts.num_tasks as f64;
+    let last = opts.num_rounds - 1;
+    println!("{}, slates of {} from {} arms: {} tasks x {} rounds",
+             opts.agent.label(), opts.k, opts.n, opts.num_tasks, opts.num_rounds);
+    println!("final round: reward {:.4} (best slate {:.4}), {:.1}% of the slate in the top {}",
+             sums.reward[last] / tasks, sums.oracle_reward[last] / tasks,
+             sums.top_k[last] / tasks, opts.k);
+    println!("total regret {:.4}", sums.cum_regret[last] / tasks);
+    Ok(())
+}
+
+fn write_csv(sums: &SlateSums, num_tasks: usize, file_name: &str) -> io::Result<()> {
+    let tasks = num_tasks as f64;
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,avg_reward,oracle_reward,pct_top_k,cum_regret")?;
+    for t in 0..sums.reward.len() {
+        writeln!(f, "{},{},{},{},{}", t, sums.reward[t] / tasks, sums.oracle_reward[t] / tasks,
+                 sums.top_k[t] / tasks, sums.cum_regret[t] / tasks)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::GaussianTestbed;
+
+    // A slate is k distinct arms, and the arms retired to pick it are all
+    // back in play afterwards.
+    #[test]
+    fn slates_are_distinct_arms() {
+        let mut rng = seeded_rng(1);
+        let mut agent = AgentSpec::Ucb { c: 2.0 }.build(5);
+        for k in 1..6 {
+            let mut slate = agent.choose_actions(k, &mut rng);
+            slate.sort();
+            slate.dedup();
+            assert_eq!(slate.len(), k);
+            assert_eq!(agent.arm_summaries().len(), 5);
+        }
+        assert_eq!(agent.choose_actions(5, &mut rng).len(), 5);
+    }
+
+    // UCB picking slates of 2 from well separated arms ends up picking the
+    // top two.
+    #[test]
+    fn ucb_learns_the_best_slate() {
+        let mut rng = seeded_rng(2);
+        let mut env = GaussianTestbed::new(vec![0.0, 3.0, 1.0, 4.0, 2.0]);
+        let mut agent = AgentSpec::Ucb { c: 2.0 }.build(5);
+        let outcome = play_slates(&mut env, &mut *agent, 2, 500, &mut rng);
+        assert!(outcome.oracle_rewards.iter().all(|&r| r == 7.0));
+        assert!(outcome.top_k[400..].iter().filter(|&&top| top == 2).count() >= 90);
+        assert!(outcome.regrets[400..].iter().fold(0.0, |acc, r| acc + r) < 20.0);
+    }
+}
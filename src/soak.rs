@@ -0,0 +1,295 @@
+// Soak testing. The `soak` mode runs one agent (given with the usual agent
+// flags) online for a long stretch of simulated traffic: `--plays` plays of
+// a random-walk testbed, whose true values keep drifting so the agent never
+// settles, in windows of `--window` plays. After every window the agent is
+// saved (see agent_state) to the `--checkpoint` file, the saves before it
+// rotated to "<file>.1", "<file>.2" and so on, keeping `--keep` in all, and
+// play goes on with the agent restored from the new save, so every
+// checkpoint is one a restarted service could pick up from. A line per
+// window is written to the output:
+//
+//     window,plays,avg_regret,plays_per_sec,state_bytes,rss_kb
+//
+// where state_bytes is the size of the saved agent and rss_kb the process's
+// resident memory (0 where /proc/self/status can't be read). The run fails
+// if a save ends up more than `--max-growth` times the size of the first
+// one, or a window's throughput falls below `--min-throughput` times the
+// first window's, the signs of an agent whose memory or cost per play
+// grows with the plays it has made.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use rand::Rng;
+
+use crate::agent_state::SavedAgent;
+use crate::environment::Testbed;
+use crate::error::Error;
+use crate::output::OutputFile;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{parse_value, seeded_rng, Agent};
+
+pub struct Options {
+    pub n: usize,
+    pub num_plays: usize,
+    pub window: usize,
+    pub seed: Option<usize>,
+
+    // standard deviation of the true values' steps
+    pub sigma: f64,
+    pub agent: AgentSpec,
+    pub checkpoint: String,
+
+    // saves kept, the newest included
+    pub keep: usize,
+    pub max_growth: f64,
+    pub min_throughput: f64,
+    pub output: String,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_plays: 10_000_000,
+            window: 100_000,
+            seed: None,
+            sigma: 0.01,
+            agent: AgentSpec::from_flags(flags)?,
+            checkpoint: "soak.json".to_string(),
+            keep: 3,
+            max_growth: 1.5,
+            min_throughput: 0.5,
+            output: "soak.csv".to_string(),
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "window" => opts.window = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "walk-sigma" => opts.sigma = parse_value(key, value)?,
+                "checkpoint" => opts.checkpoint = value.clone(),
+                "keep" => opts.keep = parse_value(key, value)?,
+                "max-growth" => opts.max_growth = parse_value(key, value)?,
+                "min-throughput" => opts.min_throughput = parse_value(key, value)?,
+                "output" => opts.output = value.clone(),
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_plays == 0 || opts.window == 0 || opts.keep == 0 {
+            return Err("arms, plays, window and keep must all be positive".to_string());
+        }
+        if !(opts.sigma >= 0.0 && opts.sigma.is_finite()) {
+            return Err("walk-sigma must be non-negative".to_string());
+        }
+        if opts.max_growth.is_nan() || opts.max_growth < 1.0 {
+            return Err("max-growth must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&opts.min_throughput) {
+            return Err("min-throughput must be in [0, 1]".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// What happened in one window of plays.
+pub struct Window {
+    pub plays: usize,
+    pub avg_regret: f64,
+    pub plays_per_sec: f64,
+    pub state_bytes: u64,
+    pub rss_kb: u64,
+}
+
+// The process's resident memory in kB, from the VmRSS line of
+// /proc/self/status, or 0 if there is none.
+fn rss_kb() -> u64 {
+    let status = match fs::read_to_string("/proc/self/status") {
+        Ok(status) => status,
+        Err(_) => return 0,
+    };
+    status.lines()
+          .find(|line| line.starts_with("VmRSS:"))
+          .and_then(|line| line.split_whitespace().nth(1))
+          .and_then(|kb| kb.parse().ok())
+          .unwrap_or(0)
+}
+
+// The name of the `i`-th newest save of `path`.
+fn rotated(path: &str, i: usize) -> String {
+    if i == 0 {
+        path.to_string()
+    } else {
+        format!("{}.{}", path, i)
+    }
+}
+
+// Writes `saved` to `path`, shifting the saves already there along and
+// dropping the oldest so that `keep` are left. Returns the size of the save.
+fn save_rotated(saved: &SavedAgent, path: &str, keep: usize) -> Result<u64, Error> {
+    let tmp = format!("{}.tmp", path);
+    saved.save(&tmp)?;
+    let rotate = || -> io::Result<u64> {
+        for i in (1..keep).rev() {
+            if fs::metadata(rotated(path, i - 1)).is_ok() {
+                fs::rename(rotated(path, i - 1), rotated(path, i))?;
+            }
+        }
+        fs::rename(&tmp, path)?;
+        Ok(fs::metadata(path)?.len())
+    };
+    rotate().map_err(|e| Error::io(format!("rotate the saves of `{}`", path), e))
+}
+
+// Plays the whole run, handing every window to `report` as it ends.
+fn soak<R: Rng>(rng: &mut R, opts: &Options, report: &mut dyn FnMut(&Window) -> io::Result<()>)
+                -> Result<Vec<Window>, String> {
+    let mut env = Testbed::RandomWalk { sigma: opts.sigma }.draw(opts.n, 0, rng);
+    let mut agent: Box<dyn Agent> = opts.agent.build(opts.n);
+    let mut windows = vec![];
+    let mut played = 0;
+    while played < opts.num_plays {
+        let plays = opts.window.min(opts.num_plays - played);
+        let mut regret = 0.0;
+        let started = Instant::now();
+        for _ in 0..plays {
+            let action = agent.choose_action(rng);
+            let means = env.means();
+            regret += means.iter().fold(f64::NEG_INFINITY, |acc, &m| acc.max(m)) - means[action];
+            if let Some(reward) = env.step(action, rng) {
+                agent.receive_reward(reward, action);
+            }
+        }
+        let seconds = started.elapsed().as_secs_f64();
+        played += plays;
+
+        let saved = SavedAgent::new(&opts.agent, &*agent);
+        let state_bytes = save_rotated(&saved, &opts.checkpoint, opts.keep)?;
+        agent = SavedAgent::load(&opts.checkpoint)?.agent()?;
+        let window = Window {
+            plays,
+            avg_regret: regret / plays as f64,
+            plays_per_sec: if seconds > 0.0 { plays as f64 / seconds } else { f64::INFINITY },
+            state_bytes,
+            rss_kb: rss_kb(),
+        };
+        report(&window).map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+        windows.push(window);
+    }
+    Ok(windows)
+}
+
+// Checks that the saves stayed within `max_growth` of the first one's size
+// and every full window's throughput within `min_throughput` of the
+// first's. A last window cut short by the end of the run is too short to
+// time.
+fn check(windows: &[Window], max_growth: f64, min_throughput: f64) -> Result<(), String> {
+    let first = &windows[0];
+    for (i, window) in windows.iter().enumerate() {
+        if window.state_bytes as f64 > max_growth * first.state_bytes as f64 {
+            return Err(format!("the saved agent grew from {} to {} bytes by window {}",
+                               first.state_bytes, window.state_bytes, i));
+        }
+        if window.plays == first.plays
+            && window.plays_per_sec < min_throughput * first.plays_per_sec {
+            return Err(format!("throughput fell from {:.0} to {:.0} plays/s in window {}",
+                               first.plays_per_sec, window.plays_per_sec, i));
+        }
+    }
+    Ok(())
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let mut f = OutputFile::create(&opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+    writeln!(f, "window,plays,avg_regret,plays_per_sec,state_bytes,rss_kb")
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+    let mut index = 0;
+    let mut report = |w: &Window| -> io::Result<()> {
+        writeln!(f, "{},{},{},{},{},{}", index, w.plays, w.avg_regret, w.plays_per_sec,
+                 w.state_bytes, w.rss_kb)?;
+        index += 1;
+        f.flush()
+    };
+    let windows = match opts.seed {
+        Some(seed) => soak(&mut seeded_rng(seed), opts, &mut report)?,
+        None => soak(&mut rand::thread_rng(), opts, &mut report)?,
+    };
+    f.finish().map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let (first, last) = (&windows[0], &windows[windows.len() - 1]);
+    println!("{}, {} arms: {} plays in {} windows, checkpoints in `{}`",
+             opts.agent.label(), opts.n, opts.num_plays, windows.len(), opts.checkpoint);
+    println!("saved agent {} -> {} bytes, {:.0} -> {:.0} plays/s, resident memory {} -> {} kB",
+             first.state_bytes, last.state_bytes, first.plays_per_sec, last.plays_per_sec,
+             first.rss_kb, last.rss_kb);
+    check(&windows, opts.max_growth, opts.min_throughput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn options(checkpoint: &str, agent: AgentSpec, window: usize) -> Options {
+        Options {
+            n: 5,
+            num_plays: 3000,
+            window,
+            seed: None,
+            sigma: 0.01,
+            agent,
+            checkpoint: checkpoint.to_string(),
+            keep: 2,
+            max_growth: 1.5,
+            min_throughput: 0.0,
+            output: String::new(),
+        }
+    }
+
+    // Saving and restoring the agent after every window changes nothing,
+    // and only the newest `keep` saves are left.
+    #[test]
+    fn checkpoints_rotate_without_changing_the_run() {
+        let path = env::temp_dir().join(format!("soak-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let spec = AgentSpec::Ucb { c: 2.0 };
+        let last_save = |window| {
+            soak(&mut seeded_rng(4), &options(path, spec.clone(), window), &mut |_| Ok(()))
+                .unwrap();
+            fs::read_to_string(path).unwrap()
+        };
+        let whole = last_save(3000);
+        assert_eq!(last_save(700), whole);
+        assert!(fs::metadata(path).is_ok() && fs::metadata(rotated(path, 1)).is_ok());
+        assert!(fs::metadata(rotated(path, 2)).is_err());
+
+        let windows = soak(&mut seeded_rng(4), &options(path, spec, 1000), &mut |_| Ok(()))
+                          .unwrap();
+        assert_eq!(windows.len(), 3);
+        assert!(check(&windows, 1.5, 0.0).is_ok());
+        for i in 0..2 {
+            fs::remove_file(rotated(path, i)).unwrap();
+        }
+    }
+
+    fn window(plays: usize, plays_per_sec: f64, state_bytes: u64) -> Window {
+        Window { plays, avg_regret: 0.0, plays_per_sec, state_bytes, rss_kb: 0 }
+    }
+
+    #[test]
+    fn fails_on_growth_or_slowdown() {
+        let steady = [window(10, 100.0, 1000), window(10, 90.0, 1100), window(5, 1.0, 1000)];
+        assert!(check(&steady, 1.5, 0.5).is_ok());
+        let growing = [window(10, 100.0, 1000), window(10, 100.0, 1600)];
+        assert!(check(&growing, 1.5, 0.5).is_err());
+        let slowing = [window(10, 100.0, 1000), window(10, 40.0, 1000)];
+        assert!(check(&slowing, 1.5, 0.5).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+// Softmax (Boltzmann) action selection: arm a is played with probability
+//
+//     exp(Q_t(a) / tau) / sum_b exp(Q_t(b) / tau)
+//
+// High temperatures tau make all arms nearly equally likely; as tau goes to
+// zero this becomes greedy selection.
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct SoftmaxBandit {
+    stats: ArmStats,
+
+    // temperature
+    tau: f64,
+}
+
+impl SoftmaxBandit {
+    pub fn new(n: usize, tau: f64) -> SoftmaxBandit {
+        assert!(n > 0);
+        assert!(tau > 0.0);
+        SoftmaxBandit {
+            stats: ArmStats::new(n),
+            tau,
+        }
+    }
+
+    // Selection probability of each arm (0 for retired arms). The maximum
+    // estimate is subtracted before exponentiating so small temperatures
+    // don't overflow.
+    pub fn probabilities(&self) -> Vec<f64> {
+        let n = self.stats.num_arms();
+        let mut max = f64::NEG_INFINITY;
+        for &i in &self.stats.active() {
+            max = max.max(self.stats.estimate(i));
+        }
+
+        let mut weights = vec![];
+        let mut total = 0.0;
+        for i in 0..n {
+            if self.stats.is_retired(i) {
+                weights.push(0.0);
+                continue;
+            }
+            let w = ((self.stats.estimate(i) - max) / self.tau).exp();
+            weights.push(w);
+            total += w;
+        }
+        for w in weights.iter_mut() {
+            *w /= total;
+        }
+        weights
+    }
+}
+
+impl Agent for SoftmaxBandit {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        let probs = self.probabilities();
+        let x = rng.next_f64();
+        let mut cumulative = 0.0;
+        for (i, &p) in probs.iter().enumerate() {
+            cumulative += p;
+            if x < cumulative {
+                debug!("drew arm {}, chosen with probability {:.4}", i, p);
+                return i;
+            }
+        }
+        // only reachable through rounding in the cumulative sum
+        *self.stats.active().last().unwrap()
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
@@ -0,0 +1,706 @@
+// A description of an agent and its hyperparameters, independent of any
+// particular task, so runners can build a fresh agent for every task and
+// label their output with what was run. Agents registered by name (see
+// registry) are described by that name and their parameters.
+
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::bootstrap::BootstrapBandit;
+use crate::cvar::CvarBandit;
+use crate::doubling::DoublingTrick;
+use crate::explore_commit::ExploreCommitBandit;
+use crate::explore_pool::{ExplorePolicy, ExplorePoolBandit};
+use crate::forecast::ForecastBandit;
+use crate::meta::{self, MetaAgent};
+use crate::moss::MossBandit;
+use crate::preprocess::{PreprocessedAgent, Preprocessing, PREPROCESSING_FLAGS};
+use crate::registry::{self, Params};
+use crate::ring::DEFAULT_HISTORY;
+use crate::softmax::SoftmaxBandit;
+use crate::ucb::UcbBandit;
+use crate::wrappers::{ChangeResetAgent, CountBonusAgent, WrapperSpec};
+use crate::{parse_value, Agent, EpsilonGreedyBandit};
+
+// Flags read by AgentSpec::from_flags; modes that take a single agent skip
+// these when checking for unknown flags.
+pub const AGENT_FLAGS: &[&str] = &[
+    "agent", "epsilon", "step-size", "explore-fraction", "explore-policy", "c", "tau",
+    "smoothing", "trend-smoothing", "tie-tolerance", "min-samples", "mom-groups",
+    "kernel-width", "cvar-level", "horizon", "doubling", "replicates", "reward-clip",
+    "reward-normalize", "history", "meta-agents", "meta-selector",
+];
+
+// The kinds of agent built in; `--agent` also takes any registered name.
+pub const BUILTIN_AGENTS: &[&str] = &[
+    "epsilon-greedy", "explore-pool", "ucb", "softmax", "forecast", "cvar", "moss",
+    "explore-commit", "bootstrap", "meta",
+];
+
+// Whether `key` is one of AGENT_FLAGS or an `--agent.<param>` flag.
+pub fn is_agent_flag(key: &str) -> bool {
+    AGENT_FLAGS.contains(&key) || key.starts_with("agent.")
+}
+
+// Fields left out of a spec's JSON take the defaults of the matching flags.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum AgentSpec {
+    // step_size of None means sample averages; estimates within
+    // tie_tolerance of the maximum count as tied for it; arms are played
+    // until they have min_samples rewards before estimates are used;
+    // mom_groups switches sample averages to medians of that many means,
+    // over at most `history` rewards per arm (see ring); kernel_width shares
+    // rewards between nearby arms
+    EpsilonGreedy {
+        #[serde(default = "default_epsilon")]
+        epsilon: f64,
+        step_size: Option<f64>,
+        #[serde(default)]
+        tie_tolerance: f64,
+        #[serde(default)]
+        min_samples: usize,
+        mom_groups: Option<usize>,
+        kernel_width: Option<f64>,
+        history: Option<usize>,
+    },
+    ExplorePool {
+        #[serde(default = "default_explore_fraction")]
+        fraction: f64,
+        #[serde(default)]
+        policy: ExplorePolicy,
+    },
+    Ucb {
+        #[serde(default = "default_ucb_c")]
+        c: f64,
+    },
+    Softmax {
+        #[serde(default = "default_tau")]
+        tau: f64,
+    },
+
+    // smoothing of the level (alpha) and trend (beta), and the weight c of
+    // the uncertainty bonus
+    Forecast {
+        #[serde(default = "default_smoothing")]
+        alpha: f64,
+        #[serde(default = "default_trend_smoothing")]
+        beta: f64,
+        #[serde(default = "default_forecast_c")]
+        c: f64,
+    },
+
+    // the fraction of worst rewards whose mean is maximized, over at most
+    // `history` rewards per arm, and the weight c of the exploration bonus
+    Cvar {
+        #[serde(default = "default_cvar_level")]
+        level: f64,
+        #[serde(default = "default_cvar_c")]
+        c: f64,
+        history: Option<usize>,
+    },
+
+    // Agents tuned to a horizon; without one they are run by the doubling
+    // trick, starting every epoch from scratch if `reset` and from the
+    // last `history` plays otherwise.
+    Moss {
+        horizon: Option<usize>,
+        #[serde(default)]
+        reset: bool,
+        history: Option<usize>,
+    },
+    ExploreCommit {
+        horizon: Option<usize>,
+        #[serde(default)]
+        reset: bool,
+        history: Option<usize>,
+    },
+
+    // Thompson sampling from this many bootstrap replicates per arm
+    Bootstrap {
+        #[serde(default = "default_replicates")]
+        replicates: usize,
+    },
+
+    // an agent registered under `name`
+    Registered {
+        name: String,
+        #[serde(default)]
+        params: Params,
+    },
+
+    // another agent, shown its rewards after preprocessing
+    Preprocessed { preprocessing: Preprocessing, agent: Box<AgentSpec> },
+
+    // a selector choosing among agents every play (see meta)
+    Meta { selector: Box<AgentSpec>, agents: Vec<AgentSpec> },
+
+    // another agent inside a wrapper (see wrappers)
+    Wrapped { wrapper: WrapperSpec, agent: Box<AgentSpec> },
+}
+
+impl AgentSpec {
+    // Builds a spec from `--agent kind` and that kind's hyperparameter flags,
+    // defaulting to epsilon-greedy with epsilon = 0.1. `--agent.<param>` is
+    // the same as `--<param>` for a built-in kind, and the only way to give
+    // a registered agent its parameters.
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<AgentSpec, String> {
+        if let Some(preprocessing) = Preprocessing::from_flags(flags)? {
+            let mut inner = flags.clone();
+            for key in PREPROCESSING_FLAGS {
+                inner.remove(*key);
+            }
+            let spec = AgentSpec::Preprocessed {
+                preprocessing,
+                agent: Box::new(AgentSpec::from_flags(&inner)?),
+            };
+            spec.validate()?;
+            return Ok(spec);
+        }
+        let kind = flags.get("agent").map(|s| &s[..]).unwrap_or("epsilon-greedy");
+        let params: Params = flags.iter()
+            .filter(|&(key, _)| key.starts_with("agent."))
+            .map(|(key, value)| (key["agent.".len()..].to_string(), value.clone()))
+            .collect();
+        if !BUILTIN_AGENTS.contains(&kind) {
+            if !registry::is_registered(kind) {
+                return Err(registry::unknown(kind).to_string());
+            }
+            let given = AGENT_FLAGS.iter().find(|k| **k != "agent" && flags.contains_key(**k));
+            if let Some(key) = given {
+                return Err(format!("`--{}` doesn't apply to agent `{}`; its parameters are \
+                                    given as `--agent.<name>`", key, kind));
+            }
+            let spec = AgentSpec::Registered { name: kind.to_string(), params };
+            spec.validate()?;
+            return Ok(spec);
+        }
+
+        let mut merged = flags.clone();
+        for (param, value) in &params {
+            if !AGENT_FLAGS.contains(&&param[..]) || param == "agent" {
+                return Err(format!("`--agent.{}` doesn't apply to agent `{}`", param, kind));
+            }
+            if flags.contains_key(param) {
+                return Err(format!("both `--{}` and `--agent.{}` are given", param, param));
+            }
+            merged.insert(param.clone(), value.clone());
+        }
+        let flags = &merged;
+        let get = |key: &str, default: f64| -> Result<f64, String> {
+            match flags.get(key) {
+                Some(value) => parse_value(key, value),
+                None => Ok(default),
+            }
+        };
+        let allowed: &[&str] = match kind {
+            "epsilon-greedy" => &["epsilon", "step-size", "tie-tolerance", "min-samples",
+                                  "mom-groups", "kernel-width", "history"],
+            "explore-pool" => &["explore-fraction", "explore-policy"],
+            "ucb" => &["c"],
+            "softmax" => &["tau"],
+            "forecast" => &["smoothing", "trend-smoothing", "c"],
+            "cvar" => &["cvar-level", "c", "history"],
+            "moss" | "explore-commit" => &["horizon", "doubling", "history"],
+            "bootstrap" => &["replicates"],
+            "meta" => &["meta-agents", "meta-selector"],
+            _ => return Err(format!("unknown agent `{}`", kind)),
+        };
+        for key in AGENT_FLAGS {
+            if *key != "agent" && flags.contains_key(*key) && !allowed.contains(key) {
+                let flag = if params.contains_key(*key) { "--agent." } else { "--" };
+                return Err(format!("`{}{}` doesn't apply to agent `{}`", flag, key, kind));
+            }
+        }
+
+        let history = match flags.get("history") {
+            Some(value) => Some(parse_value("history", value)?),
+            None => None,
+        };
+        let spec = match kind {
+            "epsilon-greedy" => AgentSpec::EpsilonGreedy {
+                epsilon: get("epsilon", default_epsilon())?,
+                step_size: match flags.get("step-size") {
+                    Some(value) => Some(parse_value("step-size", value)?),
+                    None => None,
+                },
+                tie_tolerance: get("tie-tolerance", 0.0)?,
+                min_samples: match flags.get("min-samples") {
+                    Some(value) => parse_value("min-samples", value)?,
+                    None => 0,
+                },
+                mom_groups: match flags.get("mom-groups") {
+                    Some(value) => Some(parse_value("mom-groups", value)?),
+                    None => None,
+                },
+                kernel_width: match flags.get("kernel-width") {
+                    Some(value) => Some(parse_value("kernel-width", value)?),
+                    None => None,
+                },
+                history,
+            },
+            "explore-pool" => {
+                let policy = match flags.get("explore-policy") {
+                    Some(name) => ExplorePolicy::from_name(name)
+                                      .ok_or(format!("unknown explore policy `{}`", name))?,
+                    None => ExplorePolicy::default(),
+                };
+                AgentSpec::ExplorePool {
+                    fraction: get("explore-fraction", default_explore_fraction())?,
+                    policy,
+                }
+            }
+            "ucb" => AgentSpec::Ucb { c: get("c", default_ucb_c())? },
+            "softmax" => AgentSpec::Softmax { tau: get("tau", default_tau())? },
+            "cvar" => AgentSpec::Cvar {
+                level: get("cvar-level", default_cvar_level())?,
+                c: get("c", default_cvar_c())?,
+                history,
+            },
+            "bootstrap" => AgentSpec::Bootstrap {
+                replicates: match flags.get("replicates") {
+                    Some(value) => parse_value("replicates", value)?,
+                    None => default_replicates(),
+                },
+            },
+            "meta" => AgentSpec::Meta {
+                selector: Box::new(match flags.get("meta-selector") {
+                    Some(value) => meta::parse_agent(value)?,
+                    None => AgentSpec::from_flags(&HashMap::new())?,
+                }),
+                agents: match flags.get("meta-agents") {
+                    Some(value) => meta::parse_agents(value)?,
+                    None => return Err("agent `meta` needs `--meta-agents`".to_string()),
+                },
+            },
+            "moss" | "explore-commit" => {
+                let horizon = match flags.get("horizon") {
+                    Some(value) => Some(parse_value("horizon", value)?),
+                    None => None,
+                };
+                let reset = match flags.get("doubling").map(|s| &s[..]) {
+                    None | Some("rescale") => false,
+                    Some("reset") => true,
+                    Some(other) => return Err(format!("unknown doubling `{}`", other)),
+                };
+                if horizon.is_some() && flags.contains_key("doubling") {
+                    return Err("`--doubling` only applies without a `--horizon`".to_string());
+                }
+                if kind == "moss" {
+                    AgentSpec::Moss { horizon, reset, history }
+                } else {
+                    AgentSpec::ExploreCommit { horizon, reset, history }
+                }
+            }
+            _ => AgentSpec::Forecast {
+                alpha: get("smoothing", default_smoothing())?,
+                beta: get("trend-smoothing", default_trend_smoothing())?,
+                c: get("c", default_forecast_c())?,
+            },
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn build(&self, n: usize) -> Box<dyn Agent> {
+        match *self {
+            AgentSpec::EpsilonGreedy { epsilon, step_size, tie_tolerance, min_samples,
+                                       mom_groups, kernel_width, history } => {
+                let mut bandit = match step_size {
+                    None => EpsilonGreedyBandit::new(n, epsilon),
+                    Some(alpha) => EpsilonGreedyBandit::with_step_size(n, epsilon, alpha),
+                };
+                bandit.set_tie_tolerance(tie_tolerance);
+                bandit.set_min_samples(min_samples);
+                if let Some(groups) = mom_groups {
+                    bandit.set_median_of_means(groups, history.unwrap_or(DEFAULT_HISTORY));
+                }
+                if let Some(width) = kernel_width {
+                    bandit.set_kernel_width(width);
+                }
+                Box::new(bandit)
+            }
+            AgentSpec::ExplorePool { fraction, policy } => {
+                Box::new(ExplorePoolBandit::new(n, fraction, policy))
+            }
+            AgentSpec::Ucb { c } => Box::new(UcbBandit::new(n, c)),
+            AgentSpec::Softmax { tau } => Box::new(SoftmaxBandit::new(n, tau)),
+            AgentSpec::Forecast { alpha, beta, c } => {
+                Box::new(ForecastBandit::new(n, alpha, beta, c))
+            }
+            AgentSpec::Cvar { level, c, history } => {
+                Box::new(CvarBandit::new(n, level, c, history.unwrap_or(DEFAULT_HISTORY)))
+            }
+            AgentSpec::Bootstrap { replicates } => Box::new(BootstrapBandit::new(n, replicates)),
+            AgentSpec::Moss { horizon: Some(horizon), .. } => {
+                Box::new(MossBandit::new(n, horizon))
+            }
+            AgentSpec::Moss { horizon: None, reset, history } => {
+                let history = history.unwrap_or(DEFAULT_HISTORY);
+                Box::new(DoublingTrick::new(n, n, reset, history, Box::new(|n, horizon| {
+                    Box::new(MossBandit::new(n, horizon)) as Box<dyn Agent>
+                })))
+            }
+            AgentSpec::ExploreCommit { horizon: Some(horizon), .. } => {
+                Box::new(ExploreCommitBandit::new(n, horizon))
+            }
+            AgentSpec::ExploreCommit { horizon: None, reset, history } => {
+                let history = history.unwrap_or(DEFAULT_HISTORY);
+                Box::new(DoublingTrick::new(n, n, reset, history, Box::new(|n, horizon| {
+                    Box::new(ExploreCommitBandit::new(n, horizon)) as Box<dyn Agent>
+                })))
+            }
+            AgentSpec::Registered { ref name, ref params } => {
+                match registry::make(name, params, n) {
+                    Ok(agent) => agent,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            AgentSpec::Preprocessed { preprocessing, ref agent } => {
+                Box::new(PreprocessedAgent::new(preprocessing, agent.build(n)))
+            }
+            AgentSpec::Meta { ref selector, ref agents } => {
+                let agents: Vec<Box<dyn Agent>> = agents.iter()
+                                                        .map(|agent| agent.build(n))
+                                                        .collect();
+                Box::new(MetaAgent::new(n, selector.build(agents.len()), agents))
+            }
+            AgentSpec::Wrapped { ref wrapper, ref agent } => match *wrapper {
+                WrapperSpec::CountBonus { beta } => {
+                    Box::new(CountBonusAgent::new(beta, agent.build(n)))
+                }
+                WrapperSpec::ChangeReset { delta, threshold } => {
+                    let agent = (**agent).clone();
+                    Box::new(ChangeResetAgent::new(n, delta, threshold,
+                                                   Box::new(move |n| agent.build(n))))
+                }
+                WrapperSpec::Registered { ref name, ref params } => {
+                    match registry::wrap(name, params, agent.build(n), n) {
+                        Ok(agent) => agent,
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            },
+        }
+    }
+
+    // Turns the state() of an agent built from this spec back into the
+    // agent.
+    pub fn restore(&self, state: serde_json::Value) -> Result<Box<dyn Agent>, String> {
+        match *self {
+            AgentSpec::EpsilonGreedy { .. } => restored::<EpsilonGreedyBandit>(state),
+            AgentSpec::ExplorePool { .. } => restored::<ExplorePoolBandit>(state),
+            AgentSpec::Ucb { .. } => restored::<UcbBandit>(state),
+            AgentSpec::Softmax { .. } => restored::<SoftmaxBandit>(state),
+            AgentSpec::Forecast { .. } => restored::<ForecastBandit>(state),
+            AgentSpec::Cvar { .. } => restored::<CvarBandit>(state),
+            AgentSpec::Bootstrap { .. } => restored::<BootstrapBandit>(state),
+            AgentSpec::Moss { horizon: Some(_), .. } => restored::<MossBandit>(state),
+            AgentSpec::Moss { horizon: None, .. } => {
+                let make = Box::new(|n, horizon| {
+                    Box::new(MossBandit::new(n, horizon)) as Box<dyn Agent>
+                });
+                Ok(Box::new(DoublingTrick::from_state(make, state, &restored::<MossBandit>)?))
+            }
+            AgentSpec::ExploreCommit { horizon: Some(_), .. } => {
+                restored::<ExploreCommitBandit>(state)
+            }
+            AgentSpec::ExploreCommit { horizon: None, .. } => {
+                let make = Box::new(|n, horizon| {
+                    Box::new(ExploreCommitBandit::new(n, horizon)) as Box<dyn Agent>
+                });
+                let restore = &restored::<ExploreCommitBandit>;
+                Ok(Box::new(DoublingTrick::from_state(make, state, restore)?))
+            }
+            AgentSpec::Registered { ref name, .. } => {
+                Err(format!("registered agent `{}` can't be restored from its state", name))
+            }
+            AgentSpec::Preprocessed { preprocessing, ref agent } => {
+                let restore = |state| agent.restore(state);
+                Ok(Box::new(PreprocessedAgent::from_state(preprocessing, state, &restore)?))
+            }
+            AgentSpec::Meta { ref selector, ref agents } => {
+                let restore_selector = |state| selector.restore(state);
+                let restore_agent = |i: usize, state| match agents.get(i) {
+                    Some(agent) => agent.restore(state),
+                    None => Err("invalid state: more agents than configured".to_string()),
+                };
+                Ok(Box::new(MetaAgent::from_state(state, &restore_selector, &restore_agent)?))
+            }
+            AgentSpec::Wrapped { ref wrapper, ref agent } => {
+                let restore = |state| agent.restore(state);
+                match *wrapper {
+                    WrapperSpec::CountBonus { beta } => {
+                        Ok(Box::new(CountBonusAgent::from_state(beta, state, &restore)?))
+                    }
+                    WrapperSpec::ChangeReset { delta, threshold } => {
+                        let make = {
+                            let agent = (**agent).clone();
+                            Box::new(move |n| agent.build(n))
+                        };
+                        Ok(Box::new(ChangeResetAgent::from_state(delta, threshold, make, state,
+                                                                 &restore)?))
+                    }
+                    WrapperSpec::Registered { ref name, .. } => {
+                        Err(format!("registered wrapper `{}` can't be restored from its state",
+                                    name))
+                    }
+                }
+            }
+        }
+    }
+
+    // Checks that the hyperparameters are in range, so build() won't panic.
+    // A registered agent is checked by building one with a single arm, and a
+    // registered wrapper by wrapping one.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            AgentSpec::Registered { ref name, ref params } => {
+                registry::make(name, params, 1)?;
+            }
+            AgentSpec::Wrapped { ref wrapper, ref agent } => {
+                wrapper.validate()?;
+                agent.validate()?;
+                if let WrapperSpec::Registered { ref name, ref params } = *wrapper {
+                    registry::wrap(name, params, agent.build(1), 1)?;
+                }
+            }
+            AgentSpec::Preprocessed { ref preprocessing, ref agent } => {
+                preprocessing.validate()?;
+                agent.validate()?;
+            }
+            AgentSpec::Meta { ref selector, ref agents } => {
+                if agents.len() < 2 {
+                    return Err("a meta agent needs at least two agents to choose among"
+                               .to_string());
+                }
+                selector.validate()?;
+                for agent in agents {
+                    agent.validate()?;
+                }
+            }
+            AgentSpec::EpsilonGreedy { epsilon, step_size, tie_tolerance, mom_groups,
+                                       kernel_width, history, .. } => {
+                if !(0.0..=1.0).contains(&epsilon) {
+                    return Err("epsilon must be in [0, 1]".to_string());
+                }
+                if !(tie_tolerance >= 0.0 && tie_tolerance.is_finite()) {
+                    return Err("tie_tolerance must be non-negative".to_string());
+                }
+                if let Some(groups) = mom_groups {
+                    if groups == 0 {
+                        return Err("mom_groups must be positive".to_string());
+                    }
+                    if step_size.is_some() {
+                        return Err("mom_groups can't be combined with a step size".to_string());
+                    }
+                }
+                if let Some(width) = kernel_width {
+                    if !(width > 0.0 && width.is_finite()) {
+                        return Err("kernel_width must be positive".to_string());
+                    }
+                    if step_size.is_some() || mom_groups.is_some() {
+                        return Err("kernel_width can't be combined with a step size or \
+                                    mom_groups".to_string());
+                    }
+                }
+                if let Some(alpha) = step_size {
+                    if !(alpha > 0.0 && alpha <= 1.0) {
+                        return Err("step_size must be in (0, 1]".to_string());
+                    }
+                }
+                if history.is_some() && mom_groups.is_none() {
+                    return Err("history only applies with mom_groups; sample averages keep \
+                                no rewards".to_string());
+                }
+                validate_history(history)?;
+            }
+            AgentSpec::ExplorePool { fraction, .. } => {
+                if !(0.0..=1.0).contains(&fraction) {
+                    return Err("explore_fraction must be in [0, 1]".to_string());
+                }
+            }
+            AgentSpec::Ucb { c } => {
+                if c.is_nan() || c < 0.0 {
+                    return Err("c must be non-negative".to_string());
+                }
+            }
+            AgentSpec::Softmax { tau } => {
+                if tau.is_nan() || tau <= 0.0 {
+                    return Err("tau must be positive".to_string());
+                }
+            }
+            AgentSpec::Forecast { alpha, beta, c } => {
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    return Err("smoothing must be in (0, 1]".to_string());
+                }
+                if !(0.0..=1.0).contains(&beta) {
+                    return Err("trend smoothing must be in [0, 1]".to_string());
+                }
+                if c.is_nan() || c < 0.0 {
+                    return Err("c must be non-negative".to_string());
+                }
+            }
+            AgentSpec::Bootstrap { replicates } => {
+                if replicates == 0 {
+                    return Err("replicates must be positive".to_string());
+                }
+            }
+            AgentSpec::Moss { horizon, reset, history } |
+            AgentSpec::ExploreCommit { horizon, reset, history } => {
+                if horizon == Some(0) {
+                    return Err("horizon must be positive".to_string());
+                }
+                if history.is_some() && (horizon.is_some() || reset) {
+                    return Err("history only applies to the doubling trick without reset"
+                               .to_string());
+                }
+                validate_history(history)?;
+            }
+            AgentSpec::Cvar { level, c, history } => {
+                validate_history(history)?;
+                if !(level > 0.0 && level <= 1.0) {
+                    return Err("cvar_level must be in (0, 1]".to_string());
+                }
+                if c.is_nan() || c < 0.0 {
+                    return Err("c must be non-negative".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Short human-readable label, e.g. "eps-greedy(epsilon=0.1)".
+    pub fn label(&self) -> String {
+        match *self {
+            AgentSpec::EpsilonGreedy { epsilon, step_size, tie_tolerance, min_samples,
+                                       mom_groups, kernel_width, history } => {
+                let mut params = format!("epsilon={}", epsilon);
+                if let Some(alpha) = step_size {
+                    params.push_str(&format!(",alpha={}", alpha));
+                }
+                if tie_tolerance > 0.0 {
+                    params.push_str(&format!(",tol={}", tie_tolerance));
+                }
+                if min_samples > 0 {
+                    params.push_str(&format!(",min={}", min_samples));
+                }
+                if let Some(groups) = mom_groups {
+                    params.push_str(&format!(",mom={}", groups));
+                }
+                if let Some(width) = kernel_width {
+                    params.push_str(&format!(",kernel={}", width));
+                }
+                params.push_str(&history_label(history));
+                format!("eps-greedy({})", params)
+            }
+            AgentSpec::ExplorePool { fraction, policy } => {
+                format!("explore-pool(fraction={},policy={:?})", fraction, policy)
+            }
+            AgentSpec::Ucb { c } => format!("ucb(c={})", c),
+            AgentSpec::Softmax { tau } => format!("softmax(tau={})", tau),
+            AgentSpec::Forecast { alpha, beta, c } => {
+                format!("forecast(alpha={},beta={},c={})", alpha, beta, c)
+            }
+            AgentSpec::Cvar { level, c, history } => {
+                format!("cvar(level={},c={}{})", level, c, history_label(history))
+            }
+            AgentSpec::Bootstrap { replicates } => format!("bootstrap-ts(B={})", replicates),
+            AgentSpec::Moss { horizon, reset, history } => {
+                format!("moss({}{})", horizon_label(horizon, reset), history_label(history))
+            }
+            AgentSpec::ExploreCommit { horizon, reset, history } => {
+                format!("explore-commit({}{})", horizon_label(horizon, reset),
+                        history_label(history))
+            }
+            AgentSpec::Registered { ref name, ref params } => {
+                let params: Vec<String> = params.iter()
+                                                .map(|(key, value)| format!("{}={}", key, value))
+                                                .collect();
+                format!("{}({})", name, params.join(","))
+            }
+            AgentSpec::Preprocessed { ref preprocessing, ref agent } => {
+                format!("{}{}", agent.label(), preprocessing.label())
+            }
+            AgentSpec::Meta { ref selector, ref agents } => {
+                let agents: Vec<String> = agents.iter().map(|agent| agent.label()).collect();
+                format!("meta({};{})", selector.label(), agents.join(","))
+            }
+            AgentSpec::Wrapped { ref wrapper, ref agent } => {
+                format!("{}{}", agent.label(), wrapper.label())
+            }
+        }
+    }
+}
+
+// The defaults of the hyperparameter flags, and of the fields of a spec
+// that leaves them out.
+fn default_epsilon() -> f64 {
+    0.1
+}
+
+fn default_explore_fraction() -> f64 {
+    0.1
+}
+
+fn default_ucb_c() -> f64 {
+    2.0
+}
+
+fn default_tau() -> f64 {
+    0.1
+}
+
+fn default_smoothing() -> f64 {
+    0.1
+}
+
+fn default_trend_smoothing() -> f64 {
+    0.05
+}
+
+fn default_forecast_c() -> f64 {
+    0.5
+}
+
+fn default_cvar_level() -> f64 {
+    0.1
+}
+
+fn default_cvar_c() -> f64 {
+    1.0
+}
+
+fn default_replicates() -> usize {
+    100
+}
+
+fn horizon_label(horizon: Option<usize>, reset: bool) -> String {
+    match horizon {
+        Some(horizon) => format!("horizon={}", horizon),
+        None if reset => "doubling,reset".to_string(),
+        None => "doubling".to_string(),
+    }
+}
+
+// A history capacity other than the default, for labels.
+fn history_label(history: Option<usize>) -> String {
+    match history {
+        Some(history) => format!(",history={}", history),
+        None => String::new(),
+    }
+}
+
+fn validate_history(history: Option<usize>) -> Result<(), String> {
+    if history == Some(0) {
+        return Err("history must be positive".to_string());
+    }
+    Ok(())
+}
+
+fn restored<T: Agent + DeserializeOwned + 'static>(state: serde_json::Value)
+                                                   -> Result<Box<dyn Agent>, String> {
+    let agent: T = serde_json::from_value(state).map_err(|e| format!("invalid state: {}", e))?;
+    Ok(Box::new(agent))
+}
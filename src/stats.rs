@@ -0,0 +1,139 @@
+// Per-arm sample-average statistics shared by the agents that only need
+// counts and sums rather than the full reward history.
+
+use rand::Rng;
+
+// What an agent currently knows about one arm.
+pub struct ArmSummary {
+    pub count: usize,
+    pub mean: f64,
+}
+
+// An agent's posterior over one arm's true value, summarized by its mean
+// and variance.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArmPosterior {
+    pub mean: f64,
+    pub variance: f64,
+}
+
+// Pseudo-observations an arm's statistics start from: as if `weight`
+// rewards averaging `mean` had already been received.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArmPrior {
+    pub mean: f64,
+    pub weight: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArmStats {
+    counts: Vec<usize>,
+    sums: Vec<f64>,
+
+    // pseudo-observations from each arm's prior, if it had one
+    prior_weights: Vec<f64>,
+    prior_sums: Vec<f64>,
+
+    // retired arms are kept out of action selection until reinstated
+    retired: Vec<bool>,
+}
+
+impl ArmStats {
+    pub fn new(n: usize) -> ArmStats {
+        ArmStats {
+            counts: vec![0; n],
+            sums: vec![0.0; n],
+            prior_weights: vec![0.0; n],
+            prior_sums: vec![0.0; n],
+            retired: vec![false; n],
+        }
+    }
+
+    // Adds an arm, starting from `prior` if given, and returns its index.
+    pub fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        let prior = prior.unwrap_or(ArmPrior { mean: 0.0, weight: 0.0 });
+        assert!(prior.weight >= 0.0);
+        self.counts.push(0);
+        self.sums.push(0.0);
+        self.prior_weights.push(prior.weight);
+        self.prior_sums.push(prior.weight * prior.mean);
+        self.retired.push(false);
+        self.counts.len() - 1
+    }
+
+    pub fn set_retired(&mut self, action: usize, retired: bool) {
+        self.retired[action] = retired;
+    }
+
+    pub fn is_retired(&self, action: usize) -> bool {
+        self.retired[action]
+    }
+
+    // arms that haven't been retired
+    pub fn active(&self) -> Vec<usize> {
+        (0..self.num_arms()).filter(|&i| !self.retired[i]).collect()
+    }
+
+    pub fn num_arms(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn update(&mut self, action: usize, reward: f64) {
+        self.counts[action] += 1;
+        self.sums[action] += reward;
+        debug!("arm {}: reward {:.4}, estimate now {:.4} over {} rewards", action, reward,
+               self.estimate(action), self.counts[action]);
+    }
+
+    pub fn count(&self, action: usize) -> usize {
+        self.counts[action]
+    }
+
+    // Number of rewards received for the arm plus its prior's weight.
+    pub fn weight(&self, action: usize) -> f64 {
+        self.counts[action] as f64 + self.prior_weights[action]
+    }
+
+    // total number of rewards received over all arms
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    // Sample average of the arm's rewards (and prior pseudo-observations),
+    // or 0 if it has neither.
+    pub fn estimate(&self, action: usize) -> f64 {
+        let weight = self.weight(action);
+        if weight == 0.0 {
+            0.0
+        } else {
+            (self.sums[action] + self.prior_sums[action]) / weight
+        }
+    }
+
+    pub fn summaries(&self) -> Vec<ArmSummary> {
+        let mut summaries = vec![];
+        for i in 0..self.num_arms() {
+            summaries.push(ArmSummary { count: self.counts[i], mean: self.estimate(i) });
+        }
+        summaries
+    }
+
+    // An active arm with the highest estimate, ties broken uniformly at
+    // random.
+    pub fn choose_greedy<R: Rng>(&self, rng: &mut R) -> usize {
+        let active = self.active();
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.estimate(active[0]);
+        for &i in &active[1..] {
+            let value = self.estimate(i);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        max_actions[rng.gen_range(0, max_actions.len())]
+    }
+}
@@ -0,0 +1,374 @@
+// Stopping rules decide when an experiment has gathered enough evidence to
+// end and declare a winning arm. They only look at per-arm counts and sample
+// means, so the same rule works for a simulated task or for statistics
+// collected from a live experiment.
+//
+// The Bayesian rules assume the testbed's model: each arm's true value has a
+// standard normal prior and rewards are normal with variance 1, so the
+// posterior for an arm with k rewards and sample mean m is normal with mean
+// k*m/(k+1) and variance 1/(k+1). Quantities that have no closed form are
+// estimated from joint posterior draws.
+//
+// The PAC rule makes no such assumption about the true values: it only
+// needs the rewards to be 1-sub-Gaussian. The convergence rule is different
+// again: it doesn't weigh evidence at all, it only notices when more plays
+// have stopped changing the agent's mind.
+
+use std::cell::{Cell, RefCell};
+
+use rand::Rng;
+use rand::distributions::IndependentSample;
+use rand::distributions::gamma::Gamma;
+use rand::distributions::normal::StandardNormal;
+
+use crate::confseq::ConfidenceSequence;
+use crate::stats::ArmSummary;
+
+pub struct Decision {
+    // name of the rule that fired, e.g. "prob-best"
+    pub rule: String,
+    pub winner: usize,
+
+    // the statistic that crossed the rule's threshold
+    pub value: f64,
+
+    // number of plays made when the rule fired
+    pub plays: usize,
+}
+
+pub trait StoppingRule {
+    fn name(&self) -> String;
+
+    // Returns a decision if the experiment should stop now, after `plays`
+    // plays in total.
+    fn check(&self, arms: &[ArmSummary], plays: usize, rng: &mut dyn Rng) -> Option<Decision>;
+}
+
+// Stops once some arm's posterior probability of being the best arm is at
+// least `threshold`.
+pub struct ProbabilityBest {
+    pub threshold: f64,
+    pub samples: usize,
+}
+
+impl StoppingRule for ProbabilityBest {
+    fn name(&self) -> String {
+        "prob-best".to_string()
+    }
+
+    fn check(&self, arms: &[ArmSummary], plays: usize, mut rng: &mut dyn Rng) -> Option<Decision> {
+        let draws = posterior_draws(arms, self.samples, &mut rng);
+        let mut wins = vec![0; arms.len()];
+        for draw in &draws {
+            wins[argmax(draw)] += 1;
+        }
+
+        let winner = argmax_usize(&wins);
+        let prob = wins[winner] as f64 / self.samples as f64;
+        if prob >= self.threshold {
+            Some(Decision { rule: self.name(), winner, value: prob, plays })
+        } else {
+            None
+        }
+    }
+}
+
+// ProbabilityBest for rewards of 0 or 1, with beta posteriors.
+pub struct BetaProbabilityBest {
+    pub threshold: f64,
+    pub samples: usize,
+}
+
+impl StoppingRule for BetaProbabilityBest {
+    fn name(&self) -> String {
+        "beta-prob-best".to_string()
+    }
+
+    fn check(&self, arms: &[ArmSummary], plays: usize, mut rng: &mut dyn Rng) -> Option<Decision> {
+        let posteriors: Vec<(Gamma, Gamma)> = arms.iter().map(|arm| {
+            let successes = (arm.mean * arm.count as f64).round();
+            (Gamma::new(1.0 + successes, 1.0),
+             Gamma::new(1.0 + arm.count as f64 - successes, 1.0))
+        }).collect();
+        let mut wins = vec![0; arms.len()];
+        let mut draw = vec![0.0; arms.len()];
+        for _ in 0..self.samples {
+            // X / (X + Y) is Beta(a, b) for X ~ Gamma(a), Y ~ Gamma(b)
+            for (i, (a, b)) in posteriors.iter().enumerate() {
+                let x = a.ind_sample(&mut rng);
+                draw[i] = x / (x + b.ind_sample(&mut rng));
+            }
+            wins[argmax(&draw)] += 1;
+        }
+
+        let winner = argmax_usize(&wins);
+        let prob = wins[winner] as f64 / self.samples as f64;
+        if prob >= self.threshold {
+            Some(Decision { rule: self.name(), winner, value: prob, plays })
+        } else {
+            None
+        }
+    }
+}
+
+// Stops once the expected loss of committing to the best-looking arm,
+// E[max_j Q(j) - Q(winner)] under the posterior, drops below `epsilon`.
+pub struct ExpectedLoss {
+    pub epsilon: f64,
+    pub samples: usize,
+}
+
+impl StoppingRule for ExpectedLoss {
+    fn name(&self) -> String {
+        "expected-loss".to_string()
+    }
+
+    fn check(&self, arms: &[ArmSummary], plays: usize, mut rng: &mut dyn Rng) -> Option<Decision> {
+        let draws = posterior_draws(arms, self.samples, &mut rng);
+        let mut loss = vec![0.0; arms.len()];
+        for draw in &draws {
+            let best = draw[argmax(draw)];
+            for i in 0..arms.len() {
+                loss[i] += best - draw[i];
+            }
+        }
+
+        let mut winner = 0;
+        for i in 1..arms.len() {
+            if loss[i] < loss[winner] {
+                winner = i;
+            }
+        }
+        let expected = loss[winner] / self.samples as f64;
+        if expected < self.epsilon {
+            Some(Decision { rule: self.name(), winner, value: expected, plays })
+        } else {
+            None
+        }
+    }
+}
+
+// (epsilon, delta)-PAC best-arm identification, with LUCB's stopping
+// condition (Kalyanakrishnan et al., 2012): stops once the leader, the arm
+// with the highest sample mean, has a lower bound within epsilon of every
+// other arm's upper bound,
+//
+//     LCB(leader) > max over a != leader of UCB(a) - epsilon
+//
+// The bounds are anytime-valid confidence sequences (see confseq) at level
+// delta / K for K arms, so with probability at least 1 - delta they all hold
+// at every check, and then the leader is within epsilon of the best arm
+// whenever the rule fires, however the arms were played and however often
+// it checks. The value is max UCB(a) - LCB(leader), below epsilon when it
+// fires. `target` is the number of rewards per arm the bounds are tuned for.
+pub struct PacBestArm {
+    pub epsilon: f64,
+    pub delta: f64,
+    pub target: usize,
+}
+
+impl StoppingRule for PacBestArm {
+    fn name(&self) -> String {
+        "pac".to_string()
+    }
+
+    fn check(&self, arms: &[ArmSummary], plays: usize, _rng: &mut dyn Rng) -> Option<Decision> {
+        let cs = ConfidenceSequence::tuned_for(self.delta / arms.len() as f64, 1.0,
+                                               self.target.max(1));
+        let means: Vec<f64> = arms.iter().map(|arm| arm.mean).collect();
+        let leader = argmax(&means);
+        let (lower, _) = cs.interval(&arms[leader]);
+        let mut best_upper = f64::NEG_INFINITY;
+        for (i, arm) in arms.iter().enumerate() {
+            if i != leader {
+                best_upper = best_upper.max(cs.interval(arm).1);
+            }
+        }
+        let gap = best_upper - lower;
+        if gap < self.epsilon {
+            Some(Decision { rule: self.name(), winner: leader, value: gap, plays })
+        } else {
+            None
+        }
+    }
+}
+
+// Stops once the estimates have settled: the greedy arm (the highest sample
+// mean) has stayed the same and no arm's mean has moved by more than
+// `tolerance` between consecutive checks, `patience` checks in a row. The
+// winner is the greedy arm and the value the largest move at the last
+// check. Checks are counted from the first, and an arm joining resets the
+// count.
+pub struct Convergence {
+    tolerance: f64,
+    patience: usize,
+
+    // the means at the previous check, and how many checks in a row they
+    // have been settled
+    last: RefCell<Vec<f64>>,
+    settled: Cell<usize>,
+}
+
+impl Convergence {
+    pub fn new(tolerance: f64, patience: usize) -> Convergence {
+        assert!(tolerance >= 0.0 && patience > 0);
+        Convergence {
+            tolerance,
+            patience,
+            last: RefCell::new(vec![]),
+            settled: Cell::new(0),
+        }
+    }
+}
+
+impl StoppingRule for Convergence {
+    fn name(&self) -> String {
+        "convergence".to_string()
+    }
+
+    fn check(&self, arms: &[ArmSummary], plays: usize, _rng: &mut dyn Rng) -> Option<Decision> {
+        let means: Vec<f64> = arms.iter().map(|arm| arm.mean).collect();
+        let mut last = self.last.borrow_mut();
+        let settled = if last.len() == means.len() {
+            let moved = (0..means.len()).fold(0.0, |acc: f64, i| {
+                acc.max((means[i] - last[i]).abs())
+            });
+            if argmax(&means) == argmax(&last) && moved <= self.tolerance {
+                Some(moved)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        *last = means;
+
+        match settled {
+            Some(moved) => {
+                self.settled.set(self.settled.get() + 1);
+                if self.settled.get() >= self.patience {
+                    return Some(Decision {
+                        rule: self.name(),
+                        winner: argmax(&last),
+                        value: moved,
+                        plays,
+                    });
+                }
+            }
+            None => self.settled.set(0),
+        }
+        None
+    }
+}
+
+// Draws `samples` joint samples of the arms' true values from the posterior.
+fn posterior_draws<R: Rng>(arms: &[ArmSummary], samples: usize, rng: &mut R) -> Vec<Vec<f64>> {
+    let mut draws = vec![];
+    for _ in 0..samples {
+        let mut draw = vec![];
+        for arm in arms {
+            let k = arm.count as f64;
+            let StandardNormal(z) = rng.gen();
+            draw.push(k * arm.mean / (k + 1.0) + z / (k + 1.0).sqrt());
+        }
+        draws.push(draw);
+    }
+    draws
+}
+
+fn argmax(xs: &[f64]) -> usize {
+    let mut best = 0;
+    for i in 1..xs.len() {
+        if xs[i] > xs[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+fn argmax_usize(xs: &[usize]) -> usize {
+    let mut best = 0;
+    for i in 1..xs.len() {
+        if xs[i] > xs[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+
+    fn arms(summaries: &[(usize, f64)]) -> Vec<ArmSummary> {
+        summaries.iter().map(|&(count, mean)| ArmSummary { count, mean }).collect()
+    }
+
+    fn winner(rule: &dyn StoppingRule, summaries: &[(usize, f64)]) -> Option<usize> {
+        rule.check(&arms(summaries), 1000, &mut seeded_rng(1)).map(|d| d.winner)
+    }
+
+    // The posterior rules pick an arm far ahead of the others, and can't
+    // tell tied arms apart however many rewards they have.
+    #[test]
+    fn posterior_rules_stop_for_a_dominant_arm_alone() {
+        let rule = ProbabilityBest { threshold: 0.95, samples: 1000 };
+        assert_eq!(winner(&rule, &[(500, 0.0), (500, 1.0), (500, 0.2)]), Some(1));
+        assert_eq!(winner(&rule, &[(500, 0.5), (500, 0.5), (500, 0.5)]), None);
+
+        let rule = BetaProbabilityBest { threshold: 0.95, samples: 1000 };
+        assert_eq!(winner(&rule, &[(200, 0.8), (200, 0.2)]), Some(0));
+        assert_eq!(winner(&rule, &[(200, 0.5), (200, 0.5)]), None);
+
+        let rule = ExpectedLoss { epsilon: 0.01, samples: 1000 };
+        assert_eq!(winner(&rule, &[(500, 0.0), (500, 1.0)]), Some(1));
+        assert_eq!(winner(&rule, &[(10, 0.5), (10, 0.5)]), None);
+    }
+
+    // The rule's state carries over between checks: it fires on the
+    // `patience`-th settled check in a row, and a changed greedy arm, a move
+    // past the tolerance or a new arm starts the count over.
+    #[test]
+    fn convergence_counts_settled_checks_in_a_row() {
+        let rule = Convergence::new(0.01, 3);
+        let check = |summaries: &[(usize, f64)]| winner(&rule, summaries);
+        // the first check has nothing to compare with
+        assert_eq!(check(&[(10, 0.5), (10, 1.0)]), None);
+        assert_eq!(check(&[(11, 0.5), (11, 1.005)]), None);
+        assert_eq!(check(&[(12, 0.5), (12, 1.0)]), None);
+        assert_eq!(check(&[(13, 0.5), (13, 0.995)]), Some(1));
+
+        // tied arms trading the lead never settle
+        for i in 0..10 {
+            let lead = if i % 2 == 0 { 0.001 } else { -0.001 };
+            assert_eq!(check(&[(20, 0.5 + lead), (20, 0.5)]), None);
+        }
+
+        assert_eq!(check(&[(30, 0.5), (30, 1.0)]), None);
+        assert_eq!(check(&[(31, 0.5), (31, 1.0)]), None);
+        assert_eq!(check(&[(32, 0.5), (32, 1.1)]), None);
+        assert_eq!(check(&[(33, 0.5), (33, 1.1)]), None);
+        assert_eq!(check(&[(34, 0.5), (34, 1.1), (0, 0.0)]), None);
+        assert_eq!(check(&[(35, 0.5), (35, 1.1), (1, 0.0)]), None);
+        assert_eq!(check(&[(36, 0.5), (36, 1.1), (1, 0.0)]), None);
+        assert_eq!(check(&[(37, 0.5), (37, 1.1), (1, 0.0)]), Some(1));
+    }
+
+    // The leader's lower bound clears every other arm's upper bound when it
+    // is far ahead; tied arms' bounds overlap by more than epsilon until
+    // they have many more rewards. A target of 0 is tuned as 1.
+    #[test]
+    fn pac_stops_once_the_bounds_separate() {
+        let rule = PacBestArm { epsilon: 0.05, delta: 0.05, target: 1000 };
+        assert_eq!(winner(&rule, &[(1000, 0.0), (1000, 1.0), (1000, 0.1)]), Some(1));
+        assert_eq!(winner(&rule, &[(1000, 0.5), (1000, 0.5), (1000, 0.5)]), None);
+        assert_eq!(winner(&rule, &[(0, 0.0), (1000, 1.0)]), None);
+
+        let rule = PacBestArm { epsilon: 0.05, delta: 0.05, target: 0 };
+        let decision = rule.check(&arms(&[(1000, 0.0), (1000, 1.0)]), 2000,
+                                  &mut seeded_rng(1)).unwrap();
+        assert_eq!((decision.winner, decision.plays), (1, 2000));
+        assert!(decision.value < 0.05);
+    }
+}
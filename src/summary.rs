@@ -0,0 +1,128 @@
+// End-of-run summary of a configuration's curves: the numbers usually read
+// off the plots by hand.
+//
+//     final reward     mean reward over the last SUMMARY_WINDOW plays
+//     total reward     cumulative reward per task
+//     final optimal    mean % optimal action over the last SUMMARY_WINDOW plays
+//     total regret     cumulative regret per task
+//     plays to 90%     first play at which at least 90% of tasks played an
+//                      optimal arm, if any did
+
+use std::cmp;
+use std::io::{self, Write};
+
+use crate::Curves;
+use crate::output::OutputFile;
+
+// Plays averaged for the "final" columns (fewer if the run is shorter).
+pub const SUMMARY_WINDOW: usize = 100;
+
+pub struct RunSummary {
+    pub final_reward: f64,
+    pub total_reward: f64,
+    pub final_pct_optimal: f64,
+    pub total_regret: f64,
+    pub plays_to_90: Option<usize>,
+}
+
+impl RunSummary {
+    pub fn of(curves: &Curves) -> RunSummary {
+        let num_plays = curves.avg_reward.len();
+        let start = num_plays - cmp::min(SUMMARY_WINDOW, num_plays);
+        let window = (num_plays - start) as f64;
+        let sum = |xs: &[f64]| xs.iter().fold(0.0, |acc, x| acc + x);
+        RunSummary {
+            final_reward: sum(&curves.avg_reward[start..]) / window,
+            total_reward: sum(&curves.avg_reward),
+            final_pct_optimal: sum(&curves.pct_optimal[start..]) / window,
+            total_regret: curves.cum_regret.last().cloned().unwrap_or(0.0),
+            plays_to_90: curves.pct_optimal.iter().position(|&p| p >= 90.0).map(|t| t + 1),
+        }
+    }
+}
+
+// A column of the summary table.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {
+    FinalReward,
+    TotalReward,
+    FinalPctOptimal,
+    TotalRegret,
+    PlaysTo90,
+}
+
+pub const ALL_METRICS: &[Metric] = &[
+    Metric::FinalReward,
+    Metric::TotalReward,
+    Metric::FinalPctOptimal,
+    Metric::TotalRegret,
+    Metric::PlaysTo90,
+];
+
+impl Metric {
+    pub fn from_name(name: &str) -> Result<Metric, String> {
+        match name {
+            "final_reward" => Ok(Metric::FinalReward),
+            "total_reward" => Ok(Metric::TotalReward),
+            "final_pct_optimal" => Ok(Metric::FinalPctOptimal),
+            "total_regret" => Ok(Metric::TotalRegret),
+            "plays_to_90" => Ok(Metric::PlaysTo90),
+            _ => Err(format!("unknown metric `{}` (expected final_reward, total_reward, \
+                              final_pct_optimal, total_regret or plays_to_90)", name)),
+        }
+    }
+
+    fn header(&self) -> &'static str {
+        match *self {
+            Metric::FinalReward => "final reward",
+            Metric::TotalReward => "total reward",
+            Metric::FinalPctOptimal => "final % opt",
+            Metric::TotalRegret => "total regret",
+            Metric::PlaysTo90 => "plays to 90%",
+        }
+    }
+
+    fn cell(&self, s: &RunSummary) -> String {
+        match *self {
+            Metric::FinalReward => format!("{:.4}", s.final_reward),
+            Metric::TotalReward => format!("{:.2}", s.total_reward),
+            Metric::FinalPctOptimal => format!("{:.2}", s.final_pct_optimal),
+            Metric::TotalRegret => format!("{:.2}", s.total_regret),
+            Metric::PlaysTo90 => s.plays_to_90.map_or("-".to_string(), |t| t.to_string()),
+        }
+    }
+}
+
+// Prints one row per configuration.
+pub fn print_table(rows: &[(String, RunSummary)]) {
+    print_metrics(rows, ALL_METRICS);
+}
+
+// Prints one row per configuration, with only the given columns.
+pub fn print_metrics(rows: &[(String, RunSummary)], metrics: &[Metric]) {
+    let mut header = format!("{:<40}", "config");
+    for metric in metrics {
+        header.push_str(&format!(" {:>12}", metric.header()));
+    }
+    println!("{}", header);
+    for (label, s) in rows {
+        let mut line = format!("{:<40}", label);
+        for metric in metrics {
+            line.push_str(&format!(" {:>12}", metric.cell(s)));
+        }
+        println!("{}", line);
+    }
+}
+
+// The same table as CSV; plays_to_90 is left empty when it was never
+// reached.
+pub fn write_csv(rows: &[(String, RunSummary)], file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "config,final_reward,total_reward,final_pct_optimal,total_regret,plays_to_90")?;
+    for (label, s) in rows {
+        writeln!(f, "\"{}\",{},{},{},{},{}", label, s.final_reward, s.total_reward,
+                 s.final_pct_optimal, s.total_regret,
+                 s.plays_to_90.map_or(String::new(), |t| t.to_string()))?;
+    }
+    f.finish()
+}
@@ -0,0 +1,170 @@
+// Epsilon sweep: runs several epsilon-greedy bandits on the same testbed.
+// Each task's q* is drawn once and every epsilon plays against it, so the
+// curves differ only because of the bandits (and the reward noise), not
+// because some epsilon happened to get easier tasks.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::output::{self, Format, RunInfo};
+use crate::plot;
+use crate::progress;
+use crate::spec::AgentSpec;
+use crate::summary::{self, RunSummary};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask, Curves,
+     EpsilonGreedyBandit};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub epsilons: Vec<f64>,
+    pub seed: Option<usize>,
+
+    // Series for epsilon e are written to "<prefix><e>.dat" (or .csv), with
+    // the decimal point replaced by an underscore: eps_0_1.dat for 0.1.
+    pub prefix: String,
+    pub format: Format,
+    pub compress: bool,
+
+    // image with every epsilon's curves, if wanted
+    pub plot: Option<String>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            epsilons: vec![0.0, 0.01, 0.1],
+            seed: None,
+            prefix: "eps_".to_string(),
+            format: Format::Dat,
+            compress: false,
+            plot: None,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "prefix" => opts.prefix = value.clone(),
+                "format" => opts.format = Format::from_name(value)?,
+                "compress" => opts.compress = output::parse_compress(value)?,
+                "plot" => opts.plot = Some(value.clone()),
+                "epsilons" => {
+                    opts.epsilons = vec![];
+                    for part in value.split(',') {
+                        opts.epsilons.push(parse_value(key, part)?);
+                    }
+                }
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 || opts.num_plays == 0 {
+            return Err("arms, tasks and plays must all be positive".to_string());
+        }
+        if opts.epsilons.is_empty() {
+            return Err("at least one epsilon is required".to_string());
+        }
+        for i in 0..opts.epsilons.len() {
+            if opts.epsilons[..i].contains(&opts.epsilons[i]) {
+                return Err(format!("epsilon {} is listed twice", opts.epsilons[i]));
+            }
+        }
+        Ok(opts)
+    }
+
+    fn output_path(&self, epsilon: f64) -> String {
+        let stem = format!("{}{}", self.prefix, epsilon.to_string().replace(".", "_"));
+        output::file_name(&stem, self.format, self.compress)
+    }
+}
+
+// Returns the curves of every epsilon, in the order given.
+pub fn run_sweep<R: Rng>(rng: &mut R, n: usize, num_tasks: usize, num_plays: usize,
+                         epsilons: &[f64]) -> Vec<Curves> {
+    let seeds = task_seeds(rng, num_tasks);
+    progress::start(num_tasks);
+    let chunk_sums = map_task_chunks(0..num_tasks, |tasks| {
+        let mut sums: Vec<Curves> = epsilons.iter().map(|_| Curves::new(num_plays)).collect();
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let mut task = BanditTask::new(n);
+            let q_star = task.draw_q_star(&mut rng);
+
+            for (e, &epsilon) in epsilons.iter().enumerate() {
+                let mut bandit = EpsilonGreedyBandit::new(n, epsilon);
+                let outcome = task.play_task_on(&q_star, &mut bandit, num_plays, &mut rng);
+                sums[e].add_task(&outcome, &q_star);
+            }
+            progress::task_done(i);
+        }
+        sums
+    });
+
+    let mut curves: Vec<Curves> = epsilons.iter().map(|_| Curves::new(num_plays)).collect();
+    for sums in &chunk_sums {
+        for e in 0..epsilons.len() {
+            curves[e].add(&sums[e]);
+        }
+    }
+    for c in curves.iter_mut() {
+        c.average(num_tasks);
+    }
+    curves
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    // Without an explicit seed, pick one so the results can still record
+    // how to reproduce them.
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => rand::thread_rng().gen(),
+    };
+    let started_at = output::unix_time();
+    let curves = run_sweep(&mut seeded_rng(seed), opts.n, opts.num_tasks, opts.num_plays,
+                           &opts.epsilons);
+
+    for (e, &epsilon) in opts.epsilons.iter().enumerate() {
+        let path = opts.output_path(epsilon);
+        let spec = AgentSpec::EpsilonGreedy {
+            epsilon,
+            step_size: None,
+            tie_tolerance: 0.0,
+            min_samples: 0,
+            mom_groups: None,
+            kernel_width: None,
+            history: None,
+        };
+        let info = RunInfo::new(&spec, opts.n, opts.num_tasks, opts.num_plays, seed, started_at);
+        output::write_series(&path, opts.format, &info, &curves[e].columns())
+            .map_err(|err| format!("couldn't write `{}`: {}", path, err))?;
+        output::write_pulls(&path, &curves[e].pulls_by_rank)?;
+        println!("epsilon = {}: {}", epsilon, path);
+        progress::config_done(&spec.label());
+    }
+
+    let mut rows = vec![];
+    for (e, &epsilon) in opts.epsilons.iter().enumerate() {
+        rows.push((format!("epsilon = {}", epsilon), RunSummary::of(&curves[e])));
+    }
+    summary::print_table(&rows);
+
+    if let Some(ref path) = opts.plot {
+        let mut series = vec![];
+        for (e, &epsilon) in opts.epsilons.iter().enumerate() {
+            series.push((format!("epsilon = {}", epsilon), &curves[e]));
+        }
+        plot::plot_curves(path, &series)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+// Where a run's time goes. Every run reports its wall-clock time and plays
+// per second after the summary, and records them in the metadata of JSON
+// output. With `--timing` (`timing = true` in a config plan) the time spent
+// in the tasks is also split into phases:
+//
+//     selection    the agent choosing actions
+//     update       the agent taking in rewards
+//     environment  everything else in the play loop: drawing rewards and
+//                  recording the outcome
+//     aggregation  adding finished tasks into the run's totals and
+//                  averaging them
+//
+// Phases are measured per thread and summed, so with several threads they
+// add up to more than the wall-clock time; they're reported as shares of
+// their total. Measuring them costs a few clock reads per play, which is
+// why it's opt-in. Writing raw results and events isn't counted.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+use crate::Agent;
+
+// Seconds spent in each phase.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct PhaseTimes {
+    pub selection: f64,
+    pub update: f64,
+    pub environment: f64,
+    pub aggregation: f64,
+}
+
+impl PhaseTimes {
+    pub fn add(&mut self, other: &PhaseTimes) {
+        self.selection += other.selection;
+        self.update += other.update;
+        self.environment += other.environment;
+        self.aggregation += other.aggregation;
+    }
+
+    fn total(&self) -> f64 {
+        self.selection + self.update + self.environment + self.aggregation
+    }
+}
+
+// Phase times collected from every thread of a run.
+pub struct PhaseTimer {
+    totals: Mutex<PhaseTimes>,
+}
+
+impl PhaseTimer {
+    pub fn new() -> PhaseTimer {
+        PhaseTimer { totals: Mutex::new(PhaseTimes::default()) }
+    }
+
+    pub fn add(&self, times: &PhaseTimes) {
+        self.totals.lock().unwrap().add(times);
+    }
+
+    pub fn totals(&self) -> PhaseTimes {
+        *self.totals.lock().unwrap()
+    }
+}
+
+// How long one agent's run took.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Timing {
+    pub wall_secs: f64,
+    pub plays_per_sec: f64,
+    pub phases: Option<PhaseTimes>,
+}
+
+impl Timing {
+    // Timing of `plays` plays that started at `started`, finishing now.
+    pub fn since(started: Instant, plays: usize, phases: Option<PhaseTimes>) -> Timing {
+        let wall_secs = secs(started.elapsed());
+        Timing {
+            wall_secs,
+            plays_per_sec: if wall_secs > 0.0 { plays as f64 / wall_secs } else { 0.0 },
+            phases,
+        }
+    }
+}
+
+pub fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+// Prints every agent's timing, with its phases' shares if they were
+// measured.
+pub fn print_table(rows: &[(String, Timing)]) {
+    println!("{:<40} {:>10} {:>12} {:>10} {:>8} {:>12} {:>12}",
+             "timing", "wall (s)", "plays/s", "selection", "update", "environment",
+             "aggregation");
+    for (label, timing) in rows {
+        let mut line = format!("{:<40} {:>10.3} {:>12.0}", label, timing.wall_secs,
+                               timing.plays_per_sec);
+        if let Some(ref phases) = timing.phases {
+            let total = phases.total().max(1e-12);
+            let share = |t: f64| format!("{:.1}%", 100.0 * t / total);
+            line.push_str(&format!(" {:>10} {:>8} {:>12} {:>12}", share(phases.selection),
+                                   share(phases.update), share(phases.environment),
+                                   share(phases.aggregation)));
+        }
+        println!("{}", line);
+    }
+}
+
+// Plays `inner`, timing its choices and updates. Everything else is passed
+// straight through, so the agent plays exactly as it would alone.
+pub struct TimedAgent<'a> {
+    inner: &'a mut dyn Agent,
+    pub selection: Duration,
+    pub update: Duration,
+}
+
+impl<'a> TimedAgent<'a> {
+    pub fn new(inner: &'a mut dyn Agent) -> TimedAgent<'a> {
+        TimedAgent { inner, selection: Duration::new(0, 0), update: Duration::new(0, 0) }
+    }
+}
+
+impl<'a> Agent for TimedAgent<'a> {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        let started = Instant::now();
+        let action = self.inner.choose_action(rng);
+        self.selection += started.elapsed();
+        action
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        let started = Instant::now();
+        self.inner.receive_reward(reward, action);
+        self.update += started.elapsed();
+    }
+
+    fn receive_rewards(&mut self, rewards: &[(usize, f64)]) {
+        let started = Instant::now();
+        self.inner.receive_rewards(rewards);
+        self.update += started.elapsed();
+    }
+
+    fn warm_start(&mut self, arms: &[ArmSummary]) {
+        self.inner.warm_start(arms);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.inner.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.inner.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.inner.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.inner.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        self.inner.state()
+    }
+}
@@ -0,0 +1,249 @@
+// Paired comparison of any number of agents with common random numbers.
+// As in the paired mode, on every task all the agents play against the same
+// pre-drawn rewards (the same q* and the same reward for every arm at every
+// play) and make their random choices from the same seed, so the
+// differences between their total rewards on a task only reflect how the
+// agents differ. The agents are given with the usual agent flags behind a
+// letter prefix, from `a-` on, e.g.
+//
+//     tournament --a-agent ucb --a-c 2 --b-agent epsilon-greedy \
+//                --c-agent softmax --c-tau 0.2
+//
+// Every task's total reward for every agent, and every pair's difference,
+// are written to the output, one line per task:
+//
+//     task,A,B,C,A-B,A-C,B-C
+//
+// and for every pair the mean difference per task is printed with its
+// standard error, the fraction of tasks the first agent did better on, the
+// p-values and effect sizes of a paired t-test and a Wilcoxon signed-rank
+// test (see significance), and how many times as many tasks two
+// independent runs would have needed for the same standard error.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use crate::environment::{self, FixedRewards};
+use crate::output::OutputFile;
+use crate::paired::{agent_from_flags, mean_and_std_dev};
+use crate::progress;
+use crate::significance::{format_p, PairedTest};
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{map_task_chunks, parse_value, seeded_rng, task_seeds, BanditTask};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+
+    // (upper-case letter, agent), in the order of their prefixes
+    pub agents: Vec<(char, AgentSpec)>,
+    pub output: String,
+}
+
+// The letter of a flag with an agent prefix, e.g. 'c' for `--c-tau`.
+fn agent_letter(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some('-')) if letter.is_ascii_lowercase() && is_agent_flag(&key[2..]) => {
+            Some(letter)
+        }
+        _ => None,
+    }
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 2000,
+            num_plays: 1000,
+            seed: None,
+            agents: vec![],
+            output: "tournament.csv".to_string(),
+        };
+
+        let mut letters = BTreeSet::new();
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "output" => opts.output = value.clone(),
+                key => match agent_letter(key) {
+                    Some(letter) => {
+                        letters.insert(letter);
+                    }
+                    None => return Err(format!("unknown flag `--{}`", key)),
+                },
+            }
+        }
+        for letter in letters {
+            let agent = agent_from_flags(flags, &format!("{}-", letter))?;
+            opts.agents.push((letter.to_ascii_uppercase(), agent));
+        }
+
+        if opts.agents.len() < 2 {
+            return Err("a tournament needs at least two agents (`--a-agent`, `--b-agent`, \
+                        ...)".to_string());
+        }
+        if opts.n == 0 || opts.num_plays == 0 || opts.num_tasks < 2 {
+            return Err("arms and plays must be positive and there must be at least 2 tasks"
+                       .to_string());
+        }
+        Ok(opts)
+    }
+}
+
+// Every task's total reward for every agent, in task order.
+fn run_tournament<R: Rng>(rng: &mut R, opts: &Options) -> Vec<Vec<f64>> {
+    let seeds = task_seeds(rng, opts.num_tasks);
+    progress::start(opts.num_tasks);
+    let chunks = map_task_chunks(0..opts.num_tasks, |tasks| {
+        let mut totals = vec![];
+        for i in tasks {
+            if progress::task_lines() {
+                info!("Task #{}", i);
+            }
+            let mut rng = seeded_rng(seeds[i]);
+            let q_star = BanditTask::new(opts.n).draw_q_star(&mut rng);
+            let mut env = FixedRewards::draw(q_star, opts.num_plays, &mut rng);
+            let choice_seed: usize = rng.gen();
+
+            let mut task_totals = vec![];
+            for (_, spec) in &opts.agents {
+                env.rewind();
+                let mut agent = spec.build(opts.n);
+                let outcome = environment::play(&mut env, &mut *agent, opts.num_plays,
+                                                &mut seeded_rng(choice_seed));
+                task_totals.push(outcome.rewards.iter().fold(0.0, |acc, r| acc + r));
+            }
+            totals.push(task_totals);
+            progress::task_done(i);
+        }
+        totals
+    });
+    chunks.into_iter().flatten().collect()
+}
+
+// Mean and variance of xs.
+fn mean_and_var(xs: &[f64]) -> (f64, f64) {
+    let sum = xs.iter().fold(0.0, |acc, x| acc + x);
+    let sum_sq = xs.iter().fold(0.0, |acc, x| acc + x * x);
+    let (mean, std_dev) = mean_and_std_dev(sum, sum_sq, xs.len());
+    (mean, std_dev * std_dev)
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let totals = match opts.seed {
+        Some(seed) => run_tournament(&mut seeded_rng(seed), opts),
+        None => run_tournament(&mut rand::thread_rng(), opts),
+    };
+    write_csv(&totals, &opts.agents, &opts.output)
+        .map_err(|e| format!("couldn't write `{}`: {}", opts.output, e))?;
+
+    let k = opts.agents.len();
+    let tasks = opts.num_tasks as f64;
+    let column = |a: usize| -> Vec<f64> { totals.iter().map(|task| task[a]).collect() };
+    println!("{} agents: {} tasks x {} plays, {} arms", k, opts.num_tasks, opts.num_plays,
+             opts.n);
+    for a in 0..k {
+        let (mean, var) = mean_and_var(&column(a));
+        println!("{} = {}: total reward per task {:.4} (std err {:.4})",
+                 opts.agents[a].0, opts.agents[a].1.label(), mean, (var / tasks).sqrt());
+    }
+    println!("{:<8} {:>12} {:>10} {:>9} {:>9} {:>8} {:>10} {:>8} {:>15}",
+             "pair", "mean diff", "std err", "% better", "t-test p", "d", "Wilcoxon p", "r",
+             "unpaired tasks");
+    for a in 0..k {
+        for b in a + 1..k {
+            let diffs: Vec<f64> = totals.iter().map(|task| task[a] - task[b]).collect();
+            let (mean, var) = mean_and_var(&diffs);
+            let wins = diffs.iter().filter(|&&d| d > 0.0).count();
+            let test = PairedTest::of(&diffs);
+            let unpaired_var = mean_and_var(&column(a)).1 + mean_and_var(&column(b)).1;
+            let factor = if var > 0.0 {
+                format!("x{:.1}", unpaired_var / var)
+            } else {
+                "-".to_string()
+            };
+            println!("{:<8} {:>12.4} {:>10.4} {:>9.1} {:>9} {:>8.3} {:>10} {:>8.3} {:>15}",
+                     format!("{}-{}", opts.agents[a].0, opts.agents[b].0), mean,
+                     (var / tasks).sqrt(), 100.0 * wins as f64 / tasks, format_p(test.t_p),
+                     test.cohens_d, format_p(test.wilcoxon_p), test.rank_biserial, factor);
+        }
+    }
+    Ok(())
+}
+
+fn write_csv(totals: &[Vec<f64>], agents: &[(char, AgentSpec)], file_name: &str)
+             -> io::Result<()> {
+    let k = agents.len();
+    let mut f = OutputFile::create(file_name)?;
+    let mut header = "task".to_string();
+    for &(letter, _) in agents {
+        header.push_str(&format!(",{}", letter));
+    }
+    for a in 0..k {
+        for b in a + 1..k {
+            header.push_str(&format!(",{}-{}", agents[a].0, agents[b].0));
+        }
+    }
+    writeln!(f, "{}", header)?;
+    for (i, task) in totals.iter().enumerate() {
+        let mut line = i.to_string();
+        for total in task {
+            line.push_str(&format!(",{}", total));
+        }
+        for a in 0..k {
+            for b in a + 1..k {
+                line.push_str(&format!(",{}", task[a] - task[b]));
+            }
+        }
+        writeln!(f, "{}", line)?;
+    }
+    f.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(pairs: &[(&str, &str)]) -> Result<Options, String> {
+        let flags = pairs.iter().map(|&(key, value)| (key.to_string(), value.to_string()))
+                         .collect();
+        Options::from_flags(&flags)
+    }
+
+    // Agents are taken in the order of their letters, and a tournament
+    // needs two of them.
+    #[test]
+    fn agents_are_lettered() {
+        let opts = options(&[("c-agent", "softmax"), ("c-tau", "0.2"), ("a-agent", "ucb"),
+                             ("a-c", "2")]).unwrap();
+        let letters: Vec<char> = opts.agents.iter().map(|&(letter, _)| letter).collect();
+        assert_eq!(letters, ['A', 'C']);
+        assert_eq!(opts.agents[0].1, AgentSpec::Ucb { c: 2.0 });
+        assert!(options(&[("a-agent", "ucb")]).is_err());
+        assert!(options(&[("a-agent", "ucb"), ("b-agent", "ucb"), ("b-nonsense", "1")])
+                    .is_err());
+    }
+
+    // With common random numbers the same agent entered twice does exactly
+    // as well on every task, while a different one doesn't.
+    #[test]
+    fn the_same_agent_ties_itself_on_every_task() {
+        let mut opts = options(&[("a-agent", "ucb"), ("b-agent", "ucb"),
+                                 ("c-agent", "epsilon-greedy")]).unwrap();
+        opts.num_tasks = 20;
+        opts.num_plays = 100;
+        let totals = run_tournament(&mut seeded_rng(5), &opts);
+        assert_eq!(totals.len(), 20);
+        assert!(totals.iter().all(|task| task[0] == task[1]));
+        assert!(totals.iter().any(|task| task[0] != task[2]));
+    }
+}
@@ -0,0 +1,296 @@
+// Estimate trajectories, for debugging learning dynamics. The `trace` mode
+// plays a few tasks (one by default) and records the agent's estimate
+// Q_t(a) of every arm after each play, writing task i to
+// "<prefix>_<i>.csv":
+//
+//     # q_star = 0.31,-1.2,...
+//     step,action,reward,q_0,q_1,...
+//
+// The true values are in the comment line, so the estimates can be checked
+// against them. With `--save-agent file` the agent of the last task is saved
+// when it's done, and with `--load-agent file` every task starts from a
+// saved agent instead of a fresh one (see agent_state).
+//
+// An agent that keeps a posterior over every arm's value (bootstrap-ts) can
+// also have it dumped with `--posterior-every K`, after every K-th play, to
+// "<prefix>_<i>.posterior.csv", one line per arm and snapshot:
+//
+//     step,arm,mean,variance
+//
+// so the posteriors can be animated as they concentrate.
+//
+// The `trace-query` mode reads such a file back and shows what the agent
+// believed right after a given play (the `step` column): every arm's
+// estimate next to its true value, and how often it had been pulled.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use rand::Rng;
+
+use crate::agent_state::SavedAgent;
+use crate::environment::{self, GaussianTestbed};
+use crate::output::OutputFile;
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::stats::ArmPosterior;
+use crate::{best_arm, parse_value, seeded_rng, task_seeds, Agent, BanditTask, TaskOutcome};
+
+pub struct Options {
+    pub n: usize,
+    pub num_tasks: usize,
+    pub num_plays: usize,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+    pub prefix: String,
+    pub save_agent: Option<String>,
+    pub load_agent: Option<SavedAgent>,
+
+    // plays between posterior snapshots, if they're wanted
+    pub posterior_every: Option<usize>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        if flags.contains_key("load-agent") {
+            if let Some(key) = flags.keys().find(|k| is_agent_flag(k)) {
+                return Err(format!("`--{}` doesn't apply to a loaded agent", key));
+            }
+        }
+        let mut opts = Options {
+            n: 10,
+            num_tasks: 1,
+            num_plays: 1000,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+            prefix: "trace".to_string(),
+            save_agent: None,
+            load_agent: None,
+            posterior_every: None,
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "arms" => opts.n = parse_value(key, value)?,
+                "tasks" => opts.num_tasks = parse_value(key, value)?,
+                "plays" => opts.num_plays = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "prefix" => opts.prefix = value.clone(),
+                "save-agent" => opts.save_agent = Some(value.clone()),
+                "posterior-every" => opts.posterior_every = Some(parse_value(key, value)?),
+                "load-agent" => {
+                    let saved = SavedAgent::load(value)?;
+                    opts.agent = saved.spec.clone();
+                    opts.load_agent = Some(saved);
+                }
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.n == 0 || opts.num_tasks == 0 {
+            return Err("arms and tasks must be positive".to_string());
+        }
+        if opts.posterior_every == Some(0) {
+            return Err("posterior-every must be positive".to_string());
+        }
+        if opts.posterior_every.is_some() && opts.agent.build(opts.n).posteriors().is_none() {
+            return Err(format!("agent {} keeps no posterior to snapshot", opts.agent.label()));
+        }
+        Ok(opts)
+    }
+}
+
+// A task's trace: its outcome, the estimates after each play, and the
+// posteriors after every `posterior_every` plays, with the step they
+// followed.
+struct Trace {
+    outcome: TaskOutcome,
+    estimates: Vec<Vec<f64>>,
+    posteriors: Vec<(usize, Vec<ArmPosterior>)>,
+}
+
+// Plays one task and traces it.
+fn trace_task<R: Rng>(q_star: &[f64], agent: &mut dyn Agent, num_plays: usize,
+                      posterior_every: Option<usize>, rng: &mut R) -> Trace {
+    let mut estimates = vec![];
+    let mut posteriors = vec![];
+    let mut env = GaussianTestbed::new(q_star.to_vec());
+    let outcome = environment::play_observed(&mut env, agent, num_plays, rng,
+                                             &mut |t, agent| {
+        estimates.push(agent.arm_summaries().iter().map(|s| s.mean).collect());
+        if let Some(every) = posterior_every {
+            if (t + 1) % every == 0 {
+                posteriors.push((t, agent.posteriors().unwrap_or(vec![])));
+            }
+        }
+    });
+    Trace { outcome, estimates, posteriors }
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let mut rng = match opts.seed {
+        Some(seed) => seeded_rng(seed),
+        None => seeded_rng(rand::thread_rng().gen()),
+    };
+    let seeds = task_seeds(&mut rng, opts.num_tasks);
+    for (i, &seed) in seeds.iter().enumerate() {
+        let mut rng = seeded_rng(seed);
+        let q_star = BanditTask::new(opts.n).draw_q_star(&mut rng);
+        let mut agent = match opts.load_agent {
+            Some(ref saved) => {
+                let agent = saved.agent()?;
+                if agent.arm_summaries().len() != opts.n {
+                    return Err(format!("the loaded agent has {} arms, not {}",
+                                       agent.arm_summaries().len(), opts.n));
+                }
+                agent
+            }
+            None => opts.agent.build(opts.n),
+        };
+        let trace = trace_task(&q_star, &mut *agent, opts.num_plays, opts.posterior_every,
+                               &mut rng);
+
+        let path = format!("{}_{}.csv", opts.prefix, i);
+        write_csv(&q_star, &trace.outcome, &trace.estimates, &path)
+            .map_err(|e| format!("couldn't write `{}`: {}", path, e))?;
+        println!("task {}: {}", i, path);
+        if opts.posterior_every.is_some() {
+            let path = format!("{}_{}.posterior.csv", opts.prefix, i);
+            write_posteriors(&trace.posteriors, &path)
+                .map_err(|e| format!("couldn't write `{}`: {}", path, e))?;
+            println!("task {}: {}", i, path);
+        }
+
+        if i + 1 == opts.num_tasks {
+            if let Some(ref path) = opts.save_agent {
+                SavedAgent::new(&opts.agent, &*agent).save(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_csv(q_star: &[f64], outcome: &TaskOutcome, estimates: &[Vec<f64>],
+             file_name: &str) -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    let values: Vec<String> = q_star.iter().map(|q| q.to_string()).collect();
+    writeln!(f, "# q_star = {}", values.join(","))?;
+    let arms: Vec<String> = (0..q_star.len()).map(|a| format!("q_{}", a)).collect();
+    writeln!(f, "step,action,reward,{}", arms.join(","))?;
+    for (t, row) in estimates.iter().enumerate() {
+        let values: Vec<String> = row.iter().map(|q| q.to_string()).collect();
+        writeln!(f, "{},{},{},{}", t, outcome.actions[t], outcome.rewards[t], values.join(","))?;
+    }
+    f.finish()
+}
+
+fn write_posteriors(posteriors: &[(usize, Vec<ArmPosterior>)], file_name: &str)
+                    -> io::Result<()> {
+    let mut f = OutputFile::create(file_name)?;
+    writeln!(f, "step,arm,mean,variance")?;
+    for &(t, ref arms) in posteriors {
+        for (a, posterior) in arms.iter().enumerate() {
+            writeln!(f, "{},{},{},{}", t, a, posterior.mean, posterior.variance)?;
+        }
+    }
+    f.finish()
+}
+
+pub struct QueryOptions {
+    pub path: String,
+    pub play: usize,
+}
+
+impl QueryOptions {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<QueryOptions, String> {
+        let mut path = None;
+        let mut play = None;
+        for (key, value) in flags {
+            match &key[..] {
+                "trace" => path = Some(value.clone()),
+                "play" => play = Some(parse_value(key, value)?),
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+        Ok(QueryOptions {
+            path: path.ok_or("`--trace` is required")?,
+            play: play.ok_or("`--play` is required")?,
+        })
+    }
+}
+
+// The agent's state right after one play of a traced task.
+pub struct Snapshot {
+    pub play: usize,
+    pub action: usize,
+    pub reward: f64,
+    pub q_star: Vec<f64>,
+    pub estimates: Vec<f64>,
+
+    // plays of each arm so far, this one included
+    pub pulls: Vec<usize>,
+}
+
+// Reads the trace at `path` up to play `play` and reconstructs the state
+// there.
+pub fn snapshot_at(path: &str, play: usize) -> Result<Snapshot, String> {
+    let file = File::open(path).map_err(|e| format!("couldn't open `{}`: {}", path, e))?;
+    let bad = |line: usize| format!("`{}` line {} isn't a trace line", path, line + 1);
+    let mut q_star = None;
+    let mut pulls = vec![];
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("couldn't read `{}`: {}", path, e))?;
+        if i == 0 {
+            if !line.starts_with("# q_star = ") {
+                return Err(bad(i));
+            }
+            let values: Result<Vec<f64>, _> = line["# q_star = ".len()..]
+                                                  .split(',').map(|q| q.parse()).collect();
+            let values = values.map_err(|_| bad(i))?;
+            pulls = vec![0; values.len()];
+            q_star = Some(values);
+            continue;
+        }
+        if i == 1 {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 3 + pulls.len() {
+            return Err(bad(i));
+        }
+        let action: usize = fields[1].parse().map_err(|_| bad(i))?;
+        if action >= pulls.len() {
+            return Err(bad(i));
+        }
+        pulls[action] += 1;
+        if i - 2 == play {
+            let estimates: Result<Vec<f64>, _> = fields[3..].iter().map(|q| q.parse()).collect();
+            return Ok(Snapshot {
+                play,
+                action,
+                reward: fields[2].parse().map_err(|_| bad(i))?,
+                q_star: q_star.unwrap_or(vec![]),
+                estimates: estimates.map_err(|_| bad(i))?,
+                pulls,
+            });
+        }
+    }
+    Err(format!("`{}` doesn't record play {}", path, play))
+}
+
+pub fn query(opts: &QueryOptions) -> Result<(), String> {
+    let snapshot = snapshot_at(&opts.path, opts.play)?;
+    let greedy = best_arm(&snapshot.estimates);
+    println!("{} after play {}: played arm {} for reward {}",
+             opts.path, snapshot.play, snapshot.action, snapshot.reward);
+    println!("{:>5} {:>10} {:>10} {:>8}", "arm", "q_star", "estimate", "pulls");
+    for a in 0..snapshot.q_star.len() {
+        println!("{:>5} {:>10.4} {:>10.4} {:>8}{}", a, snapshot.q_star[a],
+                 snapshot.estimates[a], snapshot.pulls[a], if a == greedy { " *" } else { "" });
+    }
+    println!("* greedy arm");
+    Ok(())
+}
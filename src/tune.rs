@@ -0,0 +1,135 @@
+// Bandit-driven tuning: the crate's agents allocating trials among competing
+// configurations of an objective that isn't a simulated testbed. Each
+// candidate configuration is an arm, and every trial evaluates the objective
+// (which may be noisy) on the candidate the agent picks and feeds the score
+// back as the reward, so promising candidates get most of the trial budget.
+//
+// The `tune` mode demonstrates this on picking the learning rate of
+// stochastic gradient descent on the noisy quadratic loss (w - 3)^2: each
+// trial runs SGD_STEPS steps from w = 0 with gradients perturbed by standard
+// normal noise, and scores the result by -ln(1 + loss), so diverging rates
+// get a low score rather than an unbounded one.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+
+use crate::spec::{is_agent_flag, AgentSpec};
+use crate::{parse_value, seeded_rng, Agent};
+
+// How the trials were spent and what they found.
+pub struct TuneReport {
+    pub trials: Vec<usize>,
+    pub mean_score: Vec<f64>,
+
+    // the candidate with the highest mean score among those tried
+    pub best: usize,
+}
+
+// Spends `trials` evaluations of `objective` on `candidates`, letting `agent`
+// (which must have one arm per candidate) choose which one to evaluate each
+// time.
+pub fn tune<C, F, R>(candidates: &[C], agent: &mut dyn Agent, trials: usize, rng: &mut R,
+                     mut objective: F) -> TuneReport
+    where F: FnMut(&C, &mut R) -> f64, R: Rng
+{
+    assert!(!candidates.is_empty());
+    let mut counts = vec![0; candidates.len()];
+    let mut sums = vec![0.0; candidates.len()];
+    for _ in 0..trials {
+        let c = agent.choose_action(rng);
+        let score = objective(&candidates[c], rng);
+        agent.receive_reward(score, c);
+        counts[c] += 1;
+        sums[c] += score;
+    }
+
+    let mean_score: Vec<f64> = (0..candidates.len()).map(|c| {
+        if counts[c] == 0 { f64::NAN } else { sums[c] / counts[c] as f64 }
+    }).collect();
+    let mut best = 0;
+    for c in 1..candidates.len() {
+        if counts[c] > 0 && (counts[best] == 0 || mean_score[c] > mean_score[best]) {
+            best = c;
+        }
+    }
+    TuneReport { trials: counts, mean_score, best }
+}
+
+// SGD steps per trial of the demo objective.
+const SGD_STEPS: usize = 50;
+
+// One trial of the demo objective: the score of SGD with learning rate `lr`.
+fn sgd_score<R: Rng>(lr: &f64, rng: &mut R) -> f64 {
+    let mut w = 0.0;
+    for _ in 0..SGD_STEPS {
+        let StandardNormal(noise) = rng.gen();
+        w -= lr * (2.0 * (w - 3.0) + noise);
+        if !w.is_finite() || w.abs() > 1e6 {
+            break;
+        }
+    }
+    let loss = (w - 3.0) * (w - 3.0);
+    if loss.is_finite() { -(1.0 + loss).ln() } else { -(1e12f64).ln() }
+}
+
+pub struct Options {
+    pub trials: usize,
+    pub seed: Option<usize>,
+    pub agent: AgentSpec,
+    pub learning_rates: Vec<f64>,
+}
+
+impl Options {
+    pub fn from_flags(flags: &HashMap<String, String>) -> Result<Options, String> {
+        let mut opts = Options {
+            trials: 500,
+            seed: None,
+            agent: AgentSpec::from_flags(flags)?,
+            learning_rates: vec![0.001, 0.01, 0.05, 0.1, 0.3, 0.6, 0.9, 1.1],
+        };
+
+        for (key, value) in flags {
+            match &key[..] {
+                "trials" => opts.trials = parse_value(key, value)?,
+                "seed" => opts.seed = Some(parse_value(key, value)?),
+                "learning-rates" => {
+                    opts.learning_rates = vec![];
+                    for part in value.split(',') {
+                        opts.learning_rates.push(parse_value(key, part)?);
+                    }
+                }
+                key if is_agent_flag(key) => {}
+                _ => return Err(format!("unknown flag `--{}`", key)),
+            }
+        }
+
+        if opts.trials == 0 {
+            return Err("trials must be positive".to_string());
+        }
+        if opts.learning_rates.is_empty() {
+            return Err("at least one learning rate is required".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+pub fn run(opts: &Options) -> Result<(), String> {
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => rand::thread_rng().gen(),
+    };
+    let mut agent = opts.agent.build(opts.learning_rates.len());
+    let report = tune(&opts.learning_rates, &mut *agent, opts.trials, &mut seeded_rng(seed),
+                      sgd_score);
+
+    println!("tuning the SGD learning rate with {}: {} trials, seed {}",
+             opts.agent.label(), opts.trials, seed);
+    println!("{:>14} {:>8} {:>12}", "learning rate", "trials", "mean score");
+    for (c, &lr) in opts.learning_rates.iter().enumerate() {
+        println!("{:>14} {:>8} {:>12.4}{}", lr, report.trials[c], report.mean_score[c],
+                 if c == report.best { " *" } else { "" });
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+// Upper-confidence-bound action selection: always play the arm maximizing
+//
+//     Q_t(a) + c * sqrt(ln t / N_t(a))
+//
+// where N_t(a) is how often a has been played. Arms that haven't been
+// played yet count as maximizing, so every arm is tried once first. An arm
+// added with a prior counts its pseudo-observations in N_t(a) instead.
+
+use rand::Rng;
+
+use crate::stats::{ArmPrior, ArmStats, ArmSummary};
+use crate::Agent;
+
+#[derive(Serialize, Deserialize)]
+pub struct UcbBandit {
+    stats: ArmStats,
+
+    // degree of exploration
+    c: f64,
+}
+
+impl UcbBandit {
+    pub fn new(n: usize, c: f64) -> UcbBandit {
+        assert!(n > 0);
+        assert!(c >= 0.0);
+        UcbBandit {
+            stats: ArmStats::new(n),
+            c,
+        }
+    }
+
+    fn upper_bound(&self, action: usize) -> f64 {
+        let weight = self.stats.weight(action);
+        if weight == 0.0 {
+            return f64::INFINITY;
+        }
+        let t = self.stats.total() as f64;
+        self.stats.estimate(action) + self.c * (t.max(1.0).ln() / weight).sqrt()
+    }
+}
+
+impl Agent for UcbBandit {
+    fn choose_action(&mut self, mut rng: &mut dyn Rng) -> usize {
+        let rng = &mut rng;
+        let active = self.stats.active();
+        let mut max_actions = vec![active[0]];
+        let mut max_value = self.upper_bound(active[0]);
+        for &i in &active[1..] {
+            let value = self.upper_bound(i);
+            if value > max_value {
+                max_actions.clear();
+                max_actions.push(i);
+                max_value = value;
+            } else if value == max_value {
+                max_actions.push(i);
+            }
+        }
+        let action = max_actions[rng.gen_range(0, max_actions.len())];
+        debug!("arm {} has the highest upper bound, {:.4}", action, max_value);
+        action
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.stats.update(action, reward);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.stats.summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.stats.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.stats.set_retired(arm, retired);
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
@@ -0,0 +1,134 @@
+// Warm starts, for simulating an agent deployed on top of existing data: the
+// agent is told what is already known about the arms before its first play,
+// either as a log of (arm, reward) observations, which it receives as though
+// it had played them itself, or as a count and mean reward per arm. The
+// latter reach Agent::warm_start, which tells the agent `count` rewards
+// equal to the mean, so agents that keep every reward (cvar, bootstrap) see
+// no spread in them.
+//
+// In a plan (see config), `warm_start = "history.csv"` warm-starts every
+// agent from a CSV file that is either an interaction log like a replay
+// testbed's, with columns step, arm and reward, or has the columns
+//
+//     arm,count,mean
+//
+// with one line per arm that has history; arms left out start from nothing.
+
+use std::fs::File;
+use std::io::Read;
+
+use crate::config;
+use crate::stats::ArmSummary;
+use crate::Agent;
+
+pub enum WarmStart {
+    // (arm, reward) observations, oldest first
+    Log(Vec<(usize, f64)>),
+    Summaries(Vec<ArmSummary>),
+}
+
+impl WarmStart {
+    pub fn read(path: &str, arms: usize) -> Result<WarmStart, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("couldn't read warm start `{}`: {}", path, e))?;
+        let mut lines = text.lines().enumerate()
+                            .filter(|&(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        let header: Vec<&str> = match lines.next() {
+            Some((_, line)) => line.split(',').map(|c| c.trim()).collect(),
+            None => return Err(format!("warm start `{}` is empty", path)),
+        };
+        if header.len() < 3 || header[..3] != ["arm", "count", "mean"] {
+            return Ok(WarmStart::Log(config::read_log(path, arms)?));
+        }
+
+        let mut summaries: Vec<ArmSummary> =
+            (0..arms).map(|_| ArmSummary { count: 0, mean: 0.0 }).collect();
+        let mut seen = vec![false; arms];
+        for (i, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let bad = || format!("warm start `{}` line {}: expected arm, count and mean",
+                                 path, i + 1);
+            if fields.len() < 3 {
+                return Err(bad());
+            }
+            let arm: usize = fields[0].parse().map_err(|_| bad())?;
+            let count: usize = fields[1].parse().map_err(|_| bad())?;
+            let mean: f64 = fields[2].parse().map_err(|_| bad())?;
+            if arm >= arms {
+                return Err(format!("warm start `{}` line {}: arm {} but there are {} arms",
+                                   path, i + 1, arm, arms));
+            }
+            if seen[arm] {
+                return Err(format!("warm start `{}` line {}: arm {} is given twice",
+                                   path, i + 1, arm));
+            }
+            if !mean.is_finite() {
+                return Err(format!("warm start `{}` line {}: mean must be finite", path, i + 1));
+            }
+            seen[arm] = true;
+            summaries[arm] = ArmSummary { count, mean };
+        }
+        Ok(WarmStart::Summaries(summaries))
+    }
+
+    pub fn apply(&self, agent: &mut dyn Agent) {
+        match *self {
+            WarmStart::Log(ref log) => agent.receive_rewards(log),
+            WarmStart::Summaries(ref arms) => agent.warm_start(arms),
+        }
+    }
+
+    pub fn observations(&self) -> usize {
+        match *self {
+            WarmStart::Log(ref log) => log.len(),
+            WarmStart::Summaries(ref arms) => arms.iter().map(|arm| arm.count).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    use crate::ucb::UcbBandit;
+
+    fn read(name: &str, contents: &str) -> Result<WarmStart, String> {
+        let path = env::temp_dir().join(format!("warm-start-{}-{}", name, ::std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, contents).unwrap();
+        let read = WarmStart::read(path, 3);
+        fs::remove_file(path).unwrap();
+        read
+    }
+
+    fn summaries(agent: &dyn Agent) -> Vec<(usize, f64)> {
+        agent.arm_summaries().iter().map(|arm| (arm.count, arm.mean)).collect()
+    }
+
+    // Either form of history leaves the agent knowing the same about the
+    // arms, and arms left out start from nothing.
+    #[test]
+    fn logs_and_summaries_warm_start_alike() {
+        let by_arm = read("summaries", "arm,count,mean\n2,2,1.5\n0,1,-1\n").unwrap();
+        let log = read("log", "step,arm,reward\n0,2,1\n1,0,-1\n2,2,2\n").unwrap();
+        for history in &[by_arm, log] {
+            assert_eq!(history.observations(), 3);
+            let mut agent = UcbBandit::new(3, 2.0);
+            history.apply(&mut agent);
+            assert_eq!(summaries(&agent), [(1, -1.0), (0, 0.0), (2, 1.5)]);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_summaries() {
+        for contents in &["", "arm,count,mean\n3,1,0\n", "arm,count,mean\n1,1,0\n1,2,0\n",
+                          "arm,count,mean\n1,1,inf\n", "arm,count,mean\n1,-1,0\n",
+                          "arm,count,mean\n1,1\n"] {
+            assert!(read("bad", contents).is_err(), "{:?}", contents);
+        }
+    }
+}
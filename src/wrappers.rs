@@ -0,0 +1,399 @@
+// Agents built around other agents, declared in a config plan so a
+// complex agent can be put together without writing Rust. An agent's
+// `wrappers` are applied in order, each around the agent and the wrappers
+// before it:
+//
+//     [[agents]]
+//     name = "adaptive"
+//     epsilon = 0.1
+//     wrappers = [{ kind = "count-bonus", beta = 0.5 },
+//                 { kind = "change-reset", threshold = 20.0 }]
+//
+// is a change-detecting restart around a count bonus around epsilon-greedy.
+// The built-in wrappers are
+//
+//     preprocess    reward_clip and reward_normalize, as for any agent (see
+//                   preprocess)
+//     count-bonus   shows the agent every reward plus beta / sqrt(N), N the
+//                   rewards of that arm so far (this one included), so arms
+//                   it has seen little of look better than they have paid
+//                   and any agent explores more early on
+//     change-reset  a two-sided Page-Hinkley test on every arm's rewards
+//                   (with tolerance `delta`, 0 by default); when an arm's
+//                   test exceeds `threshold` the agent is rebuilt from
+//                   scratch and every test starts over, for testbeds whose
+//                   arms drift or switch
+//
+// and any other kind is a wrapper registered under that name (see
+// registry), given its `params`. The agent's estimates are in the units of
+// the rewards it was shown, as with preprocessing.
+
+use rand::Rng;
+
+use crate::registry::Params;
+use crate::stats::{ArmPosterior, ArmPrior, ArmSummary};
+use crate::Agent;
+
+// The kinds of wrapper built in; a config's wrappers also take any
+// registered name.
+pub const BUILTIN_WRAPPERS: &[&str] = &["preprocess", "count-bonus", "change-reset"];
+
+// A wrapper and its parameters; preprocess is AgentSpec::Preprocessed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum WrapperSpec {
+    CountBonus { beta: f64 },
+    ChangeReset {
+        #[serde(default)]
+        delta: f64,
+        threshold: f64,
+    },
+
+    // a wrapper registered under `name`
+    Registered {
+        name: String,
+        #[serde(default)]
+        params: Params,
+    },
+}
+
+impl WrapperSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            WrapperSpec::CountBonus { beta } => {
+                if !(beta >= 0.0 && beta.is_finite()) {
+                    return Err("beta must be non-negative".to_string());
+                }
+            }
+            WrapperSpec::ChangeReset { delta, threshold } => {
+                if !(delta >= 0.0 && delta.is_finite()) {
+                    return Err("delta must be non-negative".to_string());
+                }
+                if threshold.is_nan() || threshold <= 0.0 {
+                    return Err("threshold must be positive".to_string());
+                }
+            }
+            WrapperSpec::Registered { .. } => {}
+        }
+        Ok(())
+    }
+
+    // Appended to the label of the agent it wraps, e.g. "+count-bonus(0.5)".
+    pub fn label(&self) -> String {
+        match *self {
+            WrapperSpec::CountBonus { beta } => format!("+count-bonus({})", beta),
+            WrapperSpec::ChangeReset { delta, threshold } => {
+                format!("+change-reset(delta={},threshold={})", delta, threshold)
+            }
+            WrapperSpec::Registered { ref name, ref params } => {
+                let params: Vec<String> = params.iter()
+                                                .map(|(key, value)| format!("{}={}", key, value))
+                                                .collect();
+                format!("+{}({})", name, params.join(","))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CountBonusState {
+    agent: serde_json::Value,
+    counts: Vec<usize>,
+}
+
+pub struct CountBonusAgent {
+    beta: f64,
+    agent: Box<dyn Agent>,
+    counts: Vec<usize>,
+}
+
+impl CountBonusAgent {
+    pub fn new(beta: f64, agent: Box<dyn Agent>) -> CountBonusAgent {
+        let n = agent.arm_summaries().len();
+        CountBonusAgent { beta, agent, counts: vec![0; n] }
+    }
+
+    // Rebuilds a wrapper from its state(), restoring the wrapped agent with
+    // `restore`.
+    pub fn from_state(beta: f64, state: serde_json::Value,
+                      restore: &dyn Fn(serde_json::Value) -> Result<Box<dyn Agent>, String>)
+                      -> Result<CountBonusAgent, String> {
+        let state: CountBonusState = serde_json::from_value(state).map_err(|e| e.to_string())?;
+        Ok(CountBonusAgent { beta, agent: restore(state.agent)?, counts: state.counts })
+    }
+}
+
+impl Agent for CountBonusAgent {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        self.agent.choose_action(rng)
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.counts[action] += 1;
+        let bonus = self.beta / (self.counts[action] as f64).sqrt();
+        self.agent.receive_reward(reward + bonus, action);
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.agent.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.counts.push(0);
+        self.agent.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.agent.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.agent.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(CountBonusState {
+            agent: self.agent.state(),
+            counts: self.counts.clone(),
+        }).unwrap()
+    }
+}
+
+// A two-sided Page-Hinkley test on one arm's rewards: the cumulative
+// deviations of the rewards from their running mean, less `delta` to
+// detect a rise and plus it to detect a fall, and how far each has come
+// from its extreme.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct PageHinkley {
+    count: usize,
+    mean: f64,
+    rise: f64,
+    rise_min: f64,
+    fall: f64,
+    fall_max: f64,
+}
+
+impl PageHinkley {
+    // Adds a reward, and says whether the arm's rewards have changed.
+    fn update(&mut self, reward: f64, delta: f64, threshold: f64) -> bool {
+        self.count += 1;
+        self.mean += (reward - self.mean) / self.count as f64;
+        self.rise += reward - self.mean - delta;
+        self.rise_min = self.rise_min.min(self.rise);
+        self.fall += reward - self.mean + delta;
+        self.fall_max = self.fall_max.max(self.fall);
+        self.rise - self.rise_min > threshold || self.fall_max - self.fall > threshold
+    }
+}
+
+// Everything but the agent builder, which can't be saved.
+#[derive(Serialize, Deserialize)]
+struct ChangeResetState {
+    agent: serde_json::Value,
+    n: usize,
+    added: Vec<Option<ArmPrior>>,
+    retired: Vec<bool>,
+    tests: Vec<PageHinkley>,
+    resets: usize,
+}
+
+pub struct ChangeResetAgent {
+    delta: f64,
+    threshold: f64,
+
+    // builds a fresh agent for n arms
+    make: Box<dyn Fn(usize) -> Box<dyn Agent>>,
+    agent: Box<dyn Agent>,
+
+    // arms the agent was built with, and the priors of arms added since, so
+    // a rebuilt agent has the same arms
+    n: usize,
+    added: Vec<Option<ArmPrior>>,
+    retired: Vec<bool>,
+
+    tests: Vec<PageHinkley>,
+    resets: usize,
+}
+
+impl ChangeResetAgent {
+    pub fn new(n: usize, delta: f64, threshold: f64, make: Box<dyn Fn(usize) -> Box<dyn Agent>>)
+               -> ChangeResetAgent {
+        ChangeResetAgent {
+            delta,
+            threshold,
+            agent: make(n),
+            make,
+            n,
+            added: vec![],
+            retired: vec![false; n],
+            tests: vec![PageHinkley::default(); n],
+            resets: 0,
+        }
+    }
+
+    // Rebuilds a wrapper from its state(), restoring the current agent with
+    // `restore`.
+    pub fn from_state(delta: f64, threshold: f64, make: Box<dyn Fn(usize) -> Box<dyn Agent>>,
+                      state: serde_json::Value,
+                      restore: &dyn Fn(serde_json::Value) -> Result<Box<dyn Agent>, String>)
+                      -> Result<ChangeResetAgent, String> {
+        let state: ChangeResetState = serde_json::from_value(state).map_err(|e| e.to_string())?;
+        Ok(ChangeResetAgent {
+            delta,
+            threshold,
+            make,
+            agent: restore(state.agent)?,
+            n: state.n,
+            added: state.added,
+            retired: state.retired,
+            tests: state.tests,
+            resets: state.resets,
+        })
+    }
+
+    // Starts over with a fresh agent, with the arms and retirements the old
+    // one had.
+    fn reset(&mut self) {
+        self.agent = (self.make)(self.n);
+        for &prior in &self.added {
+            self.agent.add_arm(prior);
+        }
+        for (arm, &retired) in self.retired.iter().enumerate() {
+            if retired {
+                self.agent.set_retired(arm, true);
+            }
+        }
+        for test in &mut self.tests {
+            *test = PageHinkley::default();
+        }
+        self.resets += 1;
+        debug!("change detected; restarting the agent (reset {})", self.resets);
+    }
+}
+
+impl Agent for ChangeResetAgent {
+    fn choose_action(&mut self, rng: &mut dyn Rng) -> usize {
+        self.agent.choose_action(rng)
+    }
+
+    fn receive_reward(&mut self, reward: f64, action: usize) {
+        self.agent.receive_reward(reward, action);
+        if self.tests[action].update(reward, self.delta, self.threshold) {
+            self.reset();
+        }
+    }
+
+    fn arm_summaries(&self) -> Vec<ArmSummary> {
+        self.agent.arm_summaries()
+    }
+
+    fn add_arm(&mut self, prior: Option<ArmPrior>) -> usize {
+        self.added.push(prior);
+        self.retired.push(false);
+        self.tests.push(PageHinkley::default());
+        self.agent.add_arm(prior)
+    }
+
+    fn set_retired(&mut self, arm: usize, retired: bool) {
+        self.retired[arm] = retired;
+        self.agent.set_retired(arm, retired);
+    }
+
+    fn posteriors(&self) -> Option<Vec<ArmPosterior>> {
+        self.agent.posteriors()
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(ChangeResetState {
+            agent: self.agent.state(),
+            n: self.n,
+            added: self.added.clone(),
+            retired: self.retired.clone(),
+            tests: self.tests.clone(),
+            resets: self.resets,
+        }).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    
+
+    use crate::config::AgentConfig;
+    use crate::spec::AgentSpec;
+
+    fn configured(config: &str) -> Result<AgentSpec, String> {
+        toml::from_str::<AgentConfig>(config).map_err(|e| e.to_string())?.spec()
+    }
+
+    #[test]
+    fn config_wrappers_nest_in_order() {
+        let spec = configured(r#"
+            name = "adaptive"
+            epsilon = 0.1
+            reward_clip = [-3.0, 3.0]
+            wrappers = [{ kind = "count-bonus", beta = 0.5 },
+                        { kind = "preprocess", reward_normalize = "minmax" },
+                        { kind = "change-reset", threshold = 20.0 }]
+        "#).unwrap();
+        assert_eq!(spec.label(), "eps-greedy(epsilon=0.1)+count-bonus(0.5)+minmax\
+                                  +change-reset(delta=0,threshold=20)+clip[-3,3]");
+
+        let bad = [r#"kind = "count-bonus""#,
+                   r#"kind = "count-bonus", beta = 0.5, threshold = 1.0"#,
+                   r#"kind = "change-reset", threshold = 0.0"#,
+                   r#"kind = "preprocess""#,
+                   r#"kind = "no-such-wrapper""#];
+        for wrapper in &bad {
+            let config = format!("name = \"bad\"\nepsilon = 0.1\nwrappers = [{{ {} }}]",
+                                 wrapper);
+            assert!(configured(&config).is_err(), "{}", wrapper);
+        }
+    }
+
+    #[test]
+    fn count_bonus_shows_the_bonus() {
+        let mut agent = configured(r#"
+            name = "bonus"
+            epsilon = 0.0
+            wrappers = [{ kind = "count-bonus", beta = 1.0 }]
+        "#).unwrap().build(2);
+        agent.receive_reward(0.0, 0);
+        agent.receive_reward(0.0, 0);
+        agent.receive_reward(0.0, 0);
+        agent.receive_reward(0.0, 0);
+        let expected = (1.0 + 1.0 / 2f64.sqrt() + 1.0 / 3f64.sqrt() + 0.5) / 4.0;
+        assert!((agent.arm_summaries()[0].mean - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn change_reset_restarts_after_a_change() {
+        let spec = configured(r#"
+            name = "reset"
+            epsilon = 0.1
+            wrappers = [{ kind = "change-reset", delta = 0.05, threshold = 5.0 }]
+        "#).unwrap();
+        let mut agent = spec.build(2);
+        agent.add_arm(None);
+        agent.set_retired(1, true);
+        for _ in 0..100 {
+            agent.receive_reward(1.0, 0);
+        }
+        assert_eq!(agent.arm_summaries()[0].count, 100);
+
+        // the state comes back as it was, and the arm's rewards dropping
+        // soon restarts the agent, with the arm added and the one retired
+        let mut agent = spec.restore(agent.state()).unwrap();
+        let mut plays = 0;
+        while agent.arm_summaries()[0].count > 0 {
+            agent.receive_reward(-1.0, 0);
+            plays += 1;
+            assert!(plays < 20, "no change detected");
+        }
+        assert_eq!(agent.arm_summaries().len(), 3);
+        let mut rng = ::rand::thread_rng();
+        for _ in 0..100 {
+            assert!(agent.choose_action(&mut rng) != 1);
+        }
+    }
+}
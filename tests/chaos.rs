@@ -0,0 +1,164 @@
+// Kills checkpointed runs of the binary at random points, resumes them
+// until they finish, and checks that every file they write ends up the same
+// as an uninterrupted run's: the series, the event logs and the summary.
+// Every run is left alone until a checkpoint has been written, so each one
+// resumes from somewhere, and is then killed after a delay drawn from a
+// fixed seed as a fraction of how long the uninterrupted run took, so most
+// kills land mid-run, some before the resumed run's next checkpoint and
+// some in the middle of writing a file.
+
+extern crate flate2;
+extern crate rand;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::read::MultiGzDecoder;
+use rand::{Rng, SeedableRng, StdRng};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_n-armed-bandit-greedy");
+
+// Interruptions per run before it is left to finish.
+const KILLS: usize = 6;
+
+// How long a run may take to write its first checkpoint.
+const CHECKPOINT_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn plan(compress: &str, events: &str) -> String {
+    format!(r#"
+        compress = "{}"
+        events = "{}"
+        summary = "summary.txt"
+
+        [testbed]
+        kind = "random-walk"
+        walk_sigma = 0.01
+        arms = 10
+        tasks = 320
+        plays = 100
+        seed = 11
+
+        [[agents]]
+        name = "eps"
+        epsilon = 0.1
+
+        [[agents]]
+        name = "ucb"
+        kind = "ucb"
+        c = 2.0
+        reward_normalize = "minmax"
+    "#, compress, events)
+}
+
+fn fresh_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chaos-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn command(dir: &Path, checkpoint: &[&str]) -> Command {
+    let mut command = Command::new(BINARY);
+    command.current_dir(dir)
+           .args(&["--config", "plan.toml", "--quiet", "--checkpoint-every", "32"][..])
+           .args(checkpoint)
+           .stdout(Stdio::null())
+           .stderr(Stdio::null());
+    command
+}
+
+// Waits until there is a checkpoint in `dir` or `child` has exited, polling
+// for at most CHECKPOINT_TIMEOUT.
+fn wait_for_checkpoint(dir: &Path, child: &mut Child) {
+    let started = Instant::now();
+    loop {
+        let written = fs::read_dir(dir).unwrap().any(|entry| {
+            let file_name = entry.unwrap().file_name();
+            let file_name = file_name.to_str().unwrap();
+            file_name.starts_with("checkpoint") && !file_name.ends_with(".tmp")
+        });
+        if written || child.try_wait().unwrap().is_some() {
+            return;
+        }
+        assert!(started.elapsed() < CHECKPOINT_TIMEOUT, "no checkpoint in {:?}", dir);
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+// A file's contents, decompressed if gzipped.
+fn contents(path: &Path) -> Vec<u8> {
+    let mut contents = vec![];
+    let mut f = File::open(path).unwrap();
+    if path.extension() == Some("gz".as_ref()) {
+        MultiGzDecoder::new(f).read_to_end(&mut contents).unwrap();
+    } else {
+        f.read_to_end(&mut contents).unwrap();
+    }
+    contents
+}
+
+// Runs `plan` once straight through and once with KILLS interruptions, and
+// compares what the two wrote. Returns how many of the kills caught the
+// run before it finished.
+fn check_chaos(name: &str, plan: &str, seed: usize) -> usize {
+    let whole = fresh_dir(&format!("{}-whole", name));
+    fs::write(whole.join("plan.toml"), plan).unwrap();
+    let started = Instant::now();
+    let status = command(&whole, &["--checkpoint", "checkpoint"]).status().unwrap();
+    assert!(status.success());
+    let elapsed = started.elapsed();
+
+    let chaos = fresh_dir(&format!("{}-chaos", name));
+    fs::write(chaos.join("plan.toml"), plan).unwrap();
+    let seed: &[_] = &[seed];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let mut killed = 0;
+    for i in 0..KILLS + 1 {
+        let flag = if i == 0 { "--checkpoint" } else { "--resume" };
+        let mut child = command(&chaos, &[flag, "checkpoint"]).spawn().unwrap();
+        if i == KILLS {
+            assert!(child.wait().unwrap().success());
+            break;
+        }
+        wait_for_checkpoint(&chaos, &mut child);
+        thread::sleep(elapsed.mul_f64(rng.gen_range(0.0, 0.6)));
+        match child.try_wait().unwrap() {
+            Some(status) => {
+                assert!(status.success());
+                break;
+            }
+            None => {
+                child.kill().unwrap();
+                child.wait().unwrap();
+                killed += 1;
+            }
+        }
+    }
+
+    for entry in fs::read_dir(&whole).unwrap() {
+        let file_name = entry.unwrap().file_name();
+        if file_name.to_str().unwrap().starts_with("checkpoint") {
+            continue;
+        }
+        assert!(contents(&whole.join(&file_name)) == contents(&chaos.join(&file_name)),
+                "{:?} differs after {} interruptions", file_name, killed);
+    }
+    fs::remove_dir_all(&whole).unwrap();
+    fs::remove_dir_all(&chaos).unwrap();
+    killed
+}
+
+#[test]
+fn interrupted_runs_end_as_uninterrupted_ones() {
+    assert!(check_chaos("plain", &plan("none", "csv"), 1) > 0);
+}
+
+#[test]
+fn interrupted_compressed_runs_end_as_uninterrupted_ones() {
+    assert!(check_chaos("gzip", &plan("gzip", "jsonl"), 2) > 0);
+}